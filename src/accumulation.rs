@@ -0,0 +1,69 @@
+use crate::camera::Camera;
+use crate::renderer::{Color, RenderTarget};
+use crate::taa::jittered;
+use rayon::prelude::*;
+
+/// Render quality mode for stills. While the scene stands still every frame is drawn with a
+/// different sub-pixel jitter and averaged with the ones before, converging to an
+/// anti-aliased image after `frames` frames, which is then presented until something
+/// changes. Any change to the scene starts over.
+#[derive(Debug, Clone)]
+pub struct Accumulation {
+    /// Frames averaged into the final image.
+    pub frames: u32,
+    sum: Vec<[f32; 3]>,
+    count: u32,
+}
+impl Default for Accumulation {
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
+#[allow(unused)]
+impl Accumulation {
+    pub fn new(frames: u32) -> Self {
+        Self {
+            frames: frames.max(1),
+            sum: vec![],
+            count: 0,
+        }
+    }
+    /// Frames averaged so far.
+    pub fn progress(&self) -> u32 {
+        self.count
+    }
+    pub fn converged(&self) -> bool {
+        self.count >= self.frames
+    }
+    pub fn reset(&mut self) {
+        self.sum.clear();
+        self.count = 0;
+    }
+    /// Starts over unless the scene fingerprint is the same as last frame's.
+    pub(crate) fn track(&mut self, unchanged: bool) {
+        if !unchanged {
+            self.reset();
+        }
+    }
+    /// The camera with this frame's jitter, draw with it before `add`.
+    pub(crate) fn jittered(&self, camera: &Camera, size: (u32, u32)) -> Camera {
+        jittered(camera, self.count + 1, size)
+    }
+    /// Adds the frame in `target` and replaces it with the average so far.
+    pub fn add(&mut self, target: &mut RenderTarget) {
+        if self.sum.len() != target.color.len() {
+            self.reset();
+            self.sum = vec![[0.0; 3]; target.color.len()];
+        }
+        self.count += 1;
+        let scale = 1.0 / self.count as f32;
+        self.sum
+            .par_iter_mut()
+            .zip(target.color.par_iter_mut())
+            .for_each(|(sum, pixel)| {
+                let color = Color::from_u32(*pixel);
+                *sum = [sum[0] + color.r, sum[1] + color.g, sum[2] + color.b];
+                *pixel = Color::new(sum[0] * scale, sum[1] * scale, sum[2] * scale, 1.0).as_u32();
+            });
+    }
+}
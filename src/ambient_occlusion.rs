@@ -0,0 +1,71 @@
+use crate::bvh::Bvh;
+use crate::geometry::{Model, Ray};
+use crate::renderer::Color;
+use nalgebra::Vector3;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+
+/// Bakes ambient occlusion into the vertex colors of a copy of `model`. Each vertex casts
+/// `samples` cosine weighted rays over its normal's hemisphere, rays hitting the model within
+/// `max_distance` count as occluded. Colors are gray, white meaning fully open.
+#[allow(unused)]
+pub fn bake_vertex_ao(model: &Model, samples: u32, max_distance: f32) -> Model {
+    let model = &model.unwelded();
+    let bvh = Bvh::from_model(model);
+    let mut baked = model.clone();
+    let face_normals = model
+        .vertices
+        .chunks_exact(3)
+        .map(|t| {
+            let (a, b, c) = (
+                t[0].position.xyz(),
+                t[1].position.xyz(),
+                t[2].position.xyz(),
+            );
+            (b - a)
+                .cross(&(c - a))
+                .try_normalize(1e-12)
+                .unwrap_or(Vector3::y())
+        })
+        .collect::<Vec<_>>();
+
+    baked
+        .vertices
+        .par_iter_mut()
+        .enumerate()
+        .for_each(|(index, vertex)| {
+            let normal = vertex
+                .normal
+                .and_then(|n| n.try_normalize(1e-12))
+                .unwrap_or(face_normals[index / 3]);
+            let origin = vertex.position.xyz() + normal * (max_distance * 1e-3);
+            // Seeded per vertex so a bake is reproducible
+            let mut rng = XorShiftRng::seed_from_u64(index as u64);
+            let occluded = (0..samples)
+                .filter(|_| {
+                    let direction = cosine_sample(&normal, rng.random(), rng.random());
+                    bvh.occluded(&Ray::new(origin, direction), max_distance)
+                })
+                .count();
+            let open = 1.0 - occluded as f32 / samples.max(1) as f32;
+            vertex.color = Some(Color::new(open, open, open, 1.0));
+        });
+    baked
+}
+
+/// Direction on the hemisphere around `normal` with a cosine distribution.
+pub fn cosine_sample(normal: &Vector3<f32>, u: f32, v: f32) -> Vector3<f32> {
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = normal.cross(&helper).normalize();
+    let bitangent = normal.cross(&tangent);
+    let radius = u.sqrt();
+    let angle = v * std::f32::consts::TAU;
+    tangent * (radius * angle.cos())
+        + bitangent * (radius * angle.sin())
+        + normal * (1.0 - u).sqrt()
+}
@@ -0,0 +1,391 @@
+use crate::attachment::AttachmentPoints;
+use nalgebra::{Matrix4, Quaternion, Translation3, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Local transform of a node, kept apart so poses can be blended.
+#[derive(Debug, Copy, Clone)]
+pub struct NodePose {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+impl NodePose {
+    pub fn blend(&self, other: &NodePose, t: f32) -> NodePose {
+        NodePose {
+            translation: self.translation.lerp(&other.translation, t),
+            rotation: self
+                .rotation
+                .try_slerp(&other.rotation, t, 1e-6)
+                .unwrap_or(if t < 0.5 {
+                    self.rotation
+                } else {
+                    other.rotation
+                }),
+            scale: self.scale.lerp(&other.scale, t),
+        }
+    }
+    pub fn to_matrix(self) -> Matrix4<f32> {
+        Translation3::from(self.translation).to_homogeneous()
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RigNode {
+    name: Option<String>,
+    parent: Option<usize>,
+    rest: NodePose,
+}
+
+#[derive(Debug, Clone)]
+enum Keys {
+    Translation(Vec<Vector3<f32>>),
+    Rotation(Vec<UnitQuaternion<f32>>),
+    Scale(Vec<Vector3<f32>>),
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    node: usize,
+    times: Vec<f32>,
+    keys: Keys,
+}
+impl Channel {
+    /// Linear interpolation between the keys around `time`, step and cubic samplers are
+    /// treated as linear.
+    fn apply(&self, time: f32, pose: &mut NodePose) {
+        let Some(last) = self.times.len().checked_sub(1) else {
+            return;
+        };
+        let next = self.times.partition_point(|t| *t <= time).min(last);
+        let previous = next.saturating_sub(1);
+        let span = self.times[next] - self.times[previous];
+        let t = if span > f32::EPSILON {
+            ((time - self.times[previous]) / span).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        match &self.keys {
+            Keys::Translation(keys) if keys.len() > next => {
+                pose.translation = keys[previous].lerp(&keys[next], t);
+            }
+            Keys::Rotation(keys) if keys.len() > next => {
+                pose.rotation = keys[previous]
+                    .try_slerp(&keys[next], t, 1e-6)
+                    .unwrap_or(keys[next]);
+            }
+            Keys::Scale(keys) if keys.len() > next => {
+                pose.scale = keys[previous].lerp(&keys[next], t);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// Seconds, the time of the last key.
+    pub duration: f32,
+    channels: Vec<Channel>,
+}
+
+/// The node hierarchy and animation clips of a glTF file, shared by every controller
+/// playing it.
+#[derive(Debug, Clone)]
+pub struct Rig {
+    nodes: Vec<RigNode>,
+    clips: Vec<AnimationClip>,
+}
+#[allow(unused)]
+impl Rig {
+    pub fn from_gltf(path: &str) -> Result<Self, String> {
+        let (gltf, buffers, _) = gltf::import(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mut nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                RigNode {
+                    name: node.name().map(str::to_owned),
+                    parent: None,
+                    rest: NodePose {
+                        translation: Vector3::from(translation),
+                        rotation: UnitQuaternion::from_quaternion(Quaternion::from(rotation)),
+                        scale: Vector3::from(scale),
+                    },
+                }
+            })
+            .collect::<Vec<_>>();
+        for node in gltf.nodes() {
+            for child in node.children() {
+                nodes[child.index()].parent = Some(node.index());
+            }
+        }
+        let clips = gltf
+            .animations()
+            .enumerate()
+            .map(|(index, animation)| {
+                let channels = animation
+                    .channels()
+                    .filter_map(|channel| {
+                        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                        let times = reader.read_inputs()?.collect::<Vec<_>>();
+                        use gltf::animation::util::ReadOutputs;
+                        let keys = match reader.read_outputs()? {
+                            ReadOutputs::Translations(values) => {
+                                Keys::Translation(values.map(Vector3::from).collect())
+                            }
+                            ReadOutputs::Rotations(values) => Keys::Rotation(
+                                values
+                                    .into_f32()
+                                    .map(|r| UnitQuaternion::from_quaternion(Quaternion::from(r)))
+                                    .collect(),
+                            ),
+                            ReadOutputs::Scales(values) => {
+                                Keys::Scale(values.map(Vector3::from).collect())
+                            }
+                            ReadOutputs::MorphTargetWeights(_) => return None,
+                        };
+                        Some(Channel {
+                            node: channel.target().node().index(),
+                            times,
+                            keys,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let duration = channels
+                    .iter()
+                    .filter_map(|channel| channel.times.last())
+                    .fold(0.0, |a: f32, b| a.max(*b));
+                AnimationClip {
+                    name: animation
+                        .name()
+                        .map_or(format!("clip{}", index), str::to_owned),
+                    duration,
+                    channels,
+                }
+            })
+            .collect();
+        Ok(Self { nodes, clips })
+    }
+    pub fn clip(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+    pub fn clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+    /// Local node poses of a clip at `time`, nodes it doesn't animate keep their rest pose.
+    pub fn sample(&self, clip: usize, time: f32) -> Vec<NodePose> {
+        let mut pose = self.nodes.iter().map(|node| node.rest).collect::<Vec<_>>();
+        if let Some(clip) = self.clips.get(clip) {
+            for channel in &clip.channels {
+                if let Some(node) = pose.get_mut(channel.node) {
+                    channel.apply(time, node);
+                }
+            }
+        }
+        pose
+    }
+    /// Model space transforms of every named node for a pose.
+    pub fn attachment_points(&self, pose: &[NodePose]) -> AttachmentPoints {
+        let mut world: Vec<Option<Matrix4<f32>>> = vec![None; self.nodes.len()];
+        let mut points = AttachmentPoints::new();
+        for index in 0..self.nodes.len() {
+            let transform = self.world_transform(index, pose, &mut world);
+            if let Some(name) = &self.nodes[index].name {
+                points.insert(name, transform);
+            }
+        }
+        points
+    }
+    fn world_transform(
+        &self,
+        index: usize,
+        pose: &[NodePose],
+        cache: &mut [Option<Matrix4<f32>>],
+    ) -> Matrix4<f32> {
+        if let Some(transform) = cache[index] {
+            return transform;
+        }
+        let local = pose[index].to_matrix();
+        let transform = match self.nodes[index].parent {
+            Some(parent) => self.world_transform(parent, pose, cache) * local,
+            None => local,
+        };
+        cache[index] = Some(transform);
+        transform
+    }
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    clip: usize,
+    speed: f32,
+    looping: bool,
+}
+
+/// Switches to `to` from `from` (any state when `None`) once a parameter crosses a
+/// threshold, e.g. idle to walk when "speed" goes above 0.1.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub from: Option<String>,
+    pub to: String,
+    pub parameter: String,
+    pub threshold: f32,
+    /// Fires when the parameter is above the threshold, below it otherwise.
+    pub above: bool,
+    /// Cross-fade time in seconds.
+    pub fade: f32,
+}
+
+#[derive(Debug, Clone)]
+struct Playing {
+    state: String,
+    time: f32,
+}
+
+/// Plays a rig's clips as named states with cross-fades between them, attached to an entity
+/// with `Entity::with_animation` it poses the entity's attachment points every frame.
+#[derive(Debug, Clone)]
+pub struct AnimationController {
+    rig: Arc<Rig>,
+    states: HashMap<String, State>,
+    transitions: Vec<Transition>,
+    parameters: HashMap<String, f32>,
+    /// Multiplies the speed of every state.
+    pub speed: f32,
+    current: Option<Playing>,
+    /// The state faded out of, and how far the fade has come in 0..1.
+    previous: Option<(Playing, f32)>,
+    fade: f32,
+}
+#[allow(unused)]
+impl AnimationController {
+    pub fn new(rig: Arc<Rig>) -> Self {
+        Self {
+            rig,
+            states: HashMap::new(),
+            transitions: vec![],
+            parameters: HashMap::new(),
+            speed: 1.0,
+            current: None,
+            previous: None,
+            fade: 0.0,
+        }
+    }
+    /// Adds a state playing the named clip, the first state added starts playing.
+    pub fn with_state(mut self, name: &str, clip: &str, speed: f32, looping: bool) -> Self {
+        if let Some(clip) = self.rig.clip(clip) {
+            self.states.insert(
+                name.to_owned(),
+                State {
+                    clip,
+                    speed,
+                    looping,
+                },
+            );
+            if self.current.is_none() {
+                self.current = Some(Playing {
+                    state: name.to_owned(),
+                    time: 0.0,
+                });
+            }
+        }
+        self
+    }
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transitions.push(transition);
+        self
+    }
+    pub fn set_parameter(&mut self, name: &str, value: f32) {
+        self.parameters.insert(name.to_owned(), value);
+    }
+    pub fn state(&self) -> Option<&str> {
+        self.current.as_ref().map(|playing| playing.state.as_str())
+    }
+    /// Cross-fades to a state over `fade` seconds, restarting it unless it is playing.
+    pub fn play(&mut self, state: &str, fade: f32) {
+        if !self.states.contains_key(state) || self.state() == Some(state) {
+            return;
+        }
+        let next = Playing {
+            state: state.to_owned(),
+            time: 0.0,
+        };
+        self.previous = self
+            .current
+            .replace(next)
+            .filter(|_| fade > 0.0)
+            .map(|playing| (playing, 0.0));
+        self.fade = fade;
+    }
+    /// Advances playback and fades, then follows the first transition whose condition holds.
+    pub fn update(&mut self, delta: f32) {
+        let advance =
+            |playing: &mut Playing, states: &HashMap<String, State>, rig: &Rig, speed: f32| {
+                let Some(state) = states.get(&playing.state) else {
+                    return;
+                };
+                let duration = rig.clips[state.clip].duration;
+                playing.time += delta * state.speed * speed;
+                playing.time = if state.looping && duration > 0.0 {
+                    playing.time.rem_euclid(duration)
+                } else {
+                    playing.time.clamp(0.0, duration)
+                };
+            };
+        if let Some(playing) = &mut self.current {
+            advance(playing, &self.states, &self.rig, self.speed);
+        }
+        if let Some((playing, progress)) = &mut self.previous {
+            advance(playing, &self.states, &self.rig, self.speed);
+            *progress += delta / self.fade.max(f32::EPSILON);
+            if *progress >= 1.0 {
+                self.previous = None;
+            }
+        }
+
+        let state = self.state().map(str::to_owned);
+        let fired = self.transitions.iter().find(|transition| {
+            let from = transition.from.is_none() || transition.from == state;
+            let value = self
+                .parameters
+                .get(&transition.parameter)
+                .copied()
+                .unwrap_or(0.0);
+            let crossed = if transition.above {
+                value > transition.threshold
+            } else {
+                value < transition.threshold
+            };
+            from && crossed && state.as_deref() != Some(transition.to.as_str())
+        });
+        if let Some(transition) = fired.cloned() {
+            self.play(&transition.to, transition.fade);
+        }
+    }
+    /// Local node poses, blended while a cross-fade runs.
+    pub fn pose(&self) -> Vec<NodePose> {
+        let sample = |playing: &Playing| match self.states.get(&playing.state) {
+            Some(state) => self.rig.sample(state.clip, playing.time),
+            None => self.rig.sample(usize::MAX, 0.0),
+        };
+        let current = self
+            .current
+            .as_ref()
+            .map_or_else(|| self.rig.sample(usize::MAX, 0.0), sample);
+        match &self.previous {
+            Some((previous, progress)) => sample(previous)
+                .iter()
+                .zip(&current)
+                .map(|(from, to)| from.blend(to, *progress))
+                .collect(),
+            None => current,
+        }
+    }
+    pub fn attachment_points(&self) -> AttachmentPoints {
+        self.rig.attachment_points(&self.pose())
+    }
+}
@@ -0,0 +1,60 @@
+use crate::Asset;
+use crate::error::SoftRastError;
+use crate::geometry::Texture;
+use crate::offline::load_any_model;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::thread;
+
+/// Loads models and textures on background threads so file reads and decoding don't stall
+/// frames. Each request runs on its own thread, finished loads are picked up with `poll`.
+pub struct AssetLoader {
+    sender: Sender<(String, Result<Asset, SoftRastError>)>,
+    /// Behind a lock so a scene holding a loader can still be shared with render threads.
+    receiver: Mutex<Receiver<(String, Result<Asset, SoftRastError>)>>,
+    /// Requests not yet returned by `poll`.
+    pending: usize,
+}
+impl Default for AssetLoader {
+    fn default() -> Self {
+        let (sender, receiver) = channel();
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+            pending: 0,
+        }
+    }
+}
+#[allow(unused)]
+impl AssetLoader {
+    /// Loads an OBJ, PLY or glTF model like `Command::load_model`.
+    pub fn request_model(&mut self, path: &str) {
+        self.request(path, |path| load_any_model(path).map(Asset::Model));
+    }
+    pub fn request_texture(&mut self, path: &str) {
+        self.request(path, |path| Texture::new(path).map(Asset::Texture));
+    }
+    /// Loads finished since the last call, with the path they were requested with.
+    pub fn poll(&mut self) -> Vec<(String, Result<Asset, SoftRastError>)> {
+        let receiver = self
+            .receiver
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let finished = receiver.try_iter().collect::<Vec<_>>();
+        self.pending -= finished.len();
+        finished
+    }
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+    fn request(&mut self, path: &str, load: fn(&str) -> Result<Asset, SoftRastError>) {
+        let sender = self.sender.clone();
+        let path = path.to_owned();
+        self.pending += 1;
+        thread::spawn(move || {
+            let result = load(&path);
+            // The loader is gone when nobody waits for the result any more
+            let _ = sender.send((path, result));
+        });
+    }
+}
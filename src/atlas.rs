@@ -0,0 +1,185 @@
+use crate::geometry::{Model, Texture, push_sub_mesh};
+use crate::sprite::{SpriteFrame, SpriteSheet};
+use image::{DynamicImage, RgbaImage};
+use nalgebra::Vector2;
+
+/// Where one packed texture ended up in an atlas.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AtlasRegion {
+    /// Pixel rectangle without padding.
+    pub frame: SpriteFrame,
+    /// UV corners as (min, max), v pointing up like `Texture::sample`.
+    pub uv_min: Vector2<f32>,
+    pub uv_max: Vector2<f32>,
+}
+impl AtlasRegion {
+    /// Maps a UV of the source texture into the atlas, clamped to the region since repeats
+    /// can't wrap inside an atlas.
+    pub fn map(&self, uv: &Vector2<f32>) -> Vector2<f32> {
+        let uv = uv.map(|c| c.clamp(0.0, 1.0));
+        self.uv_min + (self.uv_max - self.uv_min).component_mul(&uv)
+    }
+}
+
+/// Packs many small textures into one with a shelf packer, tallest first.
+#[derive(Debug, Clone)]
+pub struct AtlasBuilder {
+    /// Pixels between regions, filled with each region's edge pixels so filtering doesn't
+    /// bleed in from its neighbours.
+    pub padding: u32,
+    pub max_width: u32,
+    images: Vec<RgbaImage>,
+}
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self {
+            padding: 2,
+            max_width: 4096,
+            images: vec![],
+        }
+    }
+}
+#[allow(unused)]
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+    /// Adds a texture, the returned index picks its region in `Atlas::regions`.
+    pub fn add(&mut self, texture: &Texture) -> usize {
+        self.images.push(texture.texture.to_rgba8());
+        self.images.len() - 1
+    }
+    pub fn build(&self) -> Atlas {
+        let padding = self.padding;
+        let padded =
+            |image: &RgbaImage| (image.width() + padding * 2, image.height() + padding * 2);
+        let area = self.images.iter().map(|image| {
+            let (w, h) = padded(image);
+            w as u64 * h as u64
+        });
+        let widest = self
+            .images
+            .iter()
+            .map(|image| padded(image).0)
+            .max()
+            .unwrap_or(1);
+        // Roughly square, rounded to a power of two
+        let width = ((area.sum::<u64>() as f64).sqrt().ceil() as u32)
+            .max(widest)
+            .next_power_of_two()
+            .min(self.max_width.max(widest));
+
+        let mut order = (0..self.images.len()).collect::<Vec<_>>();
+        order.sort_by_key(|index| std::cmp::Reverse(self.images[*index].height()));
+        let mut positions = vec![(0, 0); self.images.len()];
+        let (mut x, mut y, mut shelf) = (0, 0, 0);
+        for index in order {
+            let (w, h) = padded(&self.images[index]);
+            if x + w > width {
+                (x, y) = (0, y + shelf);
+                shelf = 0;
+            }
+            positions[index] = (x, y);
+            x += w;
+            shelf = shelf.max(h);
+        }
+        let height = (y + shelf).max(1);
+
+        let mut atlas = RgbaImage::new(width, height);
+        let mut regions = Vec::with_capacity(self.images.len());
+        for (image, (x, y)) in self.images.iter().zip(positions) {
+            let (w, h) = padded(image);
+            for py in 0..h {
+                for px in 0..w {
+                    // Padding repeats the nearest edge pixel
+                    let sx = px
+                        .saturating_sub(padding)
+                        .min(image.width().saturating_sub(1));
+                    let sy = py
+                        .saturating_sub(padding)
+                        .min(image.height().saturating_sub(1));
+                    if image.width() > 0 && image.height() > 0 {
+                        atlas.put_pixel(x + px, y + py, *image.get_pixel(sx, sy));
+                    }
+                }
+            }
+            let frame = SpriteFrame {
+                x: x + padding,
+                y: y + padding,
+                width: image.width(),
+                height: image.height(),
+            };
+            regions.push(AtlasRegion {
+                frame,
+                uv_min: Vector2::new(
+                    frame.x as f32 / width as f32,
+                    1.0 - (frame.y + frame.height) as f32 / height as f32,
+                ),
+                uv_max: Vector2::new(
+                    (frame.x + frame.width) as f32 / width as f32,
+                    1.0 - frame.y as f32 / height as f32,
+                ),
+            });
+        }
+        Atlas {
+            texture: Texture::from_image(DynamicImage::ImageRgba8(atlas)),
+            regions,
+        }
+    }
+}
+
+/// Packed textures and where each one went, in the order they were added.
+#[derive(Debug, Clone)]
+pub struct Atlas {
+    pub texture: Texture,
+    pub regions: Vec<AtlasRegion>,
+}
+#[allow(unused)]
+impl Atlas {
+    /// Rewrites the UVs of every sub mesh whose material slot maps to a region in `slots`
+    /// (indexed by material slot) and moves those sub meshes to slot 0, so one material with
+    /// the atlas texture draws them all. Slots mapping to `None` are left as they are, so
+    /// slot 0 should be one of the mapped ones.
+    pub fn remap_model(&self, model: &Model, slots: &[Option<usize>]) -> Model {
+        let mut model = model.unwelded();
+        let slot_ranges = if model.sub_meshes.is_empty() {
+            vec![(0..model.vertices.len(), 0)]
+        } else {
+            model
+                .sub_meshes
+                .iter()
+                .map(|sub_mesh| (sub_mesh.range.clone(), sub_mesh.material_index))
+                .collect()
+        };
+        let mut sub_meshes = vec![];
+        for (range, slot) in slot_ranges {
+            let region = slots
+                .get(slot)
+                .copied()
+                .flatten()
+                .and_then(|index| self.regions.get(index));
+            match region {
+                Some(region) => {
+                    for vertex in &mut model.vertices[range.clone()] {
+                        vertex.uv = vertex.uv.map(|uv| region.map(&uv));
+                    }
+                    push_sub_mesh(&mut sub_meshes, range, 0);
+                }
+                None => push_sub_mesh(&mut sub_meshes, range, slot),
+            }
+        }
+        model.sub_meshes = sub_meshes;
+        model
+    }
+    /// The atlas as a sprite sheet with one frame per packed texture.
+    pub fn sprite_sheet(&self) -> SpriteSheet {
+        SpriteSheet::new(
+            self.texture.clone(),
+            self.regions.iter().map(|region| region.frame).collect(),
+        )
+    }
+}
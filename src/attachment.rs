@@ -0,0 +1,61 @@
+use crate::EntityId;
+use nalgebra::Matrix4;
+use std::collections::HashMap;
+
+/// Named model space transforms on a model, e.g. hands, sockets or hardpoints, queried with
+/// `Entity::attachment_transform`.
+#[derive(Debug, Clone, Default)]
+pub struct AttachmentPoints {
+    points: HashMap<String, Matrix4<f32>>,
+}
+#[allow(unused)]
+impl AttachmentPoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Every named node of a glTF file, joints included, with its transform composed down
+    /// the node hierarchy from the scene root.
+    pub fn from_gltf(path: &str) -> Result<Self, String> {
+        let (gltf, _, _) = gltf::import(path).map_err(|err| format!("{}: {}", path, err))?;
+        let mut points = Self::new();
+        for scene in gltf.scenes() {
+            for node in scene.nodes() {
+                points.add_node(&node, &Matrix4::identity());
+            }
+        }
+        Ok(points)
+    }
+    fn add_node(&mut self, node: &gltf::Node, parent: &Matrix4<f32>) {
+        let local = Matrix4::from(node.transform().matrix());
+        let transform = parent * local;
+        if let Some(name) = node.name() {
+            self.points.insert(name.to_owned(), transform);
+        }
+        for child in node.children() {
+            self.add_node(&child, &transform);
+        }
+    }
+    pub fn with_point(mut self, name: &str, transform: Matrix4<f32>) -> Self {
+        self.insert(name, transform);
+        self
+    }
+    /// Adds or moves a point, e.g. to pose a joint.
+    pub fn insert(&mut self, name: &str, transform: Matrix4<f32>) {
+        self.points.insert(name.to_owned(), transform);
+    }
+    pub fn get(&self, name: &str) -> Option<&Matrix4<f32>> {
+        self.points.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.points.keys().map(String::as_str)
+    }
+}
+
+/// Keeps an entity on an attachment point of a spawned entity, see `Entity::attach_to`.
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub parent: EntityId,
+    pub point: String,
+    /// The child's transform relative to the point.
+    pub offset: Matrix4<f32>,
+}
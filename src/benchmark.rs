@@ -0,0 +1,291 @@
+use crate::geometry::Texture;
+use crate::primitives::{cube, uv_sphere};
+use crate::renderer::{Color, Material, Sampler, UvTransform};
+use crate::{Entity, Scene};
+use image::{DynamicImage, Rgba, RgbaImage};
+use nalgebra::{Isometry3, Point3, Scale3, UnitQuaternion, Vector3};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOG_PATH: &str = "benchmark.csv";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BenchmarkShape {
+    Cube,
+    Sphere,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BenchmarkMaterial {
+    Solid,
+    Lit,
+    Textured,
+    Checker,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct BenchmarkConfig {
+    pub count: usize,
+    pub shape: BenchmarkShape,
+    pub material: BenchmarkMaterial,
+    /// Spin every entity, otherwise the scene is static.
+    pub motion: bool,
+    /// Seconds to run before the summary is logged and the benchmark ends.
+    pub duration: f32,
+}
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            count: 100,
+            shape: BenchmarkShape::Cube,
+            material: BenchmarkMaterial::Textured,
+            motion: true,
+            duration: 10.0,
+        }
+    }
+}
+impl BenchmarkConfig {
+    /// Parses console arguments in any order: a count, `cube`/`sphere`,
+    /// `solid`/`lit`/`textured`/`checker`, `static`/`spin` and `<seconds>s`.
+    pub fn parse(args: &[&str]) -> Result<Self, String> {
+        let mut config = Self::default();
+        for arg in args {
+            match *arg {
+                "cube" => config.shape = BenchmarkShape::Cube,
+                "sphere" => config.shape = BenchmarkShape::Sphere,
+                "solid" => config.material = BenchmarkMaterial::Solid,
+                "lit" => config.material = BenchmarkMaterial::Lit,
+                "textured" => config.material = BenchmarkMaterial::Textured,
+                "checker" => config.material = BenchmarkMaterial::Checker,
+                "static" => config.motion = false,
+                "spin" => config.motion = true,
+                arg => {
+                    if let Some(seconds) = arg.strip_suffix('s').and_then(|s| s.parse().ok()) {
+                        config.duration = seconds;
+                    } else if let Ok(count) = arg.parse() {
+                        config.count = count;
+                    } else {
+                        return Err(format!("unknown benchmark option '{}'", arg));
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Procedural stress test scene, replaces the user scene while running. Frame times are
+/// printed every second and a summary row is appended to `benchmark.csv` at the end.
+pub struct Benchmark {
+    pub scene: Scene,
+    config: BenchmarkConfig,
+    elapsed: f32,
+    frame_times: Vec<f32>,
+    second_frames: Vec<f32>,
+    second_start: f32,
+    finished: bool,
+}
+impl Benchmark {
+    pub fn new(config: BenchmarkConfig) -> Self {
+        let model = match config.shape {
+            BenchmarkShape::Cube => cube(1.0),
+            BenchmarkShape::Sphere => uv_sphere(0.6, 24, 12),
+        };
+        let side = (config.count as f32).sqrt().ceil().max(1.0) as usize;
+        let spacing = 2.0;
+        let offset = (side - 1) as f32 * spacing * 0.5;
+        let light_dir = Vector3::new(1.0, 1.0, 0.5).normalize();
+        let texture = checker_texture(64, 8);
+
+        let mut scene = Scene::new();
+        for index in 0..config.count {
+            let (x, z) = ((index % side) as f32, (index / side) as f32);
+            let position = Isometry3::translation(x * spacing - offset, 0.0, z * spacing - offset);
+            let color = Color::new(
+                0.3 + 0.7 * x / side as f32,
+                0.5,
+                0.3 + 0.7 * z / side as f32,
+                1.0,
+            );
+            let id = format!("bench{}", index);
+            let scale = Scale3::identity();
+            let entity = match config.material {
+                BenchmarkMaterial::Solid => {
+                    Entity::new(&id, &model, &position, &scale, Material::SolidColor(color))
+                }
+                BenchmarkMaterial::Lit => Entity::new(
+                    &id,
+                    &model,
+                    &position,
+                    &scale,
+                    Material::LitSolid {
+                        color,
+                        light_dir,
+                        emissive: None,
+                        specular: None,
+                    },
+                ),
+                BenchmarkMaterial::Textured => Entity::new(
+                    &id,
+                    &model,
+                    &position,
+                    &scale,
+                    Material::LitTexture {
+                        texture: texture.clone(),
+                        light_dir,
+                        uv_transform: UvTransform::default(),
+                        sampler: Sampler::default(),
+                        emissive: None,
+                        specular: None,
+                    },
+                ),
+                BenchmarkMaterial::Checker => Entity::new(
+                    &id,
+                    &model,
+                    &position,
+                    &scale,
+                    Material::Checkerboard {
+                        color_a: color,
+                        color_b: Color::new(0.1, 0.1, 0.1, 1.0),
+                        scale: 8.0,
+                    },
+                ),
+            };
+            scene.entities.push(entity);
+        }
+        let extent = side as f32 * spacing;
+        scene.camera.position = Point3::new(0.0, extent * 0.6 + 2.0, extent * 0.8 + 4.0);
+        scene.camera.orientation = UnitQuaternion::face_towards(
+            &(Point3::origin() - scene.camera.position),
+            &Vector3::y(),
+        );
+        scene.camera.far = extent * 4.0 + 10.0;
+
+        println!(
+            "Benchmark: {} {:?} {:?}, motion {}, {} s",
+            config.count, config.shape, config.material, config.motion, config.duration
+        );
+        Self {
+            scene,
+            config,
+            elapsed: 0.0,
+            frame_times: vec![],
+            second_frames: vec![],
+            second_start: 0.0,
+            finished: false,
+        }
+    }
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+    pub fn update(&mut self, delta: Duration, size: (u32, u32)) {
+        let delta = delta.as_secs_f32();
+        self.scene.camera.aspect_ratio = size.0 as f32 / size.1.max(1) as f32;
+        if self.config.motion {
+            for (index, entity) in self.scene.entities.iter_mut().enumerate() {
+                let axis = Vector3::new(1.0, (index % 7) as f32 * 0.3, 0.5);
+                let spin = UnitQuaternion::from_scaled_axis(axis.normalize() * delta);
+                entity.position.rotation = spin * entity.position.rotation;
+            }
+        }
+        // The first frame includes scene setup, leave it out of the stats
+        if self.elapsed > 0.0 {
+            self.frame_times.push(delta * 1000.0);
+            self.second_frames.push(delta * 1000.0);
+        }
+        self.elapsed += delta;
+        if self.elapsed - self.second_start >= 1.0 {
+            let (average, min, max) = summarize(&self.second_frames);
+            println!(
+                "Benchmark {:.0} s: {:.1} fps, {:.2} ms avg, {:.2} min, {:.2} max",
+                self.elapsed,
+                1000.0 / average,
+                average,
+                min,
+                max
+            );
+            self.second_frames.clear();
+            self.second_start = self.elapsed;
+        }
+        if self.elapsed >= self.config.duration && !self.finished {
+            self.finished = true;
+            self.log_summary();
+        }
+    }
+    fn log_summary(&self) {
+        let (average, min, max) = summarize(&self.frame_times);
+        let triangles = self
+            .scene
+            .entities
+            .iter()
+            .map(|entity| entity.active_model().triangle_count())
+            .sum::<usize>();
+        println!(
+            "Benchmark done: {} frames, {} triangles, {:.1} fps, {:.2} ms avg, {:.2} min, {:.2} max",
+            self.frame_times.len(),
+            triangles,
+            1000.0 / average,
+            average,
+            min,
+            max
+        );
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let is_new = std::fs::metadata(LOG_PATH).is_err();
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(LOG_PATH)
+            .and_then(|mut file| {
+                if is_new {
+                    writeln!(
+                        file,
+                        "timestamp,count,shape,material,motion,triangles,frames,avg_ms,min_ms,max_ms,fps"
+                    )?;
+                }
+                writeln!(
+                    file,
+                    "{},{},{:?},{:?},{},{},{},{:.3},{:.3},{:.3},{:.2}",
+                    timestamp,
+                    self.config.count,
+                    self.config.shape,
+                    self.config.material,
+                    self.config.motion,
+                    triangles,
+                    self.frame_times.len(),
+                    average,
+                    min,
+                    max,
+                    1000.0 / average
+                )
+            });
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+    }
+}
+
+/// Average, min and max of frame times in milliseconds.
+fn summarize(times: &[f32]) -> (f32, f32, f32) {
+    if times.is_empty() {
+        return (0.0, 0.0, 0.0);
+    }
+    let average = times.iter().sum::<f32>() / times.len() as f32;
+    let min = times.iter().copied().fold(f32::MAX, f32::min);
+    let max = times.iter().copied().fold(f32::MIN, f32::max);
+    (average, min, max)
+}
+
+fn checker_texture(size: u32, cells: u32) -> Texture {
+    let cell = (size / cells.max(1)).max(1);
+    let image = RgbaImage::from_fn(size, size, |x, y| {
+        if (x / cell + y / cell).is_multiple_of(2) {
+            Rgba([230, 230, 230, 255])
+        } else {
+            Rgba([200, 60, 40, 255])
+        }
+    });
+    Texture::from_image(DynamicImage::ImageRgba8(image))
+}
@@ -0,0 +1,191 @@
+use code_adv_soft_rast_lib::{
+    OfflineRender, PathTraceSettings, TriangleFiller, Turntable, compare_rasterizers,
+    render_panorama, render_to_file, render_turntable,
+};
+use std::env;
+use std::process::exit;
+
+const USAGE: &str = "usage: softrast-render <model.obj|ply|gltf|glb> [--texture tex.png] [--size WxH] \
+[--camera x,y,z] [--target x,y,z] [--fov degrees] [--wireframe] [--raytrace] [--shadows] \
+[--path-trace samples] [--bounces n] [--out out.png] [--turntable frames] [--panorama face_size] \
+[--compare-reference box|spans]   \
+(with --turntable, --out is a .gif or a pattern like turn_###.png; \
+with --panorama, the output is an equirectangular image --size W wide and W/2 high; \
+with --compare-reference, it shows where that triangle filler differs from the reference rasterizer)";
+
+/// Channel difference `--compare-reference` still counts as a match.
+const COMPARE_TOLERANCE: u8 = 2;
+
+struct Args {
+    options: OfflineRender,
+    turntable: Option<Turntable>,
+    panorama: Option<u32>,
+    compare: Option<TriangleFiller>,
+}
+
+fn parse_vector(value: &str) -> Result<[f32; 3], String> {
+    let numbers = value
+        .split(',')
+        .map(|n| {
+            n.trim()
+                .parse::<f32>()
+                .map_err(|err| format!("{}: {}", value, err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    numbers
+        .try_into()
+        .map_err(|_| format!("expected x,y,z, got '{}'", value))
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut options = OfflineRender::default();
+    let mut turntable: Option<Turntable> = None;
+    let mut panorama: Option<u32> = None;
+    let mut compare: Option<TriangleFiller> = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--texture" => options.texture = Some(value()?),
+            "--size" => {
+                let size = value()?;
+                let (width, height) = size
+                    .split_once('x')
+                    .ok_or(format!("expected WxH, got '{}'", size))?;
+                options.width = width
+                    .parse()
+                    .map_err(|_| format!("bad width '{}'", width))?;
+                options.height = height
+                    .parse()
+                    .map_err(|_| format!("bad height '{}'", height))?;
+                if options.width == 0 || options.height == 0 {
+                    return Err(format!("size must not be zero, got '{}'\n{}", size, USAGE));
+                }
+            }
+            "--camera" => options.camera_position = Some(parse_vector(&value()?)?),
+            "--target" => options.camera_target = Some(parse_vector(&value()?)?),
+            "--fov" => {
+                let fov = value()?;
+                options.fov = fov.parse().map_err(|_| format!("bad fov '{}'", fov))?;
+            }
+            "--out" => options.out = value()?,
+            "--wireframe" => options.wireframe = true,
+            "--raytrace" => options.ray_traced = true,
+            "--shadows" => {
+                options.ray_traced = true;
+                options.shadows = true;
+            }
+            "--path-trace" => {
+                let samples = value()?;
+                options.path_trace = Some(PathTraceSettings {
+                    samples: samples
+                        .parse()
+                        .map_err(|_| format!("bad sample count '{}'", samples))?,
+                    ..options.path_trace.unwrap_or_default()
+                });
+            }
+            "--bounces" => {
+                let bounces = value()?;
+                options.path_trace = Some(PathTraceSettings {
+                    bounces: bounces
+                        .parse()
+                        .map_err(|_| format!("bad bounce count '{}'", bounces))?,
+                    ..options.path_trace.unwrap_or_default()
+                });
+            }
+            "--turntable" => {
+                let frames = value()?;
+                turntable = Some(Turntable {
+                    frames: frames
+                        .parse()
+                        .map_err(|_| format!("bad frame count '{}'", frames))?,
+                    ..Turntable::default()
+                });
+            }
+            "--panorama" => {
+                let face_size = value()?;
+                panorama = Some(
+                    face_size
+                        .parse()
+                        .map_err(|_| format!("bad face size '{}'", face_size))?,
+                );
+            }
+            "--compare-reference" => {
+                compare = match value()?.as_str() {
+                    "box" => Some(TriangleFiller::BoundingBox),
+                    "spans" => Some(TriangleFiller::Spans),
+                    other => {
+                        return Err(format!(
+                            "unknown triangle filler '{}', expected box or spans",
+                            other
+                        ));
+                    }
+                };
+            }
+            "-h" | "--help" => return Err(USAGE.to_owned()),
+            model if !model.starts_with("--") && options.model.is_empty() => {
+                options.model = model.to_owned();
+            }
+            other => return Err(format!("unexpected argument '{}'\n{}", other, USAGE)),
+        }
+    }
+    if options.model.is_empty() {
+        return Err(USAGE.to_owned());
+    }
+    if let Some(turntable) = &mut turntable {
+        turntable.out = options.out.clone();
+    }
+    Ok(Args {
+        options,
+        turntable,
+        panorama,
+        compare,
+    })
+}
+
+fn main() {
+    let Args {
+        options,
+        turntable,
+        panorama,
+        compare,
+    } = match parse_args() {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(2);
+        }
+    };
+    if let Some(filler) = compare {
+        match compare_rasterizers(&options, filler, COMPARE_TOLERANCE) {
+            Ok(diff) => {
+                println!(
+                    "{} of {} pixels differ, largest channel difference {}",
+                    diff.differing, diff.pixels, diff.max_difference
+                );
+                if let Some((x, y)) = diff.first {
+                    println!("first difference at {},{}, see {}", x, y, options.out);
+                }
+                exit(if diff.matches() { 0 } else { 1 });
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                exit(1);
+            }
+        }
+    }
+    let result = match (&turntable, panorama) {
+        (Some(turntable), _) => render_turntable(&options, turntable),
+        (None, Some(face_size)) => render_panorama(&options, face_size),
+        (None, None) => render_to_file(&options, |done, total| {
+            println!("{}/{} samples", done, total)
+        }),
+    };
+    match result {
+        Ok(_) => println!("Wrote {}", options.out),
+        Err(err) => {
+            eprintln!("{}", err);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::renderer::{BlitFilter, Color, RenderTarget};
+use rayon::prelude::*;
+
+/// Weights of a separable blur, applied once along rows and once along columns.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BlurKernel {
+    /// Gaussian falloff cut off at `radius` pixels.
+    Gaussian { radius: u32, sigma: f32 },
+    /// Equal weights over `radius` pixels to either side, cheaper and boxier.
+    Box { radius: u32 },
+}
+impl BlurKernel {
+    /// A gaussian whose radius covers three standard deviations.
+    pub fn gaussian(radius: u32) -> Self {
+        Self::Gaussian {
+            radius,
+            sigma: (radius as f32 / 3.0).max(0.5),
+        }
+    }
+    /// Normalized weights from `-radius` to `radius`.
+    fn weights(&self) -> Vec<f32> {
+        let weights = match *self {
+            Self::Gaussian { radius, sigma } => (-(radius as i32)..=radius as i32)
+                .map(|offset| (-((offset * offset) as f32) / (2.0 * sigma * sigma)).exp())
+                .collect::<Vec<_>>(),
+            Self::Box { radius } => vec![1.0; radius as usize * 2 + 1],
+        };
+        let total = weights.iter().sum::<f32>();
+        weights.into_iter().map(|weight| weight / total).collect()
+    }
+}
+
+/// Blurs the colors of `target` in place. With `downsample` above one the blur runs on a
+/// target that many times smaller and is scaled back up bilinearly, so wide blurs for bloom
+/// or depth of field stay cheap, the kernel radius is then in downsampled pixels.
+pub fn blur(target: &mut RenderTarget, kernel: BlurKernel, downsample: u32) {
+    if downsample <= 1 {
+        let size = target.size();
+        blur_colors(&mut target.color, size, &kernel);
+        return;
+    }
+    let (width, height) = target.size();
+    let mut small = RenderTarget::new((width / downsample).max(1), (height / downsample).max(1));
+    small.blit(target, target.rect(), small.rect(), BlitFilter::Bilinear);
+    let size = small.size();
+    blur_colors(&mut small.color, size, &kernel);
+    target.blit(&small, small.rect(), target.rect(), BlitFilter::Bilinear);
+}
+
+/// A blurred copy of `source`, leaving it untouched, e.g. for a bloom chain.
+pub fn blurred(source: &RenderTarget, kernel: BlurKernel, downsample: u32) -> RenderTarget {
+    let (width, height) = source.size();
+    let downsample = downsample.max(1);
+    let mut target = RenderTarget::new((width / downsample).max(1), (height / downsample).max(1));
+    target.blit(source, source.rect(), target.rect(), BlitFilter::Bilinear);
+    let size = target.size();
+    blur_colors(&mut target.color, size, &kernel);
+    target
+}
+
+/// Horizontal then vertical pass over packed colors, in floats so the two passes don't
+/// round twice.
+fn blur_colors(colors: &mut [u32], size: (u32, u32), kernel: &BlurKernel) {
+    let weights = kernel.weights();
+    let radius = (weights.len() / 2) as i32;
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let source = colors
+        .par_iter()
+        .map(|color| {
+            let color = Color::from_u32(*color);
+            [color.r, color.g, color.b]
+        })
+        .collect::<Vec<_>>();
+    // Edges repeat the border pixel
+    let pass = |source: &[[f32; 3]], step_x: bool| {
+        let mut out = vec![[0.0; 3]; source.len()];
+        out.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                let mut sum = [0.0; 3];
+                for (i, weight) in weights.iter().enumerate() {
+                    let offset = i as i32 - radius;
+                    let (sx, sy) = if step_x {
+                        ((x as i32 + offset).clamp(0, width as i32 - 1) as usize, y)
+                    } else {
+                        (x, (y as i32 + offset).clamp(0, height as i32 - 1) as usize)
+                    };
+                    let sample = source[sy * width + sx];
+                    for channel in 0..3 {
+                        sum[channel] += sample[channel] * weight;
+                    }
+                }
+                *pixel = sum;
+            }
+        });
+        out
+    };
+    let horizontal = pass(&source, true);
+    let vertical = pass(&horizontal, false);
+    colors
+        .par_iter_mut()
+        .zip(vertical)
+        .for_each(|(color, [r, g, b])| {
+            *color = Color::new(r, g, b, 1.0).as_u32();
+        });
+}
@@ -0,0 +1,157 @@
+use crate::geometry::{Model, Ray};
+use nalgebra::Point3;
+
+const LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Copy, Clone)]
+struct BvhNode {
+    min: Point3<f32>,
+    max: Point3<f32>,
+    /// Leaves index `count` triangles from `start`, inner nodes have `count == 0` and their
+    /// children at `start` and `start + 1`.
+    start: usize,
+    count: usize,
+}
+
+/// Bounding volume hierarchy over the triangles of a model, built by splitting at the median
+/// of the longest axis. Used for ray queries such as baking and picking.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// Triangles with their index in the source, reordered by the build.
+    triangles: Vec<([Point3<f32>; 3], usize)>,
+}
+#[allow(unused)]
+impl Bvh {
+    pub fn from_model(model: &Model) -> Self {
+        let triangles = model
+            .triangle_vertices()
+            .chunks_exact(3)
+            .map(|t| {
+                [
+                    t[0].position.xyz(),
+                    t[1].position.xyz(),
+                    t[2].position.xyz(),
+                ]
+            })
+            .collect();
+        Self::from_triangles(triangles)
+    }
+    pub fn from_triangles(triangles: Vec<[Point3<f32>; 3]>) -> Self {
+        let mut bvh = Self {
+            nodes: vec![],
+            triangles: triangles.into_iter().zip(0..).collect(),
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.nodes.push(bvh.node(0, bvh.triangles.len()));
+            bvh.split(0);
+        }
+        bvh
+    }
+    fn node(&self, start: usize, count: usize) -> BvhNode {
+        let mut min = Point3::from([f32::MAX; 3]);
+        let mut max = Point3::from([f32::MIN; 3]);
+        for point in self.triangles[start..start + count]
+            .iter()
+            .flat_map(|(t, _)| t)
+        {
+            min = min.inf(point);
+            max = max.sup(point);
+        }
+        BvhNode {
+            min,
+            max,
+            start,
+            count,
+        }
+    }
+    fn split(&mut self, index: usize) {
+        let BvhNode {
+            min,
+            max,
+            start,
+            count,
+        } = self.nodes[index];
+        if count <= LEAF_SIZE {
+            return;
+        }
+        let extent = max - min;
+        let axis = extent.imax();
+        let centroid = |t: &[Point3<f32>; 3]| t[0][axis] + t[1][axis] + t[2][axis];
+        let half = count / 2;
+        self.triangles[start..start + count]
+            .select_nth_unstable_by(half, |a, b| centroid(&a.0).total_cmp(&centroid(&b.0)));
+
+        let left = self.nodes.len();
+        self.nodes.push(self.node(start, half));
+        self.nodes.push(self.node(start + half, count - half));
+        self.nodes[index].start = left;
+        self.nodes[index].count = 0;
+        self.split(left);
+        self.split(left + 1);
+    }
+    /// Distance to the closest triangle hit no further than `max_distance`.
+    pub fn intersect(&self, ray: &Ray, max_distance: f32) -> Option<f32> {
+        self.closest_hit(ray, max_distance).map(|(t, _)| t)
+    }
+    /// Distance and source triangle index of the closest hit no further than `max_distance`.
+    pub fn closest_hit(&self, ray: &Ray, max_distance: f32) -> Option<(f32, usize)> {
+        let mut closest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            let limit = closest.map_or(max_distance, |(t, _)| t);
+            if ray
+                .intersect_aabb(&node.min, &node.max)
+                .is_none_or(|t| t > limit)
+            {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+                continue;
+            }
+            for ([a, b, c], source) in &self.triangles[node.start..node.start + node.count] {
+                if let Some(t) = ray.intersect_triangle(a, b, c)
+                    && t <= closest.map_or(max_distance, |(t, _)| t)
+                {
+                    closest = Some((t, *source));
+                }
+            }
+        }
+        closest
+    }
+    /// True if anything is hit within `max_distance`, stops at the first hit.
+    pub fn occluded(&self, ray: &Ray, max_distance: f32) -> bool {
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = self.nodes.get(index) else {
+                continue;
+            };
+            if ray
+                .intersect_aabb(&node.min, &node.max)
+                .is_none_or(|t| t > max_distance)
+            {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start);
+                stack.push(node.start + 1);
+                continue;
+            }
+            let hit = self.triangles[node.start..node.start + node.count]
+                .iter()
+                .any(|([a, b, c], _)| {
+                    ray.intersect_triangle(a, b, c)
+                        .is_some_and(|t| t <= max_distance)
+                });
+            if hit {
+                return true;
+            }
+        }
+        false
+    }
+}
@@ -1,13 +1,88 @@
-use nalgebra::{Matrix4, Point3, Unit, UnitQuaternion, Vector3};
+use crate::geometry::{Plane, Ray};
+use nalgebra::{Matrix4, Point2, Point3, Unit, UnitQuaternion, Vector2, Vector3};
+
+/// How view space points map to the screen. Non-linear projections bend straight lines, so
+/// triangles are tessellated before projecting, see `Command::set_projection`.
+#[derive(Debug, Copy, Clone, Default)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Equidistant fisheye, the angle from the view axis maps linearly to the distance from
+    /// the center. `fov` is the vertical field of view in radians and can pass 180 degrees.
+    Fisheye { fov: f32 },
+    /// Panini projection, wide horizontal views with straight verticals. `distance` 0 is
+    /// rectilinear, 1 is the classic Panini.
+    Panini { distance: f32 },
+    /// Maps a view space point to NDC x and y given the vertical field of view and aspect
+    /// ratio, `None` for points it can't show.
+    Custom(fn(&Point3<f32>, f32, f32) -> Option<Point2<f32>>),
+}
+impl Projection {
+    pub fn is_linear(&self) -> bool {
+        matches!(self, Self::Perspective)
+    }
+    /// NDC x and y of a view space point.
+    pub fn project(&self, point: &Point3<f32>, fov: f32, aspect_ratio: f32) -> Option<Point2<f32>> {
+        match self {
+            Self::Perspective => {
+                let scale = (fov * 0.5).tan();
+                let depth = -point.z * scale;
+                (point.z < 0.0)
+                    .then(|| Point2::new(point.x / (depth * aspect_ratio), point.y / depth))
+            }
+            Self::Fisheye { fov } => {
+                let direction = point.coords.try_normalize(1e-9)?;
+                let angle = (-direction.z).clamp(-1.0, 1.0).acos();
+                // Straight behind is a singularity that would smear over the whole image
+                if angle > std::f32::consts::PI * 0.99 {
+                    return None;
+                }
+                let radius = angle / (fov * 0.5);
+                let around = direction
+                    .xy()
+                    .try_normalize(1e-9)
+                    .unwrap_or(Vector2::zeros());
+                Some(Point2::new(
+                    around.x * radius / aspect_ratio,
+                    around.y * radius,
+                ))
+            }
+            Self::Panini { distance } => {
+                let horizontal = (point.x * point.x + point.z * point.z).sqrt();
+                if horizontal < 1e-9 {
+                    return None;
+                }
+                let longitude = point.x.atan2(-point.z);
+                let denominator = distance + longitude.cos();
+                if denominator <= 1e-3 {
+                    return None;
+                }
+                let s = (distance + 1.0) / denominator;
+                let scale = (fov * 0.5).tan();
+                Some(Point2::new(
+                    s * longitude.sin() / (scale * aspect_ratio),
+                    s * (point.y / horizontal) / scale,
+                ))
+            }
+            Self::Custom(project) => project(point, fov, aspect_ratio),
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
 pub(crate) struct Camera {
+    /// Relative to `world_offset`.
     pub position: Point3<f32>,
+    /// Double precision origin of `position`, the camera's half of
+    /// `Entity::with_world_position`, see `Scene::set_camera_world_position`.
+    pub world_offset: Vector3<f64>,
     pub orientation: UnitQuaternion<f32>,
     pub fov: f32,
     pub aspect_ratio: f32,
     pub near: f32,
     pub far: f32,
+    /// Sub-pixel offset of the projection in NDC units, for temporal anti-aliasing.
+    pub jitter: Vector2<f32>,
 }
 #[allow(unused)]
 impl Camera {
@@ -21,16 +96,27 @@ impl Camera {
         far: f32,
     ) -> Self {
         let direction = (target - position).normalize();
-        let orientation = UnitQuaternion::face_towards(&direction, &up);
+        // `face_towards` turns +z to the direction, the camera looks down its -z
+        let orientation = UnitQuaternion::face_towards(&-direction, &up);
         Self {
             position,
+            world_offset: Vector3::zeros(),
             orientation,
             fov,
             aspect_ratio,
             near,
             far,
+            jitter: Vector2::zeros(),
         }
     }
+    /// Full precision position, compare with `Entity::world_translation`.
+    pub fn world_position(&self) -> Vector3<f64> {
+        self.world_offset + self.position.coords.cast::<f64>()
+    }
+    /// `world_position` in single precision, for world space math the view matrix also does.
+    pub fn eye(&self) -> Point3<f32> {
+        Point3::from(self.world_position().cast::<f32>())
+    }
     pub fn forward(&self) -> Vector3<f32> {
         self.orientation * Vector3::new(0.0, 0.0, -1.0)
     }
@@ -67,13 +153,69 @@ impl Camera {
 
         self.orientation = self.orientation * pitch_rot * yaw_rot;
     }
+    /// The rotation part of the view matrix, for transforms already relative to the camera.
+    pub fn get_rotation_matrix(&self) -> Matrix4<f32> {
+        self.orientation.inverse().to_homogeneous()
+    }
     pub fn get_view_matrix(&self) -> Matrix4<f32> {
         let rotation_matrix = self.orientation.to_rotation_matrix();
-        let translation = Matrix4::new_translation(&(-self.position.coords));
+        let translation = Matrix4::new_translation(&(-self.eye().coords));
         rotation_matrix.to_homogeneous().try_inverse().unwrap() * translation
     }
+    /// The camera mirrored across a plane. Mirroring flips handedness, so the result is
+    /// rebuilt as a proper rotation and its image comes out flipped horizontally.
+    pub fn reflected(&self, point: &Point3<f32>, normal: &Vector3<f32>) -> Camera {
+        let normal = normal.normalize();
+        let reflect = |v: Vector3<f32>| v - normal * (2.0 * v.dot(&normal));
+        let forward = reflect(self.forward());
+        let up = reflect(self.up());
+        let mut camera = *self;
+        let eye = point + reflect(self.eye() - point);
+        camera.position = eye - self.world_offset.cast::<f32>();
+        camera.orientation = UnitQuaternion::face_towards(&-forward, &up);
+        camera
+    }
+    /// World space ray through a pixel of a target with the given size.
+    pub fn screen_ray(&self, x: f32, y: f32, size: (u32, u32)) -> Ray {
+        let ndc_x = 2.0 * x / size.0 as f32 - 1.0;
+        let ndc_y = 1.0 - 2.0 * y / size.1 as f32;
+        let inverse = (self.get_perspective_matrix() * self.get_view_matrix())
+            .try_inverse()
+            .unwrap_or(Matrix4::identity());
+        let near = inverse.transform_point(&Point3::new(ndc_x, ndc_y, -1.0));
+        let far = inverse.transform_point(&Point3::new(ndc_x, ndc_y, 1.0));
+        Ray::new(near, far - near)
+    }
+    /// World space corners of the view frustum cut off at `distance` or the far plane,
+    /// whichever is nearer. The near rectangle comes first, both counter clockwise from the
+    /// bottom left as seen by the camera.
+    pub fn frustum_corners(&self, distance: f32) -> [Point3<f32>; 8] {
+        let tan = (self.fov * 0.5).tan();
+        let rectangle = |depth: f32| {
+            let (x, y) = (tan * depth * self.aspect_ratio, tan * depth);
+            [(-x, -y), (x, -y), (x, y), (-x, y)]
+                .map(|(x, y)| self.eye() + self.orientation * Vector3::new(x, y, -depth))
+        };
+        let [a, b, c, d] = rectangle(self.near);
+        let [e, f, g, h] = rectangle(distance.min(self.far));
+        [a, b, c, d, e, f, g, h]
+    }
+    /// World space planes of the view frustum, facing inwards: left, right, bottom, top,
+    /// near and far.
+    pub fn frustum_planes(&self) -> [Plane; 6] {
+        planes_of(&(self.get_perspective_matrix() * self.get_view_matrix()))
+    }
+    /// `frustum_planes` in view space, where triangles are clipped.
+    pub fn view_frustum_planes(&self) -> [Plane; 6] {
+        planes_of(&self.get_perspective_matrix())
+    }
     pub fn get_perspective_matrix(&self) -> Matrix4<f32> {
-        Matrix4::new_perspective(self.aspect_ratio, self.fov, self.near, self.far)
+        let mut projection =
+            Matrix4::new_perspective(self.aspect_ratio, self.fov, self.near, self.far);
+        // Clip w is -z, so this shifts NDC by the jitter at every depth
+        projection[(0, 2)] -= self.jitter.x;
+        projection[(1, 2)] -= self.jitter.y;
+        projection
     }
 }
 impl Default for Camera {
@@ -89,3 +231,20 @@ impl Default for Camera {
         )
     }
 }
+
+/// Inward facing planes of the clip volume of `m`, in the space `m` transforms from.
+fn planes_of(m: &Matrix4<f32>) -> [Plane; 6] {
+    let row = |i: usize| m.row(i).transpose();
+    [
+        row(3) + row(0),
+        row(3) - row(0),
+        row(3) + row(1),
+        row(3) - row(1),
+        row(3) + row(2),
+        row(3) - row(2),
+    ]
+    .map(|p| {
+        let length = p.xyz().magnitude();
+        Plane::new(p.xyz() / length, p.w / length)
+    })
+}
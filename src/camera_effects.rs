@@ -0,0 +1,105 @@
+use crate::camera::Camera;
+use nalgebra::UnitQuaternion;
+
+/// Procedural camera motion applied on top of a base `Camera` without changing it:
+/// trauma based shake, walking bob and an FOV kick that springs back.
+#[derive(Debug, Copy, Clone)]
+pub struct CameraEffects {
+    /// Current shake amount in 0..=1, the visible shake scales with its square.
+    pub trauma: f32,
+    /// Trauma removed per second.
+    pub trauma_decay: f32,
+    /// Largest yaw/pitch/roll offset in radians at full trauma.
+    pub max_shake_angle: f32,
+    /// Largest positional offset at full trauma.
+    pub max_shake_offset: f32,
+    /// Speed the shake noise is scrolled at.
+    pub shake_frequency: f32,
+    pub bob_amplitude: f32,
+    /// Bob cycles per unit of distance walked.
+    pub bob_frequency: f32,
+    /// Fraction of the FOV kick left after one second.
+    pub fov_kick_decay: f32,
+    fov_kick: f32,
+    bob_phase: f32,
+    bob_weight: f32,
+    time: f32,
+}
+impl Default for CameraEffects {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            trauma_decay: 0.8,
+            max_shake_angle: 0.08,
+            max_shake_offset: 0.1,
+            shake_frequency: 15.0,
+            bob_amplitude: 0.04,
+            bob_frequency: 0.35,
+            fov_kick_decay: 0.02,
+            fov_kick: 0.0,
+            bob_phase: 0.0,
+            bob_weight: 0.0,
+            time: 0.0,
+        }
+    }
+}
+#[allow(unused)]
+impl CameraEffects {
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+    /// Widens the field of view by `amount` (same units as `Camera::fov`), easing back over time.
+    pub fn kick_fov(&mut self, amount: f32) {
+        self.fov_kick += amount;
+    }
+    /// Advances the effects, `walk_speed` is the distance moved per second and drives the bob.
+    pub fn update(&mut self, delta: f32, walk_speed: f32) {
+        self.time += delta;
+        self.trauma = (self.trauma - self.trauma_decay * delta).max(0.0);
+        self.fov_kick *= self.fov_kick_decay.powf(delta);
+
+        let walking = if walk_speed > f32::EPSILON { 1.0 } else { 0.0 };
+        // Ease the bob in and out so stopping doesn't snap the camera
+        self.bob_weight += (walking - self.bob_weight) * (delta * 8.0).min(1.0);
+        self.bob_phase += walk_speed * delta * self.bob_frequency * std::f32::consts::TAU;
+    }
+    /// The base camera with every effect composed on top.
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        let mut result = *camera;
+
+        let shake = self.trauma * self.trauma;
+        let t = self.time * self.shake_frequency;
+        let pitch = self.max_shake_angle * shake * noise(t, 0);
+        let yaw = self.max_shake_angle * shake * noise(t, 1);
+        let roll = self.max_shake_angle * shake * noise(t, 2);
+        let offset_x = self.max_shake_offset * shake * noise(t, 3);
+        let offset_y = self.max_shake_offset * shake * noise(t, 4);
+
+        let bob = self.bob_amplitude * self.bob_weight;
+        let bob_x = bob * 0.5 * self.bob_phase.sin();
+        let bob_y = bob * (2.0 * self.bob_phase).sin().abs();
+
+        result.position += camera.right() * (offset_x + bob_x) + camera.up() * (offset_y + bob_y);
+        result.orientation =
+            camera.orientation * UnitQuaternion::from_euler_angles(pitch, yaw, roll);
+        result.fov = camera.fov + self.fov_kick;
+        result
+    }
+}
+
+/// Smooth 1D value noise in -1..=1, `seed` selects an independent channel.
+fn noise(x: f32, seed: u32) -> f32 {
+    let hash = |i: i32| {
+        let mut h = (i as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x1656_67b1);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x2c1b_3c6d);
+        h ^= h >> 12;
+        h as f32 / u32::MAX as f32 * 2.0 - 1.0
+    };
+    let i = x.floor();
+    let f = x - i;
+    let s = f * f * (3.0 - 2.0 * f);
+    let a = hash(i as i32);
+    let b = hash(i as i32 + 1);
+    a + (b - a) * s
+}
@@ -0,0 +1,301 @@
+use crate::error::SoftRastError;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+/// Where `Command::save_config` writes when the app wasn't given a config file.
+pub const DEFAULT_CONFIG_PATH: &str = "softrast.toml";
+
+/// User settings kept between runs: window size, render scale, quality toggles and key
+/// bindings. Stored as a small TOML file with `[window]`, `[quality]` and `[bindings]` tables,
+/// see `App::with_config_file`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    /// Borderless fullscreen on the current monitor.
+    pub fullscreen: bool,
+    /// Fraction of the window resolution frames are rendered at and scaled to the window, up
+    /// to 2 for supersampling.
+    pub render_scale: f32,
+    pub quality: QualitySettings,
+    /// Key of each action, see `InputState::action`.
+    pub bindings: BTreeMap<String, String>,
+}
+impl Default for Config {
+    fn default() -> Self {
+        let bindings = [
+            ("forward", "w"),
+            ("back", "s"),
+            ("left", "a"),
+            ("right", "d"),
+            ("up", "z"),
+            ("down", "c"),
+            ("roll_left", "q"),
+            ("roll_right", "e"),
+            ("shake", "x"),
+        ];
+        Self {
+            width: 1067,
+            height: 600,
+            fullscreen: false,
+            render_scale: 1.0,
+            quality: QualitySettings::default(),
+            bindings: bindings
+                .iter()
+                .map(|(action, key)| (action.to_string(), key.to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// The quality toggles of the `[quality]` table.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QualitySettings {
+    pub static_caching: bool,
+    pub oit: bool,
+    pub shadows: bool,
+    pub perspective_correct: bool,
+    pub collapse_subpixel: bool,
+    pub show_stats: bool,
+}
+impl Default for QualitySettings {
+    fn default() -> Self {
+        Self {
+            static_caching: false,
+            oit: false,
+            shadows: false,
+            perspective_correct: true,
+            collapse_subpixel: false,
+            show_stats: false,
+        }
+    }
+}
+
+#[allow(unused)]
+impl Config {
+    /// Reads a config file, keys it doesn't have keep their defaults.
+    pub fn load(path: &str) -> Result<Self, SoftRastError> {
+        let contents = fs::read_to_string(path).map_err(|source| SoftRastError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        Self::parse(&contents, path)
+    }
+    /// `load`, or the defaults when the file doesn't exist yet.
+    pub fn load_or_default(path: &str) -> Result<Self, SoftRastError> {
+        match Self::load(path) {
+            Err(SoftRastError::Io { source, .. })
+                if source.kind() == std::io::ErrorKind::NotFound =>
+            {
+                Ok(Self::default())
+            }
+            result => result,
+        }
+    }
+    pub fn save(&self, path: &str) -> Result<(), SoftRastError> {
+        fs::write(path, self.to_toml()).map_err(|source| SoftRastError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    }
+    /// Parses the TOML subset `to_toml` writes: tables, and keys with string, number or
+    /// boolean values. `path` only names the source in errors.
+    pub fn parse(contents: &str, path: &str) -> Result<Self, SoftRastError> {
+        let mut config = Self::default();
+        let mut table = String::new();
+        for (number, line) in contents.lines().enumerate() {
+            let error = |message: String| SoftRastError::Parse {
+                path: path.to_owned(),
+                line: number + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                table = name.trim().to_owned();
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(format!("expected key = value, got '{}'", line)))?;
+            config
+                .set(&format!("{}.{}", table, key.trim()), value)
+                .map_err(error)?;
+        }
+        Ok(config)
+    }
+    /// Sets one setting from its dotted name and TOML value, e.g. `window.render_scale` and
+    /// `0.5` or `bindings.forward` and `"w"`. Quotes around strings are optional, quoted ones
+    /// take the escapes `to_toml` writes.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = strip_comment(value.trim());
+        let text = match value
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+        {
+            Some(quoted) => {
+                unescape(quoted).ok_or_else(|| format!("{}: bad escape in {}", key, value))?
+            }
+            None => value.to_owned(),
+        };
+        let expected = |what: &str| format!("{}: expected {}, got '{}'", key, what, text);
+        let flag = || text.parse::<bool>().map_err(|_| expected("true or false"));
+        let size = || text.parse::<u32>().map_err(|_| expected("a size"));
+        let quality = &mut self.quality;
+        match key {
+            "window.width" => self.width = size()?.max(1),
+            "window.height" => self.height = size()?.max(1),
+            "window.fullscreen" => self.fullscreen = flag()?,
+            "window.render_scale" => {
+                let scale = text.parse::<f32>().map_err(|_| expected("a number"))?;
+                if !scale.is_finite() || scale <= 0.0 {
+                    return Err(format!("{}: must be above 0", key));
+                }
+                self.render_scale = scale.min(2.0);
+            }
+            "quality.static_caching" => quality.static_caching = flag()?,
+            "quality.oit" => quality.oit = flag()?,
+            "quality.shadows" => quality.shadows = flag()?,
+            "quality.perspective_correct" => quality.perspective_correct = flag()?,
+            "quality.collapse_subpixel" => quality.collapse_subpixel = flag()?,
+            "quality.show_stats" => quality.show_stats = flag()?,
+            _ => match key.strip_prefix("bindings.") {
+                Some(action) if !action.is_empty() && !text.is_empty() => {
+                    self.bindings.insert(action.to_owned(), text);
+                }
+                _ => return Err(format!("unknown setting '{}'", key)),
+            },
+        }
+        Ok(())
+    }
+    pub fn to_toml(&self) -> String {
+        let quality = &self.quality;
+        let mut toml = String::new();
+        let _ = writeln!(toml, "[window]");
+        let _ = writeln!(toml, "width = {}", self.width);
+        let _ = writeln!(toml, "height = {}", self.height);
+        let _ = writeln!(toml, "fullscreen = {}", self.fullscreen);
+        let _ = writeln!(toml, "render_scale = {:?}", self.render_scale);
+        let _ = writeln!(toml, "\n[quality]");
+        let _ = writeln!(toml, "static_caching = {}", quality.static_caching);
+        let _ = writeln!(toml, "oit = {}", quality.oit);
+        let _ = writeln!(toml, "shadows = {}", quality.shadows);
+        let _ = writeln!(
+            toml,
+            "perspective_correct = {}",
+            quality.perspective_correct
+        );
+        let _ = writeln!(toml, "collapse_subpixel = {}", quality.collapse_subpixel);
+        let _ = writeln!(toml, "show_stats = {}", quality.show_stats);
+        let _ = writeln!(toml, "\n[bindings]");
+        for (action, key) in &self.bindings {
+            let _ = writeln!(toml, "{} = {}", action, toml_string(key));
+        }
+        toml
+    }
+}
+
+/// `value` without a trailing `# comment`, a `#` inside a string is kept.
+fn strip_comment(value: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, ch) in value.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return value[..index].trim_end(),
+            _ => {}
+        }
+    }
+    value
+}
+
+/// `text` as a TOML basic string, quoted and escaped.
+fn toml_string(text: &str) -> String {
+    let mut quoted = String::from('"');
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            ch if ch.is_control() => {
+                let _ = write!(quoted, "\\u{:04X}", ch as u32);
+            }
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The contents of a TOML basic string without its quotes, `None` for an unknown escape or
+/// an unescaped quote.
+fn unescape(quoted: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut chars = quoted.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => return None,
+            '\\' => text.push(match chars.next()? {
+                '"' => '"',
+                '\\' => '\\',
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                'u' => {
+                    let code = chars.by_ref().take(4).collect::<String>();
+                    if code.len() != 4 {
+                        return None;
+                    }
+                    char::from_u32(u32::from_str_radix(&code, 16).ok()?)?
+                }
+                _ => return None,
+            }),
+            ch => text.push(ch),
+        }
+    }
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_back_what_to_toml_writes() {
+        let mut config = Config {
+            width: 640,
+            render_scale: 0.75,
+            ..Config::default()
+        };
+        config.quality.shadows = true;
+        for (action, key) in [
+            ("quote", "\""),
+            ("backslash", "\\"),
+            ("hash", "#"),
+            ("tab", "\t"),
+            ("bell", "\u{7}"),
+        ] {
+            config.bindings.insert(action.to_owned(), key.to_owned());
+        }
+        let toml = config.to_toml();
+        assert_eq!(Config::parse(&toml, "test.toml").unwrap(), config);
+    }
+
+    #[test]
+    fn set_rejects_unknown_escapes() {
+        let mut config = Config::default();
+        assert!(config.set("bindings.forward", r#""\q""#).is_err());
+        assert!(config.set("bindings.forward", r#""a"b""#).is_err());
+        config.set("bindings.forward", r#""\"" # quote"#).unwrap();
+        assert_eq!(config.bindings["forward"], "\"");
+    }
+}
@@ -0,0 +1,637 @@
+use crate::accumulation::Accumulation;
+use crate::benchmark::BenchmarkConfig;
+use crate::debug::{IndexLabels, LabelTarget};
+use crate::determinism::Deterministic;
+use crate::display::FullscreenMode;
+use crate::exposure::AutoExposure;
+use crate::outline::Outline;
+use crate::palette::Palette;
+use crate::renderer::{Color, DepthCue, Interlace, RenderTarget, TriangleFiller};
+use crate::stereo::{Stereo, StereoMode};
+use crate::text::{LINE_HEIGHT, draw_text};
+use crate::threads::RenderThreads;
+use crate::{Command, RECORDING_PATH};
+use std::collections::BTreeMap;
+
+/// Runs a console command with its arguments, the returned text is printed to the output.
+pub type ConsoleHandler = Box<dyn FnMut(&[&str], &mut Command) -> String>;
+
+enum ConsoleAction {
+    Handler(ConsoleHandler),
+    /// Registered by the user state through `Command`, forwarded as a `SoftRastEvent`.
+    Forward,
+    Help,
+    Clear,
+}
+
+struct ConsoleCommand {
+    help: String,
+    action: ConsoleAction,
+}
+
+/// Drop down console toggled with the backquote key. Lines typed into it are split on
+/// whitespace, the first word picks a command from the registry.
+pub struct Console {
+    pub open: bool,
+    /// Output lines kept before the oldest are dropped.
+    pub max_output: usize,
+    input: String,
+    output: Vec<String>,
+    commands: BTreeMap<String, ConsoleCommand>,
+}
+impl Default for Console {
+    fn default() -> Self {
+        let mut console = Self {
+            open: false,
+            max_output: 200,
+            input: String::new(),
+            output: vec![],
+            commands: BTreeMap::new(),
+        };
+        console.add("help", "List commands", ConsoleAction::Help);
+        console.add("clear", "Clear the output", ConsoleAction::Clear);
+        console.register("echo", "Print the arguments", |args, _| args.join(" "));
+        console.register(
+            "mode",
+            "Set render mode, any of: shaded wireframe points",
+            |args, command| {
+                let shaded = args.contains(&"shaded");
+                let wireframe = args.contains(&"wireframe");
+                let points = args.contains(&"points");
+                if !(shaded || wireframe || points) {
+                    return "usage: mode [shaded] [wireframe] [points]".to_owned();
+                }
+                command.set_render_mode(shaded, wireframe, points);
+                format!(
+                    "shaded {} wireframe {} points {}",
+                    shaded, wireframe, points
+                )
+            },
+        );
+        console.register(
+            "stats",
+            "Toggle the stats overlay: stats on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.show_stats(true),
+                    Some(&"off") => command.show_stats(false),
+                    _ => return "usage: stats on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "grid",
+            "Toggle the ground grid: grid on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.show_grid(true),
+                    Some(&"off") => command.show_grid(false),
+                    _ => return "usage: grid on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "labels",
+            "Number vertices or triangles on screen: labels vertices|triangles [entity] | off",
+            |args, command| {
+                let target = match args.first() {
+                    Some(&"vertices") => LabelTarget::Vertices,
+                    Some(&"triangles") => LabelTarget::Triangles,
+                    Some(&"off") => {
+                        command.show_index_labels(None);
+                        return String::new();
+                    }
+                    _ => return "usage: labels vertices|triangles [entity] | off".to_owned(),
+                };
+                let mut labels = IndexLabels::new(target);
+                if let Some(id) = args.get(1) {
+                    labels = labels.with_entity(id);
+                }
+                command.show_index_labels(Some(labels));
+                String::new()
+            },
+        );
+        console.register(
+            "frusta",
+            "Outline camera frusta in the other views: frusta on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.show_frusta(true),
+                    Some(&"off") => command.show_frusta(false),
+                    _ => return "usage: frusta on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "exposure",
+            "Adapt the brightness automatically: exposure on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_auto_exposure(Some(AutoExposure::default())),
+                    Some(&"off") => command.set_auto_exposure(None),
+                    _ => return "usage: exposure on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "fullscreen",
+            "Cover the screen: fullscreen off | borderless [monitor] | exclusive <width> <height> [monitor]",
+            |args, command| {
+                let usage = "usage: fullscreen off | borderless [monitor] | exclusive <width> <height> [monitor]";
+                let monitor = |arg: Option<&&str>| arg.map(|arg| arg.parse::<usize>()).transpose();
+                let mode = match args {
+                    ["off"] => FullscreenMode::Windowed,
+                    ["borderless", rest @ ..] if rest.len() <= 1 => match monitor(rest.first()) {
+                        Ok(monitor) => FullscreenMode::Borderless { monitor },
+                        Err(_) => return usage.to_owned(),
+                    },
+                    ["exclusive", width, height, rest @ ..] if rest.len() <= 1 => {
+                        match (width.parse(), height.parse(), monitor(rest.first())) {
+                            (Ok(width), Ok(height), Ok(monitor)) => FullscreenMode::Exclusive {
+                                monitor,
+                                width,
+                                height,
+                                refresh_millihertz: None,
+                            },
+                            _ => return usage.to_owned(),
+                        }
+                    }
+                    _ => return usage.to_owned(),
+                };
+                command.set_fullscreen(mode);
+                String::new()
+            },
+        );
+        console.register(
+            "time",
+            "Control simulation time: time scale <factor> | pause | resume | step",
+            |args, command| {
+                match args {
+                    ["scale", factor] => match factor.parse::<f32>() {
+                        Ok(factor) => command.set_time_scale(factor),
+                        Err(_) => return format!("bad time scale '{}'", factor),
+                    },
+                    ["pause"] => command.set_paused(true),
+                    ["resume"] => command.set_paused(false),
+                    ["step"] => command.step_frame(),
+                    _ => return "usage: time scale <factor> | pause | resume | step".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "minimap",
+            "Toggle the top-down inset: minimap on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.show_minimap(true),
+                    Some(&"off") => command.show_minimap(false),
+                    _ => return "usage: minimap on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "stereo",
+            "Stereo rendering: stereo off|anaglyph|sbs [eye separation] [convergence]",
+            |args, command| {
+                let mode = match args.first() {
+                    Some(&"off") => {
+                        command.set_stereo(None);
+                        return String::new();
+                    }
+                    Some(&"anaglyph") => StereoMode::Anaglyph,
+                    Some(&"sbs") => StereoMode::SideBySide,
+                    _ => {
+                        return "usage: stereo off|anaglyph|sbs [eye separation] [convergence]"
+                            .to_owned();
+                    }
+                };
+                let mut stereo = Stereo::new(mode);
+                if let Some(separation) = args.get(1).and_then(|arg| arg.parse().ok()) {
+                    stereo.eye_separation = separation;
+                }
+                if let Some(convergence) = args.get(2).and_then(|arg| arg.parse().ok()) {
+                    stereo.convergence = convergence;
+                }
+                command.set_stereo(Some(stereo));
+                String::new()
+            },
+        );
+        console.register(
+            "outline",
+            "Toggle edge outlines: outline on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_outline(Some(Outline::default())),
+                    Some(&"off") => command.set_outline(None),
+                    _ => return "usage: outline on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "palette",
+            "Show only 256 palette colors: palette on|dither|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_palette(Some(Palette::default())),
+                    Some(&"dither") => {
+                        command.set_palette(Some(Palette::default().with_dither(true)))
+                    }
+                    Some(&"off") => command.set_palette(None),
+                    _ => return "usage: palette on|dither|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "accumulate",
+            "Average jittered frames into a still while nothing moves: accumulate [frames] | off",
+            |args, command| {
+                match args {
+                    [] => command.set_accumulation(Some(Accumulation::default())),
+                    ["off"] => command.set_accumulation(None),
+                    [frames] => match frames.parse() {
+                        Ok(frames) => command.set_accumulation(Some(Accumulation::new(frames))),
+                        Err(_) => return "usage: accumulate [frames] | off".to_owned(),
+                    },
+                    _ => return "usage: accumulate [frames] | off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "taa",
+            "Toggle temporal anti-aliasing: taa on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_taa(true),
+                    Some(&"off") => command.set_taa(false),
+                    _ => return "usage: taa on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "oit",
+            "Order independent transparency instead of sorting: oit on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_oit(true),
+                    Some(&"off") => command.set_oit(false),
+                    _ => return "usage: oit on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "interlace",
+            "Render half the pixels per frame: interlace off|checker|rows",
+            |args, command| {
+                match args.first() {
+                    Some(&"off") => command.set_interlace(None),
+                    Some(&"checker") => command.set_interlace(Some(Interlace::Checkerboard)),
+                    Some(&"rows") => command.set_interlace(Some(Interlace::Rows)),
+                    _ => return "usage: interlace off|checker|rows".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "cache",
+            "Skip drawing frames where nothing changed: cache on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_static_caching(true),
+                    Some(&"off") => command.set_static_caching(false),
+                    _ => return "usage: cache on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "raytrace",
+            "Trace rays instead of rasterizing, or only shadows from rasterized pixels: raytrace on|shadows|hybrid|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_ray_traced(true, false),
+                    Some(&"shadows") => command.set_ray_traced(true, true),
+                    Some(&"hybrid") => command.set_ray_traced(false, true),
+                    Some(&"off") => command.set_ray_traced(false, false),
+                    _ => return "usage: raytrace on|shadows|hybrid|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "filler",
+            "Scan triangles by bounding box or edge spans: filler box|spans",
+            |args, command| {
+                match args.first() {
+                    Some(&"box") => command.set_triangle_filler(TriangleFiller::BoundingBox),
+                    Some(&"spans") => command.set_triangle_filler(TriangleFiller::Spans),
+                    _ => return "usage: filler box|spans".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "deterministic",
+            "Reproducible frames with a fixed time step and seed: deterministic [seed] | off",
+            |args, command| {
+                match args {
+                    [] => command.set_deterministic(Some(Deterministic::default())),
+                    ["off"] => command.set_deterministic(None),
+                    [seed] => match seed.parse() {
+                        Ok(seed) => command.set_deterministic(Some(Deterministic::new(seed))),
+                        Err(_) => return "usage: deterministic [seed] | off".to_owned(),
+                    },
+                    _ => return "usage: deterministic [seed] | off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "threads",
+            "Set the render threads: threads <count>|auto [reserve <cores>] [small-tiles]",
+            |args, command| {
+                let usage = "usage: threads <count>|auto [reserve <cores>] [small-tiles]";
+                let mut threads = match args.first() {
+                    Some(&"auto") => RenderThreads::default(),
+                    Some(count) => match count.parse() {
+                        Ok(count) => RenderThreads::new(count),
+                        Err(_) => return usage.to_owned(),
+                    },
+                    None => return usage.to_owned(),
+                };
+                let mut rest = &args[1..];
+                while !rest.is_empty() {
+                    rest = match rest {
+                        ["reserve", cores, rest @ ..] => match cores.parse() {
+                            Ok(cores) => {
+                                threads.reserved = cores;
+                                rest
+                            }
+                            Err(_) => return usage.to_owned(),
+                        },
+                        ["small-tiles", rest @ ..] => {
+                            threads.small_tiles = true;
+                            rest
+                        }
+                        _ => return usage.to_owned(),
+                    };
+                }
+                command.set_render_threads(threads);
+                format!("{} render threads", threads.thread_count())
+            },
+        );
+        console.register(
+            "subpixel",
+            "Draw triangles smaller than a pixel as points: subpixel on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_collapse_subpixel(true),
+                    Some(&"off") => command.set_collapse_subpixel(false),
+                    _ => return "usage: subpixel on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "framegraph",
+            "Print the passes of the next frame, optionally write Graphviz: framegraph [file.dot]",
+            |args, command| {
+                command.dump_frame_graph(args.first().copied());
+                String::new()
+            },
+        );
+        console.register(
+            "config",
+            "Show, change or save the settings: config [set <table.key> <value> | save]",
+            |args, command| match args {
+                [] => command.config().to_toml(),
+                ["set", key, value @ ..] if !value.is_empty() => {
+                    let mut config = command.config().clone();
+                    match config.set(key, &value.join(" ")) {
+                        Ok(_) => {
+                            command.set_config(config);
+                            String::new()
+                        }
+                        Err(err) => err,
+                    }
+                }
+                ["save"] => {
+                    command.save_config();
+                    String::new()
+                }
+                _ => "usage: config [set <table.key> <value> | save]".to_owned(),
+            },
+        );
+        console.register(
+            "perspective",
+            "Perspective correct attribute interpolation, off warps like affine mapping: perspective on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_perspective_correct(true),
+                    Some(&"off") => command.set_perspective_correct(false),
+                    _ => return "usage: perspective on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "reference",
+            "Rasterize with the slow scalar reference: reference on|off",
+            |args, command| {
+                match args.first() {
+                    Some(&"on") => command.set_reference_rasterizer(true),
+                    Some(&"off") => command.set_reference_rasterizer(false),
+                    _ => return "usage: reference on|off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "depthcue",
+            "Fade wireframe and points with distance: depthcue [start end] | off",
+            |args, command| {
+                match args {
+                    [] => command.set_depth_cue(Some(DepthCue::default())),
+                    ["off"] => command.set_depth_cue(None),
+                    [start, end] => match (start.parse(), end.parse()) {
+                        (Ok(start), Ok(end)) => {
+                            command.set_depth_cue(Some(DepthCue::new(start, end)))
+                        }
+                        _ => return "usage: depthcue [start end] | off".to_owned(),
+                    },
+                    _ => return "usage: depthcue [start end] | off".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "probes",
+            "Bake irradiance probes around the scene",
+            |_, command| {
+                command.bake_probes();
+                String::new()
+            },
+        );
+        console.register(
+            "bench",
+            "Run a stress test: bench [count] [cube|sphere] [solid|lit|textured|checker] [static|spin] [seconds]s, or bench stop",
+            |args, command| {
+                if args.first() == Some(&"stop") {
+                    command.stop_benchmark();
+                    return "benchmark stopped".to_owned();
+                }
+                match BenchmarkConfig::parse(args) {
+                    Ok(config) => {
+                        command.start_benchmark(config);
+                        String::new()
+                    }
+                    Err(err) => err,
+                }
+            },
+        );
+        console.register(
+            "record",
+            "Record input to a file: record start|stop [path]",
+            |args, command| {
+                let path = args.get(1).copied().unwrap_or(RECORDING_PATH);
+                match args.first() {
+                    Some(&"start") => command.start_recording(path),
+                    Some(&"stop") => command.stop_recording(),
+                    _ => return "usage: record start|stop [path]".to_owned(),
+                }
+                String::new()
+            },
+        );
+        console.register(
+            "replay",
+            "Replay recorded input: replay [path]",
+            |args, command| {
+                let path = args.first().copied().unwrap_or(RECORDING_PATH);
+                command.start_replay(path);
+                format!("replaying {}", path)
+            },
+        );
+        console
+    }
+}
+#[allow(unused)]
+impl Console {
+    fn add(&mut self, name: &str, help: &str, action: ConsoleAction) {
+        self.commands.insert(
+            name.to_owned(),
+            ConsoleCommand {
+                help: help.to_owned(),
+                action,
+            },
+        );
+    }
+    /// Adds or replaces a command handled by a closure.
+    pub fn register(
+        &mut self,
+        name: &str,
+        help: &str,
+        handler: impl FnMut(&[&str], &mut Command) -> String + 'static,
+    ) {
+        self.add(name, help, ConsoleAction::Handler(Box::new(handler)));
+    }
+    /// Adds or replaces a command that is returned from `submit` instead of run here.
+    pub fn register_forwarded(&mut self, name: &str, help: &str) {
+        self.add(name, help, ConsoleAction::Forward);
+    }
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+    pub fn print(&mut self, text: &str) {
+        self.output.extend(text.lines().map(str::to_owned));
+        let excess = self.output.len().saturating_sub(self.max_output);
+        self.output.drain(..excess);
+    }
+    /// Appends typed text to the input line, control characters and the toggle key are ignored.
+    pub fn type_text(&mut self, text: &str) {
+        self.input
+            .extend(text.chars().filter(|ch| !ch.is_control() && *ch != '`'));
+    }
+    pub fn backspace(&mut self) {
+        self.input.pop();
+    }
+    /// Runs the input line. Forwarded commands come back as their name and arguments.
+    pub fn submit(&mut self, command: &mut Command) -> Option<(String, Vec<String>)> {
+        let line = std::mem::take(&mut self.input);
+        self.execute(&line, command)
+    }
+    pub fn execute(&mut self, line: &str, command: &mut Command) -> Option<(String, Vec<String>)> {
+        let mut words = line.split_whitespace();
+        let name = words.next()?;
+        let args = words.collect::<Vec<_>>();
+        self.print(&format!("> {}", line.trim()));
+
+        let Some(entry) = self.commands.get_mut(name) else {
+            self.print(&format!("unknown command '{}', try 'help'", name));
+            return None;
+        };
+        let result = match &mut entry.action {
+            ConsoleAction::Handler(handler) => handler(&args, command),
+            ConsoleAction::Forward => {
+                return Some((
+                    name.to_owned(),
+                    args.iter().map(|arg| arg.to_string()).collect(),
+                ));
+            }
+            ConsoleAction::Help => self
+                .commands
+                .iter()
+                .map(|(name, entry)| format!("{:<10} {}", name, entry.help))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            ConsoleAction::Clear => {
+                self.output.clear();
+                String::new()
+            }
+        };
+        if !result.is_empty() {
+            self.print(&result);
+        }
+        None
+    }
+    /// Draws the console over the top half of the target with the newest output at the bottom.
+    pub fn draw(&self, target: &mut RenderTarget) {
+        let (width, height) = target.size();
+        let console_height = height / 2;
+        target.blend_rect(
+            0,
+            0,
+            width,
+            console_height,
+            Color::new(0.05, 0.05, 0.1, 0.8),
+        );
+        let line_height = LINE_HEIGHT as i32;
+        let margin = 4;
+        let input_y = console_height as i32 - line_height - margin;
+        draw_text(
+            target,
+            margin,
+            input_y,
+            &format!("> {}_", self.input),
+            Color::new(1.0, 1.0, 0.4, 1.0),
+            1,
+        );
+        let mut y = input_y - line_height;
+        for line in self.output.iter().rev() {
+            if y < 0 {
+                break;
+            }
+            draw_text(target, margin, y, line, Color::new(0.9, 0.9, 0.9, 1.0), 1);
+            y -= line_height;
+        }
+    }
+}
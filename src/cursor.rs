@@ -0,0 +1,110 @@
+use crate::error::SoftRastError;
+use crate::geometry::Texture;
+use crate::renderer::{Color, RenderTarget};
+use image::{GenericImageView, Rgba};
+use winit::event_loop::ActiveEventLoop;
+use winit::window::{CursorGrabMode, CursorIcon, CustomCursor, Icon, Window};
+
+/// How the mouse cursor looks while it is free, in the editor or after
+/// `Command::set_cursor_grab(false)`. A grabbed cursor is always hidden.
+#[derive(Debug, Clone, Default)]
+pub enum CursorStyle {
+    /// The platform's arrow.
+    #[default]
+    System,
+    Hidden,
+    /// An image handed to the window system, `hotspot` is the pixel that points.
+    Image {
+        texture: Texture,
+        hotspot: (u32, u32),
+    },
+    /// Drawn into the frame at the cursor position, looks the same on every platform but
+    /// only moves at the frame rate.
+    Software {
+        texture: Texture,
+        hotspot: (u32, u32),
+    },
+}
+impl CursorStyle {
+    /// Draws a software cursor with its hotspot at `x`, `y`, the other styles draw nothing.
+    pub(crate) fn draw(&self, target: &mut RenderTarget, x: i32, y: i32) {
+        let CursorStyle::Software { texture, hotspot } = self else {
+            return;
+        };
+        let image = &texture.texture;
+        let (left, top) = (x - hotspot.0 as i32, y - hotspot.1 as i32);
+        for (px, py, Rgba([r, g, b, a])) in image.pixels() {
+            if a > 0 {
+                target.blend_rect(
+                    left + px as i32,
+                    top + py as i32,
+                    1,
+                    1,
+                    Color::from_rgba(r, g, b, a),
+                );
+            }
+        }
+    }
+}
+
+/// Grabs or frees the cursor of `window` and shows `style` while it is free.
+pub(crate) fn apply_cursor(
+    window: &Window,
+    event_loop: &ActiveEventLoop,
+    style: &CursorStyle,
+    free: bool,
+) -> Result<(), SoftRastError> {
+    let grab = if free {
+        CursorGrabMode::None
+    } else {
+        CursorGrabMode::Confined
+    };
+    if let Err(err) = window.set_cursor_grab(grab) {
+        eprintln!("{:?}", err);
+    }
+    match style {
+        CursorStyle::System => window.set_cursor(CursorIcon::Default),
+        CursorStyle::Image { texture, hotspot } => {
+            let image = texture.texture.to_rgba8();
+            let size =
+                |value: u32| u16::try_from(value).map_err(|_| cursor_error("image too large"));
+            let source = CustomCursor::from_rgba(
+                image.into_raw(),
+                size(texture.texture.width())?,
+                size(texture.texture.height())?,
+                size(hotspot.0)?,
+                size(hotspot.1)?,
+            )
+            .map_err(|err| cursor_error(&err.to_string()))?;
+            window.set_cursor(event_loop.create_custom_cursor(source));
+        }
+        CursorStyle::Hidden | CursorStyle::Software { .. } => {}
+    }
+    window.set_cursor_visible(
+        free && matches!(style, CursorStyle::System | CursorStyle::Image { .. }),
+    );
+    Ok(())
+}
+
+/// Sets the icon of `window` from a texture, `None` restores the platform default.
+pub(crate) fn apply_window_icon(
+    window: &Window,
+    icon: Option<&Texture>,
+) -> Result<(), SoftRastError> {
+    let icon = match icon {
+        Some(texture) => {
+            let image = texture.texture.to_rgba8();
+            let (width, height) = image.dimensions();
+            let icon = Icon::from_rgba(image.into_raw(), width, height)
+                .map_err(|err| SoftRastError::Window(format!("icon: {}", err)))?;
+            Some(icon)
+        }
+        None => None,
+    };
+    window.set_window_icon(icon);
+    Ok(())
+}
+
+fn cursor_error(message: &str) -> SoftRastError {
+    SoftRastError::Window(format!("cursor: {}", message))
+}
@@ -0,0 +1,145 @@
+use crate::debug::DebugDraw;
+use crate::geometry::{Model, Vertex};
+use crate::renderer::Color;
+use nalgebra::{Point3, UnitQuaternion, Vector2, Vector3};
+
+/// A piecewise cubic curve, evaluated with `t` running from 0 to 1 over all segments.
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub enum Curve {
+    /// Cubic Bézier segments sharing end points: 4, 7, 10 … control points.
+    Bezier(Vec<Point3<f32>>),
+    /// Uniform Catmull-Rom spline passing through every point.
+    CatmullRom(Vec<Point3<f32>>),
+}
+#[allow(unused)]
+impl Curve {
+    pub fn segment_count(&self) -> usize {
+        match self {
+            Self::Bezier(points) => points.len().saturating_sub(1) / 3,
+            Self::CatmullRom(points) => points.len().saturating_sub(1),
+        }
+    }
+    /// Splits the global `t` into a segment index and a local parameter.
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let segments = self.segment_count().max(1);
+        let scaled = t.clamp(0.0, 1.0) * segments as f32;
+        let index = (scaled.floor() as usize).min(segments - 1);
+        (index, scaled - index as f32)
+    }
+    /// The four control points of the segment in Bézier form.
+    fn segment(&self, index: usize) -> [Point3<f32>; 4] {
+        match self {
+            Self::Bezier(points) => [
+                points[index * 3],
+                points[index * 3 + 1],
+                points[index * 3 + 2],
+                points[index * 3 + 3],
+            ],
+            Self::CatmullRom(points) => {
+                let last = points.len() - 1;
+                let p0 = points[index.saturating_sub(1)];
+                let p1 = points[index];
+                let p2 = points[(index + 1).min(last)];
+                let p3 = points[(index + 2).min(last)];
+                [p1, p1 + (p2 - p0) / 6.0, p2 - (p3 - p1) / 6.0, p2]
+            }
+        }
+    }
+    pub fn evaluate(&self, t: f32) -> Point3<f32> {
+        if self.segment_count() == 0 {
+            return self.points().first().copied().unwrap_or(Point3::origin());
+        }
+        let (index, t) = self.locate(t);
+        let [p0, p1, p2, p3] = self.segment(index);
+        let s = 1.0 - t;
+        Point3::from(
+            p0.coords * (s * s * s)
+                + p1.coords * (3.0 * s * s * t)
+                + p2.coords * (3.0 * s * t * t)
+                + p3.coords * (t * t * t),
+        )
+    }
+    /// Unnormalized derivative with respect to the segment parameter.
+    pub fn tangent(&self, t: f32) -> Vector3<f32> {
+        if self.segment_count() == 0 {
+            return Vector3::zeros();
+        }
+        let (index, t) = self.locate(t);
+        let [p0, p1, p2, p3] = self.segment(index);
+        let s = 1.0 - t;
+        (p1 - p0) * (3.0 * s * s) + (p2 - p1) * (6.0 * s * t) + (p3 - p2) * (3.0 * t * t)
+    }
+    pub fn points(&self) -> &[Point3<f32>] {
+        match self {
+            Self::Bezier(points) | Self::CatmullRom(points) => points,
+        }
+    }
+    /// Evenly spaced (in `t`) points along the curve, `samples + 1` including both ends.
+    pub fn sample(&self, samples: usize) -> Vec<Point3<f32>> {
+        let samples = samples.max(1);
+        (0..=samples)
+            .map(|i| self.evaluate(i as f32 / samples as f32))
+            .collect()
+    }
+    pub fn draw(&self, debug: &mut DebugDraw, samples: usize, color: Color) {
+        debug.polyline(&self.sample(samples), color);
+    }
+    /// Extrudes a circle along the curve, oriented with parallel transported frames so the
+    /// tube doesn't twist. U runs around the tube and V along it.
+    pub fn to_tube(&self, samples: usize, radius: f32, sides: usize) -> Model {
+        let samples = samples.max(1);
+        let sides = sides.max(3);
+        let color = Color::new(1.0, 1.0, 1.0, 1.0);
+
+        let mut rings = Vec::with_capacity(samples + 1);
+        let mut previous_tangent: Option<Vector3<f32>> = None;
+        let mut normal = Vector3::zeros();
+        for i in 0..=samples {
+            let t = i as f32 / samples as f32;
+            let center = self.evaluate(t);
+            let tangent = self
+                .tangent(t)
+                .try_normalize(f32::EPSILON)
+                .or(previous_tangent)
+                .unwrap_or(Vector3::z());
+            normal = match previous_tangent {
+                None => {
+                    let helper = if tangent.x.abs() < 0.9 {
+                        Vector3::x()
+                    } else {
+                        Vector3::y()
+                    };
+                    tangent.cross(&helper).normalize()
+                }
+                Some(previous) => UnitQuaternion::rotation_between(&previous, &tangent)
+                    .map(|rotation| rotation * normal)
+                    .unwrap_or(normal),
+            };
+            let binormal = tangent.cross(&normal);
+            let ring = (0..=sides)
+                .map(|j| {
+                    let angle = j as f32 / sides as f32 * std::f32::consts::TAU;
+                    let direction = normal * angle.cos() + binormal * angle.sin();
+                    let mut vertex = Vertex::new(&(center + direction * radius))
+                        .with_normal(direction)
+                        .with_uv(Vector2::new(j as f32 / sides as f32, t));
+                    vertex.color = Some(color);
+                    vertex
+                })
+                .collect::<Vec<_>>();
+            rings.push(ring);
+            previous_tangent = Some(tangent);
+        }
+
+        let mut vertices = Vec::with_capacity(samples * sides * 6);
+        for pair in rings.windows(2) {
+            let (ring, next) = (&pair[0], &pair[1]);
+            for j in 0..sides {
+                let (a, b, c, d) = (ring[j], next[j], next[j + 1], ring[j + 1]);
+                vertices.extend([a, d, c, a, c, b]);
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+}
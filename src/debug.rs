@@ -0,0 +1,336 @@
+use crate::Entity;
+use crate::camera::Camera;
+use crate::renderer::{Color, RenderTarget};
+use crate::text::{LINE_HEIGHT, draw_text, text_width};
+use nalgebra::{Matrix4, Point3, Vector3};
+
+#[derive(Copy, Clone, Debug)]
+pub struct DebugLine {
+    pub start: Point3<f32>,
+    pub end: Point3<f32>,
+    pub color: Color,
+}
+
+/// Screen space text, `x` and `y` are the top left corner in pixels.
+#[derive(Clone, Debug)]
+pub struct DebugText {
+    pub x: i32,
+    pub y: i32,
+    pub text: String,
+    pub color: Color,
+}
+
+/// Text anchored to a point in the world, centered above it and always facing the camera.
+#[derive(Clone, Debug)]
+pub struct DebugLabel {
+    pub position: Point3<f32>,
+    pub text: String,
+    pub color: Color,
+    pub scale: u32,
+    /// Hidden behind geometry closer than the anchor instead of drawn over it.
+    pub depth_tested: bool,
+    /// Camera distances where the label starts fading out and where it is gone.
+    pub fade: Option<(f32, f32)>,
+}
+#[allow(unused)]
+impl DebugLabel {
+    pub fn new(position: Point3<f32>, text: &str) -> Self {
+        Self {
+            position,
+            text: text.to_owned(),
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+            scale: 1,
+            depth_tested: false,
+            fade: Some((30.0, 50.0)),
+        }
+    }
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+    pub fn with_depth_test(mut self, depth_tested: bool) -> Self {
+        self.depth_tested = depth_tested;
+        self
+    }
+    /// `None` keeps the label at full strength at any distance.
+    pub fn with_fade(mut self, fade: Option<(f32, f32)>) -> Self {
+        self.fade = fade;
+        self
+    }
+    /// Opacity at `distance` from the camera.
+    fn opacity(&self, distance: f32) -> f32 {
+        match self.fade {
+            Some((start, end)) if end > start => {
+                1.0 - ((distance - start) / (end - start)).clamp(0.0, 1.0)
+            }
+            Some((start, _)) if distance >= start => 0.0,
+            _ => 1.0,
+        }
+    }
+}
+
+/// Immediate mode debug geometry, drawn on top of the scene and cleared every frame.
+#[derive(Default, Clone, Debug)]
+pub struct DebugDraw {
+    pub lines: Vec<DebugLine>,
+    pub texts: Vec<DebugText>,
+    pub labels: Vec<DebugLabel>,
+}
+#[allow(unused)]
+impl DebugDraw {
+    pub fn line(&mut self, start: Point3<f32>, end: Point3<f32>, color: Color) {
+        self.lines.push(DebugLine { start, end, color });
+    }
+    pub fn polyline(&mut self, points: &[Point3<f32>], color: Color) {
+        for pair in points.windows(2) {
+            self.line(pair[0], pair[1], color);
+        }
+    }
+    /// The edges of `camera`'s view frustum, cut off at `distance`.
+    pub(crate) fn frustum(&mut self, camera: &Camera, distance: f32, color: Color) {
+        let corners = camera.frustum_corners(distance);
+        for i in 0..4 {
+            self.line(corners[i], corners[(i + 1) % 4], color);
+            self.line(corners[i + 4], corners[(i + 1) % 4 + 4], color);
+            self.line(corners[i], corners[i + 4], color);
+        }
+    }
+    /// Three circles around the axes, e.g. the range of a point light.
+    pub fn sphere(&mut self, center: Point3<f32>, radius: f32, color: Color) {
+        for (u, v) in [
+            (Vector3::x(), Vector3::y()),
+            (Vector3::y(), Vector3::z()),
+            (Vector3::z(), Vector3::x()),
+        ] {
+            self.circle(center, u * radius, v * radius, color);
+        }
+    }
+    /// A cone from `apex` along `direction` with the half angle `angle` in radians, e.g. the
+    /// reach of a spot light.
+    pub fn cone(
+        &mut self,
+        apex: Point3<f32>,
+        direction: Vector3<f32>,
+        range: f32,
+        angle: f32,
+        color: Color,
+    ) {
+        let Some(axis) = direction.try_normalize(1e-6) else {
+            return;
+        };
+        let side = if axis.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        let u = axis.cross(&side).normalize();
+        let v = axis.cross(&u);
+        let radius = range * angle.min(1.5).tan();
+        let center = apex + axis * range;
+        self.circle(center, u * radius, v * radius, color);
+        for direction in [u, v, -u, -v] {
+            self.line(apex, center + direction * radius, color);
+        }
+    }
+    /// Ellipse spanned by the two axis vectors, in 32 segments.
+    fn circle(&mut self, center: Point3<f32>, u: Vector3<f32>, v: Vector3<f32>, color: Color) {
+        let points = (0..=32)
+            .map(|i| {
+                let angle = i as f32 / 32.0 * std::f32::consts::TAU;
+                center + u * angle.cos() + v * angle.sin()
+            })
+            .collect::<Vec<_>>();
+        self.polyline(&points, color);
+    }
+    pub fn text(&mut self, x: i32, y: i32, text: &str, color: Color) {
+        self.texts.push(DebugText {
+            x,
+            y,
+            text: text.to_owned(),
+            color,
+        });
+    }
+    /// Annotates a point in the world, e.g. `label(spawn, "spawn point")`. White, drawn over
+    /// geometry and faded out between 30 and 50 units, `add_label` takes other settings.
+    pub fn label(&mut self, position: Point3<f32>, text: &str) {
+        self.labels.push(DebugLabel::new(position, text));
+    }
+    pub fn add_label(&mut self, label: DebugLabel) {
+        self.labels.push(label);
+    }
+    pub fn clear(&mut self) {
+        self.lines.clear();
+        self.texts.clear();
+        self.labels.clear();
+    }
+}
+
+/// What `IndexLabels` numbers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LabelTarget {
+    /// Entries of the model's vertex list, at their position.
+    Vertices,
+    /// Triangles in draw order, at their center.
+    Triangles,
+}
+
+/// Numbers the vertices or triangles of the active entity models in the main view, for
+/// debugging the importers and the clipper. Labels hidden behind geometry or farther than
+/// `max_distance` are skipped, and at most `max_labels` of the nearest are drawn.
+#[derive(Debug, Clone)]
+pub struct IndexLabels {
+    pub target: LabelTarget,
+    /// Only entities with this id, every entity when `None`.
+    pub entity: Option<String>,
+    pub max_distance: f32,
+    pub max_labels: usize,
+    pub color: Color,
+}
+#[allow(unused)]
+impl IndexLabels {
+    pub fn new(target: LabelTarget) -> Self {
+        Self {
+            target,
+            entity: None,
+            max_distance: 20.0,
+            max_labels: 500,
+            color: Color::new(1.0, 1.0, 0.3, 1.0),
+        }
+    }
+    pub fn with_entity(mut self, id: &str) -> Self {
+        self.entity = Some(id.to_owned());
+        self
+    }
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+}
+
+/// View distance of a depth buffer value.
+fn linear_depth(camera: &Camera, depth: f32) -> f32 {
+    let (near, far) = (camera.near, camera.far);
+    2.0 * far * near / ((far + near) - depth * (far - near)).max(1e-6)
+}
+
+/// Draws world anchored labels, farthest first so nearer ones end up on top.
+pub(crate) fn draw_labels(target: &mut RenderTarget, labels: &[DebugLabel], camera: &Camera) {
+    let vp_mat = camera.get_perspective_matrix() * camera.get_view_matrix();
+    let (width, height) = target.size();
+    let mut visible = labels
+        .iter()
+        .filter_map(|label| {
+            let distance = (label.position - camera.eye()).magnitude();
+            let opacity = label.opacity(distance);
+            let clip = vp_mat * label.position.to_homogeneous();
+            if opacity <= 0.0 || clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.xyz() / clip.w;
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z.abs() > 1.0 {
+                return None;
+            }
+            let x = (((ndc.x + 1.0) * 0.5 * width as f32) as u32).min(width - 1);
+            let y = (((1.0 - ndc.y) * 0.5 * height as f32) as u32).min(height - 1);
+            if label.depth_tested {
+                let depth = target.depth()[(y * width + x) as usize];
+                if depth != f32::MAX
+                    && linear_depth(camera, ndc.z) > linear_depth(camera, depth) + 0.01
+                {
+                    return None;
+                }
+            }
+            Some((distance, x as i32, y as i32, opacity, label))
+        })
+        .collect::<Vec<_>>();
+    visible.sort_by(|a, b| b.0.total_cmp(&a.0));
+    for (_, x, y, opacity, label) in visible {
+        let left = x - text_width(&label.text, label.scale) as i32 / 2;
+        let top = y - (LINE_HEIGHT * label.scale * label.text.lines().count().max(1) as u32) as i32;
+        let color = Color {
+            a: label.color.a * opacity,
+            ..label.color
+        };
+        draw_text(target, left, top, &label.text, color, label.scale);
+    }
+}
+
+/// Draws `labels` over the depth buffer of the world entities.
+pub fn draw_index_labels(
+    target: &mut RenderTarget,
+    labels: &IndexLabels,
+    entities: &[Entity],
+    camera: &Camera,
+) {
+    let vp_mat = camera.get_perspective_matrix() * camera.get_view_matrix();
+    let (width, height) = target.size();
+    let distance = |depth: f32| linear_depth(camera, depth);
+    let mut visible = vec![];
+    for entity in entities {
+        if labels.entity.as_ref().is_some_and(|id| *id != entity.id) {
+            continue;
+        }
+        let mut local = entity.model_matrix();
+        local.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+        let world = Matrix4::new_translation(&entity.world_translation().cast::<f32>()) * local;
+        let model = entity.active_model();
+        let points = match labels.target {
+            LabelTarget::Vertices => model
+                .vertices
+                .iter()
+                .map(|vertex| vertex.position.xyz())
+                .collect::<Vec<_>>(),
+            LabelTarget::Triangles => model
+                .triangle_vertices()
+                .chunks_exact(3)
+                .map(|triangle| {
+                    let [a, b, c] = [0, 1, 2].map(|i| triangle[i].position.xyz());
+                    a + ((b - a) + (c - a)) / 3.0
+                })
+                .collect(),
+        };
+        for (index, point) in points.iter().enumerate() {
+            let point = world.transform_point(point);
+            let camera_distance = (point - camera.eye()).magnitude();
+            if camera_distance > labels.max_distance {
+                continue;
+            }
+            let clip = vp_mat * point.to_homogeneous();
+            if clip.w <= 0.0 {
+                continue;
+            }
+            let ndc = clip.xyz() / clip.w;
+            if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z.abs() > 1.0 {
+                continue;
+            }
+            let x = (((ndc.x + 1.0) * 0.5 * width as f32) as u32).min(width - 1);
+            let y = (((1.0 - ndc.y) * 0.5 * height as f32) as u32).min(height - 1);
+            // Labels lie on the surface they number, so they pass a little behind it
+            let depth = target.depth()[(y * width + x) as usize];
+            if depth != f32::MAX && distance(ndc.z) > distance(depth) * 1.01 + 0.01 {
+                continue;
+            }
+            visible.push((camera_distance, x as i32, y as i32, index));
+        }
+    }
+    visible.sort_by(|a, b| a.0.total_cmp(&b.0));
+    visible.truncate(labels.max_labels);
+    for (_, x, y, index) in visible {
+        draw_text(
+            target,
+            x + 2,
+            y - LINE_HEIGHT as i32 / 2,
+            &index.to_string(),
+            labels.color,
+            1,
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::Entity;
+use crate::geometry::{Model, Vertex, clip_polygon};
+use nalgebra::{Isometry3, Point3, Scale3, Vector2, Vector3};
+
+/// A box projector that stamps a texture onto the geometry it overlaps.
+///
+/// The decal looks down its local +Z axis, the box spans `-half_extents..=half_extents`
+/// and the texture covers the X/Y face of the box.
+#[allow(unused)]
+#[derive(Debug, Copy, Clone)]
+pub struct Decal {
+    pub transform: Isometry3<f32>,
+    pub half_extents: Vector3<f32>,
+    /// Distance the generated geometry is pulled towards the projector to avoid z-fighting.
+    pub bias: f32,
+}
+#[allow(unused)]
+impl Decal {
+    pub fn new(
+        position: Point3<f32>,
+        direction: Vector3<f32>,
+        up: Vector3<f32>,
+        size: Vector3<f32>,
+    ) -> Self {
+        Self {
+            transform: Isometry3::face_towards(&position, &(position + direction), &up),
+            half_extents: size / 2.0,
+            bias: 0.001,
+        }
+    }
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+    /// Builds a world space decal mesh from every entity in `entities`, meant to be drawn
+    /// with an identity transform and a `Material::Decal`.
+    pub fn project(&self, entities: &[Entity]) -> Model {
+        let mut vertices = Vec::new();
+        for entity in entities {
+            vertices.extend(
+                self.project_model(&entity.model, &entity.position, &entity.scale)
+                    .vertices,
+            );
+        }
+        Model::from_vertices(&vertices)
+    }
+    pub fn project_model(
+        &self,
+        model: &Model,
+        position: &Isometry3<f32>,
+        scale: &Scale3<f32>,
+    ) -> Model {
+        let to_local = self.transform.inverse() * position;
+        let direction = self.transform.transform_vector(&Vector3::z());
+        let mut vertices = Vec::new();
+
+        for triangle in model.triangle_vertices().chunks_exact(3) {
+            let local = triangle
+                .iter()
+                .map(|vertex| {
+                    let mut v = *vertex;
+                    let scaled = scale.transform_point(&vertex.position.xyz());
+                    v.position = to_local.transform_point(&scaled).to_homogeneous().into();
+                    v.normal = vertex.normal.map(|n| to_local.transform_vector(&n));
+                    v
+                })
+                .collect::<Vec<_>>();
+
+            if !self.faces_projector(&local) {
+                continue;
+            }
+
+            let polygon = self.clip_to_box(local);
+            if polygon.len() < 3 {
+                continue;
+            }
+
+            let polygon = polygon
+                .into_iter()
+                .map(|mut v| {
+                    let p = v.position.xyz();
+                    v.uv = Some(Vector2::new(
+                        p.x / (2.0 * self.half_extents.x) + 0.5,
+                        p.y / (2.0 * self.half_extents.y) + 0.5,
+                    ));
+                    let world = self.transform.transform_point(&p) - direction * self.bias;
+                    v.position = world.to_homogeneous().into();
+                    v.normal = v
+                        .normal
+                        .map(|n| self.transform.transform_vector(&n).normalize());
+                    v
+                })
+                .collect::<Vec<_>>();
+
+            for i in 1..polygon.len() - 1 {
+                vertices.push(polygon[0]);
+                vertices.push(polygon[i]);
+                vertices.push(polygon[i + 1]);
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+    fn faces_projector(&self, triangle: &[Vertex]) -> bool {
+        match (triangle[0].normal, triangle[1].normal, triangle[2].normal) {
+            (Some(n0), Some(n1), Some(n2)) => (n0 + n1 + n2).z < 0.0,
+            // Without normals the winding is the only hint, so keep both sides
+            _ => true,
+        }
+    }
+    /// Sutherland-Hodgman clip of a polygon against the six faces of the decal box.
+    fn clip_to_box(&self, polygon: Vec<Vertex>) -> Vec<Vertex> {
+        let mut polygon = polygon;
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                let limit = self.half_extents[axis];
+                let distance = |v: &Vertex| limit - sign * v.position[axis];
+                polygon = clip_polygon(&polygon, distance);
+                if polygon.is_empty() {
+                    return polygon;
+                }
+            }
+        }
+        polygon
+    }
+}
@@ -0,0 +1,106 @@
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+
+/// Seed of `deterministic_rng` while a deterministic mode is on.
+static SEED: Mutex<Option<u64>> = Mutex::new(None);
+/// Generators handed out per consumer since the seed was set.
+static STREAMS: LazyLock<Mutex<HashMap<String, u64>>> = LazyLock::new(Default::default);
+
+/// Renders that are bit exact across runs and machines, for golden image tests and
+/// replays. Frames advance by `frame_delta` instead of the clock, targets are cut into the
+/// same grid of `TILE_SIZE` tiles whatever the thread count and `RenderThreads` settings (see
+/// `RenderTarget::set_deterministic`), frame time driven quality changes are off and
+/// `deterministic_rng` is seeded from `seed`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Deterministic {
+    pub seed: u64,
+    pub frame_delta: Duration,
+}
+impl Default for Deterministic {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            frame_delta: Duration::from_nanos(16_666_667),
+        }
+    }
+}
+#[allow(unused)]
+impl Deterministic {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+    pub fn with_frame_delta(mut self, frame_delta: Duration) -> Self {
+        self.frame_delta = frame_delta;
+        self
+    }
+}
+
+/// A generator for one consumer of randomness, e.g. `"randomize_model_colors"`. Seeded from
+/// the OS unless a deterministic mode set a seed, then the seed, the consumer's name and how
+/// often it asked before decide the sequence, so consumers don't repeat each other and runs
+/// repeat exactly.
+pub fn deterministic_rng(consumer: &str) -> XorShiftRng {
+    let Some(seed) = *SEED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) else {
+        return XorShiftRng::from_os_rng();
+    };
+    let mut streams = STREAMS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let calls = streams.entry(consumer.to_owned()).or_default();
+    let stream = mix(mix(seed ^ fnv1a(consumer)) ^ *calls);
+    *calls += 1;
+    XorShiftRng::seed_from_u64(stream)
+}
+
+/// Spelled out instead of `DefaultHasher`, whose output may change between Rust releases.
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// The splitmix64 finalizer, so nearby inputs give unrelated seeds.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
+
+/// Sets the seed of `deterministic_rng`, or back to OS seeding with `None`, and restarts
+/// every consumer's sequence.
+pub(crate) fn set_seed(seed: Option<u64>) {
+    *SEED.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = seed;
+    STREAMS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    fn first(consumer: &str) -> u64 {
+        deterministic_rng(consumer).random()
+    }
+
+    #[test]
+    fn seeded_streams_differ_per_consumer_and_call_and_repeat_after_reseeding() {
+        set_seed(Some(7));
+        let (a, a_again, b) = (first("a"), first("a"), first("b"));
+        assert_ne!(a, a_again);
+        assert_ne!(a, b);
+        set_seed(Some(7));
+        assert_eq!((first("a"), first("a"), first("b")), (a, a_again, b));
+        set_seed(Some(8));
+        assert_ne!(first("a"), a);
+        set_seed(None);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::error::SoftRastError;
+use winit::event_loop::ActiveEventLoop;
+use winit::monitor::{MonitorHandle, VideoModeHandle};
+use winit::window::{Fullscreen, Window};
+
+/// A resolution and refresh rate a monitor can switch to in exclusive fullscreen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VideoMode {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u16,
+    /// Refresh rate in thousandths of a hertz, 59940 for 59.94 Hz.
+    pub refresh_millihertz: u32,
+}
+
+/// A connected monitor of `SoftRastEvent::Monitors`, `index` is what `FullscreenMode` takes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonitorInfo {
+    pub index: usize,
+    pub name: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    pub modes: Vec<VideoMode>,
+}
+
+/// How the window covers the screen. `monitor` indexes `MonitorInfo::index`, `None` is the
+/// monitor the window is on.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    /// A window without decorations covering the monitor at its desktop resolution, switches
+    /// instantly and plays well with other windows.
+    Borderless { monitor: Option<usize> },
+    /// Switches the monitor to the video mode closest to `width` x `height`, the highest
+    /// refresh rate of that size unless `refresh_millihertz` asks for one.
+    Exclusive {
+        monitor: Option<usize>,
+        width: u32,
+        height: u32,
+        refresh_millihertz: Option<u32>,
+    },
+}
+
+/// Every connected monitor with its video modes, largest and fastest first.
+pub(crate) fn monitors(event_loop: &ActiveEventLoop) -> Vec<MonitorInfo> {
+    event_loop
+        .available_monitors()
+        .enumerate()
+        .map(|(index, monitor)| {
+            let mut modes = monitor
+                .video_modes()
+                .map(|mode| VideoMode {
+                    width: mode.size().width,
+                    height: mode.size().height,
+                    bit_depth: mode.bit_depth(),
+                    refresh_millihertz: mode.refresh_rate_millihertz(),
+                })
+                .collect::<Vec<_>>();
+            modes.sort_by_key(|mode| {
+                std::cmp::Reverse((mode.width * mode.height, mode.refresh_millihertz))
+            });
+            modes.dedup();
+            MonitorInfo {
+                index,
+                name: monitor.name(),
+                width: monitor.size().width,
+                height: monitor.size().height,
+                modes,
+            }
+        })
+        .collect()
+}
+
+/// Switches `window` to `mode`. The window reports the new size through `Resized`, which
+/// reallocates the render target.
+pub(crate) fn apply_fullscreen(
+    window: &Window,
+    event_loop: &ActiveEventLoop,
+    mode: &FullscreenMode,
+) -> Result<(), SoftRastError> {
+    let monitor = |index: Option<usize>| match index {
+        Some(index) => event_loop
+            .available_monitors()
+            .nth(index)
+            .ok_or_else(|| SoftRastError::Window(format!("no monitor {}", index)))
+            .map(Some),
+        None => Ok(window
+            .current_monitor()
+            .or_else(|| event_loop.primary_monitor())),
+    };
+    let fullscreen = match *mode {
+        FullscreenMode::Windowed => None,
+        FullscreenMode::Borderless { monitor: index } => {
+            Some(Fullscreen::Borderless(monitor(index)?))
+        }
+        FullscreenMode::Exclusive {
+            monitor: index,
+            width,
+            height,
+            refresh_millihertz,
+        } => {
+            let handle = monitor(index)?
+                .ok_or_else(|| SoftRastError::Window("no monitor found".to_owned()))?;
+            Some(Fullscreen::Exclusive(closest_mode(
+                &handle,
+                width,
+                height,
+                refresh_millihertz,
+            )?))
+        }
+    };
+    window.set_fullscreen(fullscreen);
+    Ok(())
+}
+
+/// The mode nearest in size, then in refresh rate.
+fn closest_mode(
+    monitor: &MonitorHandle,
+    width: u32,
+    height: u32,
+    refresh_millihertz: Option<u32>,
+) -> Result<VideoModeHandle, SoftRastError> {
+    monitor
+        .video_modes()
+        .min_by_key(|mode| {
+            let size = mode.size();
+            let distance = size.width.abs_diff(width) as u64 + size.height.abs_diff(height) as u64;
+            let refresh = match refresh_millihertz {
+                Some(refresh) => mode.refresh_rate_millihertz().abs_diff(refresh),
+                None => u32::MAX - mode.refresh_rate_millihertz(),
+            };
+            (distance, refresh, std::cmp::Reverse(mode.bit_depth()))
+        })
+        .ok_or_else(|| {
+            SoftRastError::Unsupported("exclusive fullscreen on this monitor".to_owned())
+        })
+}
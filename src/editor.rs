@@ -0,0 +1,273 @@
+use crate::camera::Camera;
+use crate::error::SoftRastError;
+use crate::geometry::Ray;
+use crate::gizmo::{Gizmo, GizmoMode};
+use crate::renderer::{Color, RenderTarget};
+use crate::{Entity, InputState, Scene};
+use nalgebra::{Isometry3, Point3, Quaternion, Scale3, Translation3, UnitQuaternion};
+use std::fs;
+use std::time::Duration;
+use winit::event::MouseButton;
+
+/// Built-in editing overlay. While active the user app is paused, the editor flies its own
+/// camera, entities are selected by clicking and moved with the gizmo.
+#[derive(Default)]
+pub struct Editor {
+    pub active: bool,
+    camera: Option<Camera>,
+    gizmo: Gizmo,
+    selected: Option<usize>,
+    was_clicked: bool,
+    was_cycled: bool,
+}
+#[allow(unused)]
+impl Editor {
+    /// Enters or leaves the editor, the editor camera starts where the scene camera is.
+    pub fn toggle(&mut self, scene: Option<&Scene>) {
+        self.active = !self.active;
+        self.camera = None;
+        if let (true, Some(scene)) = (self.active, scene) {
+            self.camera = Some(scene.camera);
+        }
+    }
+    /// Clicks select what the last frame drew under the cursor when `target` has an id
+    /// buffer, and fall back to ray picking bounding spheres otherwise.
+    pub fn update(
+        &mut self,
+        input: &InputState,
+        delta: Duration,
+        scene: &mut Scene,
+        target: &RenderTarget,
+    ) {
+        let camera = self.camera.get_or_insert(scene.camera);
+        let speed = delta.as_secs_f32() * 5.0;
+        let keys = &input.pressed_keys;
+        let axis = |positive: &str, negative: &str| {
+            (keys.contains(positive) as i32 - keys.contains(negative) as i32) as f32 * speed
+        };
+        camera.move_local(axis("w", "s"), axis("d", "a"), axis("z", "c"));
+        if input.pressed_buttons.contains(&MouseButton::Right) {
+            camera.look(
+                input.mouse_dx as f32,
+                input.mouse_dy as f32,
+                delta.as_secs_f32(),
+            );
+        }
+        scene.camera = *camera;
+
+        if keys.contains("g") {
+            self.gizmo.mode = GizmoMode::Translate;
+        }
+        if keys.contains("r") {
+            self.gizmo.mode = GizmoMode::Rotate;
+        }
+        if keys.contains("t") {
+            self.gizmo.mode = GizmoMode::Scale;
+        }
+        let cycled = keys.contains("n");
+        if cycled && !self.was_cycled && !scene.entities.is_empty() {
+            self.selected = Some(self.selected.map_or(0, |i| (i + 1) % scene.entities.len()));
+        }
+        self.was_cycled = cycled;
+        if self.selected.is_some_and(|i| i >= scene.entities.len()) {
+            self.selected = None;
+        }
+
+        let ray = scene.camera.screen_ray(
+            input.cursor_x as f32,
+            input.cursor_y as f32,
+            input.window_size,
+        );
+        let pressed = input.pressed_buttons.contains(&MouseButton::Left);
+        let clicked = pressed && !self.was_clicked;
+        self.was_clicked = pressed;
+
+        let mut consumed = false;
+        if let Some(index) = self.selected {
+            let camera = scene.camera;
+            let entity = &mut scene.entities[index];
+            let start = gizmo_pose(entity);
+            let mut pose = start;
+            consumed = self
+                .gizmo
+                .interact(&ray, pressed, &camera, &mut pose, &mut entity.scale);
+            entity.position.translation.vector +=
+                pose.translation.vector - start.translation.vector;
+            entity.position.rotation = pose.rotation;
+        }
+        if clicked && !consumed {
+            self.selected = if target.ids().is_empty() {
+                pick(scene, &ray)
+            } else {
+                target
+                    .id_at_window(
+                        input.cursor_x as u32,
+                        input.cursor_y as u32,
+                        input.window_size,
+                    )
+                    .map(|pixel| pixel.entity)
+            };
+        }
+
+        if let Some(outline) = &mut scene.outline {
+            outline.selected = self.selected.into_iter().collect();
+        }
+        if let Some(index) = self.selected {
+            let pose = gizmo_pose(&scene.entities[index]);
+            self.gizmo.draw(&mut scene.debug, &pose, &scene.camera);
+            draw_bounds(scene, index);
+        }
+    }
+    /// Selection and transform summary, shown in the window title.
+    pub fn status(&self, scene: Option<&Scene>) -> String {
+        let selected = self
+            .selected
+            .zip(scene)
+            .and_then(|(index, scene)| scene.entities.get(index));
+        match selected {
+            Some(entity) => {
+                let t = entity.position.translation.vector;
+                let (roll, pitch, yaw) = entity.position.rotation.euler_angles();
+                let s = entity.scale.vector;
+                format!(
+                    "Editor {:?} | {} pos ({:.2}, {:.2}, {:.2}) rot ({:.1}, {:.1}, {:.1}) scale ({:.2}, {:.2}, {:.2})",
+                    self.gizmo.mode,
+                    entity.id,
+                    t.x,
+                    t.y,
+                    t.z,
+                    roll.to_degrees(),
+                    pitch.to_degrees(),
+                    yaw.to_degrees(),
+                    s.x,
+                    s.y,
+                    s.z
+                )
+            }
+            None => format!("Editor {:?} | nothing selected", self.gizmo.mode),
+        }
+    }
+}
+
+/// Nearest bounding sphere hit among the entities whose bounds the ray enters.
+fn pick(scene: &mut Scene, ray: &Ray) -> Option<usize> {
+    scene.spatial.update(&scene.entities);
+    let mut best: Option<(usize, f32)> = None;
+    for (index, _) in scene.spatial.raycast(ray, f32::MAX) {
+        let (center, radius) = world_bounding_sphere(&scene.entities[index]);
+        if let Some(t) = ray.intersect_sphere(&center, radius)
+            && best.is_none_or(|(_, best_t)| t < best_t)
+        {
+            best = Some((index, t));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+fn world_bounding_sphere(entity: &Entity) -> (Point3<f32>, f32) {
+    let (min, max) = entity.active_model().bounds();
+    let model_mat = entity.model_matrix();
+    let offset = entity.world_offset.cast::<f32>();
+    let center = model_mat.transform_point(&nalgebra::center(&min, &max)) + offset;
+    let scale = (0..3)
+        .map(|i| model_mat.fixed_view::<3, 1>(0, i).magnitude())
+        .fold(0.0, f32::max);
+    (center, (max - min).magnitude() * 0.5 * scale)
+}
+
+/// Where the gizmo sits: the world position of the drawn model, with the entity's own
+/// rotation since that is what the gizmo turns.
+fn gizmo_pose(entity: &Entity) -> Isometry3<f32> {
+    Isometry3::from_parts(
+        Translation3::from(entity.world_translation().cast::<f32>()),
+        entity.position.rotation,
+    )
+}
+
+fn draw_bounds(scene: &mut Scene, index: usize) {
+    let entity = &scene.entities[index];
+    let (min, max) = entity.active_model().bounds();
+    let (model_mat, offset) = (entity.model_matrix(), entity.world_offset.cast::<f32>());
+    let corner = |i: usize| {
+        let local = Point3::new(
+            if i & 1 == 0 { min.x } else { max.x },
+            if i & 2 == 0 { min.y } else { max.y },
+            if i & 4 == 0 { min.z } else { max.z },
+        );
+        model_mat.transform_point(&local) + offset
+    };
+    let color = Color::new(1.0, 0.6, 0.0, 1.0);
+    let edges = [
+        (0, 1),
+        (2, 3),
+        (4, 5),
+        (6, 7),
+        (0, 2),
+        (1, 3),
+        (4, 6),
+        (5, 7),
+        (0, 4),
+        (1, 5),
+        (2, 6),
+        (3, 7),
+    ];
+    let lines = edges.map(|(a, b)| (corner(a), corner(b)));
+    for (a, b) in lines {
+        scene.debug.line(a, b, color);
+    }
+}
+
+/// Writes one line per entity: `id px py pz qx qy qz qw sx sy sz`.
+pub fn save_layout(scene: &Scene, path: &str) -> Result<(), SoftRastError> {
+    let mut contents = String::new();
+    for entity in &scene.entities {
+        let t = entity.position.translation.vector;
+        let q = entity.position.rotation.coords;
+        let s = entity.scale.vector;
+        contents.push_str(&format!(
+            "{} {} {} {} {} {} {} {} {} {} {}\n",
+            entity.id, t.x, t.y, t.z, q.x, q.y, q.z, q.w, s.x, s.y, s.z
+        ));
+    }
+    fs::write(path, contents).map_err(|source| SoftRastError::Io {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// Applies transforms written by `save_layout` to the entities with matching ids. Nothing
+/// moves when a line is malformed.
+pub fn load_layout(scene: &mut Scene, path: &str) -> Result<(), SoftRastError> {
+    let contents = fs::read_to_string(path).map_err(|source| SoftRastError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    let mut layout = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let mut parts = line.split_whitespace();
+        let Some(id) = parts.next() else { continue };
+        let numbers = parts
+            .map(|n| n.parse::<f32>().ok())
+            .collect::<Option<Vec<_>>>()
+            .filter(|numbers| numbers.len() == 10)
+            .ok_or_else(|| SoftRastError::Parse {
+                path: path.to_owned(),
+                line: index + 1,
+                message: format!("expected an id and 10 numbers, got '{}'", line),
+            })?;
+        layout.push((id, numbers));
+    }
+    for (id, numbers) in layout {
+        if let Some(entity) = scene.entities.iter_mut().find(|e| e.id == id) {
+            let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+                numbers[6], numbers[3], numbers[4], numbers[5],
+            ));
+            entity.position = Isometry3::from_parts(
+                Translation3::new(numbers[0], numbers[1], numbers[2]),
+                rotation,
+            );
+            entity.scale = Scale3::new(numbers[7], numbers[8], numbers[9]);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::fmt::{Display, Formatter};
+
+/// Everything that can go wrong loading assets or setting up the window.
+#[derive(Debug)]
+pub enum SoftRastError {
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A malformed line in a text format, `line` counts from 1.
+    Parse {
+        path: String,
+        line: usize,
+        message: String,
+    },
+    /// An image the decoders couldn't read.
+    Image {
+        path: String,
+        message: String,
+    },
+    Gltf {
+        path: String,
+        message: String,
+    },
+    /// A model the loaders or the physics couldn't make sense of, the message names it.
+    Model(String),
+    /// A file extension or feature no loader handles.
+    Unsupported(String),
+    /// Creating the window, its context or its surface failed.
+    Window(String),
+    /// Something asked for by name that isn't registered, e.g. a prefab.
+    NotFound(String),
+}
+impl Display for SoftRastError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {}", path, source),
+            Self::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path, line, message),
+            Self::Image { path, message } => write!(f, "{}: {}", path, message),
+            Self::Gltf { path, message } => write!(f, "{}: {}", path, message),
+            Self::Model(message) => write!(f, "{}", message),
+            Self::Unsupported(what) => write!(f, "unsupported {}", what),
+            Self::Window(message) => write!(f, "window: {}", message),
+            Self::NotFound(what) => write!(f, "no {}", what),
+        }
+    }
+}
+impl std::error::Error for SoftRastError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+impl SoftRastError {
+    /// An `image` crate error reading or writing `path`, keeping I/O failures as `Io`.
+    pub(crate) fn from_image(path: &str, err: image::ImageError) -> Self {
+        match err {
+            image::ImageError::IoError(source) => Self::Io {
+                path: path.to_owned(),
+                source,
+            },
+            err => Self::Image {
+                path: path.to_owned(),
+                message: err.to_string(),
+            },
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use crate::renderer::{Color, RenderTarget};
+use rayon::prelude::*;
+
+const BINS: usize = 64;
+/// Log2 luminance range covered by the histogram, darker and brighter pixels land in the
+/// first and last bin.
+const MIN_LOG: f32 = -10.0;
+const MAX_LOG: f32 = 0.0;
+
+/// Brightens or darkens the rendered frame towards a target brightness, adapting over time
+/// like an eye. Each frame builds a histogram of log luminance, drops the darkest and
+/// brightest pixels by `low_percentile` and `high_percentile` so a few lamps or shadows
+/// don't swing it, and moves the exposure towards `key` over the average of the rest.
+///
+/// The target holds 8 bit colors, so highlights clipped while drawing stay clipped, the
+/// gain is in lifting dark frames and evening out the transitions.
+#[derive(Debug, Clone)]
+pub struct AutoExposure {
+    /// Average luminance the frame is exposed to.
+    pub key: f32,
+    pub min_exposure: f32,
+    pub max_exposure: f32,
+    /// Share of the darkest pixels ignored, 0 to 1.
+    pub low_percentile: f32,
+    /// Share of the brightest pixels ignored, 0 to 1.
+    pub high_percentile: f32,
+    /// Adaptation rates per second when the frame gets brighter and darker. Eyes adapt to
+    /// light faster than to the dark.
+    pub speed_up: f32,
+    pub speed_down: f32,
+    exposure: f32,
+    target_exposure: f32,
+    last_time: Option<f32>,
+}
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            key: 0.35,
+            min_exposure: 0.25,
+            max_exposure: 4.0,
+            low_percentile: 0.5,
+            high_percentile: 0.05,
+            speed_up: 3.0,
+            speed_down: 1.0,
+            exposure: 1.0,
+            target_exposure: 1.0,
+            last_time: None,
+        }
+    }
+}
+#[allow(unused)]
+impl AutoExposure {
+    pub fn new(key: f32) -> Self {
+        Self {
+            key,
+            ..Self::default()
+        }
+    }
+    pub fn with_range(mut self, min_exposure: f32, max_exposure: f32) -> Self {
+        self.min_exposure = min_exposure;
+        self.max_exposure = max_exposure.max(min_exposure);
+        self
+    }
+    pub fn with_speed(mut self, speed_up: f32, speed_down: f32) -> Self {
+        self.speed_up = speed_up;
+        self.speed_down = speed_down;
+        self
+    }
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+    /// Close enough to the target that the image no longer changes visibly.
+    pub fn settled(&self) -> bool {
+        (self.exposure - self.target_exposure).abs() < self.target_exposure * 0.01
+    }
+    /// Meters the frame in `target`, adapts by the time since the previous call and scales
+    /// the frame by the new exposure.
+    pub fn apply(&mut self, target: &mut RenderTarget, time: f32) {
+        let histogram = histogram(&target.color);
+        if let Some(average) = self.average_luminance(&histogram) {
+            let target_exposure = self.key / average.max(1e-4);
+            self.target_exposure = target_exposure.clamp(self.min_exposure, self.max_exposure);
+        }
+        let delta = self
+            .last_time
+            .map_or(f32::MAX, |last| (time - last).max(0.0));
+        self.last_time = Some(time);
+        // Lower exposure means the scene got brighter
+        let speed = if self.target_exposure < self.exposure {
+            self.speed_up
+        } else {
+            self.speed_down
+        };
+        self.exposure += (self.target_exposure - self.exposure) * (1.0 - (-delta * speed).exp());
+        if (self.exposure - 1.0).abs() < 1e-3 {
+            return;
+        }
+        let exposure = self.exposure;
+        target.color.par_iter_mut().for_each(|pixel| {
+            *pixel = (Color::from_u32(*pixel) * exposure).as_u32();
+        });
+    }
+    /// Mean luminance of the histogram without the ignored tails.
+    fn average_luminance(&self, histogram: &[u32; BINS]) -> Option<f32> {
+        let total = histogram.iter().sum::<u32>() as f32;
+        if total == 0.0 {
+            return None;
+        }
+        let mut skip_low = total * self.low_percentile.clamp(0.0, 1.0);
+        let mut keep = total * (1.0 - self.low_percentile - self.high_percentile).clamp(0.0, 1.0);
+        let (mut sum, mut count) = (0.0, 0.0);
+        for (bin, pixels) in histogram.iter().enumerate() {
+            let mut pixels = *pixels as f32;
+            let skipped = pixels.min(skip_low);
+            skip_low -= skipped;
+            pixels -= skipped;
+            let kept = pixels.min(keep);
+            keep -= kept;
+            let log = MIN_LOG + (bin as f32 + 0.5) / BINS as f32 * (MAX_LOG - MIN_LOG);
+            sum += log * kept;
+            count += kept;
+        }
+        (count > 0.0).then(|| (sum / count).exp2())
+    }
+}
+
+/// Pixel counts by log2 luminance between `MIN_LOG` and `MAX_LOG`.
+fn histogram(color: &[u32]) -> [u32; BINS] {
+    color
+        .par_chunks(4096)
+        .map(|chunk| {
+            let mut bins = [0u32; BINS];
+            for pixel in chunk {
+                let color = Color::from_u32(*pixel);
+                let luminance = 0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b;
+                let log = luminance.max(1e-6).log2();
+                let bin = ((log - MIN_LOG) / (MAX_LOG - MIN_LOG) * BINS as f32) as isize;
+                bins[bin.clamp(0, BINS as isize - 1) as usize] += 1;
+            }
+            bins
+        })
+        .reduce(
+            || [0; BINS],
+            |mut a, b| {
+                for (a, b) in a.iter_mut().zip(b) {
+                    *a += b;
+                }
+                a
+            },
+        )
+}
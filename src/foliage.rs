@@ -0,0 +1,178 @@
+use crate::Entity;
+use crate::geometry::{Model, Texture, Vertex};
+use crate::renderer::{Color, Material, MaterialOptions, Sampler, UvTransform};
+use nalgebra::{Isometry3, Matrix4, Point3, Rotation3, Scale3, Vector2, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+/// Scatters grass or plant cards over a surface. Every instance is two crossed quads with a
+/// random rotation and scale, baked into one model so thousands of them are a single draw.
+/// Cards are alpha tested and double sided, and their tops sway in the wind through
+/// `wind_sway`.
+#[derive(Debug, Copy, Clone)]
+pub struct Foliage {
+    /// Instances per square unit of surface.
+    pub density: f32,
+    pub card_width: f32,
+    pub card_height: f32,
+    /// Random uniform scale range of each instance.
+    pub scale: (f32, f32),
+    /// Sideways sway of the card tops in model units.
+    pub wind: f32,
+    /// Triangles whose normal has a smaller up component are left bare, e.g. cliffs.
+    pub max_slope: f32,
+    pub alpha_cutoff: f32,
+    pub seed: u64,
+}
+impl Default for Foliage {
+    fn default() -> Self {
+        Self {
+            density: 4.0,
+            card_width: 0.6,
+            card_height: 0.5,
+            scale: (0.7, 1.3),
+            wind: 0.08,
+            max_slope: 0.6,
+            alpha_cutoff: 0.5,
+            seed: 1,
+        }
+    }
+}
+#[allow(unused)]
+impl Foliage {
+    pub fn new(density: f32) -> Self {
+        Self {
+            density,
+            ..Self::default()
+        }
+    }
+    pub fn with_card(mut self, width: f32, height: f32) -> Self {
+        self.card_width = width;
+        self.card_height = height;
+        self
+    }
+    pub fn with_scale(mut self, min: f32, max: f32) -> Self {
+        self.scale = (min, max.max(min));
+        self
+    }
+    pub fn with_wind(mut self, wind: f32) -> Self {
+        self.wind = wind;
+        self
+    }
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    /// Two quads crossed at right angles standing on the origin. The vertex color alpha is
+    /// the sway weight `wind_sway` reads, zero at the bottom and `wind` at the top.
+    pub fn card(&self) -> Model {
+        let (half, height) = (self.card_width * 0.5, self.card_height);
+        let mut vertices = Vec::with_capacity(12);
+        for axis in [Vector3::x(), Vector3::z()] {
+            let normal = axis.cross(&Vector3::y());
+            let corner = |side: f32, top: bool| {
+                let position = Point3::from(
+                    axis * side * half + Vector3::y() * if top { height } else { 0.0 },
+                );
+                let mut vertex = Vertex::new(&position)
+                    .with_normal(normal)
+                    .with_uv(Vector2::new(
+                        (side + 1.0) * 0.5,
+                        if top { 1.0 } else { 0.0 },
+                    ));
+                vertex.color = Some(Color::new(1.0, 1.0, 1.0, if top { self.wind } else { 0.0 }));
+                vertex
+            };
+            let quad = [
+                corner(-1.0, false),
+                corner(1.0, false),
+                corner(1.0, true),
+                corner(-1.0, true),
+            ];
+            vertices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+        }
+        Model::from_vertices(&vertices)
+    }
+    /// Instance transforms on the surface, area weighted so the density is even.
+    pub fn scatter(&self, surface: &Model) -> Vec<Matrix4<f32>> {
+        let mut rng = XorShiftRng::seed_from_u64(self.seed);
+        let mut instances = vec![];
+        for triangle in surface.triangle_vertices().chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i].position.xyz());
+            let cross = (b - a).cross(&(c - a));
+            let area = cross.magnitude() * 0.5;
+            let Some(normal) = cross.try_normalize(1e-9) else {
+                continue;
+            };
+            if normal.y < self.max_slope {
+                continue;
+            }
+            // The fractional part of the expected count becomes a chance of one more
+            let expected = area * self.density;
+            let count = expected.floor() as u32 + (rng.random::<f32>() < expected.fract()) as u32;
+            for _ in 0..count {
+                let (mut u, mut v) = (rng.random::<f32>(), rng.random::<f32>());
+                if u + v > 1.0 {
+                    (u, v) = (1.0 - u, 1.0 - v);
+                }
+                let position = a + (b - a) * u + (c - a) * v;
+                let yaw = rng.random_range(0.0..std::f32::consts::TAU);
+                let scale = rng.random_range(self.scale.0..=self.scale.1);
+                instances.push(
+                    Matrix4::new_translation(&position.coords)
+                        * Rotation3::from_axis_angle(&Vector3::y_axis(), yaw).to_homogeneous()
+                        * Matrix4::new_scaling(scale),
+                );
+            }
+        }
+        instances
+    }
+    /// Every instance baked into one model in the surface's space.
+    pub fn build(&self, surface: &Model) -> Model {
+        let card = self.card();
+        let parts = self
+            .scatter(surface)
+            .into_iter()
+            .map(|instance| (card.clone(), instance))
+            .collect::<Vec<_>>();
+        Model::merge(&parts)
+    }
+    /// An entity drawing the foliage over `surface` placed at `position`, with the card
+    /// texture alpha tested and the wind applied.
+    pub fn entity(
+        &self,
+        id: &str,
+        surface: &Model,
+        position: &Isometry3<f32>,
+        texture: Texture,
+    ) -> Entity {
+        let material = Material::Decal {
+            texture,
+            alpha_cutoff: self.alpha_cutoff,
+            uv_transform: UvTransform::default(),
+            sampler: Sampler::default(),
+        };
+        Entity::new(
+            id,
+            &self.build(surface),
+            position,
+            &Scale3::new(1.0, 1.0, 1.0),
+            MaterialOptions::new(material).double_sided(),
+        )
+        .with_vertex_modifier(wind_sway)
+    }
+}
+
+/// Vertex modifier swaying foliage built by `Foliage`, by the sway weight in the vertex color
+/// alpha. The phase follows the position so neighbouring plants move together in gusts.
+pub fn wind_sway(vertex: &mut Vertex, time: f32) {
+    let weight = vertex.color.map_or(0.0, |color| color.a);
+    if weight == 0.0 {
+        return;
+    }
+    let (x, z) = (vertex.position.x, vertex.position.z);
+    let phase = time * 1.7 + x * 0.35 + z * 0.25;
+    let gust = 0.6 + 0.4 * (time * 0.4 + x * 0.05).sin();
+    vertex.position.x += weight * gust * phase.sin();
+    vertex.position.z += weight * gust * 0.5 * (phase * 1.3).cos();
+}
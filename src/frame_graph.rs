@@ -0,0 +1,100 @@
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+/// One pass of a frame: what it drew into, what earlier results it used and how long it took.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePass {
+    pub name: String,
+    pub target: String,
+    pub size: (u32, u32),
+    /// Targets of earlier passes, or the frame itself for passes working on what is drawn.
+    pub reads: Vec<String>,
+    pub time: Duration,
+}
+
+/// The passes of one frame in the order they ran, from `Command::dump_frame_graph`. Helps to
+/// see where the time goes once reflections, insets and post effects stack up.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameGraph {
+    pub passes: Vec<FramePass>,
+}
+impl FrameGraph {
+    pub(crate) fn record(
+        &mut self,
+        name: &str,
+        target: &str,
+        size: (u32, u32),
+        reads: &[&str],
+        start: Instant,
+    ) {
+        self.passes.push(FramePass {
+            name: name.to_owned(),
+            target: target.to_owned(),
+            size,
+            reads: reads.iter().map(|read| read.to_string()).collect(),
+            time: start.elapsed(),
+        });
+    }
+    pub fn total(&self) -> Duration {
+        self.passes.iter().map(|pass| pass.time).sum()
+    }
+    /// One line per pass, then the total.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for pass in &self.passes {
+            let _ = write!(
+                text,
+                "{:<18} {:>8.3} ms  -> {} {}x{}",
+                pass.name,
+                pass.time.as_secs_f64() * 1000.0,
+                pass.target,
+                pass.size.0,
+                pass.size.1
+            );
+            if !pass.reads.is_empty() {
+                let _ = write!(text, "  reads {}", pass.reads.join(", "));
+            }
+            text.push('\n');
+        }
+        let _ = write!(
+            text,
+            "{} passes {:.3} ms",
+            self.passes.len(),
+            self.total().as_secs_f64() * 1000.0
+        );
+        text
+    }
+    /// Graphviz source with passes as boxes and targets as ellipses, `dot -Tsvg` draws it.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph frame {\n    rankdir=LR;\n");
+        let mut targets = Vec::new();
+        for pass in &self.passes {
+            for target in pass.reads.iter().chain([&pass.target]) {
+                if !targets.contains(target) {
+                    targets.push(target.clone());
+                }
+            }
+        }
+        for target in &targets {
+            let _ = writeln!(
+                dot,
+                "    \"target {}\" [label=\"{}\" shape=ellipse];",
+                target, target
+            );
+        }
+        for (index, pass) in self.passes.iter().enumerate() {
+            let ms = pass.time.as_secs_f64() * 1000.0;
+            let _ = writeln!(
+                dot,
+                "    pass{} [label=\"{}\\n{:.3} ms\" shape=box];",
+                index, pass.name, ms
+            );
+            for read in &pass.reads {
+                let _ = writeln!(dot, "    \"target {}\" -> pass{};", read, index);
+            }
+            let _ = writeln!(dot, "    pass{} -> \"target {}\";", index, pass.target);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
@@ -1,26 +1,69 @@
-use crate::renderer::{random_color, Color};
+use crate::determinism::deterministic_rng;
+use crate::error::SoftRastError;
+use crate::renderer::{Color, random_color};
 use image::{DynamicImage, GenericImageView, Rgba};
-use nalgebra::{Isometry3, Matrix4, Point2, Point3, Point4, Vector2, Vector3};
-use std::fs::read_to_string;
-use std::ops::RangeInclusive;
+use nalgebra::{Matrix3, Matrix4, Point2, Point3, Point4, Vector2, Vector3};
 use rand::SeedableRng;
 use rand_xorshift::XorShiftRng;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::ops::{Range, RangeInclusive};
+use std::sync::Arc;
 
+/// Pixels shared between clones, so materials sampling the same image with different
+/// `Sampler`s don't copy it.
 #[derive(Debug, Clone)]
 pub struct Texture {
-    pub texture: DynamicImage,
+    pub texture: Arc<DynamicImage>,
+    /// Successively halved copies of `texture` down to 1x1, empty until `with_mipmaps`.
+    pub mips: Arc<Vec<DynamicImage>>,
 }
 impl Texture {
-    pub fn new(path: &str) -> Option<Texture> {
-        match image::open(path) {
-            Ok(image) => Some(Texture {
-                texture: image.to_owned(),
-            }),
-            Err(err) => {
-                println!("{}", err);
-                None
-            }
+    pub fn from_image(image: DynamicImage) -> Self {
+        Self {
+            texture: Arc::new(image),
+            mips: Arc::new(vec![]),
+        }
+    }
+    /// Builds the mip chain used by `sample_grad` and `sample_anisotropic`.
+    pub fn with_mipmaps(mut self) -> Self {
+        let mut mips = vec![];
+        let mut level = (*self.texture).clone();
+        while level.width() > 1 || level.height() > 1 {
+            let (width, height) = ((level.width() / 2).max(1), (level.height() / 2).max(1));
+            level = level.resize_exact(width, height, image::imageops::FilterType::Triangle);
+            mips.push(level.clone());
         }
+        self.mips = Arc::new(mips);
+        self
+    }
+    /// Loads anything `image` reads, including .hdr and .exr kept as float, plus DDS and KTX2
+    /// containers (uncompressed or BC1/BC2/BC3, decoded here) by their extension.
+    pub fn new(path: &str) -> Result<Texture, SoftRastError> {
+        let extension = std::path::Path::new(path)
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let image_error = |message: String| SoftRastError::Image {
+            path: path.to_owned(),
+            message,
+        };
+        let image = match extension.as_deref() {
+            Some(container @ ("dds" | "ktx2")) => {
+                let data = std::fs::read(path).map_err(|source| SoftRastError::Io {
+                    path: path.to_owned(),
+                    source,
+                })?;
+                let image = if container == "dds" {
+                    crate::texture_formats::load_dds(&data)
+                } else {
+                    crate::texture_formats::load_ktx2(&data)
+                };
+                image.map_err(image_error)?
+            }
+            _ => image::open(path).map_err(|err| SoftRastError::from_image(path, err))?,
+        };
+        Ok(Texture::from_image(image))
     }
 }
 impl Texture {
@@ -30,160 +73,686 @@ impl Texture {
         let x = (tex_coord.x.clamp(0.0, 1.0) * (width as f32 - 1.0)).round() as u32;
         let y = ((1.0 - tex_coord.y.clamp(0.0, 1.0)) * (height as f32 - 1.0)).round() as u32;
 
-        if (0..self.texture.width()).contains(&x) && (0..self.texture.height()).contains(&y) {
-            let Rgba([r, g, b, a]) = self.texture.get_pixel(x, y);
-            Some(Color::from_rgba(r, g, b, a))
+        if !(0..self.texture.width()).contains(&x) || !(0..self.texture.height()).contains(&y) {
+            return None;
+        }
+        Some(texel(&self.texture, x, y))
+    }
+    /// Blends the four texels around the UV of the full size texture by the fractional
+    /// distances to their centers, no mip chain needed.
+    pub fn sample_bilinear(&self, tex_coord: &Point2<f32>) -> Option<Color> {
+        if self.texture.width() == 0 || self.texture.height() == 0 {
+            return None;
+        }
+        Some(self.bilinear(0, tex_coord))
+    }
+    fn level(&self, level: usize) -> &DynamicImage {
+        match level {
+            0 => &self.texture,
+            level => &self.mips[(level - 1).min(self.mips.len() - 1)],
+        }
+    }
+    fn bilinear(&self, level: usize, uv: &Point2<f32>) -> Color {
+        let image = self.level(level);
+        let (width, height) = (image.width(), image.height());
+        let x = uv.x.clamp(0.0, 1.0) * (width as f32 - 1.0);
+        let y = (1.0 - uv.y.clamp(0.0, 1.0)) * (height as f32 - 1.0);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = texel(image, x0, y0).lerp(&texel(image, x1, y0), fx);
+        let bottom = texel(image, x0, y1).lerp(&texel(image, x1, y1), fx);
+        top.lerp(&bottom, fy)
+    }
+    /// Blends the two mip levels around `lod`, 0 being the full size texture.
+    fn trilinear(&self, uv: &Point2<f32>, lod: f32) -> Color {
+        let lod = lod.clamp(0.0, self.mips.len() as f32);
+        let (level, t) = (lod.floor() as usize, lod.fract());
+        let color = self.bilinear(level, uv);
+        if t > 0.0 {
+            color.lerp(&self.bilinear(level + 1, uv), t)
+        } else {
+            color
+        }
+    }
+    /// UV derivatives in texels of the full size texture.
+    fn texel_gradients(
+        &self,
+        ddx: &Vector2<f32>,
+        ddy: &Vector2<f32>,
+    ) -> (Vector2<f32>, Vector2<f32>) {
+        let size = Vector2::new(self.texture.width() as f32, self.texture.height() as f32);
+        (ddx.component_mul(&size), ddy.component_mul(&size))
+    }
+    /// Trilinear sample with the mip level picked from the screen space UV derivatives, the
+    /// same as `sample` without mipmaps.
+    pub fn sample_grad(
+        &self,
+        uv: &Point2<f32>,
+        ddx: &Vector2<f32>,
+        ddy: &Vector2<f32>,
+    ) -> Option<Color> {
+        if self.mips.is_empty() {
+            return self.sample(uv);
+        }
+        let (dx, dy) = self.texel_gradients(ddx, ddy);
+        let footprint = dx.magnitude().max(dy.magnitude()).max(1e-6);
+        Some(self.trilinear(uv, footprint.log2()))
+    }
+    /// Cheap anisotropic filtering: up to `max_taps` trilinear samples spread along the longer
+    /// UV derivative, each from the sharper mip level that fits the shorter one, so surfaces
+    /// seen at grazing angles stay sharp across their width.
+    pub fn sample_anisotropic(
+        &self,
+        uv: &Point2<f32>,
+        ddx: &Vector2<f32>,
+        ddy: &Vector2<f32>,
+        max_taps: u32,
+    ) -> Option<Color> {
+        if self.mips.is_empty() {
+            return self.sample(uv);
+        }
+        let (dx, dy) = self.texel_gradients(ddx, ddy);
+        let (major, minor, axis) = if dx.magnitude() >= dy.magnitude() {
+            (dx.magnitude(), dy.magnitude(), ddx)
         } else {
-            None
+            (dy.magnitude(), dx.magnitude(), ddy)
+        };
+        let taps = (major / minor.max(1e-6))
+            .ceil()
+            .clamp(1.0, max_taps.max(1) as f32);
+        let lod = (major / taps).max(1e-6).log2();
+        let (mut r, mut g, mut b, mut a) = (0.0, 0.0, 0.0, 0.0);
+        for i in 0..taps as u32 {
+            let offset = (i as f32 + 0.5) / taps - 0.5;
+            let color = self.trilinear(&(uv + axis * offset), lod);
+            (r, g, b, a) = (r + color.r, g + color.g, b + color.b, a + color.a);
+        }
+        Some(Color::new(r / taps, g / taps, b / taps, a / taps))
+    }
+}
+
+fn texel(image: &DynamicImage, x: u32, y: u32) -> Color {
+    // Float images keep values above 1 for HDR environment maps
+    match image {
+        DynamicImage::ImageRgb32F(image) => {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            Color::new(r, g, b, 1.0)
+        }
+        DynamicImage::ImageRgba32F(image) => {
+            let [r, g, b, a] = image.get_pixel(x, y).0;
+            Color::new(r, g, b, a)
+        }
+        _ => {
+            let Rgba([r, g, b, a]) = image.get_pixel(x, y);
+            Color::from_rgba(r, g, b, a)
         }
     }
 }
 
+/// A run of triangles in `Model::vertices`, or in `Model::indices` for welded models, drawn
+/// with the entity material in `material_index`.
+#[derive(Clone, Debug)]
+pub struct SubMesh {
+    pub range: Range<usize>,
+    pub material_index: usize,
+}
+
 #[derive(Clone, Debug)]
 pub struct Model {
     pub vertices: Vec<Vertex>,
+    /// Empty for a plain triangle list, otherwise every three indices into `vertices` are a
+    /// triangle, see `weld`.
+    pub indices: Vec<u32>,
+    pub sub_meshes: Vec<SubMesh>,
+    pub material_names: Vec<String>,
 }
+#[allow(unused)]
 impl Model {
     pub fn from_vertices(vertices: &[Vertex]) -> Model {
         Self {
             vertices: vertices.to_vec(),
+            indices: vec![],
+            sub_meshes: vec![],
+            material_names: vec![],
+        }
+    }
+    pub fn from_sub_meshes(vertices: &[Vertex], sub_meshes: &[SubMesh]) -> Model {
+        Self {
+            vertices: vertices.to_vec(),
+            indices: vec![],
+            sub_meshes: sub_meshes.to_vec(),
+            material_names: vec![],
+        }
+    }
+    /// Axis aligned bounds of the vertex positions as (min, max).
+    pub fn bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        let mut min = Point3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Point3::new(f32::MIN, f32::MIN, f32::MIN);
+        for vertex in &self.vertices {
+            let p = vertex.position.xyz();
+            min = min.inf(&p);
+            max = max.sup(&p);
+        }
+        if self.vertices.is_empty() {
+            (Point3::origin(), Point3::origin())
+        } else {
+            (min, max)
+        }
+    }
+    pub fn triangle_count(&self) -> usize {
+        if self.indices.is_empty() {
+            self.vertices.len() / 3
+        } else {
+            self.indices.len() / 3
+        }
+    }
+    /// Three vertices per triangle, expanding the indices of welded models.
+    pub fn triangle_vertices(&self) -> Cow<'_, [Vertex]> {
+        if self.indices.is_empty() {
+            Cow::Borrowed(&self.vertices)
+        } else {
+            Cow::Owned(
+                self.indices
+                    .iter()
+                    .map(|index| self.vertices[*index as usize])
+                    .collect(),
+            )
+        }
+    }
+    /// The model as a plain triangle list, undoing `weld`.
+    pub fn unwelded(&self) -> Model {
+        Model {
+            vertices: self.triangle_vertices().into_owned(),
+            indices: vec![],
+            sub_meshes: self.sub_meshes.clone(),
+            material_names: self.material_names.clone(),
+        }
+    }
+    /// Merges vertices whose position, normal, UV and color match within `epsilon` and
+    /// indexes the triangles into the shared vertices. Values are snapped to an `epsilon`
+    /// grid for the comparison, so near matches that straddle a grid line stay separate.
+    pub fn weld(&self, epsilon: f32) -> Model {
+        let model = self.unwelded();
+        let epsilon = epsilon.max(f32::EPSILON);
+        let snap = |value: f32| (value / epsilon).round() as i64;
+        let mut lookup = HashMap::new();
+        let mut vertices = vec![];
+        let indices = model
+            .vertices
+            .iter()
+            .map(|vertex| {
+                let p = vertex.position;
+                let key = (
+                    [p.x, p.y, p.z].map(snap),
+                    vertex.normal.map(|n| [n.x, n.y, n.z].map(snap)),
+                    vertex.uv.map(|uv| [uv.x, uv.y].map(snap)),
+                    vertex.color.map(|c| [c.r, c.g, c.b, c.a].map(snap)),
+                );
+                *lookup.entry(key).or_insert_with(|| {
+                    vertices.push(*vertex);
+                    vertices.len() as u32 - 1
+                })
+            })
+            .collect();
+        Model {
+            vertices,
+            indices,
+            sub_meshes: model.sub_meshes,
+            material_names: model.material_names,
+        }
+    }
+    /// Material slot used by the vertex at `index`, vertices outside any sub mesh use slot 0.
+    pub fn material_index(&self, index: usize) -> usize {
+        self.sub_meshes
+            .iter()
+            .find(|sub_mesh| sub_mesh.range.contains(&index))
+            .map(|sub_mesh| sub_mesh.material_index)
+            .unwrap_or(0)
+    }
+    /// Splits every triangle into four at its edge midpoints, `levels` times over.
+    /// Sub meshes keep their materials.
+    pub fn subdivided(&self, levels: u32) -> Model {
+        let mut model = self.unwelded();
+        for _ in 0..levels {
+            let mut vertices = Vec::with_capacity(model.vertices.len() * 4);
+            for t in model.vertices.chunks_exact(3) {
+                let ab = t[0].lerp(&t[1], 0.5);
+                let bc = t[1].lerp(&t[2], 0.5);
+                let ca = t[2].lerp(&t[0], 0.5);
+                vertices.extend_from_slice(&[t[0], ab, ca, ab, t[1], bc, ca, bc, t[2], ab, bc, ca]);
+            }
+            model.vertices = vertices;
+            for sub_mesh in &mut model.sub_meshes {
+                sub_mesh.range = sub_mesh.range.start * 4..sub_mesh.range.end * 4;
+            }
+        }
+        model
+    }
+    /// Bakes a transform into the positions and normals. Mirroring transforms also reverse
+    /// the triangle winding so front faces stay front faces.
+    pub fn transformed(&self, transform: &Matrix4<f32>) -> Model {
+        let linear = transform.fixed_view::<3, 3>(0, 0).into_owned();
+        let normal_mat = linear
+            .try_inverse()
+            .map_or(linear, |inverse| inverse.transpose());
+        let mut model = self.clone();
+        for vertex in &mut model.vertices {
+            let position = transform.transform_point(&vertex.position.xyz());
+            vertex.position = position.to_homogeneous().into();
+            vertex.normal = vertex
+                .normal
+                .map(|normal| (normal_mat * normal).try_normalize(1e-12).unwrap_or(normal));
+        }
+        if linear.determinant() < 0.0 {
+            if model.indices.is_empty() {
+                for triangle in model.vertices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            } else {
+                for triangle in model.indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+            }
+        }
+        model
+    }
+    /// Bakes each model's transform and concatenates them into one model. Material slots are
+    /// renumbered so each part keeps its own: the first part uses slots from 0, the next
+    /// starts after the highest slot of the first, and so on. Give the merged entity its
+    /// materials in that order.
+    pub fn merge(parts: &[(Model, Matrix4<f32>)]) -> Model {
+        let mut vertices = vec![];
+        let mut sub_meshes = vec![];
+        let mut material_names = vec![];
+        let mut first_slot = 0;
+        for (model, transform) in parts {
+            let model = model.transformed(transform).unwelded();
+            let start = vertices.len();
+            let mut slots = 1;
+            if model.sub_meshes.is_empty() {
+                push_sub_mesh(
+                    &mut sub_meshes,
+                    start..start + model.vertices.len(),
+                    first_slot,
+                );
+            }
+            // Vertices outside any sub mesh use slot 0 of their part
+            let mut covered = 0;
+            for sub_mesh in &model.sub_meshes {
+                push_sub_mesh(
+                    &mut sub_meshes,
+                    start + covered..start + sub_mesh.range.start,
+                    first_slot,
+                );
+                let range = start + sub_mesh.range.start..start + sub_mesh.range.end;
+                push_sub_mesh(&mut sub_meshes, range, first_slot + sub_mesh.material_index);
+                covered = covered.max(sub_mesh.range.end);
+                slots = slots.max(sub_mesh.material_index + 1);
+            }
+            if !model.sub_meshes.is_empty() {
+                push_sub_mesh(
+                    &mut sub_meshes,
+                    start + covered..start + model.vertices.len(),
+                    first_slot,
+                );
+            }
+            slots = slots.max(model.material_names.len());
+            material_names.extend(
+                (0..slots).map(|slot| model.material_names.get(slot).cloned().unwrap_or_default()),
+            );
+            vertices.extend_from_slice(&model.vertices);
+            first_slot += slots;
         }
+        Model {
+            vertices,
+            indices: vec![],
+            sub_meshes,
+            material_names,
+        }
+    }
+}
+/// Appends `range` to the last sub mesh if it continues it with the same material.
+pub fn push_sub_mesh(sub_meshes: &mut Vec<SubMesh>, range: Range<usize>, material_index: usize) {
+    if range.is_empty() {
+        return;
     }
+    if let Some(last) = sub_meshes.last_mut()
+        && last.material_index == material_index
+        && last.range.end == range.start
+    {
+        last.range.end = range.end;
+        return;
+    }
+    sub_meshes.push(SubMesh {
+        range,
+        material_index,
+    });
 }
-pub fn load_model(file: &str) -> Model {
+/// Loads a Wavefront OBJ mesh, triangles and quads with `usemtl` material slots.
+pub fn load_model(path: &str) -> Result<Model, SoftRastError> {
     let color = Color::new(1.0, 1.0, 1.0, 1.0);
-    let file = match read_to_string(file) {
-        Ok(file) => file,
-        Err(err) => panic!("{}", err),
+    let file = read_to_string(path).map_err(|source| SoftRastError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+    let parse_error = |line: usize, message: String| SoftRastError::Parse {
+        path: path.to_owned(),
+        line: line + 1,
+        message,
+    };
+    let floats = |line: usize, numbers: &str| {
+        numbers
+            .split_whitespace()
+            .map(|n| {
+                n.parse::<f32>()
+                    .map_err(|_| parse_error(line, format!("bad number '{}'", n)))
+            })
+            .collect::<Result<Vec<_>, _>>()
     };
     let mut vertice_positions = Vec::new();
+    let mut vertice_colors = Vec::new();
     let mut vertice_normals = Vec::new();
     let mut vertice_uvs = Vec::new();
 
     let mut faces = Vec::new();
     let mut vertices = Vec::new();
+    let mut material_names = Vec::<String>::new();
+    let mut sub_meshes = Vec::new();
+    let mut material = 0;
 
-    for line in file.lines() {
+    for (line_number, line) in file.lines().enumerate() {
+        if line.starts_with("usemtl ") {
+            let name = line[6..].trim();
+            material = match material_names.iter().position(|n| n == name) {
+                Some(index) => index,
+                None => {
+                    material_names.push(name.to_string());
+                    material_names.len() - 1
+                }
+            };
+        }
         if line.starts_with("v ") {
-            let numbers = line[1..]
-                .trim()
-                .split_whitespace()
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<f32>>();
-            if numbers.len() == 3 {
+            let numbers = floats(line_number, &line[1..])?;
+            if numbers.len() >= 3 {
                 vertice_positions.push(Point3::new(numbers[0], numbers[1], numbers[2]));
+                // The `v x y z r g b` extension, colors in 0..1 or, from some exporters, 0..255
+                vertice_colors.push(match numbers[..] {
+                    [_, _, _, r, g, b] if r > 1.0 || g > 1.0 || b > 1.0 => {
+                        Some(Color::new(r / 255.0, g / 255.0, b / 255.0, 1.0))
+                    }
+                    [_, _, _, r, g, b] => Some(Color::new(r, g, b, 1.0)),
+                    _ => None,
+                });
             }
         }
         if line.starts_with("f ") {
             let numbers = line[1..]
-                .trim()
                 .split_whitespace()
                 .map(|n| {
                     let mut split_line = n.split('/');
-                    let position = split_line.next().unwrap().parse::<usize>().unwrap();
-                    let uv = split_line.next().unwrap().parse::<usize>().ok();
-                    let normal = split_line.next().unwrap().parse::<usize>().ok();
-                    (position, uv, normal)
+                    let position = split_line
+                        .next()
+                        .and_then(|index| index.parse::<usize>().ok())
+                        .filter(|index| (1..=vertice_positions.len()).contains(index))
+                        .ok_or_else(|| {
+                            parse_error(line_number, format!("bad vertex index '{}'", n))
+                        })?;
+                    let uv = split_line
+                        .next()
+                        .and_then(|index| index.parse::<usize>().ok());
+                    let normal = split_line
+                        .next()
+                        .and_then(|index| index.parse::<usize>().ok());
+                    Ok((position, uv, normal))
                 })
-                .collect::<Vec<_>>();
-            faces.push(numbers.as_slice().to_owned());
+                .collect::<Result<Vec<_>, SoftRastError>>()?;
+            faces.push((material, numbers.as_slice().to_owned()));
         }
         if line.starts_with("vn ") {
-            let numbers = line[2..]
-                .trim()
-                .split_whitespace()
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<f32>>();
+            let numbers = floats(line_number, &line[2..])?;
             if numbers.len() == 3 {
                 vertice_normals.push(Vector3::new(numbers[0], numbers[1], numbers[2]));
             }
         }
         if line.starts_with("vt ") {
-            let numbers = line[2..]
-                .trim()
-                .split_whitespace()
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<f32>>();
+            let numbers = floats(line_number, &line[2..])?;
             if numbers.len() == 2 {
                 vertice_uvs.push(Vector2::new(numbers[0], numbers[1]));
             }
         }
     }
 
-    for face in faces {
+    for (material, face) in faces {
+        let start = vertices.len();
+        let corner = |index: usize| {
+            let face = &face[index];
+            let color = vertice_colors
+                .get(face.0 - 1)
+                .copied()
+                .flatten()
+                .unwrap_or(color);
+            vertex_from_face(
+                face,
+                &vertice_positions,
+                &vertice_uvs,
+                &vertice_normals,
+                Some(color),
+            )
+        };
         match face.len() {
-            3 => {
-                vertices.push(vertex_from_face(
-                    &face[0],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[1],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[2],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
+            3 => vertices.extend([corner(0), corner(1), corner(2)]),
+            4 => vertices.extend([
+                corner(0),
+                corner(1),
+                corner(2),
+                corner(0),
+                corner(2),
+                corner(3),
+            ]),
+            n => eprintln!("Unsupported face {} vertices", n),
+        }
+        push_sub_mesh(&mut sub_meshes, start..vertices.len(), material);
+    }
+    let mut model = Model::from_sub_meshes(&vertices, &sub_meshes);
+    model.material_names = material_names;
+    Ok(model)
+}
+
+/// Loads a PLY mesh, ASCII or binary. Reads positions, normals, UVs and per-vertex colors,
+/// polygon faces are triangulated as fans.
+pub fn load_ply(file: &str) -> Result<Model, String> {
+    let data = std::fs::read(file).map_err(|err| format!("{}: {}", file, err))?;
+    let header_end = data
+        .windows(11)
+        .position(|window| window == b"end_header\n" || window == b"end_header\r")
+        .ok_or_else(|| format!("{}: missing end_header", file))?;
+    let header = String::from_utf8_lossy(&data[..header_end]);
+    let body_start = header_end
+        + data[header_end..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .unwrap_or(0)
+        + 1;
+
+    let mut format = "ascii";
+    let mut elements: Vec<(String, usize, Vec<PlyProperty>)> = vec![];
+    for line in header.lines() {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        match words[..] {
+            ["format", name, ..] => format = name,
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("{}: bad element count", file))?;
+                elements.push((name.to_owned(), count, vec![]));
             }
-            4 => {
-                vertices.push(vertex_from_face(
-                    &face[0],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[1],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[2],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-
-                vertices.push(vertex_from_face(
-                    &face[0],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[2],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
-                vertices.push(vertex_from_face(
-                    &face[3],
-                    &vertice_positions,
-                    &vertice_uvs,
-                    &vertice_normals,
-                    Some(color),
-                ));
+            ["property", "list", count, item, name] => {
+                if let Some(element) = elements.last_mut() {
+                    element.2.push(PlyProperty::List(
+                        name.to_owned(),
+                        count.to_owned(),
+                        item.to_owned(),
+                    ));
+                }
+            }
+            ["property", kind, name] => {
+                if let Some(element) = elements.last_mut() {
+                    element
+                        .2
+                        .push(PlyProperty::Scalar(name.to_owned(), kind.to_owned()));
+                }
+            }
+            _ => {}
+        }
+    }
+    let text = String::from_utf8_lossy(&data[body_start..]);
+    let mut body = match format {
+        "ascii" => PlyBody::Ascii(text.split_ascii_whitespace()),
+        "binary_little_endian" => PlyBody::Binary(&data[body_start..], false),
+        "binary_big_endian" => PlyBody::Binary(&data[body_start..], true),
+        other => return Err(format!("{}: unsupported format {}", file, other)),
+    };
+
+    let mut points = vec![];
+    let mut faces = vec![];
+    for (name, count, properties) in &elements {
+        for _ in 0..*count {
+            let mut values = HashMap::new();
+            let mut indices = vec![];
+            for property in properties {
+                match property {
+                    PlyProperty::Scalar(name, kind) => {
+                        let value = body
+                            .read(kind)
+                            .ok_or_else(|| format!("{}: truncated", file))?;
+                        let scale = if kind.contains("char") || kind.contains("int8") {
+                            1.0 / 255.0
+                        } else {
+                            1.0
+                        };
+                        values.insert(name.as_str(), (value, scale));
+                    }
+                    PlyProperty::List(list, count, item) => {
+                        let length = body
+                            .read(count)
+                            .ok_or_else(|| format!("{}: truncated", file))?;
+                        for _ in 0..length as usize {
+                            let index = body
+                                .read(item)
+                                .ok_or_else(|| format!("{}: truncated", file))?;
+                            if list == "vertex_indices" || list == "vertex_index" {
+                                indices.push(index as usize);
+                            }
+                        }
+                    }
+                }
+            }
+            match name.as_str() {
+                "vertex" => {
+                    let get = |names: &[&str]| {
+                        names
+                            .iter()
+                            .find_map(|name| values.get(name))
+                            .map(|(value, _)| *value as f32)
+                    };
+                    // Colors as stored, normalized when they are bytes
+                    let channel = |name: &str| {
+                        values
+                            .get(name)
+                            .map(|(value, scale)| (*value * *scale) as f32)
+                    };
+                    let mut vertex = Vertex::new(&Point3::new(
+                        get(&["x"]).unwrap_or(0.0),
+                        get(&["y"]).unwrap_or(0.0),
+                        get(&["z"]).unwrap_or(0.0),
+                    ));
+                    if let (Some(x), Some(y), Some(z)) = (get(&["nx"]), get(&["ny"]), get(&["nz"]))
+                    {
+                        vertex = vertex.with_normal(Vector3::new(x, y, z));
+                    }
+                    if let (Some(u), Some(v)) =
+                        (get(&["u", "s", "texture_u"]), get(&["v", "t", "texture_v"]))
+                    {
+                        vertex = vertex.with_uv(Vector2::new(u, v));
+                    }
+                    if let (Some(r), Some(g), Some(b)) =
+                        (channel("red"), channel("green"), channel("blue"))
+                    {
+                        vertex.color = Some(Color::new(r, g, b, channel("alpha").unwrap_or(1.0)));
+                    }
+                    points.push(vertex);
+                }
+                "face" => faces.push(indices),
+                _ => {}
+            }
+        }
+    }
+
+    let mut vertices = vec![];
+    for face in faces {
+        for i in 1..face.len().saturating_sub(1) {
+            for index in [face[0], face[i], face[i + 1]] {
+                vertices.push(
+                    *points
+                        .get(index)
+                        .ok_or_else(|| format!("{}: bad vertex index {}", file, index))?,
+                );
+            }
+        }
+    }
+    Ok(Model::from_vertices(&vertices))
+}
+
+enum PlyProperty {
+    /// Name and type.
+    Scalar(String, String),
+    /// Name, count type and item type.
+    List(String, String, String),
+}
+
+enum PlyBody<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    /// Remaining bytes and whether they are big endian.
+    Binary(&'a [u8], bool),
+}
+impl PlyBody<'_> {
+    fn read(&mut self, kind: &str) -> Option<f64> {
+        match self {
+            PlyBody::Ascii(words) => words.next()?.parse().ok(),
+            PlyBody::Binary(data, big_endian) => {
+                let size = match kind {
+                    "char" | "int8" | "uchar" | "uint8" => 1,
+                    "short" | "int16" | "ushort" | "uint16" => 2,
+                    "int" | "int32" | "uint" | "uint32" | "float" | "float32" => 4,
+                    "double" | "float64" => 8,
+                    _ => return None,
+                };
+                let mut bytes = [0u8; 8];
+                bytes[..size].copy_from_slice(data.get(..size)?);
+                *data = &data[size..];
+                if *big_endian {
+                    bytes[..size].reverse();
+                }
+                Some(match kind {
+                    "char" | "int8" => bytes[0] as i8 as f64,
+                    "uchar" | "uint8" => bytes[0] as f64,
+                    "short" | "int16" => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    "ushort" | "uint16" => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+                    "int" | "int32" => {
+                        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                    }
+                    "uint" | "uint32" => {
+                        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                    }
+                    "float" | "float32" => {
+                        f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+                    }
+                    _ => f64::from_le_bytes(bytes),
+                })
             }
-            n => eprintln!("Unsupported face {} vertices", n),
         }
     }
-    Model::from_vertices(&vertices)
 }
 
 #[inline(always)]
@@ -195,11 +764,12 @@ pub fn vertex_from_face(
     color: Option<Color>,
 ) -> Vertex {
     let mut vertex = Vertex::new(&pos[face.0 - 1]);
-    if let Some(index) = face.1 {
-        vertex = vertex.with_uv(uv[index - 1])
+    // Attribute indices past the lists are ignored rather than trusted
+    if let Some(uv) = face.1.and_then(|index| uv.get(index.wrapping_sub(1))) {
+        vertex = vertex.with_uv(*uv)
     }
-    if let Some(index) = face.2 {
-        vertex = vertex.with_normal(norm[index - 1])
+    if let Some(normal) = face.2.and_then(|index| norm.get(index.wrapping_sub(1))) {
+        vertex = vertex.with_normal(*normal)
     }
     if let Some(color) = color {
         vertex.color = Some(color);
@@ -207,6 +777,8 @@ pub fn vertex_from_face(
     vertex
 }
 
+/// Screen space box around some vertices, clamped so the minimum is never below zero and the
+/// maximum never past the screen size.
 #[derive(Debug)]
 pub struct Bounds {
     pub min_x: f32,
@@ -223,10 +795,10 @@ impl Bounds {
                 min_y: 0.0,
                 max_x: 0.0,
                 max_y: 0.0,
-            }
+            };
         }
 
-        let first = points[0].clone();
+        let first = points[0];
         let mut min_x = first.position.x;
         let mut min_y = first.position.y;
         let mut max_x = first.position.x;
@@ -238,7 +810,7 @@ impl Bounds {
             max_x = max_x.max(point.position.x);
             max_y = max_y.max(point.position.y);
         }
-        
+
         Self {
             min_x: min_x.max(0.0),
             min_y: min_y.max(0.0),
@@ -259,11 +831,159 @@ impl Bounds {
 //     let edge3 = edge_cross(&triangle[1].position.xy(), &triangle[0].position.xy(), p);
 //     edge1 >= 0.0 && edge2 >= 0.0 && edge3 >= 0.0
 // }
+/// A plane as `normal · p + distance = 0`, points with a positive distance are in front.
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+#[allow(unused)]
+impl Plane {
+    pub fn new(normal: Vector3<f32>, distance: f32) -> Self {
+        Self { normal, distance }
+    }
+    pub fn from_point_normal(point: &Point3<f32>, normal: &Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        Self {
+            normal,
+            distance: -normal.dot(&point.coords),
+        }
+    }
+    pub fn signed_distance(&self, point: &Point3<f32>) -> f32 {
+        self.normal.dot(&point.coords) + self.distance
+    }
+    /// The plane moved by a rigid transform such as a view matrix.
+    pub fn transformed(&self, matrix: &Matrix4<f32>) -> Self {
+        let point = matrix.transform_point(&Point3::from(self.normal * -self.distance));
+        Self::from_point_normal(&point, &matrix.transform_vector(&self.normal))
+    }
+}
+#[derive(Debug, Copy, Clone)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+#[allow(unused)]
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.normalize(),
+        }
+    }
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+    /// Distance along the ray to the plane, if it is hit in front of the origin.
+    pub fn intersect_plane(&self, plane: &Plane) -> Option<f32> {
+        let denominator = plane.normal.dot(&self.direction);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let t = -plane.signed_distance(&self.origin) / denominator;
+        (t >= 0.0).then_some(t)
+    }
+    /// Distance along the ray to the first hit with a sphere, zero when starting inside it.
+    pub fn intersect_sphere(&self, center: &Point3<f32>, radius: f32) -> Option<f32> {
+        let to_center = center - self.origin;
+        let projection = to_center.dot(&self.direction);
+        let distance_squared = to_center.magnitude_squared() - projection * projection;
+        if distance_squared > radius * radius {
+            return None;
+        }
+        let half_chord = (radius * radius - distance_squared).sqrt();
+        let (near, far) = (projection - half_chord, projection + half_chord);
+        if far < 0.0 { None } else { Some(near.max(0.0)) }
+    }
+    /// Distance along the ray to a triangle, both sides count (Möller–Trumbore).
+    pub fn intersect_triangle(
+        &self,
+        a: &Point3<f32>,
+        b: &Point3<f32>,
+        c: &Point3<f32>,
+    ) -> Option<f32> {
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let p = self.direction.cross(&edge2);
+        let determinant = edge1.dot(&p);
+        if determinant.abs() < 1e-8 {
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+        let to_origin = self.origin - a;
+        let u = to_origin.dot(&p) * inverse;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let q = to_origin.cross(&edge1);
+        let v = self.direction.dot(&q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = edge2.dot(&q) * inverse;
+        (t >= 0.0).then_some(t)
+    }
+    /// Entry distance into an axis aligned box, zero when starting inside it.
+    pub fn intersect_aabb(&self, min: &Point3<f32>, max: &Point3<f32>) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut t0 = (min[axis] - self.origin[axis]) * inverse;
+            let mut t1 = (max[axis] - self.origin[axis]) * inverse;
+            if t0 > t1 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            near = near.max(t0);
+            far = far.min(t1);
+            if near > far {
+                return None;
+            }
+        }
+        Some(near)
+    }
+    /// Parameters `(t, s)` of the closest points between the ray and the line
+    /// `point + s * direction`.
+    pub fn closest_to_line(&self, point: &Point3<f32>, direction: &Vector3<f32>) -> (f32, f32) {
+        let w = self.origin - point;
+        let a = self.direction.dot(&self.direction);
+        let b = self.direction.dot(direction);
+        let c = direction.dot(direction);
+        let d = self.direction.dot(&w);
+        let e = direction.dot(&w);
+        let denominator = a * c - b * b;
+        if denominator.abs() < 1e-6 {
+            return (0.0, e / c);
+        }
+        ((b * e - c * d) / denominator, (a * e - b * d) / denominator)
+    }
+}
+/// Keeps the part of the polygon where `distance` is positive.
+pub fn clip_polygon(polygon: &[Vertex], distance: impl Fn(&Vertex) -> f32) -> Vec<Vertex> {
+    let mut output = Vec::with_capacity(polygon.len() + 2);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        let d_current = distance(current);
+        let d_next = distance(next);
+        if d_current >= 0.0 {
+            output.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            let t = d_current / (d_current - d_next);
+            output.push(current.lerp(next, t));
+        }
+    }
+    output
+}
+/// Twice the signed area of `a`, `b`, `p`, zero on the line through `a` and `b`, and always
+/// `-2.0 * signed_area(a, b, p)`.
 pub fn edge_cross(a: &Point2<f32>, b: &Point2<f32>, p: &Point2<f32>) -> f32 {
     let ab = b - a;
     let ap = p - a;
     ab.x * ap.y - ab.y * ap.x
 }
+/// Weights of the corners at screen position `p`. They sum to one and weighting the corners
+/// by them gives back `p`, degenerate triangles get a third each.
 pub fn triangle_barycentric(triangle: &[Vertex], p: &Point2<f32>) -> Vector3<f32> {
     let a = triangle[0].position.xy();
     let b = triangle[1].position.xy();
@@ -272,13 +992,16 @@ pub fn triangle_barycentric(triangle: &[Vertex], p: &Point2<f32>) -> Vector3<f32
     let area_bcp = signed_area(&b, &c, p);
     let area_cap = signed_area(&c, &a, p);
 
-    let inv_area_sum = 1.0 / (area_abp + area_bcp + area_cap);
+    // Degenerate triangles have no meaningful weights, share them out instead of dividing by 0
+    let area_sum = area_abp + area_bcp + area_cap;
+    if !area_sum.is_finite() || area_sum.abs() < f32::EPSILON {
+        return Vector3::repeat(1.0 / 3.0);
+    }
+    let inv_area_sum = 1.0 / area_sum;
     let weight_a = area_bcp * inv_area_sum;
     let weight_b = area_cap * inv_area_sum;
     let weight_c = area_abp * inv_area_sum;
-    let weights = Vector3::new(weight_a, weight_b, weight_c);
-
-    weights
+    Vector3::new(weight_a, weight_b, weight_c)
 }
 
 pub fn signed_area(a: &Point2<f32>, b: &Point2<f32>, c: &Point2<f32>) -> f32 {
@@ -316,8 +1039,17 @@ impl Vertex {
         self.uv = Some(uv);
         self
     }
+    /// Linear blend of every attribute, attributes missing on either side are dropped.
+    pub fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        Vertex {
+            position: Point4::from(self.position.coords.lerp(&other.position.coords, t)),
+            normal: self.normal.zip(other.normal).map(|(a, b)| a.lerp(&b, t)),
+            color: self.color.zip(other.color).map(|(a, b)| a.lerp(&b, t)),
+            uv: self.uv.zip(other.uv).map(|(a, b)| a.lerp(&b, t)),
+        }
+    }
     pub fn model_to_view(&self, mv_mat: &Matrix4<f32>) -> Vertex {
-        let mut v = self.clone();
+        let mut v = *self;
         v.position = mv_mat
             .transform_point(&v.position.xyz())
             .to_homogeneous()
@@ -325,11 +1057,14 @@ impl Vertex {
         v
     }
     pub fn model_to_view_mut(&mut self, mv_mat: &Matrix4<f32>) -> &mut Self {
-        self.position = mv_mat.transform_point(&self.position.xyz()).to_homogeneous().into();
+        self.position = mv_mat
+            .transform_point(&self.position.xyz())
+            .to_homogeneous()
+            .into();
         self
     }
     pub fn view_to_clip(&self, v_mat: &Matrix4<f32>) -> Vertex {
-        let mut v = self.clone();
+        let mut v = *self;
         v.position = v_mat
             .transform_point(&v.position.xyz())
             .to_homogeneous()
@@ -337,11 +1072,14 @@ impl Vertex {
         v
     }
     pub fn view_to_clip_mut(&mut self, v_mat: &Matrix4<f32>) -> &mut Self {
-        self.position = v_mat.transform_point(&self.position.xyz()).to_homogeneous().into();
+        self.position = v_mat
+            .transform_point(&self.position.xyz())
+            .to_homogeneous()
+            .into();
         self
     }
     pub fn world_to_clip(&self, mvp_mat: &Matrix4<f32>) -> Vertex {
-        let mut v = self.clone();
+        let mut v = *self;
         v.position = mvp_mat
             .transform_point(&v.position.xyz())
             .to_homogeneous()
@@ -349,11 +1087,14 @@ impl Vertex {
         v
     }
     pub fn world_to_clip_mut(&mut self, mvp_mat: &Matrix4<f32>) -> &mut Self {
-        self.position = mvp_mat.transform_point(&self.position.xyz()).to_homogeneous().into();
+        self.position = mvp_mat
+            .transform_point(&self.position.xyz())
+            .to_homogeneous()
+            .into();
         self
     }
     pub fn clip_to_ndc(&self) -> Vertex {
-        let mut v = self.clone();
+        let mut v = *self;
         let position = if v.position.w != 0.0 {
             v.position / v.position.w
         } else {
@@ -371,7 +1112,7 @@ impl Vertex {
         self
     }
     pub fn ndc_to_screen(&self, size: (u32, u32)) -> Vertex {
-        let mut v = self.clone();
+        let mut v = *self;
         v.position.x = (v.position.x + 1.0) * 0.5 * size.0 as f32;
         v.position.y = (1.0 - v.position.y) * 0.5 * size.1 as f32;
         v
@@ -381,27 +1122,172 @@ impl Vertex {
         self.position.y = (1.0 - self.position.y) * 0.5 * size.1 as f32;
         self
     }
-    pub fn update_normal(&self, model_mat: &Isometry3<f32>) -> Vertex {
+    pub fn update_normal(&self, normal_mat: &Matrix3<f32>) -> Vertex {
         if let Some(normal) = self.normal {
-            let mut v = self.clone();
-            v.normal = Some(model_mat.transform_vector(&normal).normalize());
+            let mut v = *self;
+            v.normal = Some((normal_mat * normal).normalize());
             v
         } else {
-            self.clone()
+            *self
         }
     }
-    pub fn update_normal_mut(&mut self, model_mat: &Isometry3<f32>) -> &mut Self {
+    pub fn update_normal_mut(&mut self, normal_mat: &Matrix3<f32>) -> &mut Self {
         if let Some(normal) = self.normal {
-            self.normal = Some(model_mat.transform_vector(&normal).normalize());
+            self.normal = Some((normal_mat * normal).normalize());
         }
         self
     }
 }
+/// Random vertex colors, the same sequence of models every run in a deterministic mode.
 pub fn randomize_model_colors(model: &Model) -> Model {
+    randomize_model_colors_with(model, &mut deterministic_rng("randomize_model_colors"))
+}
+/// Random vertex colors from a fixed seed.
+#[allow(unused)]
+pub fn randomize_model_colors_seeded(model: &Model, seed: u64) -> Model {
+    randomize_model_colors_with(model, &mut XorShiftRng::seed_from_u64(seed))
+}
+fn randomize_model_colors_with(model: &Model, rng: &mut XorShiftRng) -> Model {
     let mut model = model.clone();
-    let mut rng = XorShiftRng::from_os_rng();
     for vertex in model.vertices.iter_mut() {
-        vertex.color = Some(random_color(&mut rng));
+        vertex.color = Some(random_color(rng));
     }
     model
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Camera;
+    use proptest::prelude::*;
+
+    fn point(extent: f32) -> impl Strategy<Value = Point2<f32>> {
+        (-extent..extent, -extent..extent).prop_map(|(x, y)| Point2::new(x, y))
+    }
+
+    fn screen_vertex(point: &Point2<f32>) -> Vertex {
+        Vertex::new(&Point3::new(point.x, point.y, 0.5))
+    }
+
+    fn test_camera() -> Camera {
+        Camera::new(
+            Point3::new(1.0, 2.0, 5.0),
+            Point3::origin(),
+            Vector3::y(),
+            70.0,
+            4.0 / 3.0,
+            0.1,
+            100.0,
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn barycentric_weights_sum_to_one_and_give_back_the_point(
+            corners in [point(500.0), point(500.0), point(500.0)],
+            p in point(1000.0),
+        ) {
+            // Slivers are ill conditioned, keep triangles whose area is a fair share of the
+            // square on their longest side
+            let longest = (0..3)
+                .map(|i| (corners[(i + 1) % 3] - corners[i]).norm_squared())
+                .fold(0.0, f32::max);
+            let area = signed_area(&corners[0], &corners[1], &corners[2]).abs();
+            prop_assume!(area >= 1.0 && area >= 0.01 * longest);
+            let triangle = corners.map(|corner| screen_vertex(&corner));
+            let weights = triangle_barycentric(&triangle, &p);
+            prop_assert!((weights.sum() - 1.0).abs() < 1e-3, "{} for {}", weights, p);
+            let back = corners[0].coords * weights.x
+                + corners[1].coords * weights.y
+                + corners[2].coords * weights.z;
+            prop_assert!((back - p.coords).norm() < 0.1, "{} for {}", back, p);
+        }
+
+        #[test]
+        fn edge_cross_is_minus_twice_the_signed_area(
+            a in point(500.0),
+            b in point(500.0),
+            p in point(500.0),
+        ) {
+            let cross = edge_cross(&a, &b, &p);
+            let area = signed_area(&a, &b, &p);
+            prop_assert!((cross + 2.0 * area).abs() <= 1e-3 * cross.abs().max(1.0));
+            prop_assert_eq!(edge_cross(&a, &b, &a), 0.0);
+            prop_assert!(
+                (edge_cross(&a, &b, &p) + edge_cross(&b, &a, &p)).abs()
+                    <= 1e-3 * cross.abs().max(1.0)
+            );
+        }
+
+        #[test]
+        fn bounds_stay_on_the_target_and_hold_the_visible_corners(
+            screen in (1u32..400, 1u32..400),
+            corners in [point(600.0), point(600.0), point(600.0)],
+        ) {
+            let bounds = Bounds::new(corners.map(|corner| screen_vertex(&corner)), screen);
+            prop_assert!(bounds.min_x >= 0.0 && bounds.min_y >= 0.0);
+            prop_assert!(bounds.max_x <= screen.0 as f32 && bounds.max_y <= screen.1 as f32);
+            for corner in corners {
+                let on_screen = (0.0..=screen.0 as f32).contains(&corner.x)
+                    && (0.0..=screen.1 as f32).contains(&corner.y);
+                if on_screen {
+                    prop_assert!((bounds.min_x..=bounds.max_x).contains(&corner.x));
+                    prop_assert!((bounds.min_y..=bounds.max_y).contains(&corner.y));
+                }
+            }
+        }
+
+        #[test]
+        fn ndc_and_screen_round_trip(
+            size in (1u32..2000, 1u32..2000),
+            ndc in point(1.0),
+        ) {
+            let screen = screen_vertex(&ndc).ndc_to_screen(size).position;
+            prop_assert!((0.0..=size.0 as f32).contains(&screen.x));
+            prop_assert!((0.0..=size.1 as f32).contains(&screen.y));
+            // The ray back through the pixel starts on the near plane at the same NDC
+            let camera = test_camera();
+            let mvp = camera.get_perspective_matrix() * camera.get_view_matrix();
+            let ray = camera.screen_ray(screen.x, screen.y, size);
+            let back = mvp.transform_point(&ray.origin).xy();
+            prop_assert!((back - ndc).norm() < 1e-3, "{} for {}", back, ndc);
+        }
+
+        #[test]
+        fn projected_points_lie_on_the_ray_through_their_pixel(
+            x in -3.0f32..3.0,
+            y in -3.0f32..3.0,
+            z in -3.0f32..3.0,
+        ) {
+            let camera = test_camera();
+            let size = (640, 480);
+            let mvp = camera.get_perspective_matrix() * camera.get_view_matrix();
+            let point = Point3::new(x, y, z);
+            let mut vertex = Vertex::new(&point);
+            vertex
+                .world_to_clip_mut(&mvp)
+                .clip_to_ndc_mut()
+                .ndc_to_screen_mut(size);
+            let ray = camera.screen_ray(vertex.position.x, vertex.position.y, size);
+            let along = (point - ray.origin).dot(&ray.direction);
+            prop_assert!((ray.at(along) - point).norm() < 1e-3 * along.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn degenerate_triangles_get_a_third_each() {
+        let line =
+            [(0.0, 0.0), (1.0, 1.0), (2.0, 2.0)].map(|(x, y)| screen_vertex(&Point2::new(x, y)));
+        let weights = triangle_barycentric(&line, &Point2::new(5.0, 0.0));
+        assert_eq!(weights, Vector3::repeat(1.0 / 3.0));
+    }
+
+    #[test]
+    fn empty_bounds_are_zero() {
+        let empty = Bounds::new(Vec::<Vertex>::new(), (10, 10));
+        assert_eq!(
+            (empty.min_x, empty.max_x, empty.min_y, empty.max_y),
+            (0.0, 0.0, 0.0, 0.0)
+        );
+    }
+}
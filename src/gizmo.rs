@@ -0,0 +1,231 @@
+use crate::camera::Camera;
+use crate::debug::DebugDraw;
+use crate::geometry::{Plane, Ray};
+use crate::renderer::Color;
+use nalgebra::{Isometry3, Point3, Scale3, Unit, UnitQuaternion, Vector3};
+
+#[allow(unused)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+#[allow(unused)]
+#[derive(Debug, Copy, Clone)]
+struct Drag {
+    axis: usize,
+    start_param: f32,
+    start_vector: Vector3<f32>,
+    start_position: Isometry3<f32>,
+    start_scale: Scale3<f32>,
+}
+
+/// World axis aligned manipulator for one entity transform, drawn through the debug lines
+/// and driven by a picking ray.
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct Gizmo {
+    pub mode: GizmoMode,
+    /// Gizmo length as a fraction of its distance to the camera, keeping its screen size fixed.
+    pub screen_size: f32,
+    hovered: Option<usize>,
+    drag: Option<Drag>,
+    was_pressed: bool,
+}
+impl Default for Gizmo {
+    fn default() -> Self {
+        Self {
+            mode: GizmoMode::Translate,
+            screen_size: 0.15,
+            hovered: None,
+            drag: None,
+            was_pressed: false,
+        }
+    }
+}
+#[allow(unused)]
+impl Gizmo {
+    pub fn new(mode: GizmoMode) -> Self {
+        Self {
+            mode,
+            ..Self::default()
+        }
+    }
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+    fn axis(index: usize) -> Vector3<f32> {
+        let mut axis = Vector3::zeros();
+        axis[index] = 1.0;
+        axis
+    }
+    fn size(&self, center: &Point3<f32>, camera: &Camera) -> f32 {
+        (center - camera.eye()).magnitude() * self.screen_size
+    }
+    /// Parameter along an axis line for translate/scale, or the in-plane direction for rotate.
+    fn grab(&self, ray: &Ray, center: &Point3<f32>, axis: usize) -> Option<(f32, Vector3<f32>)> {
+        let direction = Self::axis(axis);
+        match self.mode {
+            GizmoMode::Translate | GizmoMode::Scale => {
+                let (_, s) = ray.closest_to_line(center, &direction);
+                Some((s, Vector3::zeros()))
+            }
+            GizmoMode::Rotate => {
+                let t = ray.intersect_plane(&Plane::from_point_normal(center, &direction))?;
+                Some((0.0, ray.at(t) - center))
+            }
+        }
+    }
+    /// The handle under the ray, nearest to the ray origin.
+    pub fn hit_test(&self, ray: &Ray, position: &Isometry3<f32>, camera: &Camera) -> Option<usize> {
+        let center = Point3::from(position.translation.vector);
+        let size = self.size(&center, camera);
+        let threshold = size * 0.08;
+        let mut best: Option<(usize, f32)> = None;
+        for axis in 0..3 {
+            let direction = Self::axis(axis);
+            let hit = match self.mode {
+                GizmoMode::Translate | GizmoMode::Scale => {
+                    let (t, s) = ray.closest_to_line(&center, &direction);
+                    let distance = (ray.at(t) - (center + direction * s)).magnitude();
+                    (t >= 0.0 && (0.0..=size).contains(&s) && distance < threshold).then_some(t)
+                }
+                GizmoMode::Rotate => ray
+                    .intersect_plane(&Plane::from_point_normal(&center, &direction))
+                    .filter(|t| ((ray.at(*t) - center).magnitude() - size).abs() < threshold),
+            };
+            if let Some(t) = hit
+                && best.is_none_or(|(_, best_t)| t < best_t)
+            {
+                best = Some((axis, t));
+            }
+        }
+        best.map(|(axis, _)| axis)
+    }
+    /// Runs hover, drag start, dragging and release for this frame. Returns true while the
+    /// gizmo is hovered or dragged, so callers can skip their own click handling.
+    pub fn interact(
+        &mut self,
+        ray: &Ray,
+        pressed: bool,
+        camera: &Camera,
+        position: &mut Isometry3<f32>,
+        scale: &mut Scale3<f32>,
+    ) -> bool {
+        let just_pressed = pressed && !self.was_pressed;
+        self.was_pressed = pressed;
+
+        if let Some(drag) = self.drag {
+            if !pressed {
+                self.drag = None;
+                return true;
+            }
+            let center = Point3::from(drag.start_position.translation.vector);
+            if let Some((param, vector)) = self.grab(ray, &center, drag.axis) {
+                self.apply(&drag, param, vector, position, scale);
+            }
+            return true;
+        }
+
+        self.hovered = self.hit_test(ray, position, camera);
+        if let (true, Some(axis)) = (just_pressed, self.hovered) {
+            let center = Point3::from(position.translation.vector);
+            if let Some((start_param, start_vector)) = self.grab(ray, &center, axis) {
+                self.drag = Some(Drag {
+                    axis,
+                    start_param,
+                    start_vector,
+                    start_position: *position,
+                    start_scale: *scale,
+                });
+            }
+        }
+        self.hovered.is_some()
+    }
+    fn apply(
+        &self,
+        drag: &Drag,
+        param: f32,
+        vector: Vector3<f32>,
+        position: &mut Isometry3<f32>,
+        scale: &mut Scale3<f32>,
+    ) {
+        let axis = Self::axis(drag.axis);
+        match self.mode {
+            GizmoMode::Translate => {
+                *position = drag.start_position;
+                position.translation.vector += axis * (param - drag.start_param);
+            }
+            GizmoMode::Rotate => {
+                let (from, to) = (drag.start_vector, vector);
+                let angle = axis.dot(&from.cross(&to)).atan2(from.dot(&to));
+                let rotation = UnitQuaternion::from_axis_angle(&Unit::new_normalize(axis), angle);
+                *position = drag.start_position;
+                position.rotation = rotation * drag.start_position.rotation;
+            }
+            GizmoMode::Scale => {
+                if drag.start_param.abs() > f32::EPSILON {
+                    *scale = drag.start_scale;
+                    scale.vector[drag.axis] *= param / drag.start_param;
+                }
+            }
+        }
+    }
+    pub fn draw(&self, debug: &mut DebugDraw, position: &Isometry3<f32>, camera: &Camera) {
+        let center = Point3::from(position.translation.vector);
+        let size = self.size(&center, camera);
+        let active = self.drag.map(|drag| drag.axis).or(self.hovered);
+        for axis in 0..3 {
+            let direction = Self::axis(axis);
+            let color = if active == Some(axis) {
+                Color::new(1.0, 1.0, 0.0, 1.0)
+            } else {
+                let mut c = Color::new(0.2, 0.2, 0.2, 1.0);
+                match axis {
+                    0 => c.r = 1.0,
+                    1 => c.g = 1.0,
+                    _ => c.b = 1.0,
+                }
+                c
+            };
+            // Any vector perpendicular to the axis, used for arrow heads and rings
+            let side = Self::axis((axis + 1) % 3);
+            let other = Self::axis((axis + 2) % 3);
+            let tip = center + direction * size;
+            match self.mode {
+                GizmoMode::Translate => {
+                    debug.line(center, tip, color);
+                    let base = tip - direction * (size * 0.2);
+                    for offset in [side, -side, other, -other] {
+                        debug.line(tip, base + offset * (size * 0.07), color);
+                    }
+                }
+                GizmoMode::Scale => {
+                    debug.line(center, tip, color);
+                    let h = size * 0.05;
+                    let corners = [
+                        tip + (side + other) * h,
+                        tip + (side - other) * h,
+                        tip + (-side - other) * h,
+                        tip + (-side + other) * h,
+                    ];
+                    for i in 0..4 {
+                        debug.line(corners[i], corners[(i + 1) % 4], color);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    let segments = 32;
+                    let ring = (0..=segments)
+                        .map(|i| {
+                            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+                            center + (side * angle.cos() + other * angle.sin()) * size
+                        })
+                        .collect::<Vec<_>>();
+                    debug.polyline(&ring, color);
+                }
+            }
+        }
+    }
+}
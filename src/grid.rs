@@ -0,0 +1,91 @@
+use crate::renderer::Color;
+use nalgebra::{Point3, Vector2};
+
+/// Infinite ground grid on the plane `y = height`, drawn per pixel behind the scene depth.
+/// Each level is `level_scale` times coarser than the previous one, levels fade out once
+/// their lines get closer together than a pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct Grid {
+    pub height: f32,
+    pub cell_size: f32,
+    pub levels: u32,
+    pub level_scale: f32,
+    /// Distances where the grid starts and finishes fading out.
+    pub fade_start: f32,
+    pub fade_end: f32,
+    pub color: Color,
+    pub x_axis_color: Color,
+    pub z_axis_color: Color,
+}
+impl Default for Grid {
+    fn default() -> Self {
+        Self {
+            height: 0.0,
+            cell_size: 1.0,
+            levels: 3,
+            level_scale: 10.0,
+            fade_start: 20.0,
+            fade_end: 80.0,
+            color: Color::new(0.6, 0.6, 0.6, 0.8),
+            x_axis_color: Color::new(0.9, 0.2, 0.2, 1.0),
+            z_axis_color: Color::new(0.2, 0.3, 0.9, 1.0),
+        }
+    }
+}
+impl Grid {
+    /// Grid color at a point on the plane, `footprint` is the world size of the pixel along
+    /// x and z. The alpha is the line coverage, `None` where nothing is drawn.
+    pub fn shade(
+        &self,
+        point: &Point3<f32>,
+        footprint: &Vector2<f32>,
+        distance: f32,
+    ) -> Option<Color> {
+        let fade = 1.0 - smoothstep(self.fade_start, self.fade_end, distance);
+        if fade <= 0.0 {
+            return None;
+        }
+        let x_axis = line_coverage(point.z, footprint.y, f32::INFINITY);
+        let z_axis = line_coverage(point.x, footprint.x, f32::INFINITY);
+        let (mut color, mut coverage) = if x_axis > 0.0 {
+            (self.x_axis_color, x_axis)
+        } else if z_axis > 0.0 {
+            (self.z_axis_color, z_axis)
+        } else {
+            (self.color, 0.0)
+        };
+        if coverage == 0.0 {
+            let mut cell = self.cell_size;
+            for _ in 0..self.levels {
+                let lines = line_coverage(point.x, footprint.x, cell).max(line_coverage(
+                    point.z,
+                    footprint.y,
+                    cell,
+                ));
+                // Lines closer than two pixels apart turn into noise, fade the level out
+                let density = footprint.x.max(footprint.y) / cell;
+                coverage = coverage.max(lines * (1.0 - density * 2.0).clamp(0.0, 1.0));
+                cell *= self.level_scale;
+            }
+        }
+        color.a *= coverage * fade;
+        (color.a > 0.0).then_some(color)
+    }
+}
+
+/// How much of a one pixel wide line on every multiple of `cell` covers `coord`, lines on
+/// an infinite `cell` only exist at zero.
+fn line_coverage(coord: f32, width: f32, cell: f32) -> f32 {
+    let width = width.max(1e-6);
+    let distance = if cell.is_finite() {
+        ((coord / cell + 0.5).rem_euclid(1.0) - 0.5).abs() * cell
+    } else {
+        coord.abs()
+    };
+    (1.0 - distance / width).clamp(0.0, 1.0)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,70 @@
+use crate::camera::Camera;
+use crate::renderer::Color;
+use nalgebra::{UnitQuaternion, Vector3};
+
+/// A second view of the scene drawn over a rectangle of the main target each frame, e.g. a
+/// top-down minimap or a rear view mirror.
+#[derive(Debug, Copy, Clone)]
+pub struct Inset {
+    pub camera: Camera,
+    /// Rectangle in fractions of the main target, (0, 0) is the top left corner.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub border: Option<Color>,
+    /// Keeps the camera this high above the main camera looking straight down, with the
+    /// main camera's heading pointing up.
+    pub follow_height: Option<f32>,
+}
+#[allow(unused)]
+impl Inset {
+    pub fn new(camera: Camera, x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self {
+            camera,
+            x,
+            y,
+            width,
+            height,
+            border: Some(Color::new(1.0, 1.0, 1.0, 1.0)),
+            follow_height: None,
+        }
+    }
+    /// Top-down view in the top right corner following the main camera.
+    pub fn minimap(height: f32) -> Self {
+        let camera = Camera {
+            fov: 1.0,
+            near: 0.1,
+            far: height * 4.0,
+            ..Camera::default()
+        };
+        Self {
+            follow_height: Some(height),
+            ..Self::new(camera, 0.73, 0.02, 0.25, 0.25)
+        }
+    }
+    /// Pixel rectangle on a target of `size`, at least one pixel wide and high.
+    pub fn rect(&self, size: (u32, u32)) -> (i32, i32, u32, u32) {
+        let (width, height) = (size.0 as f32, size.1 as f32);
+        (
+            (self.x * width) as i32,
+            (self.y * height) as i32,
+            ((self.width * width) as u32).max(1),
+            ((self.height * height) as u32).max(1),
+        )
+    }
+    /// The camera to draw with this frame.
+    pub fn view_camera(&self, main: &Camera, size: (u32, u32)) -> Camera {
+        let (_, _, width, height) = self.rect(size);
+        let mut camera = self.camera;
+        camera.aspect_ratio = width as f32 / height as f32;
+        if let Some(follow_height) = self.follow_height {
+            camera.position = main.position + Vector3::y() * follow_height;
+            camera.world_offset = main.world_offset;
+            let heading = Vector3::new(main.forward().x, 0.0, main.forward().z);
+            let up = heading.try_normalize(1e-6).unwrap_or(-Vector3::z());
+            camera.orientation = UnitQuaternion::face_towards(&-Vector3::y(), &up);
+        }
+        camera
+    }
+}
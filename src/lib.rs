@@ -1,28 +1,154 @@
+mod accumulation;
+mod ambient_occlusion;
+mod animation;
+mod asset_loader;
+mod atlas;
+mod attachment;
+mod benchmark;
+mod blur;
+mod bvh;
 mod camera;
+mod camera_effects;
+mod config;
+mod console;
+mod cursor;
+mod curves;
+mod debug;
+mod decal;
+mod determinism;
+mod display;
+mod editor;
+mod error;
+mod exposure;
+mod foliage;
+mod frame_graph;
 mod geometry;
+mod gizmo;
+mod grid;
+mod inset;
+mod marching_cubes;
+mod mirror;
 mod my_app;
+mod navmesh;
+mod nine_slice;
+mod offline;
+mod outline;
+mod palette;
+mod panorama;
+mod pathtrace;
+#[cfg(feature = "physics")]
+mod physics;
+mod portal;
+mod prefab;
+mod primitives;
+mod probes;
+mod quality;
+mod raytrace;
+pub mod render_jobs;
 mod renderer;
+mod replay;
+#[cfg(feature = "scripting")]
+mod script;
+mod sky;
+mod spatial;
+mod sprite;
+mod static_cache;
+mod stereo;
+mod streaming;
+mod taa;
+mod terrain;
+mod text;
+mod texture_formats;
+mod threads;
+mod turntable;
+mod visibility;
+mod voxel;
+mod water;
 
+use crate::asset_loader::AssetLoader;
+use crate::attachment::{Attachment, AttachmentPoints};
+use crate::benchmark::{Benchmark, BenchmarkConfig};
 use crate::camera::Camera;
-use crate::geometry::Model;
+use crate::config::DEFAULT_CONFIG_PATH;
+use crate::console::Console;
+use crate::cursor::{apply_cursor, apply_window_icon};
+use crate::debug::{DebugDraw, draw_index_labels};
+use crate::display::{apply_fullscreen, monitors};
+use crate::editor::{Editor, load_layout, save_layout};
+use crate::geometry::{Model, Plane, Ray, Texture, Vertex};
+use crate::grid::Grid;
+use crate::inset::Inset;
+use crate::mirror::{Mirror, render_reflection};
 use crate::my_app::MyApp;
-use crate::renderer::{DrawMode, Material, RenderTarget, Shader};
-use nalgebra::{Isometry3, Scale3};
+use crate::nine_slice::Panel;
+use crate::outline::Outline;
+use crate::probes::IrradianceGrid;
+use crate::raytrace::{ShadowCasters, ray_trace};
+use crate::renderer::{
+    Color, DrawMode, Interlace, Material, PixelId, QualityOverrides, RenderTarget, Sampler, Shader,
+};
+use crate::replay::{InputRecorder, InputReplay};
+#[cfg(feature = "scripting")]
+use crate::script::{Script, ScriptHost};
+use crate::spatial::{Aabb, SpatialIndex};
+use crate::sprite::Sprite;
+use crate::static_cache::scene_fingerprint;
+use crate::stereo::{Stereo, StereoMode};
+use crate::taa::Taa;
+use crate::visibility::{VisibilityState, track_visibility};
+use crate::water::WaterShader;
+use nalgebra::{Isometry3, Matrix3, Matrix4, Point3, Scale3, Vector3};
 use softbuffer::{Context, Surface};
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
 use std::num::NonZeroU32;
 use std::rc::Rc;
-use std::thread;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 use winit::application::ApplicationHandler;
 use winit::dpi::{LogicalPosition, PhysicalSize, Position, Size};
-use winit::event::{DeviceEvent, DeviceId, WindowEvent};
+use winit::event::{DeviceEvent, DeviceId, MouseButton, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::keyboard::{Key, NamedKey};
-use winit::window::{CursorGrabMode, Window, WindowAttributes, WindowId};
+use winit::window::{Fullscreen, Window, WindowAttributes, WindowId};
 
-const WIDTH: f32 = 1600.0 / 1.5;
-const HEIGHT: f32 = 900.0 / 1.5;
+pub use crate::accumulation::Accumulation;
+pub use crate::animation::{AnimationController, Rig, Transition};
+pub use crate::atlas::{Atlas, AtlasBuilder, AtlasRegion};
+pub use crate::blur::{BlurKernel, blur, blurred};
+pub use crate::camera::Projection;
+pub use crate::config::{Config, QualitySettings};
+pub use crate::cursor::CursorStyle;
+pub use crate::debug::{DebugLabel, IndexLabels, LabelTarget};
+pub use crate::determinism::{Deterministic, deterministic_rng};
+pub use crate::display::{FullscreenMode, MonitorInfo, VideoMode};
+pub use crate::error::SoftRastError;
+pub use crate::exposure::AutoExposure;
+pub use crate::foliage::{Foliage, wind_sway};
+pub use crate::frame_graph::{FrameGraph, FramePass};
+pub use crate::navmesh::{NavMesh, NavMeshSettings, draw_path};
+pub use crate::offline::{OfflineRender, RasterizerDiff, compare_rasterizers, render_to_file};
+pub use crate::palette::{Palette, PaletteRamp, PaletteShader};
+pub use crate::panorama::{render_equirectangular, render_panorama};
+pub use crate::pathtrace::PathTraceSettings;
+#[cfg(feature = "physics")]
+pub use crate::physics::{BodyKind, ColliderShape, PhysicsBody, PhysicsWorld};
+pub use crate::portal::{PortalCell, PortalSystem};
+pub use crate::prefab::{PREFAB_ORIGIN, Prefab, PrefabLibrary, PrefabMaterial, PrefabOverrides};
+pub use crate::quality::{QualityFeature, QualityGovernor};
+pub use crate::renderer::{BlitFilter, DepthCue, GeometryHook, Rect, TriangleFiller, WireStyle};
+pub use crate::sky::Sky;
+pub use crate::streaming::{Chunk, ChunkEntity, ChunkState, ChunkTrigger, WorldStreamer};
+pub use crate::terrain::{Heightmap, Terrain};
+pub use crate::threads::RenderThreads;
+pub use crate::turntable::{Turntable, render_turntable};
+pub use crate::visibility::{VisibilityChange, VisibilityEvent};
+pub use crate::water::{Water, Wave};
+
+const LAYOUT_PATH: &str = "scene_layout.txt";
+const RECORDING_PATH: &str = "input_recording.txt";
+/// Simulation time of one `Command::step_frame`.
+const STEP_DELTA: Duration = Duration::from_nanos(16_666_667);
 
 pub enum SoftRastEvent<'a> {
     Resume {},
@@ -34,23 +160,141 @@ pub enum SoftRastEvent<'a> {
         delta: Duration,
         scene: &'a mut Scene,
     },
+    /// A console command registered with `Command::register_console_command` was entered.
+    ConsoleCommand {
+        name: String,
+        args: Vec<String>,
+    },
+    /// Answer to `Command::pick`, what the previous frame drew at the pixel.
+    Picked {
+        x: u32,
+        y: u32,
+        pixel: Option<PixelId>,
+        /// Set when the entity was added through `Command::spawn`.
+        handle: Option<EntityId>,
+    },
+    /// An asset requested with `Command::load_model` or `Command::load_texture` is ready.
+    Loaded {
+        path: String,
+        asset: Asset,
+    },
+    /// The connected monitors and their video modes, after `Command::list_monitors`.
+    Monitors(Vec<MonitorInfo>),
+    /// The passes of the frame after `Command::dump_frame_graph`.
+    FrameGraph(FrameGraph),
+    /// Entities with `Entity::with_visibility_events` that came into or went out of view or
+    /// switched level of detail, after the `Render` event of the frame they changed in.
+    Visibility(Vec<VisibilityEvent>),
+    /// Loading an asset or setting up the window failed, the app keeps running where it can.
+    Error(SoftRastError),
+}
+/// A loaded asset of `SoftRastEvent::Loaded`.
+pub enum Asset {
+    Model(Model),
+    Texture(Texture),
 }
 pub enum SoftRastCommand {
     SetTitle(String),
+    SetWindowIcon(Option<Texture>),
+    SetCursor(CursorStyle),
+    SetCursorGrab(bool),
+    ListMonitors,
+    SetConfig(Config),
+    SaveConfig,
+    DumpFrameGraph {
+        dot: Option<String>,
+    },
+    SetFullscreen(FullscreenMode),
+    SetTimeScale(f32),
+    SetPaused(bool),
+    StepFrame,
+    LoadModel(String),
+    LoadTexture(String),
     SetRenderingMode {
         shaded: bool,
         wireframe: bool,
         points: bool,
     },
+    ShowStats(bool),
+    RegisterConsoleCommand {
+        name: String,
+        help: String,
+    },
+    ConsolePrint(String),
+    ReportError(SoftRastError),
+    StartRecording(String),
+    StopRecording,
+    StartReplay(String),
+    ShowGrid(bool),
+    ShowIndexLabels(Option<IndexLabels>),
+    ShowFrusta(bool),
+    SetCameraRelative(bool),
+    SetAutoExposure(Option<AutoExposure>),
+    SetPalette(Option<Palette>),
+    ShowMinimap(bool),
+    SetStereo(Option<Stereo>),
+    SetOutline(Option<Outline>),
+    SetPortals(Option<PortalSystem>),
+    SetSky(Option<Sky>),
+    SetProjection {
+        projection: Projection,
+        tessellation: u32,
+    },
+    SetQualityGovernor(Option<QualityGovernor>),
+    SetTaa(bool),
+    SetAccumulation(Option<Accumulation>),
+    SetOit(bool),
+    SetIdBuffer(bool),
+    Pick {
+        x: u32,
+        y: u32,
+    },
+    SetInterlace(Option<Interlace>),
+    SetDepthCue(Option<DepthCue>),
+    SetTriangleFiller(TriangleFiller),
+    SetRenderThreads(RenderThreads),
+    SetDeterministic(Option<Deterministic>),
+    SetCollapseSubpixel(bool),
+    SetReferenceRasterizer(bool),
+    SetPerspectiveCorrect(bool),
+    SetWireStyle(WireStyle),
+    SetRayTraced {
+        enabled: bool,
+        shadows: bool,
+    },
+    SetStaticCaching(bool),
+    Redraw,
+    BakeProbes,
+    StartBenchmark(BenchmarkConfig),
+    StopBenchmark,
+    Spawn(Box<Entity>),
+    Despawn(EntityId),
+    PlayAnimation {
+        id: EntityId,
+        state: String,
+        fade: f32,
+    },
+    SetAnimationParameter {
+        id: EntityId,
+        name: String,
+        value: f32,
+    },
 }
 
+/// Handle to an entity added with `Command::spawn`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EntityId(u64);
+
 trait UserState {
     fn handle_event(&mut self, command: &mut Command, event: SoftRastEvent);
 }
 
 struct Command {
     commands: Vec<SoftRastCommand>,
-    timer: Instant,
+    time: Duration,
+    next_entity_id: u64,
+    /// The settings in effect, changed through `set_config`.
+    config: Config,
 }
 #[allow(unused)]
 impl Command {
@@ -58,6 +302,75 @@ impl Command {
         self.commands
             .push(SoftRastCommand::SetTitle(title.to_owned()));
     }
+    /// Sets the window icon from an RGBA texture, e.g. from `load_texture`. `None` restores the
+    /// platform default.
+    pub fn set_window_icon(&mut self, icon: Option<Texture>) {
+        self.commands.push(SoftRastCommand::SetWindowIcon(icon));
+    }
+    /// Changes how the cursor looks while it is free.
+    pub fn set_cursor(&mut self, style: CursorStyle) {
+        self.commands.push(SoftRastCommand::SetCursor(style));
+    }
+    /// Confines and hides the cursor for mouse look, the default. Tool style apps release it
+    /// to point and click outside the editor.
+    pub fn set_cursor_grab(&mut self, grab: bool) {
+        self.commands.push(SoftRastCommand::SetCursorGrab(grab));
+    }
+    /// The user settings in effect, a copy to change and hand to `set_config`, e.g. from an
+    /// options menu.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+    /// Applies new user settings: window size and fullscreen, render scale, quality toggles
+    /// and key bindings. `save_config` keeps them for the next run.
+    pub fn set_config(&mut self, config: Config) {
+        self.commands.push(SoftRastCommand::SetConfig(config));
+    }
+    /// Writes the settings in effect to the app's config file, see `App::with_config_file`.
+    pub fn save_config(&mut self) {
+        self.commands.push(SoftRastCommand::SaveConfig);
+    }
+    /// Records the passes of the next frame and their timings into a `SoftRastEvent::FrameGraph`,
+    /// also written as Graphviz source to `dot` when given.
+    pub fn dump_frame_graph(&mut self, dot: Option<&str>) {
+        self.commands.push(SoftRastCommand::DumpFrameGraph {
+            dot: dot.map(str::to_owned),
+        });
+    }
+    /// Asks for a `SoftRastEvent::Monitors` with the modes `set_fullscreen` can pick from.
+    pub fn list_monitors(&mut self) {
+        self.commands.push(SoftRastCommand::ListMonitors);
+    }
+    /// Switches between a window, borderless and exclusive fullscreen, the render target
+    /// follows the new size.
+    pub fn set_fullscreen(&mut self, mode: FullscreenMode) {
+        self.commands.push(SoftRastCommand::SetFullscreen(mode));
+    }
+    /// Scales the deltas of `Update` and `Render`, `elapsed` and the animations, 0.5 is slow
+    /// motion. Frame timing and the editor camera keep running in real time.
+    pub fn set_time_scale(&mut self, scale: f32) {
+        self.commands.push(SoftRastCommand::SetTimeScale(scale));
+    }
+    /// Stops simulation time, events keep coming with a zero delta.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.commands.push(SoftRastCommand::SetPaused(paused));
+    }
+    /// While paused, advances the next frame by one sixtieth of a second times the time scale.
+    pub fn step_frame(&mut self) {
+        self.commands.push(SoftRastCommand::StepFrame);
+    }
+    /// Loads an OBJ, PLY or glTF model in the background, glTF meshes merged into one, and
+    /// answers with `SoftRastEvent::Loaded` or `SoftRastEvent::Error` once done.
+    pub fn load_model(&mut self, path: &str) {
+        self.commands
+            .push(SoftRastCommand::LoadModel(path.to_owned()));
+    }
+    /// Loads a texture like `Texture::new` in the background and answers with
+    /// `SoftRastEvent::Loaded` or `SoftRastEvent::Error` once done.
+    pub fn load_texture(&mut self, path: &str) {
+        self.commands
+            .push(SoftRastCommand::LoadTexture(path.to_owned()));
+    }
     pub fn set_render_mode(&mut self, shaded: bool, wireframe: bool, points: bool) {
         self.commands.push(SoftRastCommand::SetRenderingMode {
             shaded,
@@ -65,26 +378,261 @@ impl Command {
             points,
         })
     }
+    /// Fades wireframe lines and points with their distance from the camera, see `DepthCue`.
+    pub fn set_depth_cue(&mut self, depth_cue: Option<DepthCue>) {
+        self.commands.push(SoftRastCommand::SetDepthCue(depth_cue));
+    }
+    /// Gives the renderer its own thread pool or changes it, see `RenderThreads`.
+    pub fn set_render_threads(&mut self, threads: RenderThreads) {
+        self.commands
+            .push(SoftRastCommand::SetRenderThreads(threads));
+    }
+    /// Makes frames reproducible bit for bit, see `Deterministic`. `None` goes back to the
+    /// clock and OS seeded randomness.
+    pub fn set_deterministic(&mut self, deterministic: Option<Deterministic>) {
+        self.commands
+            .push(SoftRastCommand::SetDeterministic(deterministic));
+    }
+    /// How triangles are scanned for their pixels, `TriangleFiller::Spans` pays off with large
+    /// thin triangles. The image is the same either way.
+    pub fn set_triangle_filler(&mut self, filler: TriangleFiller) {
+        self.commands
+            .push(SoftRastCommand::SetTriangleFiller(filler));
+    }
+    /// Draws triangles too small to cover a pixel center as one pixel instead of dropping them,
+    /// keeps dense meshes solid in the distance.
+    pub fn set_collapse_subpixel(&mut self, collapse: bool) {
+        self.commands
+            .push(SoftRastCommand::SetCollapseSubpixel(collapse));
+    }
+    /// Rasterizes with the slow scalar reference instead of the optimized paths, to see
+    /// whether an artifact comes from one of them.
+    pub fn set_reference_rasterizer(&mut self, reference: bool) {
+        self.commands
+            .push(SoftRastCommand::SetReferenceRasterizer(reference));
+    }
+    /// Interpolates UVs, normals and colors perspective correctly, the default. Turning it off
+    /// shows the affine warping of old consoles.
+    pub fn set_perspective_correct(&mut self, perspective_correct: bool) {
+        self.commands
+            .push(SoftRastCommand::SetPerspectiveCorrect(perspective_correct));
+    }
+    /// Colors and point size of the wireframe and points modes, for entities without their
+    /// own `Entity::with_wire_style`.
+    pub fn set_wire_style(&mut self, style: WireStyle) {
+        self.commands.push(SoftRastCommand::SetWireStyle(style));
+    }
+    /// Switches between rasterizing and tracing rays, optionally with shadow rays.
+    pub fn set_ray_traced(&mut self, enabled: bool, shadows: bool) {
+        self.commands
+            .push(SoftRastCommand::SetRayTraced { enabled, shadows });
+    }
+    pub fn show_stats(&mut self, show: bool) {
+        self.commands.push(SoftRastCommand::ShowStats(show));
+    }
+    /// Adds a console command, entering it sends `SoftRastEvent::ConsoleCommand`.
+    pub fn register_console_command(&mut self, name: &str, help: &str) {
+        self.commands.push(SoftRastCommand::RegisterConsoleCommand {
+            name: name.to_owned(),
+            help: help.to_owned(),
+        });
+    }
+    pub fn console_print(&mut self, text: &str) {
+        self.commands
+            .push(SoftRastCommand::ConsolePrint(text.to_owned()));
+    }
+    /// Hands `err` back to the app as `SoftRastEvent::Error` on the next frame, for parts
+    /// that run without access to the app.
+    pub fn report_error(&mut self, err: SoftRastError) {
+        self.commands.push(SoftRastCommand::ReportError(err));
+    }
+    /// Records every frame's input to `path` until `stop_recording`.
+    pub fn start_recording(&mut self, path: &str) {
+        self.commands
+            .push(SoftRastCommand::StartRecording(path.to_owned()));
+    }
+    pub fn stop_recording(&mut self) {
+        self.commands.push(SoftRastCommand::StopRecording);
+    }
+    /// Feeds recorded input back frame by frame instead of the live input.
+    pub fn start_replay(&mut self, path: &str) {
+        self.commands
+            .push(SoftRastCommand::StartReplay(path.to_owned()));
+    }
+    pub fn show_grid(&mut self, show: bool) {
+        self.commands.push(SoftRastCommand::ShowGrid(show));
+    }
+    /// Numbers vertices or triangles on screen, see `IndexLabels`. `None` hides them.
+    pub fn show_index_labels(&mut self, labels: Option<IndexLabels>) {
+        self.commands.push(SoftRastCommand::ShowIndexLabels(labels));
+    }
+    /// Outlines the view frusta of the main camera and the insets in each other's views, e.g.
+    /// the main camera's coverage on the minimap, to check culling.
+    pub fn show_frusta(&mut self, show: bool) {
+        self.commands.push(SoftRastCommand::ShowFrusta(show));
+    }
+    /// See `Scene::set_camera_relative`.
+    pub fn set_camera_relative(&mut self, camera_relative: bool) {
+        self.commands
+            .push(SoftRastCommand::SetCameraRelative(camera_relative));
+    }
+    /// Adapts the brightness of the 3D views over time, see `AutoExposure`. `None` turns it
+    /// off.
+    pub fn set_auto_exposure(&mut self, exposure: Option<AutoExposure>) {
+        self.commands
+            .push(SoftRastCommand::SetAutoExposure(exposure));
+    }
+    /// Retro mode showing only the colors of `palette`, see `Palette`. `None` turns it off.
+    pub fn set_palette(&mut self, palette: Option<Palette>) {
+        self.commands.push(SoftRastCommand::SetPalette(palette));
+    }
+    /// Renders once per eye and composites as anaglyph or side by side, `None` turns it off.
+    pub fn set_stereo(&mut self, stereo: Option<Stereo>) {
+        self.commands.push(SoftRastCommand::SetStereo(stereo));
+    }
+    /// Draws screen space outlines over the main view, `None` turns them off.
+    pub fn set_outline(&mut self, outline: Option<Outline>) {
+        self.commands.push(SoftRastCommand::SetOutline(outline));
+    }
+    /// Culls entities in cells the camera can't see into through portals, `None` turns it off.
+    pub fn set_portals(&mut self, portals: Option<PortalSystem>) {
+        self.commands.push(SoftRastCommand::SetPortals(portals));
+    }
+    /// Draws a procedural sky behind the scene instead of the clear color, `None` turns it off.
+    pub fn set_sky(&mut self, sky: Option<Sky>) {
+        self.commands.push(SoftRastCommand::SetSky(sky));
+    }
+    /// Projection of the main view, e.g. `Projection::Fisheye`. Non-linear projections bend
+    /// straight edges, so every triangle edge is split `tessellation` times before projecting.
+    pub fn set_projection(&mut self, projection: Projection, tessellation: u32) {
+        self.commands.push(SoftRastCommand::SetProjection {
+            projection,
+            tessellation,
+        });
+    }
+    /// Turns expensive features off while frames are slow and back on once they are fast
+    /// again, see `QualityGovernor`. `None` restores everything.
+    pub fn set_quality_governor(&mut self, governor: Option<QualityGovernor>) {
+        self.commands
+            .push(SoftRastCommand::SetQualityGovernor(governor));
+    }
+    /// Averages jittered frames into an anti-aliased still while nothing moves, see
+    /// `Accumulation`. Takes over from TAA while enabled.
+    pub fn set_accumulation(&mut self, accumulation: Option<Accumulation>) {
+        self.commands
+            .push(SoftRastCommand::SetAccumulation(accumulation));
+    }
+    /// Temporal anti-aliasing of the main view, see `Taa`.
+    pub fn set_taa(&mut self, enabled: bool) {
+        self.commands.push(SoftRastCommand::SetTaa(enabled));
+    }
+    /// Weighted blended order independent transparency instead of sorting blended
+    /// entities, see `RenderTarget::set_oit`.
+    pub fn set_oit(&mut self, enabled: bool) {
+        self.commands.push(SoftRastCommand::SetOit(enabled));
+    }
+    /// Keeps the entity and triangle drawn at each pixel, needed by `pick`.
+    pub fn set_id_buffer(&mut self, enabled: bool) {
+        self.commands.push(SoftRastCommand::SetIdBuffer(enabled));
+    }
+    /// Looks up what is visible at a pixel in the id buffer, answered with
+    /// `SoftRastEvent::Picked` before the next update.
+    pub fn pick(&mut self, x: u32, y: u32) {
+        self.commands.push(SoftRastCommand::Pick { x, y });
+    }
+    /// Renders half the pixels each frame and reuses the rest from the previous one.
+    pub fn set_interlace(&mut self, interlace: Option<Interlace>) {
+        self.commands.push(SoftRastCommand::SetInterlace(interlace));
+    }
+    /// Skips rasterizing frames where nothing changed and presents the previous image.
+    pub fn set_static_caching(&mut self, enabled: bool) {
+        self.commands
+            .push(SoftRastCommand::SetStaticCaching(enabled));
+    }
+    /// Draws the next frame even if static caching sees no change, e.g. after replacing a
+    /// material.
+    pub fn redraw(&mut self) {
+        self.commands.push(SoftRastCommand::Redraw);
+    }
+    /// Adds or removes a top-down inset following the camera.
+    pub fn show_minimap(&mut self, show: bool) {
+        self.commands.push(SoftRastCommand::ShowMinimap(show));
+    }
+    /// Bakes an irradiance grid covering the scene's entities.
+    pub fn bake_probes(&mut self) {
+        self.commands.push(SoftRastCommand::BakeProbes);
+    }
+    /// Replaces the scene with a generated stress test until it finishes or is stopped.
+    pub fn start_benchmark(&mut self, config: BenchmarkConfig) {
+        self.commands.push(SoftRastCommand::StartBenchmark(config));
+    }
+    pub fn stop_benchmark(&mut self) {
+        self.commands.push(SoftRastCommand::StopBenchmark);
+    }
+    /// Adds the entity to the scene before the next frame, the returned id finds it with
+    /// `Scene::entity_mut` or removes it with `despawn`.
+    pub fn spawn(&mut self, mut entity: Entity) -> EntityId {
+        let id = EntityId(self.next_entity_id);
+        self.next_entity_id += 1;
+        entity.handle = Some(id);
+        self.commands.push(SoftRastCommand::Spawn(Box::new(entity)));
+        id
+    }
+    /// Removes a spawned entity before the next frame.
+    pub fn despawn(&mut self, id: EntityId) {
+        self.commands.push(SoftRastCommand::Despawn(id));
+    }
+    /// Cross-fades a spawned entity's animation to a state over `fade` seconds.
+    pub fn play_animation(&mut self, id: EntityId, state: &str, fade: f32) {
+        self.commands.push(SoftRastCommand::PlayAnimation {
+            id,
+            state: state.to_owned(),
+            fade,
+        });
+    }
+    /// Sets a parameter the transitions of a spawned entity's animation react to.
+    pub fn set_animation_parameter(&mut self, id: EntityId, name: &str, value: f32) {
+        self.commands.push(SoftRastCommand::SetAnimationParameter {
+            id,
+            name: name.to_owned(),
+            value,
+        });
+    }
+    /// Sum of all frame deltas so far, which keeps animation identical during a replay.
     pub fn elapsed(&self) -> Duration {
-        self.timer.elapsed()
+        self.time
     }
 }
 impl Default for Command {
     fn default() -> Self {
         Self {
             commands: vec![],
-            timer: Instant::now(),
+            time: Duration::ZERO,
+            next_entity_id: 0,
+            config: Config::default(),
         }
     }
 }
 #[derive(Default, Clone)]
 pub struct InputState {
     pressed_keys: HashSet<String>,
+    pressed_buttons: HashSet<MouseButton>,
     mouse_dx: f64,
     mouse_dy: f64,
+    cursor_x: f64,
+    cursor_y: f64,
+    window_size: (u32, u32),
+    /// Key of each action, from `Config::bindings`.
+    bindings: Arc<BTreeMap<String, String>>,
 }
 
 impl InputState {
+    /// Whether the key bound to `action` is held, see `Config::bindings`.
+    pub fn action(&self, action: &str) -> bool {
+        self.bindings
+            .get(action)
+            .is_some_and(|key| self.pressed_keys.contains(key))
+    }
     fn reset_mouse_motion(&mut self) {
         self.mouse_dx = 0.0;
         self.mouse_dy = 0.0;
@@ -101,6 +649,45 @@ struct AppContext {
     timer: Instant,
     input: InputState,
     draw_mode: DrawMode,
+    interlace: Option<Interlace>,
+    id_buffer: bool,
+    oit: bool,
+    static_caching: bool,
+    last_fingerprint: Option<u64>,
+    /// Frames in a row with the same fingerprint.
+    unchanged_frames: u32,
+    editor: Editor,
+    console: Console,
+    show_stats: bool,
+    #[cfg(feature = "scripting")]
+    scripts: ScriptHost,
+    recorder: Option<InputRecorder>,
+    replay: Option<InputReplay>,
+    benchmark: Option<Benchmark>,
+    governor: Option<QualityGovernor>,
+    cursor: CursorStyle,
+    cursor_grab: bool,
+    deterministic: Option<Deterministic>,
+    render_threads: RenderThreads,
+    /// The renderer's own pool, rayon's global one when `None`.
+    render_pool: Option<rayon::ThreadPool>,
+    occluded: bool,
+    /// Redraws stopped because the window can't be seen, the next frame restarts the timer.
+    suspended: bool,
+    time_scale: f32,
+    paused: bool,
+    /// Advance one frame while paused.
+    step: bool,
+    /// Collect the passes of the next frame drawn, with a Graphviz file to write them to.
+    frame_graph_request: Option<Option<String>>,
+    /// The settings in effect, the window is created from them.
+    config: Config,
+    /// Where `SaveConfig` writes, `DEFAULT_CONFIG_PATH` when `None`.
+    config_path: Option<String>,
+    /// Runs `Command::load_model` and `load_texture` in the background.
+    loader: AssetLoader,
+    /// Window sized copy of a frame rendered at a different `Config::render_scale`.
+    present_target: Option<RenderTarget>,
 }
 impl AppContext {
     pub fn new(user_state: impl UserState + 'static) -> Self {
@@ -114,49 +701,87 @@ impl AppContext {
             scene: None,
             timer: Instant::now(),
             input: InputState::default(),
-            draw_mode: DrawMode {
-                shaded: true,
-                wireframe: false,
-                points: false,
-            },
+            draw_mode: DrawMode::default(),
+            interlace: None,
+            id_buffer: false,
+            oit: false,
+            static_caching: false,
+            last_fingerprint: None,
+            unchanged_frames: 0,
+            editor: Editor::default(),
+            console: Console::default(),
+            show_stats: false,
+            #[cfg(feature = "scripting")]
+            scripts: ScriptHost::default(),
+            recorder: None,
+            replay: None,
+            benchmark: None,
+            governor: None,
+            cursor: CursorStyle::default(),
+            cursor_grab: true,
+            deterministic: None,
+            render_threads: RenderThreads::default(),
+            render_pool: None,
+            occluded: false,
+            suspended: false,
+            time_scale: 1.0,
+            paused: false,
+            step: false,
+            frame_graph_request: None,
+            config: Config::default(),
+            config_path: None,
+            loader: AssetLoader::default(),
+            present_target: None,
         }
     }
+    /// Tells the app the window couldn't be set up and stops, there is nothing to draw to.
+    fn fail_window(&mut self, event_loop: &ActiveEventLoop, message: String) {
+        let err = SoftRastError::Window(message);
+        eprintln!("{}", err);
+        self.user_state
+            .handle_event(&mut self.command, SoftRastEvent::Error(err));
+        event_loop.exit();
+    }
+    /// The cursor is shown and moves freely instead of steering the camera.
+    fn cursor_free(&self) -> bool {
+        self.editor.active || !self.cursor_grab
+    }
 }
 
 impl ApplicationHandler for AppContext {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
             let mut attributes = WindowAttributes::default();
-            attributes.inner_size = Some(Size::new(PhysicalSize::new(WIDTH, HEIGHT)));
+            let (width, height) = (self.config.width as f32, self.config.height as f32);
+            attributes.inner_size = Some(Size::new(PhysicalSize::new(width, height)));
             if let Some(monitor) = event_loop.primary_monitor() {
-                let (x,y) = (monitor.size().width / 2,monitor.size().height / 2);
-                attributes.position = Some(Position::from(LogicalPosition::new(x as f32 - WIDTH / 2. , y as f32 - HEIGHT / 2.)));
+                let (x, y) = (monitor.size().width / 2, monitor.size().height / 2);
+                attributes.position = Some(Position::from(LogicalPosition::new(
+                    x as f32 - width / 2.,
+                    y as f32 - height / 2.,
+                )));
+            }
+            if self.config.fullscreen {
+                attributes.fullscreen = Some(Fullscreen::Borderless(None));
             }
 
             let window = match event_loop.create_window(attributes) {
                 Ok(window) => Rc::new(window),
-                Err(err) => {
-                    panic!("{}", err);
-                }
+                Err(err) => return self.fail_window(event_loop, err.to_string()),
             };
             let context = match Context::new(window.clone()) {
                 Ok(context) => context,
-                Err(err) => {
-                    panic!("{}", err);
-                }
+                Err(err) => return self.fail_window(event_loop, err.to_string()),
             };
             let surface = match Surface::new(&context, window.clone()) {
                 Ok(surface) => surface,
-                Err(err) => {
-                    panic!("{}", err);
-                }
+                Err(err) => return self.fail_window(event_loop, err.to_string()),
             };
 
-            if let Err(err) = window.set_cursor_grab(CursorGrabMode::Confined) {
-                eprintln!("{:?}", err);
+            if let Err(err) = apply_cursor(&window, event_loop, &self.cursor, self.cursor_free()) {
+                self.user_state
+                    .handle_event(&mut self.command, SoftRastEvent::Error(err));
             }
-            window.set_cursor_visible(false);
-
 
             self.window = Some(window.clone());
             self.context = Some(context);
@@ -166,42 +791,396 @@ impl ApplicationHandler for AppContext {
             .handle_event(&mut self.command, SoftRastEvent::Resume {});
     }
     fn window_event(&mut self, event_loop: &ActiveEventLoop, _: WindowId, event: WindowEvent) {
-        let window = self.window.as_ref().expect("Couldn't get the window.");
-        let surface = self.surface.as_mut().expect("Couldn't get the surface.");
+        let (Some(window), Some(_)) = (self.window.as_ref(), self.surface.as_ref()) else {
+            return;
+        };
 
-        for command in self.command.commands.iter() {
+        let mut events = vec![];
+        for command in std::mem::take(&mut self.command.commands) {
             match command {
                 SoftRastCommand::SetTitle(title) => {
                     window.set_title(&title);
                 }
+                SoftRastCommand::ShowStats(show) => {
+                    self.show_stats = show;
+                }
+                SoftRastCommand::RegisterConsoleCommand { name, help } => {
+                    self.console.register_forwarded(&name, &help);
+                }
+                SoftRastCommand::ConsolePrint(text) => {
+                    self.console.print(&text);
+                }
+                SoftRastCommand::ReportError(err) => {
+                    events.push(SoftRastEvent::Error(err));
+                }
+                SoftRastCommand::StartRecording(path) => {
+                    self.replay = None;
+                    self.recorder = Some(InputRecorder::new(&path, self.command.time));
+                }
+                SoftRastCommand::StopRecording => {
+                    if let Some(recorder) = self.recorder.take() {
+                        match recorder.save() {
+                            Ok(()) => self.console.print(&format!(
+                                "saved {} input frames to {}",
+                                recorder.frame_count(),
+                                recorder.path()
+                            )),
+                            Err(err) => events.push(SoftRastEvent::Error(err)),
+                        }
+                    }
+                }
+                SoftRastCommand::StartReplay(path) => {
+                    self.recorder = None;
+                    self.replay = None;
+                    match InputReplay::load(&path) {
+                        Ok(replay) => {
+                            self.console.print(&format!(
+                                "replaying {} input frames from {}",
+                                replay.frame_count(),
+                                path
+                            ));
+                            self.command.time = replay.start;
+                            self.replay = Some(replay);
+                        }
+                        Err(err) => events.push(SoftRastEvent::Error(err)),
+                    }
+                }
+                SoftRastCommand::BakeProbes => {
+                    if let Some(scene) = &mut self.scene {
+                        let positions = scene
+                            .entities
+                            .iter()
+                            .map(|e| Point3::from(e.position.translation.vector))
+                            .collect::<Vec<_>>();
+                        if let Some(first) = positions.first() {
+                            let (min, max) = positions
+                                .iter()
+                                .fold((*first, *first), |(min, max), p| (min.inf(p), max.sup(p)));
+                            let margin = Vector3::new(1.0, 1.0, 1.0);
+                            let mut grid =
+                                IrradianceGrid::new(min - margin, max + margin, [4, 2, 4]);
+                            grid.bake(scene, 16);
+                            scene.irradiance = Some(grid);
+                            self.console.print("baked irradiance probes");
+                        }
+                    }
+                }
+                SoftRastCommand::StartBenchmark(config) => {
+                    self.benchmark = Some(Benchmark::new(config));
+                }
+                SoftRastCommand::StopBenchmark => {
+                    self.benchmark = None;
+                }
+                SoftRastCommand::Spawn(entity) => {
+                    self.scene
+                        .get_or_insert_with(Scene::new)
+                        .entities
+                        .push(*entity);
+                }
+                SoftRastCommand::Despawn(id) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.entities.retain(|entity| entity.handle != Some(id));
+                    }
+                }
+                SoftRastCommand::PlayAnimation { id, state, fade } => {
+                    if let Some(animation) = self
+                        .scene
+                        .as_mut()
+                        .and_then(|scene| scene.entity_mut(id))
+                        .and_then(|entity| entity.animation.as_mut())
+                    {
+                        animation.play(&state, fade);
+                    }
+                }
+                SoftRastCommand::SetAnimationParameter { id, name, value } => {
+                    if let Some(animation) = self
+                        .scene
+                        .as_mut()
+                        .and_then(|scene| scene.entity_mut(id))
+                        .and_then(|entity| entity.animation.as_mut())
+                    {
+                        animation.set_parameter(&name, value);
+                    }
+                }
+                SoftRastCommand::ShowGrid(show) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.grid = show.then(Grid::default);
+                    }
+                }
+                SoftRastCommand::ShowIndexLabels(labels) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.index_labels = labels;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::ShowFrusta(show) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.show_frusta = show;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetCameraRelative(camera_relative) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.set_camera_relative(camera_relative);
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetAutoExposure(exposure) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.exposure = exposure;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetPalette(palette) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.palette = palette;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetStaticCaching(enabled) => {
+                    self.static_caching = enabled;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::Redraw => {
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetDepthCue(depth_cue) => {
+                    self.draw_mode.depth_cue = depth_cue;
+                }
+                SoftRastCommand::SetTriangleFiller(filler) => {
+                    self.draw_mode.filler = filler;
+                }
+                SoftRastCommand::SetDeterministic(deterministic) => {
+                    determinism::set_seed(deterministic.map(|deterministic| deterministic.seed));
+                    self.deterministic = deterministic;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetRenderThreads(threads) => {
+                    self.render_pool = threads.build_pool();
+                    self.render_threads = threads;
+                }
+                SoftRastCommand::SetCollapseSubpixel(collapse) => {
+                    self.draw_mode.collapse_subpixel = collapse;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetReferenceRasterizer(reference) => {
+                    self.draw_mode.reference = reference;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetPerspectiveCorrect(perspective_correct) => {
+                    self.draw_mode.perspective_correct = perspective_correct;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetWireStyle(style) => {
+                    self.draw_mode.wire_style = style;
+                }
+                SoftRastCommand::SetRayTraced { enabled, shadows } => {
+                    self.draw_mode.ray_traced = enabled;
+                    self.draw_mode.shadows = shadows;
+                }
+                SoftRastCommand::SetInterlace(interlace) => {
+                    self.interlace = interlace;
+                }
+                SoftRastCommand::SetOit(enabled) => {
+                    self.oit = enabled;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetIdBuffer(enabled) => {
+                    self.id_buffer = enabled;
+                }
+                SoftRastCommand::Pick { x, y } => {
+                    let window_size = (window.inner_size().width, window.inner_size().height);
+                    let pixel = self
+                        .render_target
+                        .as_ref()
+                        .and_then(|target| target.id_at_window(x, y, window_size));
+                    let handle = pixel
+                        .zip(self.scene.as_ref())
+                        .and_then(|(pixel, scene)| scene.entities.get(pixel.entity))
+                        .and_then(|entity| entity.handle);
+                    events.push(SoftRastEvent::Picked {
+                        x,
+                        y,
+                        pixel,
+                        handle,
+                    });
+                }
+                SoftRastCommand::SetWindowIcon(icon) => {
+                    if let Err(err) = apply_window_icon(window, icon.as_ref()) {
+                        events.push(SoftRastEvent::Error(err));
+                    }
+                }
+                SoftRastCommand::SetCursor(style) => {
+                    self.cursor = style;
+                    if let Err(err) =
+                        apply_cursor(window, event_loop, &self.cursor, self.cursor_free())
+                    {
+                        events.push(SoftRastEvent::Error(err));
+                    }
+                }
+                SoftRastCommand::SetCursorGrab(grab) => {
+                    self.cursor_grab = grab;
+                    if let Err(err) =
+                        apply_cursor(window, event_loop, &self.cursor, self.cursor_free())
+                    {
+                        events.push(SoftRastEvent::Error(err));
+                    }
+                }
+                SoftRastCommand::DumpFrameGraph { dot } => {
+                    self.frame_graph_request = Some(dot);
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetConfig(config) => {
+                    let quality = config.quality;
+                    self.static_caching = quality.static_caching;
+                    self.oit = quality.oit;
+                    self.show_stats = quality.show_stats;
+                    self.draw_mode.shadows = quality.shadows;
+                    self.draw_mode.perspective_correct = quality.perspective_correct;
+                    self.draw_mode.collapse_subpixel = quality.collapse_subpixel;
+                    self.input.bindings = Arc::new(config.bindings.clone());
+                    if (config.width, config.height) != (self.config.width, self.config.height) {
+                        let _ = window
+                            .request_inner_size(PhysicalSize::new(config.width, config.height));
+                    }
+                    if config.fullscreen != self.config.fullscreen {
+                        let mode = match config.fullscreen {
+                            true => FullscreenMode::Borderless { monitor: None },
+                            false => FullscreenMode::Windowed,
+                        };
+                        if let Err(err) = apply_fullscreen(window, event_loop, &mode) {
+                            events.push(SoftRastEvent::Error(err));
+                        }
+                    }
+                    self.command.config = config.clone();
+                    self.config = config;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SaveConfig => {
+                    let path = self.config_path.as_deref().unwrap_or(DEFAULT_CONFIG_PATH);
+                    match self.config.save(path) {
+                        Ok(_) => self.console.print(&format!("Saved settings to {}", path)),
+                        Err(err) => events.push(SoftRastEvent::Error(err)),
+                    }
+                }
+                SoftRastCommand::ListMonitors => {
+                    events.push(SoftRastEvent::Monitors(monitors(event_loop)));
+                }
+                SoftRastCommand::SetFullscreen(mode) => {
+                    if let Err(err) = apply_fullscreen(window, event_loop, &mode) {
+                        events.push(SoftRastEvent::Error(err));
+                    }
+                }
+                SoftRastCommand::SetTimeScale(scale) => {
+                    if scale.is_finite() {
+                        self.time_scale = scale.clamp(0.0, 1000.0);
+                    }
+                }
+                SoftRastCommand::SetPaused(paused) => {
+                    self.paused = paused;
+                }
+                SoftRastCommand::StepFrame => {
+                    self.step = true;
+                }
+                SoftRastCommand::LoadModel(path) => {
+                    self.loader.request_model(&path);
+                }
+                SoftRastCommand::LoadTexture(path) => {
+                    self.loader.request_texture(&path);
+                }
+                SoftRastCommand::SetStereo(stereo) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.stereo = stereo;
+                    }
+                }
+                SoftRastCommand::SetOutline(outline) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.outline = outline;
+                    }
+                }
+                SoftRastCommand::SetPortals(portals) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.portals = portals;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetSky(sky) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.sky = sky;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetProjection {
+                    projection,
+                    tessellation,
+                } => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.projection = projection;
+                        scene.tessellation = tessellation.max(1);
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetQualityGovernor(governor) => {
+                    self.governor = governor;
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::SetTaa(enabled) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.taa = enabled.then(Taa::default);
+                    }
+                }
+                SoftRastCommand::SetAccumulation(accumulation) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.accumulation = accumulation;
+                    }
+                    self.last_fingerprint = None;
+                }
+                SoftRastCommand::ShowMinimap(show) => {
+                    if let Some(scene) = &mut self.scene {
+                        scene.insets.retain(|inset| inset.follow_height.is_none());
+                        if show {
+                            scene.insets.push(Inset::minimap(30.0));
+                        }
+                    }
+                }
                 SoftRastCommand::SetRenderingMode {
                     shaded,
                     wireframe,
                     points,
                 } => {
                     self.draw_mode = DrawMode {
-                        shaded: *shaded,
-                        wireframe: *wireframe,
-                        points: *points,
+                        shaded,
+                        wireframe,
+                        points,
+                        ..self.draw_mode
                     }
                 }
             }
         }
-
-        self.command.commands.clear();
+        for (path, result) in self.loader.poll() {
+            events.push(match result {
+                Ok(asset) => SoftRastEvent::Loaded { path, asset },
+                Err(err) => SoftRastEvent::Error(err),
+            });
+        }
+        for event in events {
+            self.user_state.handle_event(&mut self.command, event);
+        }
 
         match event {
             WindowEvent::Resized(size) => {
                 if size.width == 0 || size.height == 0 {
                     self.render_target = None;
-                    thread::sleep(Duration::from_secs_f32(0.015));
                     return;
                 }
+                window.request_redraw();
                 let (width, height) = { (size.width, size.height) };
-                if let Err(err) = surface.resize(
-                    NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
-                    NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
-                ) {
+                if let Some(surface) = self.surface.as_mut()
+                    && let Err(err) = surface.resize(
+                        NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
+                        NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
+                    )
+                {
                     eprintln!("{}", err);
                 }
                 self.render_target = Some(RenderTarget::new(width, height));
@@ -214,68 +1193,393 @@ impl ApplicationHandler for AppContext {
             WindowEvent::CloseRequested => {
                 event_loop.exit();
             }
+            WindowEvent::Occluded(occluded) => {
+                self.occluded = occluded;
+                if !occluded {
+                    window.request_redraw();
+                }
+            }
+            // Not every platform reports occlusion, focus is the fallback for waking up
+            WindowEvent::Focused(true) => {
+                window.request_redraw();
+            }
             WindowEvent::RedrawRequested => {
-                let delta = self.timer.elapsed();
+                let mut delta = self.timer.elapsed();
                 self.timer = Instant::now();
                 let (width, height) = {
                     let size = window.inner_size();
                     (size.width, size.height)
                 };
-
-                self.user_state.handle_event(
-                    &mut self.command,
-                    SoftRastEvent::Update {
-                        delta,
-                        input: self.input.clone(),
-                    },
+                // Stop the redraw loop while nothing can be seen, `Occluded`, `Resized` and `Focused`
+                // start it again
+                if self.occluded || window.is_minimized() == Some(true) || width == 0 || height == 0
+                {
+                    self.suspended = true;
+                    return;
+                }
+                if std::mem::take(&mut self.suspended) {
+                    delta = STEP_DELTA;
+                }
+                if let Some(deterministic) = self.deterministic {
+                    delta = deterministic.frame_delta;
+                }
+                // Frames are drawn at the render scale and scaled to the window when that differs
+                let scale = self.config.render_scale;
+                let render_size = (
+                    ((width as f32 * scale).round() as u32).max(1),
+                    ((height as f32 * scale).round() as u32).max(1),
                 );
-                self.input.reset_mouse_motion();
+                let present_size = (render_size != (width, height)).then_some((width, height));
+                // Mode switches can skip or reorder `Resized`, presenting a stale size would panic
+                let stale = self
+                    .render_target
+                    .as_ref()
+                    .is_none_or(|target| target.size() != render_size)
+                    || self.present_target.as_ref().map(RenderTarget::size) != present_size;
+                if stale {
+                    if let Some(surface) = self.surface.as_mut()
+                        && let Err(err) = surface.resize(
+                            NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
+                            NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
+                        )
+                    {
+                        eprintln!("{}", err);
+                    }
+                    self.render_target = Some(RenderTarget::new(render_size.0, render_size.1));
+                    self.present_target =
+                        present_size.map(|(width, height)| RenderTarget::new(width, height));
+                }
+
+                self.input.window_size = (width, height);
+                if let Some(replay) = &mut self.replay {
+                    match replay.next_frame() {
+                        Some((recorded_delta, input)) => {
+                            delta = recorded_delta;
+                            self.input = InputState {
+                                bindings: self.input.bindings.clone(),
+                                ..input
+                            };
+                        }
+                        None => {
+                            self.console.print("replay finished");
+                            self.replay = None;
+                            self.input = InputState {
+                                bindings: self.input.bindings.clone(),
+                                ..InputState::default()
+                            };
+                        }
+                    }
+                }
+                if let Some(recorder) = &mut self.recorder {
+                    recorder.record(delta, &self.input);
+                }
+                // Simulation time, the real delta still times the frame
+                let sim_delta = match (self.paused, std::mem::take(&mut self.step)) {
+                    (false, _) => delta.mul_f32(self.time_scale),
+                    (true, true) => STEP_DELTA.mul_f32(self.time_scale),
+                    (true, false) => Duration::ZERO,
+                };
+                self.command.time += sim_delta;
+                if self.console.open {
+                    self.input.reset_mouse_motion();
+                }
+                if !self.editor.active && self.benchmark.is_none() {
+                    self.user_state.handle_event(
+                        &mut self.command,
+                        SoftRastEvent::Update {
+                            delta: sim_delta,
+                            input: self.input.clone(),
+                        },
+                    );
+                }
 
+                let cursor_free = self.cursor_free();
                 if let Some(target) = &mut self.render_target {
-                    target.clear();
+                    target.set_interlace(self.interlace);
+                    target.set_oit(self.oit);
+                    target.set_id_buffer(self.id_buffer || self.editor.active);
+                    target.set_deterministic(self.deterministic.is_some());
+                    // The governor reacts to frame times, which deterministic renders can't
+                    let governor = self
+                        .governor
+                        .as_mut()
+                        .filter(|_| self.deterministic.is_none());
+                    let draw_mode = match governor {
+                        Some(governor) => {
+                            // The fingerprint can't see the overrides, so changes force a redraw
+                            if governor.update(delta.as_secs_f32()) {
+                                self.last_fingerprint = None;
+                            }
+                            target.set_quality_overrides(governor.overrides());
+                            governor.draw_mode(&self.draw_mode)
+                        }
+                        None => {
+                            target.set_quality_overrides(QualityOverrides::default());
+                            self.draw_mode
+                        }
+                    };
 
-                    if let Some(scene) = &mut self.scene {
-                        self.user_state.handle_event(
-                            &mut self.command,
-                            SoftRastEvent::Render { delta, scene },
+                    let time = self.command.elapsed().as_secs_f32();
+                    let scene = if let Some(benchmark) = &mut self.benchmark {
+                        benchmark.update(delta, target.size());
+                        &mut benchmark.scene
+                    } else {
+                        let scene = self.scene.get_or_insert_with(Scene::new);
+                        if self.editor.active {
+                            self.editor.update(&self.input, delta, scene, target);
+                        } else {
+                            self.user_state.handle_event(
+                                &mut self.command,
+                                SoftRastEvent::Render {
+                                    delta: sim_delta,
+                                    scene,
+                                },
+                            );
+                            #[cfg(feature = "scripting")]
+                            self.scripts.update(scene, sim_delta.as_secs_f32());
+                            if let Some(streaming) = &mut scene.streaming {
+                                streaming.update(&mut self.command, &scene.camera);
+                            }
+                            #[cfg(feature = "physics")]
+                            if let Some(physics) = &mut scene.physics {
+                                let errors = physics.update(
+                                    &mut scene.entities,
+                                    &mut scene.debug,
+                                    sim_delta.as_secs_f32(),
+                                );
+                                for err in errors {
+                                    self.user_state
+                                        .handle_event(&mut self.command, SoftRastEvent::Error(err));
+                                }
+                            }
+                            let changes = track_visibility(scene);
+                            if !changes.is_empty() {
+                                self.user_state.handle_event(
+                                    &mut self.command,
+                                    SoftRastEvent::Visibility(changes),
+                                );
+                            }
+                        }
+                        scene
+                    };
+                    if self.show_stats {
+                        let triangles = scene
+                            .entities
+                            .iter()
+                            .map(|entity| entity.active_model().triangle_count())
+                            .sum::<usize>();
+                        let stats = format!(
+                            "{:.1} fps {:.2} ms\nentities {}\ntriangles {}",
+                            1.0 / delta.as_secs_f32(),
+                            delta.as_secs_f32() * 1000.0,
+                            scene.entities.len(),
+                            triangles
                         );
-                        let camera = &scene.camera;
-                        for entity in &scene.entities {
-                            target.draw(&entity, camera, &self.draw_mode);
+                        scene.debug.text(
+                            4,
+                            render_size.1 as i32 - 40,
+                            &stats,
+                            Color::new(1.0, 1.0, 1.0, 1.0),
+                        );
+                    }
+                    for sprite in &mut scene.sprites {
+                        sprite.update(sim_delta.as_secs_f32());
+                    }
+                    for entity in &mut scene.entities {
+                        if let Some(animation) = &mut entity.animation {
+                            animation.update(sim_delta.as_secs_f32());
+                            entity.attachment_points = animation.attachment_points();
+                        }
+                    }
+                    let fingerprint = (self.static_caching || scene.accumulation.is_some())
+                        .then(|| scene_fingerprint(scene, &draw_mode, target.size()))
+                        .flatten();
+                    let unchanged = fingerprint.is_some() && fingerprint == self.last_fingerprint;
+                    if unchanged {
+                        self.unchanged_frames += 1;
+                    } else {
+                        self.unchanged_frames = 0;
+                    }
+                    self.last_fingerprint = fingerprint;
+                    // A converged still is kept until the scene changes, one in progress is drawn
+                    let (accumulating, converged) = match &mut scene.accumulation {
+                        Some(accumulation) => {
+                            accumulation.track(unchanged);
+                            (!accumulation.converged(), accumulation.converged())
                         }
+                        None => (false, false),
+                    };
+                    // Interlaced images only settle after both halves saw the same scene
+                    let cached = self.static_caching && self.unchanged_frames >= 2 && !accumulating;
+                    if (converged || cached) && target.restore_history() {
+                        scene.debug.clear();
                     } else {
-                        self.scene = Some(Scene {
-                            entities: vec![],
-                            camera: Camera::default(),
+                        target.clear();
+                        target.set_tile_size(self.render_threads.tile_size());
+                        let dot = self.frame_graph_request.take().inspect(|_| {
+                            scene.frame_graph = Some(FrameGraph::default());
                         });
+                        match &self.render_pool {
+                            Some(pool) => {
+                                pool.install(|| draw_scene(target, scene, &draw_mode, time))
+                            }
+                            None => draw_scene(target, scene, &draw_mode, time),
+                        }
+                        if let Some(graph) = scene.frame_graph.take() {
+                            if let Some(path) = dot.flatten()
+                                && let Err(source) = std::fs::write(&path, graph.to_dot())
+                            {
+                                let err = SoftRastError::Io { path, source };
+                                self.user_state
+                                    .handle_event(&mut self.command, SoftRastEvent::Error(err));
+                            }
+                            self.user_state
+                                .handle_event(&mut self.command, SoftRastEvent::FrameGraph(graph));
+                        }
+                        target.keep_history();
+                    }
+                    if self
+                        .benchmark
+                        .as_ref()
+                        .is_some_and(|benchmark| benchmark.finished())
+                    {
+                        self.benchmark = None;
                     }
-                    if let Ok(mut buffer) = surface.buffer_mut() {
-                        buffer.copy_from_slice(target.color.as_slice());
+                    // Overlays go on the window sized image so they stay sharp at any render scale
+                    let output = match &mut self.present_target {
+                        Some(present) => {
+                            let (source, window) = (target.size(), present.size());
+                            let src = Rect::new(0, 0, source.0, source.1);
+                            let dst = Rect::new(0, 0, window.0, window.1);
+                            present.blit(target, src, dst, BlitFilter::Bilinear);
+                            present
+                        }
+                        None => target,
+                    };
+                    if self.console.open {
+                        self.console.draw(output);
+                    }
+                    if cursor_free {
+                        let (x, y) = (self.input.cursor_x as i32, self.input.cursor_y as i32);
+                        self.cursor.draw(output, x, y);
+                    }
+                    if let Some(surface) = self.surface.as_mut()
+                        && let Ok(mut buffer) = surface.buffer_mut()
+                    {
+                        buffer.copy_from_slice(output.color.as_slice());
                         if let Err(err) = buffer.present() {
                             eprintln!("{}", err);
                         }
                     }
                 }
 
-                window.set_title(&format!(
+                self.input.reset_mouse_motion();
+
+                let mut title = format!(
                     "Software Renderer Windowed {}x{} @ {:?}",
                     width,
                     height,
                     1.0 / delta.as_secs_f32()
-                ));
+                );
+                if self.editor.active {
+                    title = format!("{} | {}", title, self.editor.status(self.scene.as_ref()));
+                }
+                window.set_title(&title);
                 window.request_redraw();
             }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.input.cursor_x = position.x;
+                self.input.cursor_y = position.y;
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                if state.is_pressed() {
+                    self.input.pressed_buttons.insert(button);
+                } else {
+                    self.input.pressed_buttons.remove(&button);
+                }
+            }
             WindowEvent::KeyboardInput {
                 event,
                 is_synthetic: false,
                 ..
             } => {
-                if event.state.is_pressed() {
+                let toggle = matches!(&event.logical_key, Key::Character(ch) if ch.as_str() == "`");
+                if self.console.open && event.state.is_pressed() {
+                    match &event.logical_key {
+                        Key::Named(NamedKey::Escape) => self.console.open = false,
+                        Key::Named(NamedKey::Enter) => {
+                            if let Some((name, args)) = self.console.submit(&mut self.command) {
+                                self.user_state.handle_event(
+                                    &mut self.command,
+                                    SoftRastEvent::ConsoleCommand { name, args },
+                                );
+                            }
+                        }
+                        Key::Named(NamedKey::Backspace) => self.console.backspace(),
+                        _ if toggle => self.console.open = false,
+                        _ => {
+                            if let Some(text) = &event.text {
+                                self.console.type_text(text);
+                            }
+                        }
+                    }
+                } else if toggle && event.state.is_pressed() {
+                    self.console.open = true;
+                    // Keys held when the console opens would otherwise stay pressed
+                    self.input.pressed_keys.clear();
+                } else if event.state.is_pressed() {
                     match event.logical_key {
                         Key::Named(name) => match name {
                             NamedKey::Escape => {
                                 event_loop.exit();
                             }
+                            NamedKey::F1 => {
+                                self.editor.toggle(self.scene.as_ref());
+                                if let Err(err) = apply_cursor(
+                                    window,
+                                    event_loop,
+                                    &self.cursor,
+                                    self.cursor_free(),
+                                ) {
+                                    eprintln!("{}", err);
+                                }
+                            }
+                            NamedKey::F6 => {
+                                if self.recorder.is_some() {
+                                    self.command.stop_recording();
+                                } else {
+                                    self.command.start_recording(RECORDING_PATH);
+                                }
+                            }
+                            NamedKey::F7 => {
+                                self.command.start_replay(RECORDING_PATH);
+                            }
+                            NamedKey::F5 if self.editor.active => {
+                                if let Some(scene) = &self.scene {
+                                    match save_layout(scene, LAYOUT_PATH) {
+                                        Ok(()) => self
+                                            .console
+                                            .print(&format!("saved layout to {}", LAYOUT_PATH)),
+                                        Err(err) => self.user_state.handle_event(
+                                            &mut self.command,
+                                            SoftRastEvent::Error(err),
+                                        ),
+                                    }
+                                }
+                            }
+                            NamedKey::F9 if self.editor.active => {
+                                if let Some(scene) = &mut self.scene {
+                                    match load_layout(scene, LAYOUT_PATH) {
+                                        Ok(()) => self
+                                            .console
+                                            .print(&format!("loaded layout from {}", LAYOUT_PATH)),
+                                        Err(err) => self.user_state.handle_event(
+                                            &mut self.command,
+                                            SoftRastEvent::Error(err),
+                                        ),
+                                    }
+                                }
+                            }
                             _ => {}
                         },
                         Key::Character(ch) => {
@@ -283,13 +1587,8 @@ impl ApplicationHandler for AppContext {
                         }
                         _ => {}
                     }
-                } else {
-                    match event.logical_key {
-                        Key::Character(ch) => {
-                            self.input.pressed_keys.remove(ch.as_str());
-                        }
-                        _ => {}
-                    }
+                } else if let Key::Character(ch) = event.logical_key {
+                    self.input.pressed_keys.remove(ch.as_str());
                 }
             }
             _ => {}
@@ -301,37 +1600,482 @@ impl ApplicationHandler for AppContext {
         _device_id: DeviceId,
         event: DeviceEvent,
     ) {
-        match event {
-            DeviceEvent::MouseMotion { delta } => {
-                self.input.mouse_dx = delta.0;
-                self.input.mouse_dy = delta.1;
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.input.mouse_dx = delta.0;
+            self.input.mouse_dy = delta.1;
+        }
+    }
+}
+
+/// Draws every pass of a scene into the target: mirror and water reflections, entities, the grid and
+/// the debug overlay, which is cleared afterwards.
+fn draw_scene(target: &mut RenderTarget, scene: &mut Scene, mode: &DrawMode, time: f32) {
+    scene.update_attachments();
+    for index in 0..scene.entities.len() {
+        if let Some(mirror) = scene.entities[index].mirror {
+            let start = Instant::now();
+            let texture = render_reflection(scene, index, &mirror, target.size(), mode);
+            record_reflection_pass(scene, index, "mirror", texture_size(&texture), start);
+            scene.entities[index].set_material(
+                0,
+                Material::Mirror {
+                    texture,
+                    sampler: Sampler::default(),
+                },
+            );
+        }
+        if let Some(water) = scene.entities[index].water.clone() {
+            let mirror = Mirror {
+                normal: Vector3::y(),
+                resolution_scale: water.resolution_scale,
+            };
+            let start = Instant::now();
+            let texture = render_reflection(scene, index, &mirror, target.size(), mode);
+            record_reflection_pass(scene, index, "water", texture_size(&texture), start);
+            scene.entities[index].set_material(0, WaterShader::new(water, Some(texture)));
+        }
+    }
+    let insets = scene.insets.clone();
+    let inset_cameras = insets
+        .iter()
+        .map(|inset| inset.view_camera(&scene.camera, target.size()))
+        .collect::<Vec<_>>();
+    let views = insets
+        .iter()
+        .zip(&inset_cameras)
+        .enumerate()
+        .map(|(index, (inset, camera))| {
+            let start = Instant::now();
+            let (_, _, width, height) = inset.rect(target.size());
+            let mut view = RenderTarget::new(width, height);
+            view.clear();
+            draw_view(&mut view, scene, camera, mode, time, false);
+            if scene.show_frusta {
+                let others = inset_cameras
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(_, camera)| camera);
+                view.draw_debug(&frusta_debug(&scene.camera, others), camera);
             }
-            _ => {}
+            let name = format!("inset {}", index);
+            record_pass(scene, &name, &name, (width, height), &["scene"], start);
+            view
+        })
+        .collect::<Vec<_>>();
+    let camera = scene.camera;
+    let size = target.size();
+    let start = Instant::now();
+    match scene.stereo {
+        Some(stereo) => {
+            draw_stereo(target, scene, &stereo, mode, time);
+            record_pass(scene, "stereo views", "frame", size, &["scene"], start);
+        }
+        None => {
+            if scene.outline.is_some() {
+                target.set_id_buffer(true);
+            }
+            let camera = match (&scene.accumulation, &scene.taa) {
+                (Some(accumulation), _) => accumulation.jittered(&camera, target.size()),
+                (None, Some(taa)) => taa.jittered(&camera, target.size()),
+                (None, None) => camera,
+            };
+            draw_view(target, scene, &camera, mode, time, true);
+            if let Some((first, rest)) = inset_cameras.split_first().filter(|_| scene.show_frusta) {
+                target.draw_debug(&frusta_debug(first, rest.iter()), &camera);
+            }
+            record_pass(scene, "main view", "frame", size, &["scene"], start);
+            if let Some(outline) = &scene.outline {
+                let start = Instant::now();
+                outline.apply(target, &camera);
+                record_pass(scene, "outline", "frame", size, &["frame", "ids"], start);
+            }
+            let start = Instant::now();
+            if let Some(accumulation) = &mut scene.accumulation {
+                accumulation.add(target);
+                record_pass(
+                    scene,
+                    "accumulation",
+                    "frame",
+                    size,
+                    &["frame", "accumulated"],
+                    start,
+                );
+            } else if let Some(taa) = &mut scene.taa {
+                taa.resolve(target, &camera);
+                record_pass(
+                    scene,
+                    "taa resolve",
+                    "frame",
+                    size,
+                    &["frame", "history"],
+                    start,
+                );
+            }
+        }
+    }
+    if let Some(exposure) = &mut scene.exposure {
+        let start = Instant::now();
+        exposure.apply(target, time);
+        record_pass(scene, "exposure", "frame", size, &["frame"], start);
+    }
+    let start = Instant::now();
+    for (inset, view) in insets.iter().zip(&views) {
+        let (x, y, width, height) = inset.rect(target.size());
+        target.copy_rect(view, view.rect(), x, y);
+        if let Some(border) = inset.border {
+            target.blend_rect(x - 1, y - 1, width + 2, 1, border);
+            target.blend_rect(x - 1, y + height as i32, width + 2, 1, border);
+            target.blend_rect(x - 1, y, 1, height, border);
+            target.blend_rect(x + width as i32, y, 1, height, border);
         }
     }
+    for panel in &scene.panels {
+        panel.draw(target);
+    }
+    for sprite in &scene.sprites {
+        sprite.draw(target);
+    }
+    let mut reads = vec!["frame".to_owned()];
+    reads.extend((0..views.len()).map(|index| format!("inset {}", index)));
+    let reads = reads.iter().map(String::as_str).collect::<Vec<_>>();
+    record_pass(scene, "insets and hud", "frame", size, &reads, start);
+    if let Some(palette) = &scene.palette {
+        let start = Instant::now();
+        palette.apply(target);
+        record_pass(scene, "palette", "frame", size, &["frame"], start);
+    }
+    scene.debug.clear();
 }
 
-pub fn run() {
-    match EventLoop::new() {
-        Ok(event_loop) => match event_loop.run_app(&mut AppContext::new(MyApp::default())) {
-            Ok(_) => {}
-            Err(err) => {
-                eprintln!("{}", err);
+/// Adds a pass to the frame graph being collected, if any.
+fn record_pass(
+    scene: &mut Scene,
+    name: &str,
+    target: &str,
+    size: (u32, u32),
+    reads: &[&str],
+    start: Instant,
+) {
+    if let Some(graph) = &mut scene.frame_graph {
+        graph.record(name, target, size, reads, start);
+    }
+}
+
+/// `record_pass` for the `kind` reflection of the entity at `index`, the name is only built
+/// while a frame graph is recorded.
+fn record_reflection_pass(
+    scene: &mut Scene,
+    index: usize,
+    kind: &str,
+    size: (u32, u32),
+    start: Instant,
+) {
+    if let Some(graph) = &mut scene.frame_graph {
+        let name = format!("{} reflection {}", kind, scene.entities[index].id);
+        graph.record(&name, &name, size, &["scene"], start);
+    }
+}
+
+fn texture_size(texture: &Texture) -> (u32, u32) {
+    (texture.texture.width(), texture.texture.height())
+}
+
+/// Frustum outlines of `main` and `others`, the first in yellow and the rest in cyan.
+fn frusta_debug<'a>(main: &Camera, others: impl Iterator<Item = &'a Camera>) -> DebugDraw {
+    const DISTANCE: f32 = 25.0;
+    let mut debug = DebugDraw::default();
+    debug.frustum(main, DISTANCE, Color::new(1.0, 0.9, 0.2, 1.0));
+    for camera in others {
+        debug.frustum(camera, DISTANCE, Color::new(0.2, 0.9, 1.0, 1.0));
+    }
+    debug
+}
+
+fn draw_stereo(
+    target: &mut RenderTarget,
+    scene: &mut Scene,
+    stereo: &Stereo,
+    mode: &DrawMode,
+    time: f32,
+) {
+    let [left, right] = stereo.eye_cameras(&scene.camera);
+    let (width, height) = target.size();
+    match stereo.mode {
+        StereoMode::Anaglyph => {
+            let mut right_view = RenderTarget::new(width, height);
+            right_view.clear();
+            draw_view(&mut right_view, scene, &right, mode, time, true);
+            draw_view(target, scene, &left, mode, time, true);
+            target.copy_channels(&right_view, 0x00ffff);
+        }
+        StereoMode::SideBySide => {
+            let half = (width / 2).max(1);
+            for (eye, x) in [(left, 0), (right, half as i32)] {
+                let mut view = RenderTarget::new(half, height);
+                view.clear();
+                draw_view(&mut view, scene, &eye, mode, time, true);
+                target.copy_rect(&view, view.rect(), x, 0);
+            }
+        }
+    }
+}
+
+/// Draws the entities layer by layer as seen from `camera`, with the grid and debug drawing
+/// when `overlays` is set.
+fn draw_view(
+    target: &mut RenderTarget,
+    scene: &mut Scene,
+    camera: &Camera,
+    mode: &DrawMode,
+    time: f32,
+    overlays: bool,
+) {
+    target.set_clip_planes(&scene.clip_planes, scene.clip_cap);
+    target.set_camera_relative(scene.camera_relative);
+    target.set_projection(scene.projection, scene.tessellation);
+    target.set_time(time);
+    let hybrid_shadows = mode.shadows && !mode.ray_traced;
+    target.set_shadow_casters(hybrid_shadows.then(|| Arc::new(ShadowCasters::new(scene))));
+    if let Some(sky) = &scene.sky {
+        sky.draw(target, camera, scene.light_dir());
+    }
+    if mode.ray_traced {
+        ray_trace(target, scene, camera, time, mode.shadows);
+        if overlays {
+            draw_world_overlays(target, scene, camera);
+        }
+        return;
+    }
+    let camera_position = camera.world_position();
+    let visible = cull(scene, camera);
+    // Entities that move their vertices on the fly can leave their model bounds
+    let mut order = (0..scene.entities.len())
+        .filter(|index| {
+            let entity = &scene.entities[*index];
+            visible[*index]
+                || entity.vertex_modifier.is_some()
+                || entity.geometry_hook.is_some()
+                || entity.displacement.is_some()
+                || entity.water.is_some()
+        })
+        .collect::<Vec<_>>();
+    order.sort_by(|a, b| draw_order(&scene.entities[*a], &scene.entities[*b], &camera_position));
+    let layers = order
+        .iter()
+        .map(|index| (scene.entities[*index].layer, *index))
+        .collect::<Vec<_>>();
+    let mut world_drawn = !overlays;
+    for (i, layer) in layers.chunk_by(|a, b| a.0 == b.0).enumerate() {
+        // The grid and debug lines belong to the world, draw them before its depth is cleared
+        if layer[0].0 > 0 && !world_drawn {
+            draw_world_overlays(target, scene, camera);
+            world_drawn = true;
+        }
+        if i > 0 {
+            target.clear_depth();
+        }
+        for (_, index) in layer {
+            let entity = &mut scene.entities[*index];
+            entity.select_lod(&camera.world_position());
+            let ambient = scene
+                .irradiance
+                .as_ref()
+                .map_or(Color::new(0.0, 0.0, 0.0, 1.0), |grid| {
+                    grid.sample(&Point3::from(entity.world_translation().cast::<f32>()))
+                });
+            target.set_ambient(ambient);
+            target.set_entity_id(*index as u32 + 1);
+            target.draw(entity, camera, mode);
+        }
+        target.resolve_oit();
+    }
+    if !world_drawn {
+        draw_world_overlays(target, scene, camera);
+    }
+}
+
+/// Which entities `camera` can see by their bounds, after the frustum and the portals.
+fn cull(scene: &mut Scene, camera: &Camera) -> Vec<bool> {
+    scene.spatial.update(&scene.entities);
+    let mut visible = vec![false; scene.entities.len()];
+    // The frustum planes only bound a perspective view, wider projections see around them
+    if scene.projection.is_linear() {
+        for index in scene.spatial.query_planes(&camera.frustum_planes()) {
+            visible[index] = true;
+        }
+    } else {
+        visible.fill(true);
+    }
+    let portal_views = scene
+        .portals
+        .as_ref()
+        .and_then(|portals| Some((portals, portals.visible_cells(camera)?)));
+    if let Some((portals, views)) = portal_views {
+        for (index, visible) in visible
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, visible)| **visible)
+        {
+            let Some(bounds) = scene.spatial.bounds(index) else {
+                continue;
+            };
+            let center = nalgebra::center(&bounds.min, &bounds.max);
+            if let Some(cell) = portals.cell_at(&center) {
+                *visible = views.iter().any(|(seen, planes)| {
+                    *seen == cell && !planes.iter().any(|plane| bounds.outside(plane))
+                });
             }
-        },
-        Err(e) => {
-            panic!("{}", e);
         }
-    };
+    }
+    visible
+}
+
+/// Layer first, then opaque before blended, then the sort key. Blended entities with equal
+/// keys draw back to front, opaque ones keep their scene order.
+fn draw_order(a: &Entity, b: &Entity, camera_position: &Vector3<f64>) -> Ordering {
+    let distance =
+        |entity: &Entity| (entity.world_translation() - camera_position).magnitude_squared();
+    a.layer
+        .cmp(&b.layer)
+        .then(a.is_transparent().cmp(&b.is_transparent()))
+        .then(a.sort_key.cmp(&b.sort_key))
+        .then_with(|| {
+            if a.is_transparent() {
+                distance(b).total_cmp(&distance(a))
+            } else {
+                Ordering::Equal
+            }
+        })
+}
+
+fn draw_world_overlays(target: &mut RenderTarget, scene: &Scene, camera: &Camera) {
+    if let Some(grid) = &scene.grid {
+        target.draw_grid(grid, camera);
+    }
+    target.draw_debug(&scene.debug, camera);
+    if let Some(labels) = &scene.index_labels {
+        draw_index_labels(target, labels, &scene.entities, camera);
+    }
+}
+
+/// Settings the window app starts with, `App::default().run()` is `run`.
+#[derive(Debug, Default)]
+pub struct App {
+    render_threads: RenderThreads,
+    config: Option<Config>,
+    config_path: Option<String>,
+}
+#[allow(unused)]
+impl App {
+    pub fn with_render_threads(mut self, threads: RenderThreads) -> Self {
+        self.render_threads = threads;
+        self
+    }
+    /// Starts with these settings instead of the ones in the config file.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+    /// Where settings are loaded from at startup and `Command::save_config` writes,
+    /// `DEFAULT_CONFIG_PATH` otherwise. A missing file means the defaults.
+    pub fn with_config_file(mut self, path: &str) -> Self {
+        self.config_path = Some(path.to_owned());
+        self
+    }
+    pub fn run(self) {
+        let mut context = AppContext::new(MyApp::default());
+        context.render_pool = self.render_threads.build_pool();
+        context.render_threads = self.render_threads;
+        let path = self.config_path.as_deref().unwrap_or(DEFAULT_CONFIG_PATH);
+        let config = self.config.unwrap_or_else(|| {
+            Config::load_or_default(path).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                Config::default()
+            })
+        });
+        // The window is created from the config, the rest is applied with the first commands
+        context.config = config.clone();
+        context.config_path = self.config_path;
+        context.command.set_config(config);
+        match EventLoop::new() {
+            Ok(event_loop) => match event_loop.run_app(&mut context) {
+                Ok(_) => {}
+                Err(err) => {
+                    eprintln!("{}", err);
+                }
+            },
+            Err(err) => {
+                eprintln!("{}", SoftRastError::Window(err.to_string()));
+            }
+        };
+    }
+}
+
+pub fn run() {
+    App::default().run()
+}
+
+/// A simplified model used once the camera is further than `distance` from the entity.
+struct Lod {
+    model: Model,
+    distance: f32,
+}
+
+/// Height map applied in the vertex stage, see `Entity::with_displacement`.
+struct Displacement {
+    texture: Texture,
+    /// Model space offset at full height, the red channel is the height.
+    scale: f32,
 }
 
-struct Entity {
+pub struct Entity {
     id: String,
+    /// Set for entities added through `Command::spawn`.
+    handle: Option<EntityId>,
     model: Model,
-    shader: Box<dyn Shader>,
+    materials: Vec<Box<dyn Shader>>,
     position: Isometry3<f32>,
     scale: Scale3<f32>,
+    lods: Vec<Lod>,
+    lod_hysteresis: f32,
+    lod_level: usize,
+    mirror: Option<Mirror>,
+    /// Replaces `position` and `scale` when set, for shear or matrices computed elsewhere.
+    transform: Option<Matrix4<f32>>,
+    /// Added to the translation in f64, for positions too large to keep in f32.
+    world_offset: Vector3<f64>,
+    /// Runs on every model space vertex before projection, with the render time in seconds.
+    vertex_modifier: Option<fn(&mut Vertex, f32)>,
+    /// Runs on every clipped view space triangle, pushes the triangles to rasterize instead.
+    geometry_hook: Option<GeometryHook>,
+    displacement: Option<Displacement>,
+    /// Wave displacement and reflections of a water surface, see `Water`.
+    water: Option<Water>,
+    /// Rasterize triangles back to front, for transparent meshes that overlap themselves.
+    sort_triangles: bool,
+    /// Layers draw in ascending order with the depth cleared in between, 0 is the world.
+    layer: i32,
+    /// Draw order inside a layer, before the back to front order of blended entities.
+    sort_key: i32,
+    /// Overrides `DrawMode`'s wireframe and point style for this entity.
+    wire_style: Option<WireStyle>,
+    /// Named points on the model other entities can be attached to.
+    attachment_points: AttachmentPoints,
+    /// Follows a point on another entity, applied by `Scene::update_attachments`.
+    attachment: Option<Attachment>,
+    /// Poses `attachment_points` every frame.
+    animation: Option<AnimationController>,
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+    /// Rigid body simulated by the scene's `PhysicsWorld`.
+    #[cfg(feature = "physics")]
+    physics: Option<PhysicsBody>,
+    /// Last reported view and level of detail, reported through `SoftRastEvent::Visibility`
+    /// while set.
+    visibility: Option<VisibilityState>,
 }
+#[allow(unused)]
 impl Entity {
     pub fn new(
         id: &str,
@@ -342,17 +2086,455 @@ impl Entity {
     ) -> Self {
         Self {
             id: id.to_string(),
+            handle: None,
             model: model.to_owned(),
-            shader: Box::new(shader),
-            position: position.clone(),
+            materials: vec![Box::new(shader)],
+            position: *position,
             scale: scale.to_owned(),
+            lods: vec![],
+            lod_hysteresis: 0.0,
+            lod_level: 0,
+            mirror: None,
+            transform: None,
+            world_offset: Vector3::zeros(),
+            vertex_modifier: None,
+            geometry_hook: None,
+            displacement: None,
+            water: None,
+            sort_triangles: false,
+            layer: 0,
+            sort_key: 0,
+            wire_style: None,
+            attachment_points: AttachmentPoints::new(),
+            attachment: None,
+            animation: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+            #[cfg(feature = "physics")]
+            physics: None,
+            visibility: None,
+        }
+    }
+    /// Makes the entity a planar mirror, its first material slot is replaced by the
+    /// reflection every frame.
+    pub fn with_mirror(mut self, normal: Vector3<f32>) -> Self {
+        self.mirror = Some(Mirror::new(normal));
+        self
+    }
+    /// Makes the entity a water surface, its first material slot is replaced by the water
+    /// shader with a fresh reflection every frame. Use a model from `Water::model`.
+    pub fn with_water(mut self, water: Water) -> Self {
+        self.set_material(0, WaterShader::new(water.clone(), None));
+        self.water = Some(water);
+        self
+    }
+    /// Draws this entity's wireframe and points in its own colors, e.g. to tell overlapping
+    /// debug meshes apart.
+    pub fn with_wire_style(mut self, style: WireStyle) -> Self {
+        self.wire_style = Some(style);
+        self
+    }
+    /// Runs the rhai script at `path` every frame, its `on_update(dt)` function gets the
+    /// entity transform as `this`.
+    #[cfg(feature = "scripting")]
+    pub fn with_script(mut self, path: &str) -> Self {
+        self.script = Some(Script::new(path));
+        self
+    }
+    /// Simulates the entity as a rigid body once the scene has a `PhysicsWorld`, see
+    /// `Scene::enable_physics`. Bodies start from the world pose, `world_offset` and a matrix
+    /// from `with_matrix` included. Dynamic bodies take over `position` and drop the matrix.
+    #[cfg(feature = "physics")]
+    pub fn with_physics_body(mut self, body: PhysicsBody) -> Self {
+        self.physics = Some(body);
+        self
+    }
+    /// Places the entity at a double precision position, its own translation becomes an
+    /// offset from it. Best drawn with `Scene::set_camera_relative` turned on.
+    pub fn with_world_position(mut self, position: Vector3<f64>) -> Self {
+        self.world_offset = position;
+        self.position.translation.vector = Vector3::zeros();
+        if let Some(transform) = &mut self.transform {
+            transform.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
         }
+        self
+    }
+    /// Uses a full model matrix, e.g. from glTF nodes or a physics engine, instead of the
+    /// isometry and scale. The editor gizmo keeps editing those, which are then ignored.
+    pub fn with_matrix(mut self, transform: Matrix4<f32>) -> Self {
+        self.transform = Some(transform);
+        self
+    }
+    pub fn set_matrix(&mut self, transform: Option<Matrix4<f32>>) {
+        self.transform = transform;
+    }
+    /// Model to world matrix, not including the world offset.
+    pub fn model_matrix(&self) -> Matrix4<f32> {
+        self.transform
+            .unwrap_or_else(|| self.position.to_homogeneous() * self.scale.to_homogeneous())
+    }
+    /// Inverse transpose of the model matrix, keeps normals perpendicular to their surface
+    /// under non uniform scale and shear.
+    pub fn normal_matrix(&self) -> Matrix3<f32> {
+        let linear = self.model_matrix().fixed_view::<3, 3>(0, 0).into_owned();
+        linear
+            .try_inverse()
+            .map_or(linear, |inverse| inverse.transpose())
+    }
+    /// E.g. `AttachmentPoints::from_gltf` with the same file as the model.
+    pub fn with_attachment_points(mut self, points: AttachmentPoints) -> Self {
+        self.attachment_points = points;
+        self
+    }
+    /// For posing points every frame, e.g. a joint driven by user code.
+    pub fn attachment_points_mut(&mut self) -> &mut AttachmentPoints {
+        &mut self.attachment_points
+    }
+    /// World transform of a named attachment point with the entity's current transform.
+    pub fn attachment_transform(&self, point: &str) -> Option<Matrix4<f32>> {
+        let local = self.attachment_points.get(point)?;
+        let offset = Matrix4::new_translation(&self.world_offset.cast::<f32>());
+        Some(offset * self.model_matrix() * local)
+    }
+    /// Keeps the entity on `point` of a spawned entity. Its current transform becomes the
+    /// offset from the point, so position and scale it relative to the point first.
+    pub fn attach_to(mut self, parent: EntityId, point: &str) -> Self {
+        self.attachment = Some(Attachment {
+            parent,
+            point: point.to_owned(),
+            offset: self.model_matrix(),
+        });
+        self
+    }
+    pub fn detach(&mut self) {
+        self.attachment = None;
+    }
+    /// Plays glTF node animation on the entity's attachment points, driven from `Update` with
+    /// `Command::play_animation` and `Command::set_animation_parameter`.
+    pub fn with_animation(mut self, animation: AnimationController) -> Self {
+        self.attachment_points = animation.attachment_points();
+        self.animation = Some(animation);
+        self
+    }
+    pub fn animation_mut(&mut self) -> Option<&mut AnimationController> {
+        self.animation.as_mut()
+    }
+    pub fn world_translation(&self) -> Vector3<f64> {
+        let translation = self.model_matrix().fixed_view::<3, 1>(0, 3).into_owned();
+        self.world_offset + translation.cast::<f64>()
+    }
+    /// Deforms the model every frame, e.g. waving flags or ripples. Picking and bounds still
+    /// use the undeformed model.
+    pub fn with_vertex_modifier(mut self, modifier: fn(&mut Vertex, f32)) -> Self {
+        self.vertex_modifier = Some(modifier);
+        self
+    }
+    /// Replaces each triangle with whatever the hook pushes to the output, three vertices per
+    /// triangle, e.g. subdividing, extruding fins or fur shells. Pushing nothing drops it.
+    /// Emitted triangles are clipped against the near plane again before rasterization.
+    pub fn with_geometry_hook(mut self, hook: GeometryHook) -> Self {
+        self.geometry_hook = Some(hook);
+        self
+    }
+    /// Offsets vertices along their normals by a height texture sampled at their UVs, after
+    /// subdividing the model and the LODs added so far `subdivisions` times for more detail.
+    pub fn with_displacement(mut self, texture: Texture, scale: f32, subdivisions: u32) -> Self {
+        if subdivisions > 0 {
+            self.model = self.model.subdivided(subdivisions);
+            for lod in &mut self.lods {
+                lod.model = lod.model.subdivided(subdivisions);
+            }
+        }
+        self.displacement = Some(Displacement { texture, scale });
+        self
+    }
+    /// Sorts this entity's triangles by depth every frame, costly but needed when a blended
+    /// mesh overlaps itself from some angles.
+    pub fn with_triangle_sorting(mut self) -> Self {
+        self.sort_triangles = true;
+        self
+    }
+    /// E.g. a negative layer for the skybox, positive ones for a first person weapon or 3D UI
+    /// that should never be hidden by the world.
+    pub fn with_layer(mut self, layer: i32) -> Self {
+        self.layer = layer;
+        self
+    }
+    pub fn with_sort_key(mut self, sort_key: i32) -> Self {
+        self.sort_key = sort_key;
+        self
+    }
+    /// Any material slot blends, see `Shader::blend`.
+    pub fn is_transparent(&self) -> bool {
+        self.materials.iter().any(|material| material.blend())
+    }
+    pub fn with_lod(mut self, model: &Model, distance: f32) -> Self {
+        let index = self.lods.partition_point(|lod| lod.distance <= distance);
+        self.lods.insert(
+            index,
+            Lod {
+                model: model.to_owned(),
+                distance,
+            },
+        );
+        self
+    }
+    /// Distance the camera has to move past a switch distance before the level changes.
+    pub fn with_lod_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.lod_hysteresis = hysteresis;
+        self
+    }
+    /// Reports the entity coming into and going out of the main view and its level of detail
+    /// switches as `SoftRastEvent::Visibility`, e.g. to only run AI or particles while seen.
+    pub fn with_visibility_events(mut self) -> Self {
+        self.visibility = Some(VisibilityState::default());
+        self
+    }
+    /// Passed culling at the last visibility update, always false without
+    /// `with_visibility_events`.
+    pub fn in_view(&self) -> bool {
+        self.visibility.is_some_and(|state| state.in_view)
+    }
+    /// Picks the level of detail for a camera at `camera_position`, see
+    /// `Camera::world_position`.
+    pub fn select_lod(&mut self, camera_position: &Vector3<f64>) {
+        let distance = (self.world_translation() - camera_position).magnitude() as f32;
+        let mut level = self.lod_level.min(self.lods.len());
+        while level < self.lods.len() && distance > self.lods[level].distance + self.lod_hysteresis
+        {
+            level += 1;
+        }
+        while level > 0 && distance < self.lods[level - 1].distance - self.lod_hysteresis {
+            level -= 1;
+        }
+        self.lod_level = level;
+    }
+    pub fn handle(&self) -> Option<EntityId> {
+        self.handle
+    }
+    /// The model for the current level of detail.
+    pub fn active_model(&self) -> &Model {
+        match self.lod_level {
+            0 => &self.model,
+            level => &self.lods[level - 1].model,
+        }
+    }
+    /// Appends a material slot, used by sub meshes with the next `material_index`.
+    pub fn with_material(mut self, shader: impl Shader + 'static) -> Self {
+        self.materials.push(Box::new(shader));
+        self
+    }
+    pub fn set_material(&mut self, slot: usize, shader: impl Shader + 'static) {
+        if slot < self.materials.len() {
+            self.materials[slot] = Box::new(shader);
+        } else {
+            self.materials.push(Box::new(shader));
+        }
+    }
+    /// Material for a slot, missing slots fall back to the first material.
+    pub fn material(&self, slot: usize) -> &dyn Shader {
+        self.materials
+            .get(slot)
+            .unwrap_or(&self.materials[0])
+            .as_ref()
     }
 }
 
 pub struct Scene {
     entities: Vec<Entity>,
     camera: Camera,
+    debug: DebugDraw,
+    clip_planes: Vec<Plane>,
+    clip_cap: Option<Color>,
+    /// Draw with camera relative transforms, see `Scene::set_camera_relative`.
+    camera_relative: bool,
+    grid: Option<Grid>,
+    /// Baked probes tinting the ambient light of each entity by its position.
+    irradiance: Option<IrradianceGrid>,
+    /// Secondary views drawn over the main one, in order.
+    insets: Vec<Inset>,
+    stereo: Option<Stereo>,
+    /// Edge outlines over the main view, with selected entities highlighted.
+    outline: Option<Outline>,
+    /// Temporal anti-aliasing state of the main view.
+    taa: Option<Taa>,
+    /// Still image accumulation of the main view, replaces TAA when set.
+    accumulation: Option<Accumulation>,
+    /// Nine-slice HUD panels drawn over the scene in order, below the sprites.
+    panels: Vec<Panel>,
+    /// 2D sprites drawn over everything else in order.
+    sprites: Vec<Sprite>,
+    /// Entity bounds for culling, picking and overlap queries, updated before every draw.
+    spatial: SpatialIndex,
+    /// Cells and portals culling entities hidden behind walls, see `PortalSystem`.
+    portals: Option<PortalSystem>,
+    /// Procedural background drawn before the entities, see `Sky`.
+    sky: Option<Sky>,
+    /// Vertex or triangle numbers drawn with the debug overlays.
+    index_labels: Option<IndexLabels>,
+    /// Outline the main and inset camera frusta in each other's views.
+    show_frusta: bool,
+    /// Brightness adaptation of the 3D views, before insets and the HUD are drawn.
+    exposure: Option<AutoExposure>,
+    /// Reduces the finished frame, HUD included, to the colors of a 256 color palette.
+    palette: Option<Palette>,
+    projection: Projection,
+    /// Subdivisions per triangle edge under a non-linear `projection`.
+    tessellation: u32,
+    /// Collects the passes of the next draw while set, see `Command::dump_frame_graph`.
+    frame_graph: Option<FrameGraph>,
+    /// Loads and unloads chunks of entities around the camera, see `WorldStreamer`.
+    streaming: Option<WorldStreamer>,
+    /// Rigid bodies of the entities with a `PhysicsBody`, stepped after the `Render` event.
+    #[cfg(feature = "physics")]
+    physics: Option<PhysicsWorld>,
+}
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            entities: vec![],
+            camera: Camera::default(),
+            debug: DebugDraw::default(),
+            clip_planes: vec![],
+            clip_cap: None,
+            camera_relative: false,
+            grid: None,
+            irradiance: None,
+            insets: vec![],
+            stereo: None,
+            outline: None,
+            taa: None,
+            accumulation: None,
+            panels: vec![],
+            sprites: vec![],
+            spatial: SpatialIndex::default(),
+            portals: None,
+            sky: None,
+            index_labels: None,
+            show_frusta: false,
+            exposure: None,
+            palette: None,
+            projection: Projection::Perspective,
+            tessellation: 1,
+            frame_graph: None,
+            streaming: None,
+            #[cfg(feature = "physics")]
+            physics: None,
+        }
+    }
+    /// Direction towards the light of the first lit material, the scene's sun.
+    pub fn light_dir(&self) -> Option<Vector3<f32>> {
+        self.entities
+            .iter()
+            .flat_map(|entity| entity.materials.iter())
+            .find_map(|material| material.light_dir())
+    }
+    /// A spawned entity, `None` once despawned or before its spawn has been processed.
+    pub fn entity_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities
+            .iter_mut()
+            .find(|entity| entity.handle == Some(id))
+    }
+    /// Entities whose world bounds overlap the box, e.g. for collision checks.
+    pub fn entities_in_box(&mut self, min: Point3<f32>, max: Point3<f32>) -> Vec<&Entity> {
+        self.spatial.update(&self.entities);
+        let found = self.spatial.query_box(&Aabb::new(min, max));
+        found
+            .into_iter()
+            .map(|index| &self.entities[index])
+            .collect()
+    }
+    /// Entities whose world bounds come within `radius` of `center`.
+    pub fn entities_in_sphere(&mut self, center: Point3<f32>, radius: f32) -> Vec<&Entity> {
+        self.spatial.update(&self.entities);
+        let found = self.spatial.query_sphere(&center, radius);
+        found
+            .into_iter()
+            .map(|index| &self.entities[index])
+            .collect()
+    }
+    /// Entities whose world bounds a ray enters, nearest first with the entry distance.
+    pub fn raycast_bounds(
+        &mut self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+    ) -> Vec<(&Entity, f32)> {
+        self.spatial.update(&self.entities);
+        let found = self.spatial.raycast(&Ray::new(origin, direction), f32::MAX);
+        found
+            .into_iter()
+            .map(|(index, t)| (&self.entities[index], t))
+            .collect()
+    }
+    /// Builds each entity's model-view matrix from its position relative to the camera, taken
+    /// in f64, so entities thousands of units out don't jitter. Off by default, which keeps
+    /// the plain view matrix product, see `RenderTarget::set_camera_relative`.
+    pub fn set_camera_relative(&mut self, camera_relative: bool) {
+        self.camera_relative = camera_relative;
+    }
+    /// Moves the camera to a double precision position, the camera's counterpart of
+    /// `Entity::with_world_position`. Its orientation is kept.
+    pub fn set_camera_world_position(&mut self, position: Vector3<f64>) {
+        self.camera.world_offset = position;
+        self.camera.position = Point3::origin();
+    }
+    /// Cell size in world units of the grid behind culling and the entity queries, around
+    /// the size of a typical entity works best.
+    pub fn set_spatial_cell_size(&mut self, cell_size: f32) {
+        self.spatial = SpatialIndex::new(cell_size);
+    }
+    /// World transform of a named point on a spawned entity, see `Entity::attachment_transform`.
+    pub fn attachment_transform(&self, id: EntityId, point: &str) -> Option<Matrix4<f32>> {
+        self.entities
+            .iter()
+            .find(|entity| entity.handle == Some(id))?
+            .attachment_transform(point)
+    }
+    /// Streams chunks of the world in and out around the camera, `None` stops. Entities of
+    /// loaded chunks stay in the scene until it is replaced.
+    pub fn set_streaming(&mut self, streaming: Option<WorldStreamer>) {
+        self.streaming = streaming;
+    }
+    /// Starts simulating the entities with a `PhysicsBody`.
+    #[cfg(feature = "physics")]
+    pub fn enable_physics(&mut self, world: PhysicsWorld) {
+        self.physics = Some(world);
+    }
+    /// The physics world, for impulses and velocities, `None` until `enable_physics`.
+    #[cfg(feature = "physics")]
+    pub fn physics_mut(&mut self) -> Option<&mut PhysicsWorld> {
+        self.physics.as_mut()
+    }
+    /// Moves attached entities onto their points, in scene order so chains of attachments
+    /// follow within the same frame when parents come first. Entities whose parent or point
+    /// is gone stay where they were.
+    pub fn update_attachments(&mut self) {
+        for index in 0..self.entities.len() {
+            let Some(attachment) = &self.entities[index].attachment else {
+                continue;
+            };
+            let Some(parent) = self
+                .entities
+                .iter()
+                .find(|entity| entity.handle == Some(attachment.parent))
+            else {
+                continue;
+            };
+            let Some(point) = parent.attachment_points.get(&attachment.point) else {
+                continue;
+            };
+            let transform = parent.model_matrix() * point * attachment.offset;
+            let world_offset = parent.world_offset;
+            let entity = &mut self.entities[index];
+            entity.transform = Some(transform);
+            entity.world_offset = world_offset;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,322 @@
+use crate::geometry::{Model, Vertex};
+use crate::renderer::Color;
+use nalgebra::{Point3, Vector3};
+use std::sync::OnceLock;
+
+/// Surface normal at a vertex position, used instead of the grid gradient when given.
+type NormalFn<'a> = &'a dyn Fn(&Point3<f32>) -> Vector3<f32>;
+
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+const EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (1, 2),
+    (2, 3),
+    (3, 0),
+    (4, 5),
+    (5, 6),
+    (6, 7),
+    (7, 4),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+/// Cube faces with their corners counter clockwise when viewed from outside the cube.
+const FACES: [[usize; 4]; 6] = [
+    [0, 3, 2, 1],
+    [4, 5, 6, 7],
+    [0, 1, 5, 4],
+    [3, 7, 6, 2],
+    [0, 4, 7, 3],
+    [1, 2, 6, 5],
+];
+
+/// `EDGES` index of the edge joining two corners, either way round. Corners that don't share
+/// an edge map to `u8::MAX`, which the table builder never asks for: it only joins
+/// neighbours around a face.
+const EDGE_BETWEEN: [[u8; 8]; 8] = {
+    let mut table = [[u8::MAX; 8]; 8];
+    let mut edge = 0;
+    while edge < EDGES.len() {
+        let (a, b) = EDGES[edge];
+        table[a][b] = edge as u8;
+        table[b][a] = edge as u8;
+        edge += 1;
+    }
+    table
+};
+
+/// Triangles (as edge indices) for each of the 256 inside/outside corner cases.
+///
+/// The table is generated once from the cube topology instead of being hard coded: every
+/// face contributes iso-line segments running from an edge entering the inside region to
+/// the next edge leaving it, the segments are chained into loops and each loop is fanned.
+/// Ambiguous faces always separate the inside corners, so neighbouring cells agree and the
+/// surface stays closed.
+fn triangle_table() -> &'static [Vec<[usize; 3]>] {
+    static TABLE: OnceLock<Vec<Vec<[usize; 3]>>> = OnceLock::new();
+    TABLE.get_or_init(|| (0..256).map(triangulate_case).collect())
+}
+
+fn triangulate_case(case: usize) -> Vec<[usize; 3]> {
+    let inside = |corner: usize| case & (1 << corner) != 0;
+    let mut next = [None; 12];
+    for face in FACES {
+        for k in 0..4 {
+            let (a, b) = (face[k], face[(k + 1) % 4]);
+            if inside(a) || !inside(b) {
+                continue;
+            }
+            for j in 1..4 {
+                let (c, d) = (face[(k + j) % 4], face[(k + j + 1) % 4]);
+                if inside(c) && !inside(d) {
+                    next[EDGE_BETWEEN[a][b] as usize] = Some(EDGE_BETWEEN[c][d] as usize);
+                    break;
+                }
+            }
+        }
+    }
+
+    let mut triangles = Vec::new();
+    let mut visited = [false; 12];
+    for start in 0..12 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut edges = Vec::new();
+        let mut edge = start;
+        while !visited[edge] {
+            visited[edge] = true;
+            edges.push(edge);
+            match next[edge] {
+                Some(n) => edge = n,
+                None => break,
+            }
+        }
+        for i in 1..edges.len().saturating_sub(1) {
+            triangles.push([edges[0], edges[i], edges[i + 1]]);
+        }
+    }
+    triangles
+}
+
+/// Scalar samples on a regular grid. Points with a value below the iso level are inside.
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub struct ScalarGrid {
+    pub dimensions: (usize, usize, usize),
+    pub origin: Point3<f32>,
+    pub cell_size: Vector3<f32>,
+    pub values: Vec<f32>,
+}
+#[allow(unused)]
+impl ScalarGrid {
+    pub fn new(
+        dimensions: (usize, usize, usize),
+        origin: Point3<f32>,
+        cell_size: Vector3<f32>,
+    ) -> Self {
+        Self {
+            dimensions,
+            origin,
+            cell_size,
+            values: vec![0.0; dimensions.0 * dimensions.1 * dimensions.2],
+        }
+    }
+    /// Samples `field` at `dimensions` points per axis spanning `min..=max`.
+    pub fn sample(
+        field: impl Fn(&Point3<f32>) -> f32,
+        min: Point3<f32>,
+        max: Point3<f32>,
+        dimensions: (usize, usize, usize),
+    ) -> Self {
+        let steps = Vector3::new(
+            (dimensions.0.max(2) - 1) as f32,
+            (dimensions.1.max(2) - 1) as f32,
+            (dimensions.2.max(2) - 1) as f32,
+        );
+        let mut grid = Self::new(dimensions, min, (max - min).component_div(&steps));
+        for z in 0..dimensions.2 {
+            for y in 0..dimensions.1 {
+                for x in 0..dimensions.0 {
+                    let value = field(&grid.position(x, y, z));
+                    grid.set(x, y, z, value);
+                }
+            }
+        }
+        grid
+    }
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + y * self.dimensions.0 + z * self.dimensions.0 * self.dimensions.1
+    }
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[self.index(x, y, z)]
+    }
+    pub fn set(&mut self, x: usize, y: usize, z: usize, value: f32) {
+        let index = self.index(x, y, z);
+        self.values[index] = value;
+    }
+    pub fn position(&self, x: usize, y: usize, z: usize) -> Point3<f32> {
+        self.origin + Vector3::new(x as f32, y as f32, z as f32).component_mul(&self.cell_size)
+    }
+    /// Central difference gradient, one sided at the borders.
+    pub fn gradient(&self, x: usize, y: usize, z: usize) -> Vector3<f32> {
+        let (nx, ny, nz) = self.dimensions;
+        let (x0, x1) = (x.saturating_sub(1), (x + 1).min(nx - 1));
+        let (y0, y1) = (y.saturating_sub(1), (y + 1).min(ny - 1));
+        let (z0, z1) = (z.saturating_sub(1), (z + 1).min(nz - 1));
+        Vector3::new(
+            (self.get(x1, y, z) - self.get(x0, y, z))
+                / (self.cell_size.x * (x1 - x0).max(1) as f32),
+            (self.get(x, y1, z) - self.get(x, y0, z))
+                / (self.cell_size.y * (y1 - y0).max(1) as f32),
+            (self.get(x, y, z1) - self.get(x, y, z0))
+                / (self.cell_size.z * (z1 - z0).max(1) as f32),
+        )
+    }
+    /// Extracts the iso surface with normals from the grid gradient.
+    pub fn polygonize(&self, iso: f32) -> Model {
+        self.polygonize_with(iso, None)
+    }
+    fn polygonize_with(&self, iso: f32, normal: Option<NormalFn<'_>>) -> Model {
+        let (nx, ny, nz) = self.dimensions;
+        let color = Color::new(1.0, 1.0, 1.0, 1.0);
+        let table = triangle_table();
+        let mut vertices = Vec::new();
+
+        for z in 0..nz.saturating_sub(1) {
+            for y in 0..ny.saturating_sub(1) {
+                for x in 0..nx.saturating_sub(1) {
+                    let corner =
+                        |i: usize| (x + CORNERS[i][0], y + CORNERS[i][1], z + CORNERS[i][2]);
+                    let mut case = 0;
+                    for i in 0..8 {
+                        let (cx, cy, cz) = corner(i);
+                        if self.get(cx, cy, cz) < iso {
+                            case |= 1 << i;
+                        }
+                    }
+                    for triangle in &table[case] {
+                        for edge in triangle {
+                            let (a, b) = EDGES[*edge];
+                            let (ax, ay, az) = corner(a);
+                            let (bx, by, bz) = corner(b);
+                            let (va, vb) = (self.get(ax, ay, az), self.get(bx, by, bz));
+                            let t = ((iso - va) / (vb - va)).clamp(0.0, 1.0);
+                            let position = self
+                                .position(ax, ay, az)
+                                .coords
+                                .lerp(&self.position(bx, by, bz).coords, t);
+                            let position = Point3::from(position);
+                            let n = match normal {
+                                Some(normal) => normal(&position),
+                                None => self
+                                    .gradient(ax, ay, az)
+                                    .lerp(&self.gradient(bx, by, bz), t),
+                            };
+                            let mut vertex = Vertex::new(&position);
+                            if n.magnitude_squared() > 0.0 {
+                                vertex = vertex.with_normal(n.normalize());
+                            }
+                            vertex.color = Some(color);
+                            vertices.push(vertex);
+                        }
+                    }
+                }
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+}
+
+/// Samples `field` over `min..=max` and extracts the surface where it crosses `iso`,
+/// with normals taken from the field's own gradient. Values below `iso` are inside, so
+/// signed distance functions work directly and metaball sums can be negated.
+#[allow(unused)]
+pub fn marching_cubes(
+    field: impl Fn(&Point3<f32>) -> f32,
+    min: Point3<f32>,
+    max: Point3<f32>,
+    dimensions: (usize, usize, usize),
+    iso: f32,
+) -> Model {
+    let grid = ScalarGrid::sample(&field, min, max, dimensions);
+    let h = grid.cell_size.min() * 0.5;
+    let normal = |p: &Point3<f32>| {
+        Vector3::new(
+            field(&(p + Vector3::x() * h)) - field(&(p - Vector3::x() * h)),
+            field(&(p + Vector3::y() * h)) - field(&(p - Vector3::y() * h)),
+            field(&(p + Vector3::z() * h)) - field(&(p - Vector3::z() * h)),
+        )
+    };
+    grid.polygonize_with(iso, Some(&normal))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const RADIUS: f32 = 1.0;
+
+    fn sphere(p: &Point3<f32>) -> f32 {
+        p.coords.magnitude() - RADIUS
+    }
+
+    /// Every triangle edge, keyed by its quantized end points, has to be shared by exactly
+    /// two triangles for the surface to be closed.
+    fn assert_closed(model: &Model) {
+        let key = |p: &Point3<f32>| p.coords.map(|c| (c * 1e4).round() as i32);
+        let mut edges = HashMap::new();
+        for triangle in model.triangle_vertices().chunks_exact(3) {
+            for i in 0..3 {
+                let (a, b) = (
+                    key(&triangle[i].position.xyz()),
+                    key(&triangle[(i + 1) % 3].position.xyz()),
+                );
+                let edge = if (a.x, a.y, a.z) < (b.x, b.y, b.z) {
+                    (a, b)
+                } else {
+                    (b, a)
+                };
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        assert!(!edges.is_empty());
+        assert!(edges.values().all(|count| *count == 2));
+    }
+
+    #[test]
+    fn sphere_is_closed_and_on_the_surface() {
+        let extent = Point3::new(1.5, 1.5, 1.5);
+        let model = marching_cubes(sphere, -extent, extent, (24, 24, 24), 0.0);
+        assert_closed(&model);
+        for vertex in model.triangle_vertices().iter() {
+            let distance = vertex.position.xyz().coords.magnitude();
+            assert!((distance - RADIUS).abs() < 0.02, "vertex at {}", distance);
+            let normal = vertex.normal.expect("sphere vertices have normals");
+            assert!(normal.dot(&vertex.position.xyz().coords.normalize()) > 0.99);
+        }
+    }
+
+    #[test]
+    fn grid_normals_point_out_of_the_sphere() {
+        let extent = Point3::new(1.5, 1.5, 1.5);
+        let grid = ScalarGrid::sample(sphere, -extent, extent, (16, 16, 16));
+        let model = grid.polygonize(0.0);
+        assert_closed(&model);
+        for vertex in model.triangle_vertices().iter() {
+            let normal = vertex.normal.expect("sphere vertices have normals");
+            assert!(normal.dot(&vertex.position.xyz().coords.normalize()) > 0.9);
+        }
+    }
+}
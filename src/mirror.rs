@@ -0,0 +1,48 @@
+use crate::Scene;
+use crate::geometry::Texture;
+use crate::renderer::{DrawMode, RenderTarget};
+use nalgebra::{Point3, Vector3};
+
+/// Turns an entity into a planar mirror. The plane passes through the entity origin with
+/// `normal` given in the entity's local space.
+#[derive(Debug, Copy, Clone)]
+pub struct Mirror {
+    pub normal: Vector3<f32>,
+    /// Size of the reflection pass relative to the main target.
+    pub resolution_scale: f32,
+}
+impl Mirror {
+    pub fn new(normal: Vector3<f32>) -> Self {
+        Self {
+            normal,
+            resolution_scale: 0.5,
+        }
+    }
+}
+
+/// Renders the scene as seen in the mirror on entity `index` into an offscreen target.
+/// Entities behind the mirror plane are skipped, standing in for an oblique near plane.
+pub fn render_reflection(
+    scene: &Scene,
+    index: usize,
+    mirror: &Mirror,
+    size: (u32, u32),
+    mode: &DrawMode,
+) -> Texture {
+    let entity = &scene.entities[index];
+    let normal = (entity.position.rotation * mirror.normal).normalize();
+    let point = Point3::from(entity.position.translation.vector);
+    let camera = scene.camera.reflected(&point, &normal);
+
+    let width = ((size.0 as f32 * mirror.resolution_scale) as u32).max(1);
+    let height = ((size.1 as f32 * mirror.resolution_scale) as u32).max(1);
+    let mut target = RenderTarget::new(width, height);
+    target.clear();
+    for (i, other) in scene.entities.iter().enumerate() {
+        let in_front = (other.position.translation.vector - point.coords).dot(&normal) >= 0.0;
+        if i != index && in_front {
+            target.draw(other, &camera, mode);
+        }
+    }
+    target.to_texture()
+}
@@ -1,11 +1,19 @@
-use crate::geometry::{load_model, randomize_model_colors, Model, Texture, Vertex};
-use crate::renderer::Color;
-use crate::{Camera, Command, Entity, Material, SoftRastEvent, UserState};
+use crate::camera_effects::CameraEffects;
+use crate::error::SoftRastError;
+use crate::geometry::{Model, Texture, Vertex, push_sub_mesh, randomize_model_colors};
+use crate::nine_slice::{NineSlice, Panel};
+use crate::renderer::{Color, Sampler, UvTransform};
+use crate::sprite::{Sprite, SpriteAnimation, SpriteSheet};
+use crate::{Asset, Camera, Command, Entity, EntityId, Material, SoftRastEvent, UserState};
 use nalgebra::{Isometry3, Point3, Scale3, Vector2, Vector3};
+use std::sync::Arc;
 
 pub struct MyApp {
     pub models: Vec<Model>,
     pub cam: Camera,
+    pub effects: CameraEffects,
+    /// Entities added by the `spawn` console command, newest last.
+    pub spawned: Vec<EntityId>,
 }
 impl Default for MyApp {
     fn default() -> Self {
@@ -20,6 +28,8 @@ impl Default for MyApp {
                 0.01,
                 100.0,
             ),
+            effects: CameraEffects::default(),
+            spawned: vec![],
         }
     }
 }
@@ -47,10 +57,7 @@ impl UserState for MyApp {
                             model,
                             &transform,
                             &Scale3::new(0.05, 0.05, 0.05),
-                            Material::LitTexture {
-                                texture: Texture::new("assets/SpyroTex.png").unwrap(),
-                                light_dir: Vector3::<f32>::new(1.0, 1.0, 0.0).normalize(),
-                            },
+                            lit_texture("assets/SpyroTex.png", command),
                         ));
                     }
                     if let Some(model) = models.next() {
@@ -72,50 +79,39 @@ impl UserState for MyApp {
                             model,
                             &transform,
                             &Scale3::identity(),
-                            Material::LitTexture {
-                                texture: Texture::new("assets/EEVEEUV.png").unwrap(),
-                                light_dir: Vector3::<f32>::new(1.0, 1.0, 0.0).normalize(),
-                            }
+                            lit_texture("assets/EEVEEUV.png", command),
                         ));
                     }
+                    scene.panels.extend(grass_panel(command));
+                    scene.sprites.extend(spyro_sprite(command));
                 } else {
-                    scene.camera = self.cam;
-                    if let Some(entity) = scene
-                        .entities
-                        .iter_mut()
-                        .filter(|e| e.id == "eevee".to_string())
-                        .next()
-                    {
+                    scene.camera = self.effects.apply(&self.cam);
+                    if let Some(entity) = scene.entities.iter_mut().find(|e| e.id == "eevee") {
                         entity.position = transform;
                     }
-                    if let Some(entity) = scene
-                        .entities
-                        .iter_mut()
-                        .filter(|e| e.id == "spyro".to_string())
-                        .next()
-                    {
+                    if let Some(entity) = scene.entities.iter_mut().find(|e| e.id == "spyro") {
                         entity.position = transform2;
                     }
                 }
             }
             SoftRastEvent::Update { delta, input } => {
                 let speed = delta.as_secs_f32() * 5.0;
-                if input.pressed_keys.contains("a") {
+                if input.action("left") {
                     self.cam.move_local(0.0, -speed, 0.0);
                 }
-                if input.pressed_keys.contains("d") {
+                if input.action("right") {
                     self.cam.move_local(0.0, speed, 0.0);
                 }
-                if input.pressed_keys.contains("w") {
+                if input.action("forward") {
                     self.cam.move_local(speed, 0.0, 0.0);
                 }
-                if input.pressed_keys.contains("s") {
+                if input.action("back") {
                     self.cam.move_local(-speed, 0.0, 0.0);
                 }
-                if input.pressed_keys.contains("z") {
+                if input.action("up") {
                     self.cam.move_local(0.0, 0.0, speed);
                 }
-                if input.pressed_keys.contains("c") {
+                if input.action("down") {
                     self.cam.move_local(0.0, 0.0, -speed);
                 }
                 self.cam.look(
@@ -123,10 +119,18 @@ impl UserState for MyApp {
                     input.mouse_dy as f32,
                     delta.as_secs_f32(),
                 );
-                if input.pressed_keys.contains("q") {
+                let walking = ["forward", "left", "back", "right"]
+                    .iter()
+                    .any(|action| input.action(action));
+                if input.action("shake") {
+                    self.effects.add_trauma(delta.as_secs_f32() * 2.0);
+                }
+                self.effects
+                    .update(delta.as_secs_f32(), if walking { 5.0 } else { 0.0 });
+                if input.action("roll_left") {
                     self.cam.roll(speed);
                 }
-                if input.pressed_keys.contains("e") {
+                if input.action("roll_right") {
                     self.cam.roll(-speed);
                 }
                 if input.pressed_keys.contains("1") {
@@ -151,42 +155,238 @@ impl UserState for MyApp {
                     command.set_render_mode(true, true, true);
                 }
             }
+            SoftRastEvent::ConsoleCommand { name, args } => {
+                let count = args.first().and_then(|n| n.parse().ok()).unwrap_or(1);
+                if name == "spawn" {
+                    let Some(model) = self.models.first() else {
+                        return;
+                    };
+                    for _ in 0..count {
+                        let distance = 3.0 + self.spawned.len() as f32 * 0.5;
+                        let position = self.cam.position + self.cam.forward() * distance;
+                        let id = command.spawn(
+                            Entity::new(
+                                &format!("spawn{}", self.spawned.len()),
+                                model,
+                                &Isometry3::translation(position.x, position.y, position.z),
+                                &Scale3::new(0.05, 0.05, 0.05),
+                                Material::LitSolid {
+                                    color: Color::new(0.8, 0.5, 0.2, 1.0),
+                                    light_dir: Vector3::<f32>::new(1.0, 1.0, 0.0).normalize(),
+                                    emissive: None,
+                                    specular: None,
+                                },
+                            )
+                            .with_visibility_events(),
+                        );
+                        self.spawned.push(id);
+                    }
+                    command.console_print(&format!("spawned {}", count));
+                } else if name == "despawn" {
+                    let count = count.min(self.spawned.len());
+                    for id in self.spawned.drain(self.spawned.len() - count..) {
+                        command.despawn(id);
+                    }
+                    command.console_print(&format!("despawned {}", count));
+                } else if name == "pick" {
+                    match (args.first(), args.get(1)) {
+                        (Some(x), Some(y)) => match (x.parse(), y.parse()) {
+                            (Ok(x), Ok(y)) => command.pick(x, y),
+                            _ => command.console_print("usage: pick x y"),
+                        },
+                        _ => command.console_print("usage: pick x y"),
+                    }
+                } else if name == "monitors" {
+                    command.list_monitors();
+                }
+            }
+            SoftRastEvent::Picked {
+                x,
+                y,
+                pixel,
+                handle,
+            } => {
+                let text = match pixel {
+                    Some(pixel) => format!(
+                        "({}, {}): entity {} triangle {} {:?}",
+                        x, y, pixel.entity, pixel.triangle, handle
+                    ),
+                    None => format!("({}, {}): nothing", x, y),
+                };
+                command.console_print(&text);
+            }
             SoftRastEvent::Resume {} => {
-                self.models.push(load_model("assets/spyro.obj"));
+                command.register_console_command(
+                    "spawn",
+                    "Spawn copies of the first model: spawn [count]",
+                );
+                command.register_console_command(
+                    "despawn",
+                    "Remove the newest spawned copies: despawn [count]",
+                );
+                command.register_console_command("pick", "Show what is drawn at a pixel: pick x y");
+                command.register_console_command(
+                    "monitors",
+                    "List monitors and the video modes for fullscreen",
+                );
+                command.set_id_buffer(true);
 
-                self.models.push(load_model("assets/floor.obj"));
+                command.load_model("assets/spyro.obj");
+                command.load_model("assets/floor.obj");
+                command.load_model("assets/Eevee.obj");
 
-                self.models.push(load_model("assets/Eevee.obj"));
+                if let Err(err) = load_gltf("assets/test.glb") {
+                    command.console_print(&err.to_string());
+                }
+            }
+            SoftRastEvent::Loaded { asset, .. } => {
+                if let Asset::Model(model) = asset {
+                    self.models.push(model);
+                }
+            }
+            SoftRastEvent::Monitors(monitors) => {
+                for monitor in monitors {
+                    let name = monitor.name.unwrap_or_default();
+                    let modes = monitor
+                        .modes
+                        .iter()
+                        .take(8)
+                        .map(|mode| {
+                            format!(
+                                "{}x{}@{}",
+                                mode.width,
+                                mode.height,
+                                mode.refresh_millihertz / 1000
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    let size = format!("{}x{}", monitor.width, monitor.height);
+                    command
+                        .console_print(&format!("{} {} {}: {}", monitor.index, name, size, modes));
+                }
+            }
+            SoftRastEvent::FrameGraph(graph) => {
+                for line in graph.to_text().lines() {
+                    command.console_print(line);
+                }
+            }
+            SoftRastEvent::Visibility(changes) => {
+                for change in changes {
+                    command.console_print(&format!("{} {:?}", change.id, change.change));
+                }
+            }
+            SoftRastEvent::Error(err) => {
+                eprintln!("{}", err);
+                command.console_print(&err.to_string());
+            }
+        }
+    }
+}
 
-                load_gltf("assets/test.glb");
+/// Lit material with the texture at `path`, or plain grey and a console message when it
+/// doesn't load.
+fn lit_texture(path: &str, command: &mut Command) -> Material {
+    let light_dir = Vector3::<f32>::new(1.0, 1.0, 0.0).normalize();
+    match Texture::new(path) {
+        Ok(texture) => Material::LitTexture {
+            texture,
+            light_dir,
+            uv_transform: UvTransform::default(),
+            sampler: Sampler::default(),
+            emissive: None,
+            specular: None,
+        },
+        Err(err) => {
+            command.console_print(&err.to_string());
+            Material::LitSolid {
+                color: Color::new(0.8, 0.8, 0.8, 1.0),
+                light_dir,
+                emissive: None,
+                specular: None,
             }
         }
     }
 }
 
-pub fn load_gltf(path: &str) -> Vec<Model> {
+/// A grass framed backdrop for the corner sprite, the texture's 8 pixel border kept crisp.
+fn grass_panel(command: &mut Command) -> Option<Panel> {
+    match Texture::new("assets/Grass.png") {
+        Ok(texture) => {
+            let slices = Arc::new(NineSlice::new(texture, 8, 8, 8, 8));
+            Some(Panel::new(slices, 4, 4, 72, 72))
+        }
+        Err(err) => {
+            command.console_print(&err.to_string());
+            None
+        }
+    }
+}
+
+/// The Spyro texture cut into a 4x3 sheet and flipped through, a corner sprite showing off
+/// the sheet animation.
+fn spyro_sprite(command: &mut Command) -> Option<Sprite> {
+    match Texture::new("assets/SpyroTex.png") {
+        Ok(texture) => {
+            let sheet = Arc::new(SpriteSheet::from_grid(texture, 4, 3));
+            let mut sprite = Sprite::new(sheet, 8, 8)
+                .with_animation("flip", SpriteAnimation::new((0..12).collect(), 4.0));
+            sprite.play("flip");
+            Some(sprite)
+        }
+        Err(err) => {
+            command.console_print(&err.to_string());
+            None
+        }
+    }
+}
+
+pub fn load_gltf(path: &str) -> Result<Vec<Model>, SoftRastError> {
     let mut models = Vec::new();
-    let (gltf, buffers, _) = gltf::import(path).unwrap();
+    let (gltf, buffers, _) = gltf::import(path).map_err(|err| SoftRastError::Gltf {
+        path: path.to_owned(),
+        message: err.to_string(),
+    })?;
     for scene in gltf.scenes() {
         for node in scene.nodes() {
             if let Some(mesh) = node.mesh() {
                 let mut vertices = Vec::<Vertex>::new();
+                let mut sub_meshes = Vec::new();
                 for primitive in mesh.primitives() {
+                    let start = vertices.len();
                     let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
                     let positions = if let Some(positions) = reader.read_positions() {
-                        positions.map(|p| Point3::new(p[0],p[1],p[2])).collect::<Vec<_>>()
-                    } else { Vec::new() };
+                        positions
+                            .map(|p| Point3::new(p[0], p[1], p[2]))
+                            .collect::<Vec<_>>()
+                    } else {
+                        Vec::new()
+                    };
                     let normals = if let Some(normals) = reader.read_normals() {
-                        normals.map(|n| Vector3::new(n[0],n[1],n[2])).collect::<Vec<_>>()
-                    } else { Vec::new() };
+                        normals
+                            .map(|n| Vector3::new(n[0], n[1], n[2]))
+                            .collect::<Vec<_>>()
+                    } else {
+                        Vec::new()
+                    };
                     let texcoords = if let Some(texcoords) = reader.read_tex_coords(0) {
-                        texcoords.into_f32().map(|uv| Vector2::new(uv[0],uv[1])).collect::<Vec<_>>()
-                    } else { Vec::new() };
+                        texcoords
+                            .into_f32()
+                            .map(|uv| Vector2::new(uv[0], uv[1]))
+                            .collect::<Vec<_>>()
+                    } else {
+                        Vec::new()
+                    };
                     let colors = if let Some(colors) = reader.read_colors(0) {
-                        colors.into_rgba_f32().map(|c| Color::new(c[0],c[1],c[2],c[3])).collect::<Vec<_>>()
-                    } else { Vec::new() };
-                    for (index,pos) in positions.iter().enumerate() {
+                        colors
+                            .into_rgba_f32()
+                            .map(|c| Color::new(c[0], c[1], c[2], c[3]))
+                            .collect::<Vec<_>>()
+                    } else {
+                        Vec::new()
+                    };
+                    for (index, pos) in positions.iter().enumerate() {
                         let mut vertex = Vertex::new(pos);
                         if let Some(normal) = normals.get(index) {
                             vertex.normal = Some(normal.to_owned());
@@ -199,10 +399,15 @@ pub fn load_gltf(path: &str) -> Vec<Model> {
                         }
                         vertices.push(vertex);
                     }
+                    let material = primitive.material().index().unwrap_or(0);
+                    push_sub_mesh(&mut sub_meshes, start..vertices.len(), material);
                 }
-                models.push(Model::from_vertices(vertices.as_slice()));
+                models.push(Model::from_sub_meshes(
+                    vertices.as_slice(),
+                    sub_meshes.as_slice(),
+                ));
             }
         }
     }
-    models
-}
\ No newline at end of file
+    Ok(models)
+}
@@ -0,0 +1,417 @@
+use crate::Scene;
+use crate::debug::DebugDraw;
+use crate::renderer::Color;
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+/// Height the mesh and paths are drawn above the surface, so the lines aren't hidden in it.
+const DRAW_LIFT: f32 = 0.02;
+
+/// What counts as walkable when building a `NavMesh`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NavMeshSettings {
+    /// Steepest walkable slope in radians, steeper triangles, walls and ceilings are dropped.
+    pub max_slope: f32,
+    /// Corners closer than this are merged, connecting triangles of separate meshes that
+    /// touch.
+    pub weld_distance: f32,
+}
+impl Default for NavMeshSettings {
+    fn default() -> Self {
+        Self {
+            max_slope: 45f32.to_radians(),
+            weld_distance: 0.01,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct NavTriangle {
+    corners: [usize; 3],
+    center: Point3<f32>,
+    /// Triangle across the edge from corner `i` to corner `i + 1`.
+    neighbors: [Option<usize>; 3],
+}
+
+/// The walkable triangles of level geometry, connected across shared edges, for path
+/// queries with `find_path`. The up axis is y.
+#[derive(Debug, Clone, Default)]
+pub struct NavMesh {
+    vertices: Vec<Point3<f32>>,
+    triangles: Vec<NavTriangle>,
+}
+#[allow(unused)]
+impl NavMesh {
+    /// Keeps the triangles facing up no steeper than `settings.max_slope`, front faces wind
+    /// counter clockwise.
+    pub fn from_triangles(triangles: &[[Point3<f32>; 3]], settings: &NavMeshSettings) -> Self {
+        let min_up = settings.max_slope.cos();
+        let cell = settings.weld_distance.max(1e-6);
+        let mut mesh = Self::default();
+        let mut welded = HashMap::new();
+        let mut edges = HashMap::<(usize, usize), Vec<(usize, usize)>>::new();
+        for [a, b, c] in triangles {
+            let Some(normal) = (b - a).cross(&(c - a)).try_normalize(1e-12) else {
+                continue;
+            };
+            if normal.y < min_up {
+                continue;
+            }
+            let corners = [a, b, c].map(|point| {
+                let key = (point.coords / cell).map(|v| v.round() as i64);
+                *welded.entry((key.x, key.y, key.z)).or_insert_with(|| {
+                    mesh.vertices.push(*point);
+                    mesh.vertices.len() - 1
+                })
+            });
+            if corners[0] == corners[1] || corners[1] == corners[2] || corners[2] == corners[0] {
+                continue;
+            }
+            let index = mesh.triangles.len();
+            for edge in 0..3 {
+                let (start, end) = (corners[edge], corners[(edge + 1) % 3]);
+                edges
+                    .entry((start.min(end), start.max(end)))
+                    .or_default()
+                    .push((index, edge));
+            }
+            mesh.triangles.push(NavTriangle {
+                corners,
+                center: Point3::from((a.coords + b.coords + c.coords) / 3.0),
+                neighbors: [None; 3],
+            });
+        }
+        // Edges shared by more than two triangles link the first two
+        for sharing in edges.values() {
+            if let [(first, first_edge), (second, second_edge), ..] = sharing[..] {
+                mesh.triangles[first].neighbors[first_edge] = Some(second);
+                mesh.triangles[second].neighbors[second_edge] = Some(first);
+            }
+        }
+        mesh
+    }
+    /// From the world space triangles of every entity in the scene.
+    pub fn from_scene(scene: &Scene, settings: &NavMeshSettings) -> Self {
+        let mut triangles = vec![];
+        for entity in &scene.entities {
+            let world = Matrix4::new_translation(&entity.world_offset.cast::<f32>())
+                * entity.model_matrix();
+            let vertices = entity.active_model().triangle_vertices();
+            triangles.extend(
+                vertices
+                    .chunks_exact(3)
+                    .map(|t| [0, 1, 2].map(|i| world.transform_point(&t[i].position.xyz()))),
+            );
+        }
+        Self::from_triangles(&triangles, settings)
+    }
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+    /// The triangle below or above `point` whose surface is closest to it in height, `None`
+    /// off the mesh.
+    pub fn locate(&self, point: &Point3<f32>) -> Option<usize> {
+        let mut best: Option<(usize, f32)> = None;
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            let [a, b, c] = triangle.corners.map(|corner| self.vertices[corner]);
+            let Some((u, v, w)) = barycentric_xz(point, &a, &b, &c) else {
+                continue;
+            };
+            let height = (point.y - (a.y * u + b.y * v + c.y * w)).abs();
+            if best.is_none_or(|(_, best_height)| height < best_height) {
+                best = Some((index, height));
+            }
+        }
+        best.map(|(index, _)| index)
+    }
+    /// The shortest path over the mesh from `start` to `goal` as corner points, both ends
+    /// included. A* over the triangles finds the corridor, which is then pulled tight.
+    /// `None` when either end is off the mesh or the two aren't connected.
+    pub fn find_path(&self, start: &Point3<f32>, goal: &Point3<f32>) -> Option<Vec<Point3<f32>>> {
+        let corridor = self.find_corridor(self.locate(start)?, self.locate(goal)?, goal)?;
+        let mut portals = vec![(*start, *start)];
+        for pair in corridor.windows(2) {
+            portals.push(self.portal(pair[0], pair[1])?);
+        }
+        portals.push((*goal, *goal));
+        Some(string_pull(&portals))
+    }
+    /// Every walkable triangle's edges, shared edges once.
+    pub fn draw(&self, debug: &mut DebugDraw, color: Color) {
+        let lift = Vector3::y() * DRAW_LIFT;
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            for edge in 0..3 {
+                if triangle.neighbors[edge].is_some_and(|neighbor| neighbor < index) {
+                    continue;
+                }
+                let start = self.vertices[triangle.corners[edge]] + lift;
+                let end = self.vertices[triangle.corners[(edge + 1) % 3]] + lift;
+                debug.line(start, end, color);
+            }
+        }
+    }
+    /// Triangles from `start` to `goal`, by the distances between their centers.
+    fn find_corridor(
+        &self,
+        start: usize,
+        goal: usize,
+        goal_point: &Point3<f32>,
+    ) -> Option<Vec<usize>> {
+        let mut costs = vec![f32::INFINITY; self.triangles.len()];
+        let mut came_from = vec![usize::MAX; self.triangles.len()];
+        let mut open = BinaryHeap::new();
+        costs[start] = 0.0;
+        open.push(Candidate {
+            estimate: 0.0,
+            triangle: start,
+        });
+        while let Some(Candidate { triangle, .. }) = open.pop() {
+            if triangle == goal {
+                let mut corridor = vec![goal];
+                while *corridor.last()? != start {
+                    corridor.push(came_from[*corridor.last()?]);
+                }
+                corridor.reverse();
+                return Some(corridor);
+            }
+            let center = self.triangles[triangle].center;
+            for neighbor in self.triangles[triangle].neighbors.into_iter().flatten() {
+                let next_center = self.triangles[neighbor].center;
+                let cost = costs[triangle] + (next_center - center).magnitude();
+                if cost < costs[neighbor] {
+                    costs[neighbor] = cost;
+                    came_from[neighbor] = triangle;
+                    open.push(Candidate {
+                        estimate: cost + (goal_point - next_center).magnitude(),
+                        triangle: neighbor,
+                    });
+                }
+            }
+        }
+        None
+    }
+    /// The edge between two neighboring triangles as its left and right end, seen walking
+    /// from `from` into `to`.
+    fn portal(&self, from: usize, to: usize) -> Option<(Point3<f32>, Point3<f32>)> {
+        let triangle = &self.triangles[from];
+        let edge = triangle
+            .neighbors
+            .iter()
+            .position(|neighbor| *neighbor == Some(to))?;
+        let a = self.vertices[triangle.corners[edge]];
+        let b = self.vertices[triangle.corners[(edge + 1) % 3]];
+        match area_xz(&triangle.center, &b, &a) < 0.0 {
+            true => Some((a, b)),
+            false => Some((b, a)),
+        }
+    }
+}
+
+/// A triangle to visit in A*, ordered so the heap pops the lowest estimate first.
+#[derive(Debug, Copy, Clone)]
+struct Candidate {
+    estimate: f32,
+    triangle: usize,
+}
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Candidate {}
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// Twice the signed area of the triangle seen from above, negative when `c` is left of the
+/// line from `a` to `b`.
+fn area_xz(a: &Point3<f32>, b: &Point3<f32>, c: &Point3<f32>) -> f32 {
+    (c.x - a.x) * (b.z - a.z) - (b.x - a.x) * (c.z - a.z)
+}
+
+/// Weights of the corners at `point` seen from above, `None` outside the triangle.
+fn barycentric_xz(
+    point: &Point3<f32>,
+    a: &Point3<f32>,
+    b: &Point3<f32>,
+    c: &Point3<f32>,
+) -> Option<(f32, f32, f32)> {
+    let area = area_xz(a, b, c);
+    if area.abs() < 1e-12 {
+        return None;
+    }
+    let u = area_xz(point, b, c) / area;
+    let v = area_xz(a, point, c) / area;
+    let w = 1.0 - u - v;
+    (u >= -1e-5 && v >= -1e-5 && w >= -1e-5).then_some((u, v, w))
+}
+
+/// The simple stupid funnel algorithm: walks the portals keeping the funnel of directions
+/// the path can still take, each time a side crosses over the other its corner becomes a
+/// path point and the walk restarts from there. The first and last portals are the two
+/// ends of the path.
+fn string_pull(portals: &[(Point3<f32>, Point3<f32>)]) -> Vec<Point3<f32>> {
+    let mut apex = portals[0].0;
+    let (mut left, mut right) = portals[0];
+    let (mut left_index, mut right_index) = (0, 0);
+    let mut path = vec![apex];
+    let mut i = 1;
+    while i < portals.len() {
+        let (next_left, next_right) = portals[i];
+        if area_xz(&apex, &right, &next_right) <= 0.0 {
+            if apex == right || area_xz(&apex, &left, &next_right) > 0.0 {
+                right = next_right;
+                right_index = i;
+            } else {
+                // Portals sharing the apex corner can cross over at it again
+                if path.last() != Some(&left) {
+                    path.push(left);
+                }
+                apex = left;
+                (left, right) = (apex, apex);
+                right_index = left_index;
+                i = left_index + 1;
+                continue;
+            }
+        }
+        if area_xz(&apex, &left, &next_left) >= 0.0 {
+            if apex == left || area_xz(&apex, &right, &next_left) < 0.0 {
+                left = next_left;
+                left_index = i;
+            } else {
+                if path.last() != Some(&right) {
+                    path.push(right);
+                }
+                apex = right;
+                (left, right) = (apex, apex);
+                left_index = right_index;
+                i = right_index + 1;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    let goal = portals[portals.len() - 1].0;
+    if path.last() != Some(&goal) {
+        path.push(goal);
+    }
+    path
+}
+
+/// `path` through the debug lines with a small cross at every corner, e.g. from
+/// `NavMesh::find_path`.
+pub fn draw_path(debug: &mut DebugDraw, path: &[Point3<f32>], color: Color) {
+    let lift = Vector3::y() * DRAW_LIFT;
+    let lifted = path.iter().map(|point| point + lift).collect::<Vec<_>>();
+    debug.polyline(&lifted, color);
+    for point in &lifted {
+        for axis in [Vector3::x(), Vector3::z()] {
+            debug.line(point - axis * 0.1, point + axis * 0.1, color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unit squares on the ground, two triangles each, for every cell `walkable` accepts.
+    fn floor(size: usize, walkable: impl Fn(usize, usize) -> bool) -> Vec<[Point3<f32>; 3]> {
+        let mut triangles = vec![];
+        for x in 0..size {
+            for z in 0..size {
+                if !walkable(x, z) {
+                    continue;
+                }
+                let corner =
+                    |dx: usize, dz: usize| Point3::new((x + dx) as f32, 0.0, (z + dz) as f32);
+                triangles.push([corner(0, 0), corner(0, 1), corner(1, 0)]);
+                triangles.push([corner(1, 0), corner(0, 1), corner(1, 1)]);
+            }
+        }
+        triangles
+    }
+
+    /// A U shaped corridor one cell wide around a 3x4 block: up x = 0, across z = 4 and down
+    /// x = 4. Every path through it takes the one corridor, so its corners are known.
+    fn corridor() -> NavMesh {
+        let triangles = floor(5, |x, z| x == 0 || x == 4 || z == 4);
+        NavMesh::from_triangles(&triangles, &NavMeshSettings::default())
+    }
+
+    fn assert_path(path: &[Point3<f32>], expected: &[Point3<f32>]) {
+        assert_eq!(path.len(), expected.len(), "{path:?}");
+        for (point, expected) in path.iter().zip(expected) {
+            assert!((point - expected).magnitude() < 1e-4, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn locates_points_on_the_mesh_only() {
+        let mesh = corridor();
+        assert_eq!(mesh.triangle_count(), 2 * 13);
+        assert!(mesh.locate(&Point3::new(0.5, 0.3, 0.5)).is_some());
+        assert!(mesh.locate(&Point3::new(2.5, 0.0, 1.5)).is_none());
+        assert!(mesh.locate(&Point3::new(7.0, 0.0, 1.0)).is_none());
+    }
+
+    #[test]
+    fn paths_run_straight_when_nothing_is_in_the_way() {
+        let mesh = corridor();
+        let (start, goal) = (Point3::new(0.3, 0.0, 0.4), Point3::new(0.7, 0.0, 4.6));
+        assert_path(&mesh.find_path(&start, &goal).unwrap(), &[start, goal]);
+        assert_path(&mesh.find_path(&start, &start).unwrap(), &[start]);
+    }
+
+    #[test]
+    fn paths_are_pulled_tight_around_walls() {
+        let mesh = corridor();
+        let (start, goal) = (Point3::new(0.3, 0.0, 0.4), Point3::new(4.6, 0.0, 0.3));
+        let path = mesh.find_path(&start, &goal).unwrap();
+        let corners = [Point3::new(1.0, 0.0, 4.0), Point3::new(4.0, 0.0, 4.0)];
+        assert_path(&path, &[start, corners[0], corners[1], goal]);
+        let back = mesh.find_path(&goal, &start).unwrap();
+        assert_path(&back, &[goal, corners[1], corners[0], start]);
+    }
+
+    #[test]
+    fn no_path_off_the_mesh_or_between_islands() {
+        let islands = floor(5, |x, _| x != 2);
+        let mesh = NavMesh::from_triangles(&islands, &NavMeshSettings::default());
+        let start = Point3::new(0.5, 0.0, 0.5);
+        assert!(
+            mesh.find_path(&start, &Point3::new(4.5, 0.0, 0.5))
+                .is_none()
+        );
+        assert!(
+            mesh.find_path(&start, &Point3::new(2.5, 0.0, 0.5))
+                .is_none()
+        );
+        assert!(
+            mesh.find_path(&start, &Point3::new(1.5, 0.0, 4.5))
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn steep_and_downward_triangles_are_not_walkable() {
+        let mut triangles = floor(1, |_, _| true);
+        // A wall and the underside of the floor
+        triangles.push([
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        ]);
+        triangles.extend(floor(1, |_, _| true).into_iter().map(|[a, b, c]| [a, c, b]));
+        let mesh = NavMesh::from_triangles(&triangles, &NavMeshSettings::default());
+        assert_eq!(mesh.triangle_count(), 2);
+    }
+}
@@ -0,0 +1,102 @@
+use crate::geometry::Texture;
+use crate::renderer::{Color, RenderTarget};
+use image::{GenericImageView, Rgba};
+use std::sync::Arc;
+
+/// A texture cut into a 3x3 grid by its border widths in pixels. Corners keep their size,
+/// edges stretch along one axis and the center along both, so panels of any size keep
+/// crisp borders.
+#[derive(Debug, Clone)]
+pub struct NineSlice {
+    pub texture: Texture,
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+impl NineSlice {
+    pub fn new(texture: Texture, left: u32, right: u32, top: u32, bottom: u32) -> Self {
+        Self {
+            texture,
+            left,
+            right,
+            top,
+            bottom,
+        }
+    }
+    /// Blends the texture stretched over a screen rectangle, multiplied by `tint`.
+    pub fn draw(
+        &self,
+        target: &mut RenderTarget,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        tint: Color,
+    ) {
+        let image = &self.texture.texture;
+        let (source_width, source_height) = (image.width(), image.height());
+        if source_width == 0 || source_height == 0 {
+            return;
+        }
+        for dy in 0..height {
+            let sy = slice_coord(dy, height, source_height, self.top, self.bottom);
+            for dx in 0..width {
+                let sx = slice_coord(dx, width, source_width, self.left, self.right);
+                let Rgba([r, g, b, a]) = image.get_pixel(sx, sy);
+                let mut color = Color::from_rgba(r, g, b, a).modulate(&tint);
+                color.a *= tint.a;
+                if color.a > 0.0 {
+                    target.blend_rect(x + dx as i32, y + dy as i32, 1, 1, color);
+                }
+            }
+        }
+    }
+}
+
+/// Maps a destination pixel to the source along one axis. Borders are copied one to one,
+/// the middle is stretched, and destinations smaller than both borders scale everything.
+fn slice_coord(dest: u32, dest_size: u32, source_size: u32, start: u32, end: u32) -> u32 {
+    let coord = if start + end >= dest_size || start + end >= source_size {
+        dest * source_size / dest_size.max(1)
+    } else if dest < start {
+        dest
+    } else if dest >= dest_size - end {
+        source_size - (dest_size - dest)
+    } else {
+        let middle = source_size - start - end;
+        start + (dest - start) * middle / (dest_size - start - end)
+    };
+    coord.min(source_size - 1)
+}
+
+/// A nine-slice rectangle kept in `Scene::panels`, drawn over the scene before the sprites.
+#[derive(Debug, Clone)]
+pub struct Panel {
+    pub slices: Arc<NineSlice>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub tint: Color,
+    pub visible: bool,
+}
+impl Panel {
+    pub fn new(slices: Arc<NineSlice>, x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            slices,
+            x,
+            y,
+            width,
+            height,
+            tint: Color::new(1.0, 1.0, 1.0, 1.0),
+            visible: true,
+        }
+    }
+    pub fn draw(&self, target: &mut RenderTarget) {
+        if self.visible {
+            self.slices
+                .draw(target, self.x, self.y, self.width, self.height, self.tint);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::camera::Camera;
+use crate::error::SoftRastError;
+use crate::geometry::{Model, Texture, load_model, load_ply};
+use crate::my_app::load_gltf;
+use crate::pathtrace::{PathTraceSettings, PathTracer};
+use crate::renderer::{
+    Color, DrawMode, Material, RenderTarget, Sampler, TriangleFiller, UvTransform,
+};
+use crate::{Entity, Scene, draw_scene};
+use image::{Rgba, RgbaImage};
+use nalgebra::{Isometry3, Point3, Scale3, Vector3};
+use std::path::Path;
+
+/// Settings for rendering a single model to an image without a window.
+#[derive(Debug, Clone)]
+pub struct OfflineRender {
+    /// `.obj`, `.ply`, `.gltf` or `.glb` file.
+    pub model: String,
+    pub texture: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    /// Camera placement, framed around the model bounds when not given.
+    pub camera_position: Option<[f32; 3]>,
+    pub camera_target: Option<[f32; 3]>,
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    pub wireframe: bool,
+    /// Trace rays instead of rasterizing, for reference images.
+    pub ray_traced: bool,
+    /// Shadow rays when ray tracing.
+    pub shadows: bool,
+    /// Progressive path tracing for beauty shots, overrides `ray_traced`.
+    pub path_trace: Option<PathTraceSettings>,
+    pub out: String,
+}
+impl Default for OfflineRender {
+    fn default() -> Self {
+        Self {
+            model: String::new(),
+            texture: None,
+            width: 1280,
+            height: 720,
+            camera_position: None,
+            camera_target: None,
+            fov: 50.0,
+            wireframe: false,
+            ray_traced: false,
+            shadows: false,
+            path_trace: None,
+            out: "out.png".to_owned(),
+        }
+    }
+}
+
+/// Loads a model file, glTF files are merged into one model.
+pub(crate) fn load_any_model(path: &str) -> Result<Model, SoftRastError> {
+    if !Path::new(path).exists() {
+        return Err(SoftRastError::Io {
+            path: path.to_owned(),
+            source: std::io::ErrorKind::NotFound.into(),
+        });
+    }
+    let extension = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "obj" => load_model(path),
+        "ply" => load_ply(path).map_err(SoftRastError::Model),
+        "gltf" | "glb" => {
+            let vertices = load_gltf(path)?
+                .into_iter()
+                .flat_map(|model| model.vertices)
+                .collect::<Vec<_>>();
+            Ok(Model::from_vertices(&vertices))
+        }
+        _ => Err(SoftRastError::Unsupported(format!(
+            "model format '{}'",
+            extension
+        ))),
+    }
+}
+
+/// A scene holding just the model at the origin, lit from a fixed direction, and the point
+/// the camera looks at.
+pub(crate) fn model_scene(options: &OfflineRender) -> Result<(Scene, Point3<f32>), SoftRastError> {
+    let model = load_any_model(&options.model)?;
+    let light_dir = Vector3::new(1.0, 1.0, 0.5).normalize();
+    let texture = match &options.texture {
+        Some(path) => Some(Texture::new(path)?),
+        None => None,
+    };
+    let position = Isometry3::identity();
+    let scale = Scale3::identity();
+    let entity = match texture {
+        Some(texture) => Entity::new(
+            "model",
+            &model,
+            &position,
+            &scale,
+            Material::LitTexture {
+                texture,
+                light_dir,
+                uv_transform: UvTransform::default(),
+                sampler: Sampler::default(),
+                emissive: None,
+                specular: None,
+            },
+        ),
+        None => Entity::new(
+            "model",
+            &model,
+            &position,
+            &scale,
+            Material::LitSolid {
+                color: Color::new(0.8, 0.8, 0.8, 1.0),
+                light_dir,
+                emissive: None,
+                specular: None,
+            },
+        ),
+    };
+
+    let (min, max) = model.bounds();
+    let center = nalgebra::center(&min, &max);
+    let radius = ((max - min).magnitude() * 0.5).max(1e-3);
+    let fov = options.fov.to_radians();
+    let target = options.camera_target.map_or(center, Point3::from);
+    let position = options.camera_position.map_or_else(
+        // Far enough back that the bounding sphere fits the vertical field of view
+        || target + Vector3::new(0.0, 0.3, 1.0).normalize() * (radius / (fov * 0.5).sin()),
+        Point3::from,
+    );
+    let mut scene = Scene::new();
+    scene.camera = Camera::new(
+        position,
+        target,
+        Vector3::y(),
+        fov,
+        options.width.max(1) as f32 / options.height.max(1) as f32,
+        radius * 0.01,
+        (position - target).magnitude() + radius * 4.0,
+    );
+    scene.entities.push(entity);
+    Ok((scene, target))
+}
+
+/// Draws a scene into a new target of the given size and returns the color buffer.
+pub(crate) fn render_image(scene: &mut Scene, size: (u32, u32), mode: &DrawMode) -> RgbaImage {
+    let mut target = RenderTarget::new(size.0.max(1), size.1.max(1));
+    target.clear();
+    draw_scene(&mut target, scene, mode, 0.0);
+    target.to_texture().texture.to_rgba8()
+}
+
+/// Renders `options.model` and writes the image to `options.out`. A path traced render calls
+/// `progress` with the samples per pixel done and the total after every pass.
+pub fn render_to_file(
+    options: &OfflineRender,
+    progress: impl FnMut(u32, u32),
+) -> Result<(), SoftRastError> {
+    let (mut scene, _) = model_scene(options)?;
+    if let Some(settings) = options.path_trace {
+        let image = path_trace_image(&scene, (options.width, options.height), settings, progress);
+        return save_image(&image, &options.out);
+    }
+    let mode = DrawMode {
+        shaded: true,
+        wireframe: options.wireframe,
+        points: false,
+        ray_traced: options.ray_traced,
+        shadows: options.shadows,
+        ..DrawMode::default()
+    };
+    let image = render_image(&mut scene, (options.width, options.height), &mode);
+    save_image(&image, &options.out)
+}
+
+pub(crate) fn save_image(image: &RgbaImage, path: &str) -> Result<(), SoftRastError> {
+    image
+        .save(path)
+        .map_err(|err| SoftRastError::from_image(path, err))
+}
+
+/// How far an optimized render strayed from the reference rasterizer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RasterizerDiff {
+    /// Pixels with a channel off by more than the tolerance.
+    pub differing: usize,
+    pub pixels: usize,
+    /// Largest difference of any channel, tolerated or not.
+    pub max_difference: u8,
+    /// Topmost, then leftmost differing pixel, the place to start looking.
+    pub first: Option<(u32, u32)>,
+}
+impl RasterizerDiff {
+    pub fn matches(&self) -> bool {
+        self.differing == 0
+    }
+}
+
+/// Renders `options.model` with `filler` and with the reference rasterizer, and writes an
+/// image of the differences to `options.out`: the reference dimmed, differing pixels red.
+/// Channels within `tolerance` count as equal, edge pixels can flip with float rounding
+/// between the incremental and direct edge tests.
+pub fn compare_rasterizers(
+    options: &OfflineRender,
+    filler: TriangleFiller,
+    tolerance: u8,
+) -> Result<RasterizerDiff, SoftRastError> {
+    let (mut scene, _) = model_scene(options)?;
+    let size = (options.width, options.height);
+    let mode = DrawMode {
+        wireframe: options.wireframe,
+        filler,
+        ..DrawMode::default()
+    };
+    let optimized = render_image(&mut scene, size, &mode);
+    let reference = render_image(
+        &mut scene,
+        size,
+        &DrawMode {
+            reference: true,
+            ..mode
+        },
+    );
+    let mut diff = RasterizerDiff {
+        differing: 0,
+        pixels: reference.pixels().len(),
+        max_difference: 0,
+        first: None,
+    };
+    let mut image = RgbaImage::new(reference.width(), reference.height());
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let actual = optimized.get_pixel(x, y);
+        let difference = (0..4)
+            .map(|c| expected[c].abs_diff(actual[c]))
+            .max()
+            .unwrap_or(0);
+        diff.max_difference = diff.max_difference.max(difference);
+        let pixel = if difference > tolerance {
+            diff.differing += 1;
+            diff.first.get_or_insert((x, y));
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([expected[0] / 4, expected[1] / 4, expected[2] / 4, 255])
+        };
+        image.put_pixel(x, y, pixel);
+    }
+    save_image(&image, &options.out)?;
+    Ok(diff)
+}
+
+/// Path traces a scene in passes until `settings.samples` per pixel are accumulated,
+/// calling `progress` with the samples done and the total after each pass since high sample
+/// counts take a while.
+pub(crate) fn path_trace_image(
+    scene: &Scene,
+    size: (u32, u32),
+    settings: PathTraceSettings,
+    mut progress: impl FnMut(u32, u32),
+) -> RgbaImage {
+    const SAMPLES_PER_PASS: u32 = 8;
+    let size = (size.0.max(1), size.1.max(1));
+    let mut tracer = PathTracer::new(settings, size);
+    while tracer.samples() < settings.samples {
+        let count = SAMPLES_PER_PASS.min(settings.samples - tracer.samples());
+        tracer.add_samples(scene, &scene.camera, count, 0.0);
+        progress(tracer.samples(), settings.samples);
+    }
+    let mut target = RenderTarget::new(size.0, size.1);
+    target.clear();
+    tracer.resolve(&mut target);
+    target.to_texture().texture.to_rgba8()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monkey(size: (u32, u32), out: &str) -> OfflineRender {
+        OfflineRender {
+            model: "assets/monkey.obj".to_owned(),
+            width: size.0,
+            height: size.1,
+            out: std::env::temp_dir()
+                .join(out)
+                .to_string_lossy()
+                .into_owned(),
+            ..OfflineRender::default()
+        }
+    }
+
+    #[test]
+    fn optimized_fillers_match_the_reference_rasterizer() {
+        // One size a multiple of the tiles, one cutting them and the quads short
+        for size in [(256, 128), (203, 97)] {
+            let options = monkey(size, &format!("softrast-diff-{}.png", size.0));
+            let (mut scene, _) = model_scene(&options).unwrap();
+            let image = render_image(&mut scene, size, &DrawMode::default());
+            let background = image.get_pixel(0, 0);
+            let covered = image.pixels().filter(|pixel| *pixel != background).count();
+            assert!(
+                covered > image.pixels().len() / 20,
+                "only {covered} pixels drawn"
+            );
+
+            for filler in [TriangleFiller::BoundingBox, TriangleFiller::Spans] {
+                let diff = compare_rasterizers(&options, filler, 0).unwrap();
+                assert!(diff.matches(), "{filler:?} at {size:?}: {diff:?}");
+                assert_eq!(diff.pixels, (size.0 * size.1) as usize);
+            }
+            std::fs::remove_file(&options.out).unwrap();
+        }
+    }
+
+    #[test]
+    fn zero_size_renders_a_single_pixel() {
+        let options = monkey((0, 0), "softrast-zero.png");
+        let (mut scene, _) = model_scene(&options).unwrap();
+        let image = render_image(&mut scene, (0, 0), &DrawMode::default());
+        assert_eq!(image.dimensions(), (1, 1));
+    }
+}
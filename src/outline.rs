@@ -0,0 +1,148 @@
+use crate::camera::Camera;
+use crate::renderer::{Color, RenderTarget};
+use nalgebra::Point3;
+use rayon::prelude::*;
+
+/// Screen space outlines composited after the scene is drawn. Edges are found where the
+/// view depth jumps, where normals reconstructed from depth bend sharply, and where the id
+/// buffer changes between entities. Edges around selected entities use `highlight`.
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub color: Color,
+    /// Relative change in view depth between neighbouring pixels that counts as an edge.
+    pub depth_threshold: f32,
+    /// Angle in radians between neighbouring normals that counts as a crease.
+    pub normal_threshold: f32,
+    pub highlight: Color,
+    /// Scene entity indices outlined with `highlight`.
+    pub selected: Vec<usize>,
+}
+impl Default for Outline {
+    fn default() -> Self {
+        Self {
+            color: Color::new(0.0, 0.0, 0.0, 1.0),
+            depth_threshold: 0.05,
+            normal_threshold: 0.8,
+            highlight: Color::new(1.0, 0.6, 0.1, 1.0),
+            selected: vec![],
+        }
+    }
+}
+#[allow(unused)]
+impl Outline {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            ..Self::default()
+        }
+    }
+    pub fn with_thresholds(mut self, depth: f32, normal: f32) -> Self {
+        self.depth_threshold = depth;
+        self.normal_threshold = normal;
+        self
+    }
+    /// Draws edges over the target's colors, using its depth and, when enabled, ids.
+    /// `camera` has to be the one the target was drawn with.
+    pub(crate) fn apply(&self, target: &mut RenderTarget, camera: &Camera) {
+        let (width, height) = target.size();
+        let Some(inverse_projection) = camera.get_perspective_matrix().try_inverse() else {
+            return;
+        };
+        let depth = target.depth();
+        let ids = target.ids();
+        let index = |x: u32, y: u32| (y * width + x) as usize;
+
+        // View space positions, none for background
+        let positions = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let z = depth[i as usize];
+                (z != f32::MAX).then(|| {
+                    let (x, y) = (i % width, i / width);
+                    let ndc = Point3::new(
+                        (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                        1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+                        z,
+                    );
+                    inverse_projection.transform_point(&ndc).coords
+                })
+            })
+            .collect::<Vec<_>>();
+        let position = |x: u32, y: u32| positions[index(x, y)];
+        let normals = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let center = position(x, y)?;
+                // Forward differences, backward ones at the right and bottom borders
+                let dx = if x + 1 < width {
+                    position(x + 1, y)? - center
+                } else {
+                    center - position(x.checked_sub(1)?, y)?
+                };
+                let dy = if y + 1 < height {
+                    position(x, y + 1)? - center
+                } else {
+                    center - position(x, y.checked_sub(1)?)?
+                };
+                dy.cross(&dx).try_normalize(1e-12)
+            })
+            .collect::<Vec<_>>();
+
+        let selected = |id: u32| id > 0 && self.selected.contains(&(id as usize - 1));
+        let crease = self.normal_threshold.cos();
+        let edges = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let center = positions[i as usize];
+                let id = ids.get(i as usize).copied().unwrap_or(0);
+                let mut edge = None;
+                for (nx, ny) in [
+                    (x + 1, y),
+                    (x.wrapping_sub(1), y),
+                    (x, y + 1),
+                    (x, y.wrapping_sub(1)),
+                ] {
+                    if nx >= width || ny >= height {
+                        continue;
+                    }
+                    let neighbour = position(nx, ny);
+                    let neighbour_id = ids.get(index(nx, ny)).copied().unwrap_or(0);
+                    if id != neighbour_id && (selected(id) || selected(neighbour_id)) {
+                        // Highlights go on both sides so they read against any background
+                        return Some(self.highlight);
+                    }
+                    // Other edges only on the nearer side, keeping lines one pixel wide
+                    let is_edge = match (center, neighbour) {
+                        (Some(center), Some(neighbour)) => {
+                            let (near, far) = (-center.z, -neighbour.z);
+                            if near > far {
+                                continue;
+                            }
+                            let bent = normals[i as usize]
+                                .zip(normals[index(nx, ny)])
+                                .is_some_and(|(a, b)| a.dot(&b) < crease);
+                            id != neighbour_id
+                                || (far - near) > self.depth_threshold * near
+                                || (bent && nx + ny > x + y)
+                        }
+                        (Some(_), None) => true,
+                        _ => false,
+                    };
+                    if is_edge {
+                        edge = Some(self.color);
+                    }
+                }
+                edge
+            })
+            .collect::<Vec<_>>();
+
+        for (i, edge) in edges.into_iter().enumerate() {
+            if let Some(edge) = edge {
+                let dst = Color::from_u32(target.color[i]);
+                target.color[i] = dst.lerp(&edge, edge.a).as_u32();
+            }
+        }
+    }
+}
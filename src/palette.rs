@@ -0,0 +1,250 @@
+use crate::geometry::Vertex;
+use crate::renderer::{Color, Fragment, RenderTarget, Shader, calculate_normals};
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Bits per channel of the nearest color lookup table.
+const LOOKUP_BITS: u32 = 5;
+/// 4x4 ordered dither thresholds.
+const BAYER: [[f32; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+/// A 256 color palette for DOS style rendering. With `Scene::palette` set, the finished
+/// frame is reduced to one index per pixel and presented through the palette, so everything
+/// on screen uses its colors. `PaletteShader` shades straight to indices, other materials
+/// are matched to the nearest entry.
+#[derive(Debug, Clone)]
+pub struct Palette {
+    colors: Arc<Vec<Color>>,
+    /// Index of every exact palette color, the first one wins for duplicates.
+    exact: Arc<HashMap<u32, u8>>,
+    /// Nearest index for colors cut to `LOOKUP_BITS` per channel.
+    lookup: Arc<Vec<u8>>,
+    /// Ordered dithering before matching, trades banding for a fine pattern.
+    pub dither: bool,
+}
+impl Default for Palette {
+    /// Sixteen ramps of sixteen shades, greys first.
+    fn default() -> Self {
+        let bases = [
+            (255, 255, 255),
+            (255, 64, 64),
+            (255, 140, 40),
+            (255, 220, 60),
+            (180, 255, 60),
+            (60, 220, 60),
+            (60, 255, 180),
+            (60, 220, 255),
+            (60, 120, 255),
+            (100, 60, 255),
+            (190, 60, 255),
+            (255, 60, 200),
+            (160, 110, 70),
+            (110, 140, 90),
+            (120, 130, 160),
+            (230, 190, 160),
+        ]
+        .map(|(r, g, b)| Color::from_rgba(r, g, b, 255));
+        Self::ramps(&bases, 16)
+    }
+}
+#[allow(unused)]
+impl Palette {
+    /// The first 256 of `colors`, padded with black.
+    pub fn new(colors: &[Color]) -> Self {
+        let mut colors = colors
+            .iter()
+            .take(256)
+            .map(|color| Color { a: 1.0, ..*color })
+            .collect::<Vec<_>>();
+        colors.resize(256, Color::new(0.0, 0.0, 0.0, 1.0));
+        let mut exact = HashMap::new();
+        for (index, color) in colors.iter().enumerate() {
+            exact.entry(color.as_u32()).or_insert(index as u8);
+        }
+        let levels = 1 << LOOKUP_BITS;
+        let step = 255.0 / (levels - 1) as f32;
+        let lookup = (0..levels * levels * levels)
+            .into_par_iter()
+            .map(|cell| {
+                let channel = |shift: u32| ((cell >> shift) & (levels - 1)) as f32 * step / 255.0;
+                let color = Color::new(
+                    channel(2 * LOOKUP_BITS),
+                    channel(LOOKUP_BITS),
+                    channel(0),
+                    1.0,
+                );
+                nearest(&colors, &color)
+            })
+            .collect();
+        Self {
+            colors: Arc::new(colors),
+            exact: Arc::new(exact),
+            lookup: Arc::new(lookup),
+            dither: false,
+        }
+    }
+    /// Ramps of `steps` shades from black to each of `bases`, ramp `i` starts at index
+    /// `i * steps`, see `PaletteRamp::of`.
+    pub fn ramps(bases: &[Color], steps: u8) -> Self {
+        let steps = steps.max(2);
+        let colors = bases
+            .iter()
+            .flat_map(|base| (0..steps).map(move |step| *base * (step as f32 / (steps - 1) as f32)))
+            .collect::<Vec<_>>();
+        Self::new(&colors)
+    }
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+    pub fn color(&self, index: u8) -> Color {
+        self.colors[index as usize]
+    }
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+    /// Index of the palette color closest to `color`.
+    pub fn index_of(&self, color: &Color) -> u8 {
+        if let Some(index) = self.exact.get(&color.as_u32()) {
+            return *index;
+        }
+        let shift = 8 - LOOKUP_BITS;
+        let packed = color.as_u32();
+        let (r, g, b) = ((packed >> 16) & 0xff, (packed >> 8) & 0xff, packed & 0xff);
+        let cell = (r >> shift) << (2 * LOOKUP_BITS) | (g >> shift) << LOOKUP_BITS | (b >> shift);
+        self.lookup[cell as usize]
+    }
+    /// One palette index per pixel of `target`.
+    pub fn quantize(&self, target: &RenderTarget) -> Vec<u8> {
+        let width = target.size().0 as usize;
+        let mut indices = vec![0; target.color.len()];
+        indices
+            .par_chunks_mut(width.max(1))
+            .zip(target.color.par_chunks(width.max(1)))
+            .enumerate()
+            .for_each(|(y, (indices, row))| {
+                for (x, (index, pixel)) in indices.iter_mut().zip(row).enumerate() {
+                    let mut color = Color::from_u32(*pixel);
+                    if self.dither && !self.exact.contains_key(pixel) {
+                        let offset = (BAYER[y % 4][x % 4] / 16.0 - 0.5) / 16.0;
+                        color =
+                            Color::new(color.r + offset, color.g + offset, color.b + offset, 1.0);
+                    }
+                    *index = self.index_of(&color);
+                }
+            });
+        indices
+    }
+    /// Writes the colors of `indices` into `target`, the presentation step of palette
+    /// rendering.
+    pub fn present(&self, indices: &[u8], target: &mut RenderTarget) {
+        target
+            .color
+            .par_iter_mut()
+            .zip(indices)
+            .for_each(|(pixel, index)| {
+                *pixel = self.colors[*index as usize].as_u32();
+            });
+    }
+    /// Reduces the frame in `target` to palette colors.
+    pub fn apply(&self, target: &mut RenderTarget) {
+        let indices = self.quantize(target);
+        self.present(&indices, target);
+    }
+}
+
+/// Brute force search, only used to fill the lookup table.
+fn nearest(colors: &[Color], color: &Color) -> u8 {
+    let distance = |other: &Color| {
+        let (r, g, b) = (other.r - color.r, other.g - color.g, other.b - color.b);
+        // Weighted like the eye, green differences stand out the most
+        0.3 * r * r + 0.59 * g * g + 0.11 * b * b
+    };
+    colors
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| distance(a).total_cmp(&distance(b)))
+        .map_or(0, |(index, _)| index as u8)
+}
+
+/// A run of palette entries from dark to bright, lighting picks the shade.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PaletteRamp {
+    pub start: u8,
+    pub length: u8,
+}
+#[allow(unused)]
+impl PaletteRamp {
+    pub fn new(start: u8, length: u8) -> Self {
+        Self {
+            start,
+            length: length.max(1),
+        }
+    }
+    /// Ramp `ramp` of a palette from `Palette::ramps` with `steps` shades.
+    pub fn of(ramp: u8, steps: u8) -> Self {
+        Self::new(ramp.saturating_mul(steps), steps)
+    }
+    /// The entry for `light` in 0..1.
+    pub fn index(&self, light: f32) -> u8 {
+        let step = (light.clamp(0.0, 1.0) * (self.length - 1) as f32).round() as u8;
+        self.start.saturating_add(step)
+    }
+}
+
+/// Shades with palette entries, a fixed `index` or, when lit, the shade of `ramp` matching
+/// N·L plus the ambient light.
+#[derive(Debug, Clone)]
+pub struct PaletteShader {
+    pub palette: Palette,
+    pub index: u8,
+    pub ramp: Option<PaletteRamp>,
+    pub light_dir: Vector3<f32>,
+}
+#[allow(unused)]
+impl PaletteShader {
+    pub fn new(palette: Palette, index: u8) -> Self {
+        Self {
+            palette,
+            index,
+            ramp: None,
+            light_dir: Vector3::y(),
+        }
+    }
+    pub fn lit(palette: Palette, ramp: PaletteRamp, light_dir: Vector3<f32>) -> Self {
+        Self {
+            palette,
+            index: ramp.index(1.0),
+            ramp: Some(ramp),
+            light_dir: light_dir.normalize(),
+        }
+    }
+    /// The palette index for a fragment.
+    pub fn shade_index(&self, triangle: &[Vertex], fragment: &Fragment) -> u8 {
+        let (Some(ramp), Some(normal)) =
+            (self.ramp, calculate_normals(triangle, &fragment.weights))
+        else {
+            return self.index;
+        };
+        let ambient = (fragment.ambient.r + fragment.ambient.g + fragment.ambient.b) / 3.0;
+        ramp.index(normal.dot(&self.light_dir).max(0.0) + ambient)
+    }
+}
+impl Shader for PaletteShader {
+    fn shade(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        self.palette.color(self.shade_index(triangle, fragment))
+    }
+    fn light_dir(&self) -> Option<Vector3<f32>> {
+        self.ramp.map(|_| self.light_dir)
+    }
+    fn albedo(&self, _triangle: &[Vertex], _fragment: &Fragment) -> Color {
+        self.palette.color(self.index)
+    }
+}
@@ -0,0 +1,101 @@
+use crate::Scene;
+use crate::camera::Camera;
+use crate::error::SoftRastError;
+use crate::offline::{OfflineRender, model_scene, render_image, save_image};
+use crate::renderer::DrawMode;
+use image::{Rgba, RgbaImage};
+use nalgebra::{Matrix4, Vector3};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Cube face views from `camera`: +x, -x, +y, -y, +z, -z, each with a 90 degree field of view.
+fn face_cameras(camera: &Camera) -> [Camera; 6] {
+    let faces = [
+        (Vector3::x(), Vector3::y()),
+        (-Vector3::x(), Vector3::y()),
+        (Vector3::y(), Vector3::z()),
+        (-Vector3::y(), -Vector3::z()),
+        (Vector3::z(), Vector3::y()),
+        (-Vector3::z(), Vector3::y()),
+    ];
+    faces.map(|(direction, up)| Camera {
+        world_offset: camera.world_offset,
+        ..Camera::new(
+            camera.position,
+            camera.position + direction,
+            up,
+            FRAC_PI_2,
+            1.0,
+            camera.near,
+            camera.far,
+        )
+    })
+}
+
+/// Renders the six cube faces around the scene camera and stitches them into an
+/// equirectangular panorama `width` wide and half as high, the format 360 degree photo
+/// viewers expect. The center of the image looks along -z.
+pub fn render_equirectangular(
+    scene: &mut Scene,
+    width: u32,
+    face_size: u32,
+    mode: &DrawMode,
+) -> RgbaImage {
+    let camera = scene.camera;
+    let faces = face_cameras(&camera).map(|face| {
+        scene.camera = face;
+        let view_projection = face.get_perspective_matrix() * face.get_view_matrix();
+        (
+            view_projection,
+            render_image(scene, (face_size, face_size), mode),
+        )
+    });
+    scene.camera = camera;
+
+    let (width, height) = (width.max(2), (width / 2).max(1));
+    RgbaImage::from_fn(width, height, |x, y| {
+        let longitude = (x as f32 + 0.5) / width as f32 * TAU - PI;
+        let latitude = FRAC_PI_2 - (y as f32 + 0.5) / height as f32 * PI;
+        let direction = Vector3::new(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        );
+        sample_faces(&faces, &direction).unwrap_or(Rgba([0, 0, 0, 255]))
+    })
+}
+
+/// The pixel of whichever face sees `direction`, projected through that face's camera so it
+/// matches how the face was drawn.
+fn sample_faces(faces: &[(Matrix4<f32>, RgbaImage)], direction: &Vector3<f32>) -> Option<Rgba<u8>> {
+    faces.iter().find_map(|(view_projection, image)| {
+        // Directions only, the translation of the view cancels out
+        let clip = view_projection * direction.push(0.0);
+        if clip.w <= 0.0 {
+            return None;
+        }
+        let (ndc_x, ndc_y) = (clip.x / clip.w, clip.y / clip.w);
+        if ndc_x.abs() > 1.0 || ndc_y.abs() > 1.0 {
+            return None;
+        }
+        let size = image.width();
+        let px = (((ndc_x + 1.0) * 0.5 * size as f32) as u32).min(size - 1);
+        let py = (((1.0 - ndc_y) * 0.5 * size as f32) as u32).min(size - 1);
+        Some(*image.get_pixel(px, py))
+    })
+}
+
+/// Renders `options.model` as a panorama `options.width` wide from the offline camera and
+/// writes it to `options.out`.
+pub fn render_panorama(options: &OfflineRender, face_size: u32) -> Result<(), SoftRastError> {
+    let (mut scene, _) = model_scene(options)?;
+    let mode = DrawMode {
+        shaded: true,
+        wireframe: options.wireframe,
+        points: false,
+        ray_traced: options.ray_traced,
+        shadows: options.shadows,
+        ..DrawMode::default()
+    };
+    let image = render_equirectangular(&mut scene, options.width, face_size.max(1), &mode);
+    save_image(&image, &options.out)
+}
@@ -0,0 +1,183 @@
+use crate::Scene;
+use crate::ambient_occlusion::cosine_sample;
+use crate::camera::Camera;
+use crate::geometry::Ray;
+use crate::raytrace::{SHADOW_BIAS, TracedEntity, barycentric, blocked, closest_hit};
+use crate::renderer::{Color, Fragment, RenderTarget};
+use nalgebra::{Point2, Vector3};
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+use rayon::prelude::*;
+
+#[derive(Debug, Copy, Clone)]
+pub struct PathTraceSettings {
+    /// Samples per pixel for offline renders.
+    pub samples: u32,
+    /// Diffuse bounces after the first hit, zero is direct light only.
+    pub bounces: u32,
+    /// Light from rays escaping the scene.
+    pub sky_color: Color,
+    /// Direction towards the sun, which lights every hit it can see.
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: Color,
+}
+impl Default for PathTraceSettings {
+    fn default() -> Self {
+        Self {
+            samples: 64,
+            bounces: 3,
+            sky_color: Color::new(0.6, 0.7, 0.9, 1.0),
+            sun_direction: Vector3::new(1.0, 1.0, 0.5).normalize(),
+            sun_color: Color::new(1.0, 1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Progressive diffuse path tracer. Every call to `add_samples` traces more jittered paths
+/// per pixel into an accumulation buffer, so the image converges the longer it runs.
+/// Materials are used through `Shader::albedo` and `Shader::emission`.
+pub struct PathTracer {
+    pub settings: PathTraceSettings,
+    width: u32,
+    height: u32,
+    accumulation: Vec<Vector3<f32>>,
+    samples: u32,
+}
+#[allow(unused)]
+impl PathTracer {
+    pub fn new(settings: PathTraceSettings, size: (u32, u32)) -> Self {
+        Self {
+            settings,
+            width: size.0,
+            height: size.1,
+            accumulation: vec![Vector3::zeros(); (size.0 * size.1) as usize],
+            samples: 0,
+        }
+    }
+    /// Starts over, e.g. after the camera or scene changed.
+    pub fn reset(&mut self) {
+        self.accumulation.fill(Vector3::zeros());
+        self.samples = 0;
+    }
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+    pub fn add_samples(&mut self, scene: &Scene, camera: &Camera, count: u32, time: f32) {
+        let traced = scene
+            .entities
+            .iter()
+            .map(TracedEntity::new)
+            .collect::<Vec<_>>();
+        let (width, height) = (self.width, self.height);
+        let settings = &self.settings;
+        let first = self.samples;
+        self.accumulation
+            .par_chunks_mut(width as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, sum) in row.iter_mut().enumerate() {
+                    // Seeded per pixel and pass so renders are reproducible
+                    let seed = (y as u64 * width as u64 + x as u64) << 20 | first as u64;
+                    let mut rng = XorShiftRng::seed_from_u64(seed);
+                    for _ in 0..count {
+                        let ray = camera.screen_ray(
+                            x as f32 + rng.random::<f32>(),
+                            y as f32 + rng.random::<f32>(),
+                            (width, height),
+                        );
+                        *sum += radiance(&traced, ray, settings, &mut rng, time);
+                    }
+                }
+            });
+        self.samples += count;
+    }
+    /// Writes the average of all samples so far into the target's colors.
+    pub fn resolve(&self, target: &mut RenderTarget) {
+        let scale = 1.0 / self.samples.max(1) as f32;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let average = self.accumulation[(y * self.width + x) as usize] * scale;
+                let color = Color::new(
+                    average.x.clamp(0.0, 1.0),
+                    average.y.clamp(0.0, 1.0),
+                    average.z.clamp(0.0, 1.0),
+                    1.0,
+                );
+                target.put_pixel(x as i32, y as i32, color.as_u32());
+            }
+        }
+    }
+}
+
+fn radiance(
+    traced: &[TracedEntity],
+    mut ray: Ray,
+    settings: &PathTraceSettings,
+    rng: &mut XorShiftRng,
+    time: f32,
+) -> Vector3<f32> {
+    let sky = to_vector(&settings.sky_color);
+    let sun = to_vector(&settings.sun_color);
+    let sun_direction = settings.sun_direction.normalize();
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vector3::zeros();
+    for bounce in 0..=settings.bounces {
+        let Some((distance, entity, triangle)) = closest_hit(traced, &ray, f32::MAX) else {
+            radiance += throughput.component_mul(&sky);
+            break;
+        };
+        let vertices = &entity.vertices[triangle * 3..triangle * 3 + 3];
+        let point = ray.at(distance);
+        let fragment = Fragment {
+            weights: barycentric(vertices, &point),
+            ddx: Vector3::zeros(),
+            ddy: Vector3::zeros(),
+            screen: Point2::origin(),
+            time,
+            ambient: Color::new(0.0, 0.0, 0.0, 1.0),
+            view_dir: -ray.direction.normalize(),
+            nearest_sampling: false,
+        };
+        let shader = entity.entity.material(entity.materials[triangle]);
+        let albedo = to_vector(&shader.albedo(vertices, &fragment));
+        radiance += throughput.component_mul(&to_vector(&shader.emission(vertices, &fragment)));
+
+        let (a, b, c) = (
+            vertices[0].position.xyz(),
+            vertices[1].position.xyz(),
+            vertices[2].position.xyz(),
+        );
+        let Some(face_normal) = (b - a).cross(&(c - a)).try_normalize(1e-12) else {
+            break;
+        };
+        let face_normal = if face_normal.dot(&ray.direction) > 0.0 {
+            -face_normal
+        } else {
+            face_normal
+        };
+
+        let n_dot_l = face_normal.dot(&sun_direction);
+        if n_dot_l > 0.0
+            && !blocked(
+                traced.iter().map(|t| &t.bvh),
+                &point,
+                &face_normal,
+                &sun_direction,
+            )
+        {
+            radiance += throughput.component_mul(&albedo).component_mul(&sun) * n_dot_l;
+        }
+        if bounce == settings.bounces {
+            break;
+        }
+        // Cosine sampling cancels the Lambert term, leaving just the albedo
+        throughput = throughput.component_mul(&albedo);
+        let direction = cosine_sample(&face_normal, rng.random(), rng.random());
+        ray = Ray::new(point + face_normal * SHADOW_BIAS, direction);
+    }
+    radiance
+}
+
+fn to_vector(color: &Color) -> Vector3<f32> {
+    Vector3::new(color.r, color.g, color.b)
+}
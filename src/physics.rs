@@ -0,0 +1,500 @@
+use crate::Entity;
+use crate::debug::DebugDraw;
+use crate::error::SoftRastError;
+use crate::renderer::Color;
+use nalgebra::{
+    Isometry3, Matrix3, Matrix4, Point3, Scale3, Translation3, UnitQuaternion, Vector3,
+};
+use rapier3d::prelude::{
+    CCDSolver, ColliderBuilder, ColliderSet, DefaultBroadPhase, ImpulseJointSet,
+    IntegrationParameters, IslandManager, MultibodyJointSet, NarrowPhase, PhysicsPipeline,
+    QueryPipeline, RigidBodyBuilder, RigidBodyHandle, RigidBodySet, RigidBodyType,
+};
+use std::collections::HashSet;
+
+/// Length of one physics step, the world catches up with the frame time in steps this long.
+const FIXED_STEP: f32 = 1.0 / 60.0;
+/// Steps taken at most per frame, a long stall drops time instead of stalling further.
+const MAX_STEPS: u32 = 8;
+
+/// How a body moves.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum BodyKind {
+    /// Moved by the simulation, the entity follows the body.
+    Dynamic,
+    /// Moved by the entity, pushing dynamic bodies out of the way.
+    Kinematic,
+    /// Never moves, e.g. floors and walls.
+    Fixed,
+}
+
+/// Collision shape of a body in world units, the entity's scale isn't applied except to
+/// `ConvexHull`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ColliderShape {
+    Ball {
+        radius: f32,
+    },
+    Cuboid {
+        half_extents: Vector3<f32>,
+    },
+    /// Along the local y axis, `half_height` without the caps.
+    Capsule {
+        half_height: f32,
+        radius: f32,
+    },
+    /// Convex hull of the entity's scaled model vertices.
+    ConvexHull,
+}
+
+/// Links an entity to a rigid body of the scene's `PhysicsWorld`, see
+/// `Entity::with_physics_body`. The body is created at the entity's position the first
+/// update after the entity joins the scene and removed once the entity is gone.
+#[derive(Debug, Clone)]
+pub struct PhysicsBody {
+    pub kind: BodyKind,
+    pub shape: ColliderShape,
+    pub density: f32,
+    pub friction: f32,
+    pub restitution: f32,
+    /// Velocity the body starts with.
+    pub linear_velocity: Vector3<f32>,
+    pub(crate) handle: Option<RigidBodyHandle>,
+}
+#[allow(unused)]
+impl PhysicsBody {
+    pub fn new(kind: BodyKind, shape: ColliderShape) -> Self {
+        Self {
+            kind,
+            shape,
+            density: 1.0,
+            friction: 0.5,
+            restitution: 0.0,
+            linear_velocity: Vector3::zeros(),
+            handle: None,
+        }
+    }
+    pub fn dynamic(shape: ColliderShape) -> Self {
+        Self::new(BodyKind::Dynamic, shape)
+    }
+    pub fn kinematic(shape: ColliderShape) -> Self {
+        Self::new(BodyKind::Kinematic, shape)
+    }
+    pub fn fixed(shape: ColliderShape) -> Self {
+        Self::new(BodyKind::Fixed, shape)
+    }
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction;
+        self
+    }
+    /// Bounciness, 0 stops dead and 1 bounces back at full speed.
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+    pub fn with_linear_velocity(mut self, velocity: Vector3<f32>) -> Self {
+        self.linear_velocity = velocity;
+        self
+    }
+}
+
+/// A rapier simulation of the entities with a `PhysicsBody`, stepped at a fixed rate from
+/// the frame's simulation time. Dynamic bodies write their pose back into the entities,
+/// kinematic bodies follow theirs.
+pub struct PhysicsWorld {
+    pub gravity: Vector3<f32>,
+    /// Outline the colliders through the scene's debug lines.
+    pub debug_colliders: bool,
+    /// Simulation time not yet stepped.
+    accumulator: f32,
+    parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: DefaultBroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    query_pipeline: QueryPipeline,
+}
+impl Default for PhysicsWorld {
+    fn default() -> Self {
+        Self {
+            gravity: Vector3::new(0.0, -9.81, 0.0),
+            debug_colliders: false,
+            accumulator: 0.0,
+            parameters: IntegrationParameters {
+                dt: FIXED_STEP,
+                ..IntegrationParameters::default()
+            },
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: DefaultBroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            query_pipeline: QueryPipeline::new(),
+        }
+    }
+}
+#[allow(unused)]
+impl PhysicsWorld {
+    pub fn with_gravity(mut self, gravity: Vector3<f32>) -> Self {
+        self.gravity = gravity;
+        self
+    }
+    pub fn with_debug_colliders(mut self, debug_colliders: bool) -> Self {
+        self.debug_colliders = debug_colliders;
+        self
+    }
+    /// Adds and removes bodies to match the entities, runs the fixed steps `delta` seconds
+    /// are worth and syncs the poses. Returns the bodies that couldn't be created, their
+    /// entities are left without physics.
+    pub(crate) fn update(
+        &mut self,
+        entities: &mut [Entity],
+        debug: &mut DebugDraw,
+        delta: f32,
+    ) -> Vec<SoftRastError> {
+        let errors = self.sync_bodies(entities);
+        self.accumulator = (self.accumulator + delta).min(FIXED_STEP * MAX_STEPS as f32);
+        while self.accumulator >= FIXED_STEP {
+            self.accumulator -= FIXED_STEP;
+            self.step();
+        }
+        for entity in entities.iter_mut() {
+            let Some(handle) = entity.physics.as_ref().and_then(|body| body.handle) else {
+                continue;
+            };
+            if let Some(body) = self.bodies.get(handle)
+                && body.body_type() == RigidBodyType::Dynamic
+            {
+                set_body_pose(entity, body.position());
+            }
+        }
+        if self.debug_colliders {
+            self.draw_colliders(debug);
+        }
+        errors
+    }
+    /// Pushes a dynamic body, e.g. for explosions or a thrown object.
+    pub fn apply_impulse(&mut self, entity: &Entity, impulse: Vector3<f32>) {
+        let handle = entity.physics.as_ref().and_then(|body| body.handle);
+        if let Some(body) = handle.and_then(|handle| self.bodies.get_mut(handle)) {
+            body.apply_impulse(impulse, true);
+        }
+    }
+    /// Velocity of an entity's body, `None` before the body is created.
+    pub fn linear_velocity(&self, entity: &Entity) -> Option<Vector3<f32>> {
+        let handle = entity.physics.as_ref()?.handle?;
+        self.bodies.get(handle).map(|body| *body.linvel())
+    }
+    fn step(&mut self) {
+        self.pipeline.step(
+            &self.gravity,
+            &self.parameters,
+            &mut self.islands,
+            &mut self.broad_phase,
+            &mut self.narrow_phase,
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.impulse_joints,
+            &mut self.multibody_joints,
+            &mut self.ccd_solver,
+            Some(&mut self.query_pipeline),
+            &(),
+            &(),
+        );
+    }
+    /// Creates the bodies of new entities, moves kinematic bodies to their entities and
+    /// removes the bodies of entities no longer in the scene.
+    fn sync_bodies(&mut self, entities: &mut [Entity]) -> Vec<SoftRastError> {
+        let mut errors = vec![];
+        let mut alive = HashSet::with_capacity(entities.len());
+        for entity in entities.iter_mut() {
+            let Some(body) = entity.physics.clone() else {
+                continue;
+            };
+            if let Some(handle) = body.handle {
+                if body.kind == BodyKind::Kinematic
+                    && let Some(rigid_body) = self.bodies.get_mut(handle)
+                {
+                    rigid_body.set_next_kinematic_position(body_pose(entity));
+                }
+                alive.insert(handle);
+                continue;
+            }
+            let builder = match body.kind {
+                BodyKind::Dynamic => RigidBodyBuilder::dynamic(),
+                BodyKind::Kinematic => RigidBodyBuilder::kinematic_position_based(),
+                BodyKind::Fixed => RigidBodyBuilder::fixed(),
+            };
+            let collider = match body.shape {
+                ColliderShape::Ball { radius } => ColliderBuilder::ball(radius),
+                ColliderShape::Cuboid { half_extents } => {
+                    ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+                }
+                ColliderShape::Capsule {
+                    half_height,
+                    radius,
+                } => ColliderBuilder::capsule_y(half_height, radius),
+                ColliderShape::ConvexHull => {
+                    let scale = model_scale(&entity.model_matrix());
+                    let points = entity
+                        .active_model()
+                        .vertices
+                        .iter()
+                        .map(|vertex| {
+                            Point3::from(vertex.position.xyz().coords.component_mul(&scale))
+                        })
+                        .collect::<Vec<Point3<f32>>>();
+                    match ColliderBuilder::convex_hull(&points) {
+                        Some(collider) => collider,
+                        None => {
+                            errors.push(SoftRastError::Model(format!(
+                                "{}: no convex hull for the physics body",
+                                entity.id
+                            )));
+                            entity.physics = None;
+                            continue;
+                        }
+                    }
+                }
+            };
+            let rigid_body = builder
+                .position(body_pose(entity))
+                .linvel(body.linear_velocity)
+                .build();
+            let handle = self.bodies.insert(rigid_body);
+            let collider = collider
+                .density(body.density)
+                .friction(body.friction)
+                .restitution(body.restitution)
+                .build();
+            self.colliders
+                .insert_with_parent(collider, handle, &mut self.bodies);
+            entity.physics = Some(PhysicsBody {
+                handle: Some(handle),
+                ..body
+            });
+            alive.insert(handle);
+        }
+        let gone = self
+            .bodies
+            .iter()
+            .map(|(handle, _)| handle)
+            .filter(|handle| !alive.contains(handle))
+            .collect::<Vec<_>>();
+        for handle in gone {
+            self.bodies.remove(
+                handle,
+                &mut self.islands,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                true,
+            );
+        }
+        errors
+    }
+    /// Balls as spheres, cuboids as boxes, other shapes as their world bounds. Sleeping
+    /// bodies are drawn darker.
+    fn draw_colliders(&self, debug: &mut DebugDraw) {
+        for (_, collider) in self.colliders.iter() {
+            let sleeping = collider
+                .parent()
+                .and_then(|parent| self.bodies.get(parent))
+                .is_some_and(|body| body.is_sleeping());
+            let color = match sleeping {
+                true => Color::new(0.2, 0.5, 0.2, 1.0),
+                false => Color::new(0.3, 1.0, 0.3, 1.0),
+            };
+            let pose = collider.position();
+            let shape = collider.shape();
+            if let Some(ball) = shape.as_ball() {
+                debug.sphere(Point3::from(pose.translation.vector), ball.radius, color);
+            } else if let Some(cuboid) = shape.as_cuboid() {
+                draw_box(debug, pose, &cuboid.half_extents, color);
+            } else {
+                let aabb = collider.compute_aabb();
+                let center = aabb.center();
+                let pose = Isometry3::translation(center.x, center.y, center.z);
+                draw_box(debug, &pose, &aabb.half_extents(), color);
+            }
+        }
+    }
+}
+
+/// The twelve edges of a box with `half_extents` placed at `pose`.
+fn draw_box(
+    debug: &mut DebugDraw,
+    pose: &Isometry3<f32>,
+    half_extents: &Vector3<f32>,
+    color: Color,
+) {
+    let corner = |i: usize| {
+        let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+        let local = Point3::new(
+            sign(1) * half_extents.x,
+            sign(2) * half_extents.y,
+            sign(4) * half_extents.z,
+        );
+        pose.transform_point(&local)
+    };
+    for i in 0..8 {
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                debug.line(corner(i), corner(i | bit), color);
+            }
+        }
+    }
+}
+
+/// Length of each axis of a model matrix, the scale left after taking out the rotation.
+fn model_scale(matrix: &Matrix4<f32>) -> Vector3<f32> {
+    Vector3::from_fn(|axis, _| matrix.fixed_view::<3, 1>(0, axis).norm())
+}
+
+/// World space pose of an entity's body: its world translation, offset included, and the
+/// rotation of its model matrix with the scale divided out.
+fn body_pose(entity: &Entity) -> Isometry3<f32> {
+    let matrix = entity.model_matrix();
+    let scale = model_scale(&matrix).map(|s| if s > 0.0 { s } else { 1.0 });
+    let linear = matrix.fixed_view::<3, 3>(0, 0) * Matrix3::from_diagonal(&scale.map(|s| 1.0 / s));
+    Isometry3::from_parts(
+        Translation3::from(entity.world_translation().cast::<f32>()),
+        UnitQuaternion::from_matrix(&linear),
+    )
+}
+
+/// Moves an entity to its body's world pose. A matrix set with `with_matrix` or by an
+/// attachment would hide the new position, so its scale is kept and the matrix dropped.
+fn set_body_pose(entity: &mut Entity, pose: &Isometry3<f32>) {
+    if let Some(transform) = entity.transform.take() {
+        entity.scale = Scale3::from(model_scale(&transform));
+    }
+    let translation = pose.translation.vector.cast::<f64>() - entity.world_offset;
+    entity.position =
+        Isometry3::from_parts(Translation3::from(translation.cast::<f32>()), pose.rotation);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::cube;
+    use crate::renderer::Material;
+
+    fn entity(id: &str, y: f32, body: PhysicsBody) -> Entity {
+        let material = Material::SolidColor(Color::new(1.0, 1.0, 1.0, 1.0));
+        Entity::new(
+            id,
+            &cube(1.0),
+            &Isometry3::translation(0.0, y, 0.0),
+            &Scale3::identity(),
+            material,
+        )
+        .with_physics_body(body)
+    }
+
+    #[test]
+    fn dynamic_body_falls_and_rests_on_fixed_floor() {
+        let floor = ColliderShape::Cuboid {
+            half_extents: Vector3::new(10.0, 0.5, 10.0),
+        };
+        let mut entities = vec![
+            entity("floor", -0.5, PhysicsBody::fixed(floor)),
+            entity(
+                "ball",
+                3.0,
+                PhysicsBody::dynamic(ColliderShape::Ball { radius: 0.5 }),
+            ),
+        ];
+        let mut world = PhysicsWorld::default();
+        let mut debug = DebugDraw::default();
+        for _ in 0..240 {
+            world.update(&mut entities, &mut debug, FIXED_STEP);
+        }
+        let y = entities[1].position.translation.y;
+        assert!((y - 0.5).abs() < 0.05, "ball rests at {}", y);
+        assert_eq!(entities[0].position.translation.y, -0.5);
+        let velocity = world.linear_velocity(&entities[1]).unwrap();
+        assert!(
+            velocity.magnitude() < 0.05,
+            "ball still moves at {}",
+            velocity
+        );
+    }
+
+    #[test]
+    fn bodies_far_out_collide_at_their_world_position() {
+        let offset = Vector3::new(5000.0, 0.0, -5000.0);
+        let floor = ColliderShape::Cuboid {
+            half_extents: Vector3::new(10.0, 0.5, 10.0),
+        };
+        let mut entities = vec![
+            entity("floor", 0.0, PhysicsBody::fixed(floor))
+                .with_world_position(offset - Vector3::y() * 0.5),
+            entity(
+                "ball",
+                0.0,
+                PhysicsBody::dynamic(ColliderShape::Ball { radius: 0.5 }),
+            )
+            .with_world_position(offset + Vector3::y() * 3.0),
+        ];
+        let mut world = PhysicsWorld::default();
+        let mut debug = DebugDraw::default();
+        for _ in 0..240 {
+            world.update(&mut entities, &mut debug, FIXED_STEP);
+        }
+        let y = entities[1].world_translation().y;
+        assert!((y - 0.5).abs() < 0.05, "ball rests at {}", y);
+        assert!(entities[1].position.translation.vector.x.abs() < 0.01);
+    }
+
+    #[test]
+    fn dynamic_body_replaces_the_entity_matrix() {
+        let transform =
+            Matrix4::new_translation(&Vector3::new(0.0, 3.0, 0.0)) * Matrix4::new_scaling(2.0);
+        let mut entities = vec![
+            entity(
+                "ball",
+                0.0,
+                PhysicsBody::dynamic(ColliderShape::Ball { radius: 0.5 }),
+            )
+            .with_matrix(transform),
+        ];
+        let mut world = PhysicsWorld::default();
+        let mut debug = DebugDraw::default();
+        world.update(&mut entities, &mut debug, FIXED_STEP);
+        assert!(entities[0].transform.is_none());
+        assert!((entities[0].scale.vector - Vector3::new(2.0, 2.0, 2.0)).norm() < 1e-5);
+        let y = entities[0].model_matrix()[(1, 3)];
+        assert!(y < 3.0 && y > 2.9, "ball starts falling from 3, at {}", y);
+    }
+
+    #[test]
+    fn bodies_of_removed_entities_are_dropped() {
+        let mut entities = vec![entity(
+            "ball",
+            0.0,
+            PhysicsBody::dynamic(ColliderShape::Ball { radius: 0.5 }),
+        )];
+        let mut world = PhysicsWorld::default();
+        let mut debug = DebugDraw::default();
+        world.update(&mut entities, &mut debug, FIXED_STEP);
+        assert_eq!(world.bodies.len(), 1);
+        entities.clear();
+        world.update(&mut entities, &mut debug, FIXED_STEP);
+        assert_eq!(world.bodies.len(), 0);
+        assert_eq!(world.colliders.len(), 0);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::camera::Camera;
+use crate::geometry::Plane;
+use nalgebra::{Point3, Vector3};
+
+/// Convex region of an indoor level, the space in front of all its planes.
+#[derive(Debug, Clone)]
+pub struct PortalCell {
+    pub name: String,
+    pub planes: Vec<Plane>,
+}
+#[allow(unused)]
+impl PortalCell {
+    pub fn new(name: &str, planes: Vec<Plane>) -> Self {
+        Self {
+            name: name.to_owned(),
+            planes,
+        }
+    }
+    /// An axis aligned room.
+    pub fn from_box(name: &str, min: Point3<f32>, max: Point3<f32>) -> Self {
+        let planes = (0..3)
+            .flat_map(|axis| {
+                let normal = Vector3::ith(axis, 1.0);
+                [
+                    Plane::from_point_normal(&min, &normal),
+                    Plane::from_point_normal(&max, &-normal),
+                ]
+            })
+            .collect();
+        Self::new(name, planes)
+    }
+    pub fn contains(&self, point: &Point3<f32>) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+}
+
+/// Convex opening between two cells, e.g. a doorway, seen through from either side.
+#[derive(Debug, Clone)]
+pub struct Portal {
+    pub cells: (usize, usize),
+    pub polygon: Vec<Point3<f32>>,
+}
+
+/// Cells and portals visibility for indoor scenes. From the cell holding the camera, every
+/// portal in view narrows the frustum to its outline and the cell behind it is visible
+/// through that narrower frustum, recursively. Entities in cells that are never reached are
+/// skipped, entities outside all cells are always drawn.
+#[derive(Debug, Clone, Default)]
+pub struct PortalSystem {
+    pub cells: Vec<PortalCell>,
+    pub portals: Vec<Portal>,
+}
+#[allow(unused)]
+impl PortalSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_cell(mut self, cell: PortalCell) -> Self {
+        self.cells.push(cell);
+        self
+    }
+    /// Connects the cells at indices `a` and `b` through a convex polygon.
+    pub fn with_portal(mut self, a: usize, b: usize, polygon: &[Point3<f32>]) -> Self {
+        self.portals.push(Portal {
+            cells: (a, b),
+            polygon: polygon.to_vec(),
+        });
+        self
+    }
+    pub fn cell_at(&self, point: &Point3<f32>) -> Option<usize> {
+        self.cells.iter().position(|cell| cell.contains(point))
+    }
+    /// Every cell seen from the camera with the frustums it is seen through, `None` when the
+    /// camera is outside all cells.
+    pub(crate) fn visible_cells(&self, camera: &Camera) -> Option<Vec<(usize, Vec<Plane>)>> {
+        let start = self.cell_at(&camera.eye())?;
+        let mut visible = vec![];
+        let mut path = vec![start];
+        self.traverse(
+            camera,
+            start,
+            camera.frustum_planes().to_vec(),
+            &mut path,
+            &mut visible,
+        );
+        Some(visible)
+    }
+    fn traverse(
+        &self,
+        camera: &Camera,
+        cell: usize,
+        frustum: Vec<Plane>,
+        path: &mut Vec<usize>,
+        visible: &mut Vec<(usize, Vec<Plane>)>,
+    ) {
+        for portal in &self.portals {
+            let next = match portal.cells {
+                (a, b) if a == cell => b,
+                (a, b) if b == cell => a,
+                _ => continue,
+            };
+            // Cells already on the path would only be seen again through themselves
+            if path.contains(&next) {
+                continue;
+            }
+            let mut polygon = portal.polygon.clone();
+            for plane in &frustum {
+                polygon = clip(&polygon, plane);
+            }
+            if polygon.len() < 3 {
+                continue;
+            }
+            let Some(narrowed) = portal_frustum(camera, &polygon) else {
+                continue;
+            };
+            path.push(next);
+            self.traverse(camera, next, narrowed, path, visible);
+            path.pop();
+        }
+        visible.push((cell, frustum));
+    }
+}
+
+/// Part of a convex polygon in front of the plane.
+fn clip(polygon: &[Point3<f32>], plane: &Plane) -> Vec<Point3<f32>> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let (da, db) = (plane.signed_distance(a), plane.signed_distance(b));
+        if da >= 0.0 {
+            clipped.push(*a);
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            clipped.push(a + (b - a) * (da / (da - db)));
+        }
+    }
+    clipped
+}
+
+/// Planes from the camera through each edge of the clipped portal, facing its center, with
+/// the camera's near and far planes.
+fn portal_frustum(camera: &Camera, polygon: &[Point3<f32>]) -> Option<Vec<Plane>> {
+    let center =
+        Point3::from(polygon.iter().map(|p| p.coords).sum::<Vector3<f32>>() / polygon.len() as f32);
+    let eye = camera.eye();
+    let mut planes = camera.frustum_planes()[4..].to_vec();
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let Some(normal) = (a - eye).cross(&(b - eye)).try_normalize(1e-9) else {
+            continue;
+        };
+        let plane = Plane::from_point_normal(&eye, &normal);
+        planes.push(if plane.signed_distance(&center) < 0.0 {
+            Plane::from_point_normal(&eye, &-normal)
+        } else {
+            plane
+        });
+    }
+    (planes.len() > 2).then_some(planes)
+}
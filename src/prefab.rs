@@ -0,0 +1,400 @@
+use crate::attachment::AttachmentPoints;
+use crate::error::SoftRastError;
+use crate::geometry::{Model, Texture};
+use crate::offline::load_any_model;
+use crate::renderer::{Color, Material, Sampler, UvTransform};
+use crate::{Command, Entity, EntityId};
+use nalgebra::{Isometry3, Matrix4, Scale3, Translation3, UnitQuaternion, Vector3};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+
+/// Attachment point at the origin of every spawned prefab part with a model, its children
+/// follow it.
+pub const PREFAB_ORIGIN: &str = "prefab_origin";
+
+/// Surface of a prefab part, lit by the scene's sun direction.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrefabMaterial {
+    Color(Color),
+    Texture(String),
+}
+
+/// A reusable bundle of a model, its material and transform and child parts, e.g. a lamp
+/// with its bulb. Stored in prefab files, see `PrefabLibrary::parse`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub name: String,
+    /// OBJ, PLY or glTF model, parts without one only group their children.
+    pub model: Option<String>,
+    pub material: PrefabMaterial,
+    /// Relative to the parent part.
+    pub position: Isometry3<f32>,
+    pub scale: Scale3<f32>,
+    pub children: Vec<Prefab>,
+}
+#[allow(unused)]
+impl Prefab {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            model: None,
+            material: PrefabMaterial::Color(Color::new(0.8, 0.8, 0.8, 1.0)),
+            position: Isometry3::identity(),
+            scale: Scale3::identity(),
+            children: vec![],
+        }
+    }
+    pub fn with_model(mut self, path: &str) -> Self {
+        self.model = Some(path.to_owned());
+        self
+    }
+    pub fn with_material(mut self, material: PrefabMaterial) -> Self {
+        self.material = material;
+        self
+    }
+    pub fn with_position(mut self, position: Isometry3<f32>) -> Self {
+        self.position = position;
+        self
+    }
+    pub fn with_scale(mut self, scale: Scale3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+    pub fn with_child(mut self, child: Prefab) -> Self {
+        self.children.push(child);
+        self
+    }
+    fn local_matrix(&self) -> Matrix4<f32> {
+        self.position.to_homogeneous() * self.scale.to_homogeneous()
+    }
+    /// The prefab in the format `PrefabLibrary::parse` reads.
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, "prefab", 0);
+        text
+    }
+    fn write(&self, text: &mut String, keyword: &str, depth: usize) {
+        let indent = "    ".repeat(depth);
+        let _ = writeln!(text, "{}{} {}", indent, keyword, self.name);
+        if let Some(model) = &self.model {
+            let _ = writeln!(text, "{}    model {}", indent, model);
+        }
+        match &self.material {
+            PrefabMaterial::Color(c) => {
+                let _ = writeln!(text, "{}    color {} {} {} {}", indent, c.r, c.g, c.b, c.a);
+            }
+            PrefabMaterial::Texture(path) => {
+                let _ = writeln!(text, "{}    texture {}", indent, path);
+            }
+        }
+        let t = self.position.translation.vector;
+        let (roll, pitch, yaw) = self.position.rotation.euler_angles();
+        let s = self.scale.vector;
+        let _ = writeln!(text, "{}    position {} {} {}", indent, t.x, t.y, t.z);
+        let (roll, pitch, yaw) = (roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees());
+        let _ = writeln!(text, "{}    rotation {} {} {}", indent, roll, pitch, yaw);
+        let _ = writeln!(text, "{}    scale {} {} {}", indent, s.x, s.y, s.z);
+        for child in &self.children {
+            child.write(text, "child", depth + 1);
+        }
+        let _ = writeln!(text, "{}end", indent);
+    }
+}
+
+/// Changes to a prefab for one instance, unset fields keep the prefab's own.
+#[derive(Debug, Clone, Default)]
+pub struct PrefabOverrides {
+    /// Id of the root entity, children are named `id/child`. The prefab name by default.
+    pub id: Option<String>,
+    pub position: Option<Isometry3<f32>>,
+    pub scale: Option<Scale3<f32>>,
+    /// Material of the root part.
+    pub material: Option<PrefabMaterial>,
+}
+#[allow(unused)]
+impl PrefabOverrides {
+    pub fn with_id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_owned());
+        self
+    }
+    pub fn with_position(mut self, position: Isometry3<f32>) -> Self {
+        self.position = Some(position);
+        self
+    }
+    pub fn with_scale(mut self, scale: Scale3<f32>) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+    pub fn with_material(mut self, material: PrefabMaterial) -> Self {
+        self.material = Some(material);
+        self
+    }
+}
+
+/// Named prefabs and the models and textures they use, loaded once and shared by every
+/// instance.
+#[derive(Default)]
+pub struct PrefabLibrary {
+    prefabs: HashMap<String, Prefab>,
+    models: HashMap<String, Model>,
+    textures: HashMap<String, Texture>,
+}
+#[allow(unused)]
+impl PrefabLibrary {
+    pub fn load(path: &str) -> Result<Self, SoftRastError> {
+        let mut library = Self::default();
+        library.load_file(path)?;
+        Ok(library)
+    }
+    /// Adds the prefabs of another file, replacing those with the same names.
+    pub fn load_file(&mut self, path: &str) -> Result<(), SoftRastError> {
+        let contents = fs::read_to_string(path).map_err(|source| SoftRastError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        for prefab in Self::parse(&contents, path)? {
+            self.add(prefab);
+        }
+        Ok(())
+    }
+    /// Reads prefabs written one block each:
+    ///
+    /// ```text
+    /// prefab lamp
+    ///     model assets/lamp_post.obj
+    ///     color 0.3 0.3 0.3 1
+    ///     child bulb
+    ///         model assets/bulb.obj
+    ///         texture assets/glow.png
+    ///         position 0 2.5 0
+    ///     end
+    /// end
+    /// ```
+    ///
+    /// `rotation` takes roll, pitch and yaw in degrees and `scale` one or three factors,
+    /// indentation is optional and `#` starts a comment line. `path` only names the source
+    /// in errors.
+    pub fn parse(contents: &str, path: &str) -> Result<Vec<Prefab>, SoftRastError> {
+        let mut prefabs = vec![];
+        // The open prefab and its open children, innermost last
+        let mut open: Vec<Prefab> = vec![];
+        let mut last_line = 0;
+        for (number, line) in contents.lines().enumerate() {
+            last_line = number + 1;
+            let error = |message: String| SoftRastError::Parse {
+                path: path.to_owned(),
+                line: number + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+            let numbers = || {
+                rest.split_whitespace()
+                    .map(|n| {
+                        n.parse::<f32>()
+                            .map_err(|_| error(format!("expected a number, got '{}'", n)))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            };
+            match (keyword, open.last_mut()) {
+                ("prefab", None) | ("child", Some(_)) if !rest.is_empty() => {
+                    open.push(Prefab::new(rest))
+                }
+                ("prefab", Some(_)) => {
+                    return Err(error("prefab inside a prefab, use child".to_owned()));
+                }
+                ("prefab" | "child", _) if rest.is_empty() => {
+                    return Err(error(format!("{} needs a name", keyword)));
+                }
+                ("end", Some(_)) => {
+                    let done = open.pop().expect("checked by the match");
+                    match open.last_mut() {
+                        Some(parent) => parent.children.push(done),
+                        None => prefabs.push(done),
+                    }
+                }
+                ("model", Some(prefab)) if !rest.is_empty() => prefab.model = Some(rest.to_owned()),
+                ("texture", Some(prefab)) if !rest.is_empty() => {
+                    prefab.material = PrefabMaterial::Texture(rest.to_owned());
+                }
+                ("color", Some(prefab)) => match numbers()?[..] {
+                    [r, g, b] => prefab.material = PrefabMaterial::Color(Color::new(r, g, b, 1.0)),
+                    [r, g, b, a] => prefab.material = PrefabMaterial::Color(Color::new(r, g, b, a)),
+                    _ => return Err(error("expected color r g b [a]".to_owned())),
+                },
+                ("position", Some(prefab)) => match numbers()?[..] {
+                    [x, y, z] => prefab.position.translation = Translation3::new(x, y, z),
+                    _ => return Err(error("expected position x y z".to_owned())),
+                },
+                ("rotation", Some(prefab)) => match numbers()?[..] {
+                    [roll, pitch, yaw] => {
+                        let (roll, pitch, yaw) =
+                            (roll.to_radians(), pitch.to_radians(), yaw.to_radians());
+                        prefab.position.rotation =
+                            UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+                    }
+                    _ => return Err(error("expected rotation roll pitch yaw".to_owned())),
+                },
+                ("scale", Some(prefab)) => match numbers()?[..] {
+                    [s] => prefab.scale = Scale3::new(s, s, s),
+                    [x, y, z] => prefab.scale = Scale3::new(x, y, z),
+                    _ => return Err(error("expected scale s or scale x y z".to_owned())),
+                },
+                (keyword, None) => return Err(error(format!("'{}' outside a prefab", keyword))),
+                (_, Some(_)) => return Err(error(format!("unexpected '{}'", line))),
+            }
+        }
+        if let Some(prefab) = open.first() {
+            return Err(SoftRastError::Parse {
+                path: path.to_owned(),
+                line: last_line,
+                message: format!("prefab {} is missing its end", prefab.name),
+            });
+        }
+        Ok(prefabs)
+    }
+    pub fn add(&mut self, prefab: Prefab) {
+        self.prefabs.insert(prefab.name.clone(), prefab);
+    }
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.prefabs.keys().map(String::as_str)
+    }
+    /// Spawns an instance of the named prefab, parts with a model become entities and
+    /// children follow their parent's `PREFAB_ORIGIN` point. Returns the spawned ids, the
+    /// root first. Models and textures are loaded on first use.
+    pub(crate) fn instantiate(
+        &mut self,
+        command: &mut Command,
+        name: &str,
+        overrides: &PrefabOverrides,
+    ) -> Result<Vec<EntityId>, SoftRastError> {
+        let mut prefab = self
+            .prefabs
+            .get(name)
+            .cloned()
+            .ok_or_else(|| SoftRastError::NotFound(format!("prefab named '{}'", name)))?;
+        prefab.position = overrides.position.unwrap_or(prefab.position);
+        prefab.scale = overrides.scale.unwrap_or(prefab.scale);
+        prefab.material = overrides.material.clone().unwrap_or(prefab.material);
+        let id = overrides.id.clone().unwrap_or_else(|| prefab.name.clone());
+        let mut spawned = vec![];
+        match &prefab.model {
+            // A root keeps its isometry and scale so it can be moved as usual
+            Some(path) => {
+                let material = self.material(&prefab.material)?;
+                let entity = Entity::new(
+                    &id,
+                    self.model(path)?,
+                    &prefab.position,
+                    &prefab.scale,
+                    material,
+                );
+                let root = command.spawn(entity.with_attachment_points(origin_point()));
+                spawned.push(root);
+                for child in &prefab.children {
+                    let child_id = format!("{}/{}", id, child.name);
+                    self.spawn_part(
+                        command,
+                        child,
+                        &child_id,
+                        Some(root),
+                        child.local_matrix(),
+                        &mut spawned,
+                    )?;
+                }
+            }
+            None => self.spawn_part(
+                command,
+                &prefab,
+                &id,
+                None,
+                prefab.local_matrix(),
+                &mut spawned,
+            )?,
+        }
+        Ok(spawned)
+    }
+    /// Spawns `part` at `local` from its closest ancestor with a model, or in the world
+    /// when there is none, then its children.
+    fn spawn_part(
+        &mut self,
+        command: &mut Command,
+        part: &Prefab,
+        id: &str,
+        anchor: Option<EntityId>,
+        local: Matrix4<f32>,
+        spawned: &mut Vec<EntityId>,
+    ) -> Result<(), SoftRastError> {
+        let (anchor, offset) = match &part.model {
+            Some(path) => {
+                let material = self.material(&part.material)?;
+                let model = self.model(path)?;
+                let entity = Entity::new(id, model, &part.position, &part.scale, material)
+                    .with_matrix(local)
+                    .with_attachment_points(origin_point());
+                let entity = match anchor {
+                    Some(parent) => entity.attach_to(parent, PREFAB_ORIGIN),
+                    None => entity,
+                };
+                let entity_id = command.spawn(entity);
+                spawned.push(entity_id);
+                (Some(entity_id), Matrix4::identity())
+            }
+            None => (anchor, local),
+        };
+        for child in &part.children {
+            let child_id = format!("{}/{}", id, child.name);
+            self.spawn_part(
+                command,
+                child,
+                &child_id,
+                anchor,
+                offset * child.local_matrix(),
+                spawned,
+            )?;
+        }
+        Ok(())
+    }
+    fn model(&mut self, path: &str) -> Result<&Model, SoftRastError> {
+        if !self.models.contains_key(path) {
+            self.models.insert(path.to_owned(), load_any_model(path)?);
+        }
+        Ok(&self.models[path])
+    }
+    fn material(&mut self, material: &PrefabMaterial) -> Result<Material, SoftRastError> {
+        let light_dir = Vector3::<f32>::new(1.0, 1.0, 0.0).normalize();
+        Ok(match material {
+            PrefabMaterial::Color(color) => Material::LitSolid {
+                color: *color,
+                light_dir,
+                emissive: None,
+                specular: None,
+            },
+            PrefabMaterial::Texture(path) => {
+                if !self.textures.contains_key(path) {
+                    self.textures.insert(path.clone(), Texture::new(path)?);
+                }
+                Material::LitTexture {
+                    texture: self.textures[path].clone(),
+                    light_dir,
+                    uv_transform: UvTransform::default(),
+                    sampler: Sampler::default(),
+                    emissive: None,
+                    specular: None,
+                }
+            }
+        })
+    }
+}
+
+fn origin_point() -> AttachmentPoints {
+    AttachmentPoints::new().with_point(PREFAB_ORIGIN, Matrix4::identity())
+}
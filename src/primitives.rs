@@ -0,0 +1,62 @@
+use crate::geometry::{Model, Vertex};
+use nalgebra::{Point3, Vector2, Vector3};
+
+/// Axis aligned cube centered on the origin with per face normals and a full 0..1 UV square
+/// on every face.
+pub fn cube(size: f32) -> Model {
+    let half = size * 0.5;
+    // Normal and the in-plane axes of each face, with u x v = normal so faces wind
+    // counter clockwise seen from outside
+    let faces = [
+        (Vector3::x(), -Vector3::z(), Vector3::y()),
+        (-Vector3::x(), Vector3::z(), Vector3::y()),
+        (Vector3::y(), Vector3::x(), -Vector3::z()),
+        (-Vector3::y(), Vector3::x(), Vector3::z()),
+        (Vector3::z(), Vector3::x(), Vector3::y()),
+        (-Vector3::z(), -Vector3::x(), Vector3::y()),
+    ];
+    let mut vertices = Vec::with_capacity(36);
+    for (normal, u, v) in faces {
+        let corner = |su: f32, sv: f32| {
+            let position = Point3::from((normal + u * su + v * sv) * half);
+            Vertex::new(&position)
+                .with_normal(normal)
+                .with_uv(Vector2::new((su + 1.0) * 0.5, (sv + 1.0) * 0.5))
+        };
+        let quad = [
+            corner(-1.0, -1.0),
+            corner(1.0, -1.0),
+            corner(1.0, 1.0),
+            corner(-1.0, 1.0),
+        ];
+        vertices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+    }
+    Model::from_vertices(&vertices)
+}
+
+/// UV sphere centered on the origin, `segments` around the equator and `rings` pole to pole.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> Model {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let point = |ring: u32, segment: u32| {
+        let phi = ring as f32 / rings as f32 * std::f32::consts::PI;
+        let theta = segment as f32 / segments as f32 * std::f32::consts::TAU;
+        let normal = Vector3::new(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+        Vertex::new(&Point3::from(normal * radius))
+            .with_normal(normal)
+            .with_uv(Vector2::new(
+                segment as f32 / segments as f32,
+                1.0 - ring as f32 / rings as f32,
+            ))
+    };
+    let mut vertices = Vec::with_capacity((segments * rings * 6) as usize);
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let a = point(ring, segment);
+            let b = point(ring + 1, segment);
+            let c = point(ring + 1, segment + 1);
+            let d = point(ring, segment + 1);
+            vertices.extend_from_slice(&[a, c, b, a, d, c]);
+        }
+    }
+    Model::from_vertices(&vertices)
+}
@@ -0,0 +1,123 @@
+use crate::Scene;
+use crate::camera::Camera;
+use crate::renderer::{Color, DrawMode, RenderTarget};
+use nalgebra::{Point3, Vector3};
+
+/// Regular 3D grid of light probes holding the average color seen from each probe, baked by
+/// rendering the scene around it. Sampling blends the eight surrounding probes.
+#[derive(Debug, Clone)]
+pub struct IrradianceGrid {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+    /// Probe count along each axis, at least one.
+    pub dims: [usize; 3],
+    /// How much of the baked color is added to lit materials.
+    pub intensity: f32,
+    probes: Vec<Color>,
+}
+#[allow(unused)]
+impl IrradianceGrid {
+    pub fn new(min: Point3<f32>, max: Point3<f32>, dims: [usize; 3]) -> Self {
+        let dims = dims.map(|d| d.max(1));
+        Self {
+            min,
+            max,
+            dims,
+            intensity: 1.0,
+            probes: vec![Color::new(0.0, 0.0, 0.0, 1.0); dims[0] * dims[1] * dims[2]],
+        }
+    }
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.dims[1] + y) * self.dims[0] + x
+    }
+    pub fn probe_position(&self, x: usize, y: usize, z: usize) -> Point3<f32> {
+        let t = Vector3::from([x, y, z]).zip_map(&Vector3::from(self.dims), |i, d| {
+            if d > 1 {
+                i as f32 / (d - 1) as f32
+            } else {
+                0.5
+            }
+        });
+        self.min + (self.max - self.min).component_mul(&t)
+    }
+    /// Renders a 90 degree view along each axis from every probe at `resolution` pixels
+    /// square and stores the average color.
+    pub fn bake(&mut self, scene: &Scene, resolution: u32) {
+        let directions = [
+            (Vector3::x(), Vector3::y()),
+            (-Vector3::x(), Vector3::y()),
+            (Vector3::y(), Vector3::z()),
+            (-Vector3::y(), Vector3::z()),
+            (Vector3::z(), Vector3::y()),
+            (-Vector3::z(), Vector3::y()),
+        ];
+        let mode = DrawMode::default();
+        let mut target = RenderTarget::new(resolution, resolution);
+        for z in 0..self.dims[2] {
+            for y in 0..self.dims[1] {
+                for x in 0..self.dims[0] {
+                    let position = self.probe_position(x, y, z);
+                    let mut sum = Vector3::<f32>::zeros();
+                    for (direction, up) in directions {
+                        let camera = Camera::new(
+                            position,
+                            position + direction,
+                            up,
+                            std::f32::consts::FRAC_PI_2,
+                            1.0,
+                            scene.camera.near,
+                            scene.camera.far,
+                        );
+                        target.clear();
+                        for entity in &scene.entities {
+                            target.draw(entity, &camera, &mode);
+                        }
+                        for pixel in &target.color {
+                            let color = Color::from_u32(*pixel);
+                            sum += Vector3::new(color.r, color.g, color.b);
+                        }
+                    }
+                    let count = (directions.len() as u32 * resolution * resolution) as f32;
+                    let index = self.index(x, y, z);
+                    self.probes[index] =
+                        Color::new(sum.x / count, sum.y / count, sum.z / count, 1.0);
+                }
+            }
+        }
+    }
+    /// Trilinear blend of the probes around `point`, clamped to the grid.
+    pub fn sample(&self, point: &Point3<f32>) -> Color {
+        let mut cell = [0usize; 3];
+        let mut fraction = [0.0f32; 3];
+        for axis in 0..3 {
+            let size = self.max[axis] - self.min[axis];
+            let last = self.dims[axis] - 1;
+            let t = if size > f32::EPSILON && last > 0 {
+                ((point[axis] - self.min[axis]) / size).clamp(0.0, 1.0) * last as f32
+            } else {
+                0.0
+            };
+            cell[axis] = (t.floor() as usize).min(last.saturating_sub(1));
+            fraction[axis] = if last > 0 { t - cell[axis] as f32 } else { 0.0 };
+        }
+        let mut result = Color::new(0.0, 0.0, 0.0, 1.0);
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let mut weight = 1.0;
+            let mut index = [0usize; 3];
+            for axis in 0..3 {
+                index[axis] = (cell[axis] + offset[axis]).min(self.dims[axis] - 1);
+                weight *= if offset[axis] == 1 {
+                    fraction[axis]
+                } else {
+                    1.0 - fraction[axis]
+                };
+            }
+            let probe = self.probes[self.index(index[0], index[1], index[2])];
+            result.r += probe.r * weight;
+            result.g += probe.g * weight;
+            result.b += probe.b * weight;
+        }
+        result * self.intensity
+    }
+}
@@ -0,0 +1,109 @@
+use crate::renderer::{DrawMode, QualityOverrides};
+
+/// Expensive features the `QualityGovernor` can turn off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QualityFeature {
+    /// Traced shadows of `DrawMode::shadows`.
+    Shadows,
+    /// Mip mapped and anisotropic texture filtering, falling back to nearest texels.
+    FilteredSampling,
+    /// Per pixel lighting, falling back to lighting interpolated from the triangle corners.
+    PerPixelLighting,
+}
+
+/// Keeps the frame time near a target by turning features off in the order of `policy` when
+/// frames are slow and back on, last off first on, when they are fast again. The average
+/// frame time has to leave the band of `hysteresis` around the target, and `settle_frames`
+/// have to pass since the last change, before the next step, so it doesn't flicker between
+/// two levels.
+#[derive(Debug, Clone)]
+pub struct QualityGovernor {
+    /// Seconds per frame to aim for.
+    pub target_frame_time: f32,
+    /// Relative band around the target, 0.2 steps down above 120% and up below 80%.
+    pub hysteresis: f32,
+    pub settle_frames: u32,
+    /// Features in the order they are given up.
+    pub policy: Vec<QualityFeature>,
+    /// Moving average of the frame time.
+    average: f32,
+    /// How many features of `policy` are off.
+    level: usize,
+    frames_since_change: u32,
+}
+impl Default for QualityGovernor {
+    fn default() -> Self {
+        Self::new(60.0)
+    }
+}
+#[allow(unused)]
+impl QualityGovernor {
+    pub fn new(target_fps: f32) -> Self {
+        let target_frame_time = 1.0 / target_fps.max(1.0);
+        Self {
+            target_frame_time,
+            hysteresis: 0.2,
+            settle_frames: 30,
+            policy: vec![
+                QualityFeature::Shadows,
+                QualityFeature::FilteredSampling,
+                QualityFeature::PerPixelLighting,
+            ],
+            average: target_frame_time,
+            level: 0,
+            frames_since_change: 0,
+        }
+    }
+    pub fn with_policy(mut self, policy: Vec<QualityFeature>) -> Self {
+        self.policy = policy;
+        self.level = self.level.min(self.policy.len());
+        self
+    }
+    pub fn with_hysteresis(mut self, hysteresis: f32, settle_frames: u32) -> Self {
+        self.hysteresis = hysteresis;
+        self.settle_frames = settle_frames;
+        self
+    }
+    /// Features currently off.
+    pub fn disabled(&self) -> &[QualityFeature] {
+        &self.policy[..self.level]
+    }
+    pub fn is_disabled(&self, feature: QualityFeature) -> bool {
+        self.disabled().contains(&feature)
+    }
+    pub fn average_frame_time(&self) -> f32 {
+        self.average
+    }
+    /// Feeds the last frame time in seconds, true when a feature was turned off or on.
+    pub fn update(&mut self, frame_time: f32) -> bool {
+        self.average += (frame_time - self.average) * 0.1;
+        self.frames_since_change = self.frames_since_change.saturating_add(1);
+        if self.frames_since_change < self.settle_frames {
+            return false;
+        }
+        let slow = self.average > self.target_frame_time * (1.0 + self.hysteresis);
+        let fast = self.average < self.target_frame_time * (1.0 - self.hysteresis);
+        let level = if slow && self.level < self.policy.len() {
+            self.level + 1
+        } else if fast && self.level > 0 {
+            self.level - 1
+        } else {
+            return false;
+        };
+        self.level = level;
+        self.frames_since_change = 0;
+        true
+    }
+    /// The draw mode with the disabled features taken out.
+    pub fn draw_mode(&self, mode: &DrawMode) -> DrawMode {
+        let mut mode = *mode;
+        mode.shadows &= !self.is_disabled(QualityFeature::Shadows);
+        mode
+    }
+    pub fn overrides(&self) -> QualityOverrides {
+        QualityOverrides {
+            vertex_lighting: self.is_disabled(QualityFeature::PerPixelLighting),
+            nearest_sampling: self.is_disabled(QualityFeature::FilteredSampling),
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use crate::bvh::Bvh;
+use crate::camera::Camera;
+use crate::geometry::{Ray, Vertex};
+use crate::renderer::{Color, Fragment, RenderTarget, set_face_normal};
+use crate::{Entity, Scene};
+use nalgebra::{Matrix4, Point2, Point3, Vector3};
+
+/// Light left in shadowed fragments, standing in for the ambient term.
+pub const SHADOW_FACTOR: f32 = 0.35;
+/// Offset along the normal before casting shadow rays, to avoid hitting the own surface.
+pub const SHADOW_BIAS: f32 = 1e-3;
+
+/// An entity's triangles in world space with a BVH over them.
+pub struct TracedEntity<'a> {
+    pub entity: &'a Entity,
+    pub vertices: Vec<Vertex>,
+    pub materials: Vec<usize>,
+    pub bvh: Bvh,
+}
+impl<'a> TracedEntity<'a> {
+    pub fn new(entity: &'a Entity) -> Self {
+        let world =
+            Matrix4::new_translation(&entity.world_offset.cast::<f32>()) * entity.model_matrix();
+        let normal_mat = entity.normal_matrix();
+        let model = entity.active_model();
+        let mut vertices = model
+            .triangle_vertices()
+            .iter()
+            .map(|vertex| {
+                let mut vertex = *vertex;
+                vertex.position = world
+                    .transform_point(&vertex.position.xyz())
+                    .to_homogeneous()
+                    .into();
+                vertex.update_normal_mut(&normal_mat);
+                vertex
+            })
+            .collect::<Vec<_>>();
+        let materials = (0..vertices.len() / 3)
+            .map(|index| model.material_index(index * 3))
+            .collect::<Vec<_>>();
+        for (triangle, material) in vertices.chunks_exact_mut(3).zip(&materials) {
+            if entity.material(*material).flat_shading() {
+                set_face_normal(triangle);
+            }
+        }
+        let bvh = Bvh::from_triangles(
+            vertices
+                .chunks_exact(3)
+                .map(|t| {
+                    [
+                        t[0].position.xyz(),
+                        t[1].position.xyz(),
+                        t[2].position.xyz(),
+                    ]
+                })
+                .collect(),
+        );
+        Self {
+            entity,
+            vertices,
+            materials,
+            bvh,
+        }
+    }
+}
+
+/// World space BVHs of every entity, for tracing shadow rays from rasterized fragments.
+pub struct ShadowCasters {
+    bvhs: Vec<Bvh>,
+}
+impl ShadowCasters {
+    pub fn new(scene: &Scene) -> Self {
+        Self {
+            bvhs: scene
+                .entities
+                .iter()
+                .map(|entity| TracedEntity::new(entity).bvh)
+                .collect(),
+        }
+    }
+    /// True if a ray from `point` towards the light hits anything. Surfaces facing away
+    /// from the light are left to the N·L term.
+    pub fn occluded(
+        &self,
+        point: &Point3<f32>,
+        normal: &Vector3<f32>,
+        light_dir: &Vector3<f32>,
+    ) -> bool {
+        normal.dot(light_dir) > 0.0 && blocked(self.bvhs.iter(), point, normal, light_dir)
+    }
+}
+
+pub fn blocked<'a>(
+    bvhs: impl Iterator<Item = &'a Bvh>,
+    point: &Point3<f32>,
+    normal: &Vector3<f32>,
+    light_dir: &Vector3<f32>,
+) -> bool {
+    let shadow_ray = Ray::new(point + normal * SHADOW_BIAS, *light_dir);
+    bvhs.into_iter()
+        .any(|bvh| bvh.occluded(&shadow_ray, f32::MAX))
+}
+
+/// Renders the scene by tracing one primary ray per pixel against per entity BVHs, shading
+/// hits with the same materials as the rasterizer. With `shadows`, lit materials cast a ray
+/// towards their light and are darkened when it is blocked.
+///
+/// Reference quality rather than real time: vertex modifiers, displacement, geometry hooks,
+/// blending and alpha cutoff are not traced, and screen space materials like mirrors sample
+/// their last rasterized texture.
+pub fn ray_trace(
+    target: &mut RenderTarget,
+    scene: &Scene,
+    camera: &Camera,
+    time: f32,
+    shadows: bool,
+) {
+    let size = target.size();
+    let vp_mat = camera.get_perspective_matrix() * camera.get_view_matrix();
+    let traced = scene
+        .entities
+        .iter()
+        .map(TracedEntity::new)
+        .collect::<Vec<_>>();
+
+    target.trace_pixels(|x, y| {
+        let ray = camera.screen_ray(x as f32 + 0.5, y as f32 + 0.5, size);
+        let (distance, entity, triangle) = closest_hit(&traced, &ray, camera.far)?;
+        let vertices = &entity.vertices[triangle * 3..triangle * 3 + 3];
+        let point = ray.at(distance);
+        let fragment = Fragment {
+            weights: barycentric(vertices, &point),
+            ddx: Vector3::zeros(),
+            ddy: Vector3::zeros(),
+            screen: Point2::new(
+                (x as f32 + 0.5) / size.0 as f32,
+                (y as f32 + 0.5) / size.1 as f32,
+            ),
+            time,
+            ambient: scene
+                .irradiance
+                .as_ref()
+                .map_or(Color::new(0.0, 0.0, 0.0, 1.0), |grid| {
+                    grid.sample(&Point3::from(
+                        entity.entity.world_translation().cast::<f32>(),
+                    ))
+                }),
+            view_dir: -ray.direction.normalize(),
+            nearest_sampling: false,
+        };
+        let shader = entity.entity.material(entity.materials[triangle]);
+        let mut color = shader.shade(vertices, &fragment);
+        if let Some(light_dir) = shader.light_dir().filter(|_| shadows) {
+            let normal = (vertices[1].position.xyz() - vertices[0].position.xyz())
+                .cross(&(vertices[2].position.xyz() - vertices[0].position.xyz()))
+                .normalize();
+            let normal = if normal.dot(&ray.direction) > 0.0 {
+                -normal
+            } else {
+                normal
+            };
+            if blocked(
+                traced.iter().map(|other| &other.bvh),
+                &point,
+                &normal,
+                &light_dir,
+            ) {
+                color = color * SHADOW_FACTOR;
+            }
+        }
+        Some((color, vp_mat.transform_point(&point).z))
+    });
+}
+
+/// Distance, entity and triangle index of the closest hit over all entities.
+pub fn closest_hit<'a>(
+    traced: &'a [TracedEntity],
+    ray: &Ray,
+    max_distance: f32,
+) -> Option<(f32, &'a TracedEntity<'a>, usize)> {
+    let mut closest: Option<(f32, &TracedEntity, usize)> = None;
+    for entity in traced {
+        let limit = closest.map_or(max_distance, |(t, _, _)| t);
+        if let Some((t, triangle)) = entity.bvh.closest_hit(ray, limit) {
+            closest = Some((t, entity, triangle));
+        }
+    }
+    closest
+}
+
+/// Barycentric weights of a point on a world space triangle.
+pub fn barycentric(triangle: &[Vertex], point: &Point3<f32>) -> Vector3<f32> {
+    let a = triangle[0].position.xyz();
+    let b = triangle[1].position.xyz();
+    let c = triangle[2].position.xyz();
+    let normal = (b - a).cross(&(c - a));
+    let area = normal.magnitude_squared();
+    if area <= f32::EPSILON {
+        return Vector3::new(1.0, 0.0, 0.0);
+    }
+    let weight_a = (c - b).cross(&(point - b)).dot(&normal) / area;
+    let weight_b = (a - c).cross(&(point - c)).dot(&normal) / area;
+    Vector3::new(weight_a, weight_b, 1.0 - weight_a - weight_b)
+}
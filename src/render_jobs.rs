@@ -0,0 +1,108 @@
+use crate::geometry::Texture;
+use crate::renderer::{Color, Rect, RenderTarget};
+use image::{DynamicImage, RgbaImage};
+use rayon::prelude::*;
+
+/// Replaces every pixel of `target` with what `shade` returns for its position and current
+/// color, rows are spread over the threads.
+pub fn for_each_pixel(target: &mut RenderTarget, shade: impl Fn(u32, u32, Color) -> Color + Sync) {
+    let width = target.size().0 as usize;
+    target
+        .color
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = shade(x as u32, y as u32, Color::from_u32(*pixel)).as_u32();
+            }
+        });
+}
+
+/// A square of pixels handed to `for_each_tile`, addressed in target coordinates.
+pub struct Tile {
+    pub rect: Rect,
+    pixels: Vec<Color>,
+}
+impl Tile {
+    pub fn get(&self, x: u32, y: u32) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+    pub fn set(&mut self, x: u32, y: u32, color: Color) {
+        let index = self.index(x, y);
+        self.pixels[index] = color;
+    }
+    /// Positions inside the tile, row by row.
+    pub fn positions(&self) -> impl Iterator<Item = (u32, u32)> + use<> {
+        let (x0, y0) = (self.rect.x as u32, self.rect.y as u32);
+        let (width, height) = (self.rect.width, self.rect.height);
+        (y0..y0 + height).flat_map(move |y| (x0..x0 + width).map(move |x| (x, y)))
+    }
+    fn index(&self, x: u32, y: u32) -> usize {
+        let (tx, ty) = (x - self.rect.x as u32, y - self.rect.y as u32);
+        (ty * self.rect.width + tx) as usize
+    }
+}
+
+/// Runs `job` on `tile_size` squares of `target` in parallel and writes the tiles back, for
+/// effects that work on a neighbourhood, e.g. per tile statistics. Tiles on the right and
+/// bottom edges are cut to the target.
+pub fn for_each_tile(target: &mut RenderTarget, tile_size: u32, job: impl Fn(&mut Tile) + Sync) {
+    let (width, height) = target.size();
+    let tile_size = tile_size.max(1);
+    let rects = (0..height.div_ceil(tile_size))
+        .flat_map(|ty| {
+            (0..width.div_ceil(tile_size)).map(move |tx| (tx * tile_size, ty * tile_size))
+        })
+        .map(|(x, y)| {
+            Rect::new(
+                x as i32,
+                y as i32,
+                tile_size.min(width - x),
+                tile_size.min(height - y),
+            )
+        })
+        .collect::<Vec<_>>();
+    let colors = &target.color;
+    let tiles = rects
+        .into_par_iter()
+        .map(|rect| {
+            let mut tile = Tile {
+                rect,
+                pixels: vec![],
+            };
+            tile.pixels = tile
+                .positions()
+                .map(|(x, y)| Color::from_u32(colors[(y * width + x) as usize]))
+                .collect();
+            job(&mut tile);
+            tile
+        })
+        .collect::<Vec<_>>();
+    for tile in tiles {
+        for ((x, y), color) in tile.positions().zip(&tile.pixels) {
+            target.color[(y * width + x) as usize] = color.as_u32();
+        }
+    }
+}
+
+/// A `width` x `height` texture with every texel from `texel`, computed in parallel, e.g.
+/// noise or gradients baked once at startup.
+pub fn bake_texture(width: u32, height: u32, texel: impl Fn(u32, u32) -> Color + Sync) -> Texture {
+    let mut image = RgbaImage::new(width, height);
+    image
+        .par_chunks_mut(width.max(1) as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                let color = texel(x as u32, y as u32);
+                let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+                pixel.copy_from_slice(&[
+                    channel(color.r),
+                    channel(color.g),
+                    channel(color.b),
+                    channel(color.a),
+                ]);
+            }
+        });
+    Texture::from_image(DynamicImage::ImageRgba8(image))
+}
@@ -1,13 +1,23 @@
 use crate::Entity;
-use crate::camera::Camera;
-use crate::geometry::{Bounds, Texture, Vertex, triangle_barycentric, edge_cross};
-use nalgebra::{Point2, Vector3};
+use crate::camera::{Camera, Projection};
+use crate::debug::{DebugDraw, draw_labels};
+use crate::geometry::{
+    Bounds, Plane, Ray, Texture, Vertex, clip_polygon, edge_cross, signed_area,
+    triangle_barycentric,
+};
+use crate::grid::Grid;
+use crate::raytrace::{SHADOW_FACTOR, ShadowCasters};
+use crate::text::draw_text;
+use image::{DynamicImage, Rgba, RgbaImage};
+use nalgebra::{Matrix4, Point2, Point3, Point4, Vector2, Vector3};
 use rand::Rng;
 use rand_xorshift::XorShiftRng;
 use rayon::prelude::*;
-use std::ops::Mul;
+use smallvec::SmallVec;
+use std::ops::{Index, IndexMut, Mul};
+use std::sync::Arc;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -34,10 +44,32 @@ impl Color {
             a: 1.0,
         }
     }
+    pub fn lerp(&self, other: &Color, t: f32) -> Self {
+        Color {
+            r: self.r + (other.r - self.r) * t,
+            g: self.g + (other.g - self.g) * t,
+            b: self.b + (other.b - self.b) * t,
+            a: self.a + (other.a - self.a) * t,
+        }
+    }
+    /// Inverse of `as_u32`, alpha is always 1.
+    pub fn from_u32(color: u32) -> Self {
+        Self::from_rgba((color >> 16) as u8, (color >> 8) as u8, color as u8, 255)
+    }
+    /// Component wise product, alpha is kept.
+    pub fn modulate(&self, other: &Color) -> Self {
+        Color {
+            r: self.r * other.r,
+            g: self.g * other.g,
+            b: self.b * other.b,
+            a: self.a,
+        }
+    }
+    /// Channels saturate at 1, so emissive values above it don't spill into other channels.
     pub fn as_u32(&self) -> u32 {
-        let red = (self.r * 255.0) as u32;
-        let green = (self.g * 255.0) as u32;
-        let blue = (self.b * 255.0) as u32;
+        let red = (self.r.clamp(0.0, 1.0) * 255.0) as u32;
+        let green = (self.g.clamp(0.0, 1.0) * 255.0) as u32;
+        let blue = (self.b.clamp(0.0, 1.0) * 255.0) as u32;
         blue | (green << 8) | (red << 16)
     }
 }
@@ -64,11 +96,93 @@ pub fn random_color(rng: &mut XorShiftRng) -> Color {
         1.0,
     )
 }
+/// Renders half of the pixels each frame, alternating which half, and keeps the other half
+/// from the previous frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interlace {
+    Checkerboard,
+    Rows,
+}
+impl Interlace {
+    fn renders(&self, x: u32, y: u32, frame: u32) -> bool {
+        match self {
+            Self::Checkerboard => (x + y + frame).is_multiple_of(2),
+            Self::Rows => (y + frame).is_multiple_of(2),
+        }
+    }
+}
+/// Pixel rectangle on a render target, (0, 0) is the top left corner.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+impl Rect {
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+    /// The part inside a target of `size`, `None` when nothing is left.
+    fn clipped(&self, size: (u32, u32)) -> Option<(u32, u32, u32, u32)> {
+        let x0 = self.x.clamp(0, size.0 as i32) as u32;
+        let y0 = self.y.clamp(0, size.1 as i32) as u32;
+        let x1 = (self.x + self.width as i32).clamp(0, size.0 as i32) as u32;
+        let y1 = (self.y + self.height as i32).clamp(0, size.1 as i32) as u32;
+        (x1 > x0 && y1 > y0).then_some((x0, y0, x1, y1))
+    }
+}
+
+/// How `RenderTarget::blit` samples the source when the rectangles differ in size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BlitFilter {
+    #[default]
+    Nearest,
+    /// Blends the four nearest source pixels, halving a target this way averages 2x2 blocks,
+    /// e.g. for downsampling chains.
+    Bilinear,
+}
+
+/// How `draw_triangle` finds the pixels of a triangle.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum TriangleFiller {
+    /// Tests every 2x2 quad in the bounding box, simple but long thin triangles like floors
+    /// and walls seen edge on waste most of their tests.
+    #[default]
+    BoundingBox,
+    /// Walks the edges to find where each quad row starts and ends and only tests those quads.
+    Spans,
+}
+
 #[derive(Copy, Clone)]
 pub struct DrawMode {
     pub(crate) wireframe: bool,
     pub(crate) shaded: bool,
     pub(crate) points: bool,
+    /// Trace primary rays against the scene instead of rasterizing, see `ray_trace`.
+    pub(crate) ray_traced: bool,
+    /// Cast a shadow ray towards each lit material's light, from traced hits with
+    /// `ray_traced` or from rasterized fragments without it.
+    pub(crate) shadows: bool,
+    /// Dims wireframe lines and points with distance, see `DepthCue`.
+    pub(crate) depth_cue: Option<DepthCue>,
+    /// Colors and size of wireframe lines and points, unless the entity overrides them.
+    pub(crate) wire_style: WireStyle,
+    pub(crate) filler: TriangleFiller,
+    /// Draws triangles too small to cover a pixel center as a single pixel, see
+    /// `draw_small_triangle`.
+    pub(crate) collapse_subpixel: bool,
+    /// Rasterizes with `draw_reference_triangle` instead of the optimized paths, every slice
+    /// walking every triangle, to check them against with `compare_rasterizers`.
+    pub(crate) reference: bool,
+    /// Interpolates vertex attributes in view space rather than on screen, without it textures
+    /// warp across large triangles seen at steep angles.
+    pub(crate) perspective_correct: bool,
 }
 impl Default for DrawMode {
     fn default() -> Self {
@@ -76,8 +190,233 @@ impl Default for DrawMode {
             wireframe: false,
             shaded: true,
             points: false,
+            ray_traced: false,
+            shadows: false,
+            depth_cue: None,
+            wire_style: WireStyle::default(),
+            filler: TriangleFiller::default(),
+            collapse_subpixel: false,
+            reference: false,
+            perspective_correct: true,
+        }
+    }
+}
+
+/// How the wireframe and points modes draw an entity, see `Entity::with_wire_style` to tell
+/// overlapping meshes apart.
+#[derive(Debug, Copy, Clone)]
+pub struct WireStyle {
+    pub wire_color: Color,
+    pub point_color: Color,
+    /// Radius of the points in pixels.
+    pub point_size: f32,
+}
+impl Default for WireStyle {
+    fn default() -> Self {
+        Self {
+            wire_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            point_color: Color::new(1.0, 1.0, 1.0, 1.0),
+            point_size: 2.0,
+        }
+    }
+}
+#[allow(unused)]
+impl WireStyle {
+    /// Lines and points both in `color`.
+    pub fn new(color: Color) -> Self {
+        Self {
+            wire_color: color,
+            point_color: color,
+            ..Self::default()
+        }
+    }
+    pub fn with_point_color(mut self, color: Color) -> Self {
+        self.point_color = color;
+        self
+    }
+    pub fn with_point_size(mut self, size: f32) -> Self {
+        self.point_size = size;
+        self
+    }
+}
+
+/// Fades wireframe lines and points from full brightness at `start` to `min` at `end`,
+/// distances from the camera, so the near side of a dense mesh reads over the far side.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthCue {
+    pub start: f32,
+    pub end: f32,
+    /// Brightness left at `end` and beyond.
+    pub min: f32,
+}
+impl Default for DepthCue {
+    fn default() -> Self {
+        Self {
+            start: 2.0,
+            end: 40.0,
+            min: 0.15,
+        }
+    }
+}
+#[allow(unused)]
+impl DepthCue {
+    pub fn new(start: f32, end: f32) -> Self {
+        Self {
+            start,
+            end,
+            ..Self::default()
+        }
+    }
+    pub fn with_min(mut self, min: f32) -> Self {
+        self.min = min;
+        self
+    }
+    pub fn brightness(&self, distance: f32) -> f32 {
+        let t = ((distance - self.start) / (self.end - self.start).max(1e-6)).clamp(0.0, 1.0);
+        1.0 + (self.min - 1.0) * t
+    }
+}
+
+/// Edge in pixels of the square tiles targets are rasterized in, see `RenderTarget::create_tiles`.
+pub(crate) const TILE_SIZE: u32 = 64;
+/// Triangles a tile queues without going to the heap, enough for the tiles at the edges of a
+/// mesh and most tiles of sparse scenes.
+const INLINE_BIN_LEN: usize = 32;
+/// Queues below this capacity are never trimmed.
+const MIN_BIN_CAPACITY: usize = 1024;
+/// Most triangles a tile queues, 256 KiB of indices. A tile overlapped by more walks every
+/// triangle of the entity instead, see `TriangleBins::queue`.
+const MAX_BIN_LEN: usize = 1 << 16;
+
+/// The square tiles covering a target, row by row. The last column and row are cut short by
+/// the target's edges.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct TileGrid {
+    size: u32,
+    width: u32,
+    height: u32,
+    columns: usize,
+    rows: usize,
+}
+impl TileGrid {
+    fn new(width: u32, height: u32, size: u32) -> Self {
+        let size = size.max(1);
+        Self {
+            size,
+            width,
+            height,
+            columns: width.div_ceil(size) as usize,
+            rows: height.div_ceil(size) as usize,
+        }
+    }
+    fn count(&self) -> usize {
+        self.columns * self.rows
+    }
+}
+
+/// Per tile queues of the triangles overlapping it, so a tile only walks the triangles it
+/// draws. Short queues are stored inline, longer ones live on the target and keep their
+/// capacity across entities and frames, after a spike they shrink back once a whole frame
+/// used far less. None grows past `MAX_BIN_LEN`, so a dense mesh costs at most that per tile
+/// whatever its triangle count.
+#[derive(Default)]
+struct TriangleBins {
+    queues: Vec<SmallVec<[u32; INLINE_BIN_LEN]>>,
+    /// Tiles whose queue hit `MAX_BIN_LEN` and was dropped.
+    overflowed: Vec<bool>,
+    /// Longest each queue got since the last `trim`.
+    peaks: Vec<usize>,
+}
+impl TriangleBins {
+    /// Queues the triangles of `vertices` in submission order for every tile their bounds,
+    /// grown by `pad` pixels for lines and points, overlap. Tiles are equally sized but the
+    /// last column and row, so the overlapped ones are found by division rather than testing
+    /// each, which keeps binning linear in the triangles whatever the tile count.
+    fn fill(&mut self, grid: &TileGrid, vertices: &[Vertex], pad: f32) {
+        self.queues.resize_with(grid.count(), SmallVec::new);
+        self.peaks.resize(grid.count(), 0);
+        self.overflowed.clear();
+        self.overflowed.resize(grid.count(), false);
+        for queue in &mut self.queues {
+            queue.clear();
+        }
+        if grid.count() == 0 {
+            return;
+        }
+        let size = grid.size as f32;
+        for (index, triangle) in vertices.chunks_exact(3).enumerate() {
+            let (mut min, mut max) = (
+                Point2::new(f32::MAX, f32::MAX),
+                Point2::new(f32::MIN, f32::MIN),
+            );
+            for vertex in triangle {
+                min = min.inf(&vertex.position.xy());
+                max = max.sup(&vertex.position.xy());
+            }
+            let (left, top) = ((min.x - pad).max(0.0), (min.y - pad).max(0.0));
+            let (right, bottom) = (max.x + pad, max.y + pad);
+            // Off screen, or NaN, which fails every comparison
+            if !(left < grid.width as f32
+                && right >= 0.0
+                && top < grid.height as f32
+                && bottom >= 0.0)
+            {
+                continue;
+            }
+            let columns = (left / size) as usize..=((right / size) as usize).min(grid.columns - 1);
+            let rows = (top / size) as usize..=((bottom / size) as usize).min(grid.rows - 1);
+            for row in rows {
+                let tiles =
+                    row * grid.columns + columns.start()..=row * grid.columns + columns.end();
+                for tile in tiles {
+                    let queue = &mut self.queues[tile];
+                    if self.overflowed[tile] {
+                        continue;
+                    } else if queue.len() == MAX_BIN_LEN {
+                        self.overflowed[tile] = true;
+                        self.peaks[tile] = MAX_BIN_LEN;
+                        queue.clear();
+                    } else {
+                        queue.push(index as u32);
+                    }
+                }
+            }
         }
+        for (peak, queue) in self.peaks.iter_mut().zip(&self.queues) {
+            *peak = (*peak).max(queue.len());
+        }
+    }
+    /// The triangles a tile draws in order, `None` when its queue overflowed and it has to
+    /// walk all of them.
+    fn queue(&self, tile: usize) -> Option<&[u32]> {
+        match self.overflowed.get(tile) {
+            Some(true) => None,
+            _ => Some(self.queues.get(tile).map_or(&[], |queue| queue.as_slice())),
+        }
+    }
+    fn any_overflowed(&self) -> bool {
+        self.overflowed.contains(&true)
     }
+    /// Called once per frame, frees most of a queue that held over four times what it
+    /// needed since the previous call.
+    fn trim(&mut self) {
+        for (queue, peak) in self.queues.iter_mut().zip(&mut self.peaks) {
+            if queue.capacity() > MIN_BIN_CAPACITY && queue.capacity() > *peak * 4 {
+                queue.grow((*peak * 2).max(MIN_BIN_CAPACITY));
+            }
+            *peak = 0;
+        }
+    }
+}
+
+/// Cheaper fallbacks applied over every material, see `QualityGovernor`.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct QualityOverrides {
+    /// Shades lighting at the triangle corners and interpolates it over the unlit albedo
+    /// sampled per pixel, instead of lighting every pixel.
+    pub vertex_lighting: bool,
+    /// Samples every texture with the nearest texel, ignoring the samplers' filters.
+    pub nearest_sampling: bool,
 }
 
 pub struct RenderTarget {
@@ -87,6 +426,47 @@ pub struct RenderTarget {
     height: u32,
     clear_color: u32,
     vertex_buffer: Vec<Vertex>,
+    /// Triangle queues of the tiles, reused by every draw.
+    bins: TriangleBins,
+    /// Edge of the tiles, smaller ones spread uneven work better over the threads.
+    tile_size: u32,
+    /// `TILE_SIZE` tiles whatever the settings.
+    deterministic: bool,
+    clip_planes: Vec<Plane>,
+    clip_cap: Option<Color>,
+    camera_relative: bool,
+    time: f32,
+    ambient: Color,
+    interlace: Option<Interlace>,
+    frame: u32,
+    /// Scene colors of the previous frame, filling the pixels skipped by `interlace`.
+    history: Vec<u32>,
+    /// Traced shadows for lit materials, see `set_shadow_casters`.
+    shadow_casters: Option<Arc<ShadowCasters>>,
+    /// Per pixel id of the entity drawn there, empty unless enabled with `set_id_buffer`.
+    ids: Vec<u32>,
+    /// Per pixel triangle of that entity's model, alongside `ids`.
+    triangle_ids: Vec<u32>,
+    /// Written to `ids` by opaque fragments of the entity being drawn, 0 means nothing.
+    entity_id: u32,
+    /// Weighted sums of premultiplied blended colors and their weights, empty unless
+    /// enabled with `set_oit`.
+    accumulation: Vec<[f32; 4]>,
+    /// Product of one minus the alpha of every blended fragment, alongside `accumulation`.
+    revealage: Vec<f32>,
+    quality: QualityOverrides,
+    projection: Projection,
+    /// Subdivisions per triangle edge under non-linear projections.
+    tessellation: u32,
+}
+
+/// What was drawn at a pixel, read from the id buffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PixelId {
+    /// Index into `Scene::entities` at the time of drawing.
+    pub entity: usize,
+    /// Triangle of the entity's active model, before clipping.
+    pub triangle: usize,
 }
 
 impl RenderTarget {
@@ -98,48 +478,494 @@ impl RenderTarget {
             height,
             clear_color: u32::MIN,
             vertex_buffer: vec![],
+            bins: TriangleBins::default(),
+            tile_size: TILE_SIZE,
+            deterministic: false,
+            clip_planes: vec![],
+            clip_cap: None,
+            camera_relative: false,
+            time: 0.0,
+            ambient: Color::new(0.0, 0.0, 0.0, 1.0),
+            interlace: None,
+            frame: 0,
+            history: vec![],
+            shadow_casters: None,
+            ids: vec![],
+            triangle_ids: vec![],
+            entity_id: 0,
+            accumulation: vec![],
+            revealage: vec![],
+            quality: QualityOverrides::default(),
+            projection: Projection::Perspective,
+            tessellation: 1,
+        }
+    }
+    /// World space planes applied in the clipping stage, geometry behind any of them is cut
+    /// away. With a cap color, back faces are drawn flat so cut closed meshes look solid.
+    pub fn set_clip_planes(&mut self, planes: &[Plane], cap: Option<Color>) {
+        self.clip_planes.clear();
+        self.clip_planes.extend_from_slice(planes);
+        self.clip_cap = cap;
+    }
+    /// Builds model-view matrices from the entity position relative to the camera, taken in
+    /// f64, so large world coordinates don't cancel out inside an f32 matrix product.
+    pub fn set_camera_relative(&mut self, camera_relative: bool) {
+        self.camera_relative = camera_relative;
+    }
+    /// Seconds passed to shaders through `Fragment::time`, for animated materials.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time;
+    }
+    /// Projects through `projection` instead of the camera's perspective matrix. Non-linear
+    /// projections split every triangle edge `tessellation` times first, so the bent edges
+    /// come out as curves.
+    pub fn set_projection(&mut self, projection: Projection, tessellation: u32) {
+        self.projection = projection;
+        self.tessellation = tessellation.max(1);
+    }
+    pub fn set_quality_overrides(&mut self, quality: QualityOverrides) {
+        self.quality = quality;
+    }
+    /// Ambient light added by lit materials, e.g. sampled from an irradiance grid per entity.
+    pub fn set_ambient(&mut self, ambient: Color) {
+        self.ambient = ambient;
+    }
+    /// Rasterized fragments of lit materials trace a ray towards their light against these
+    /// and are darkened when it is blocked.
+    pub fn set_shadow_casters(&mut self, casters: Option<Arc<ShadowCasters>>) {
+        self.shadow_casters = casters;
+    }
+    /// Keeps the entity and triangle drawn at every pixel, for outlines around entities
+    /// and picking exactly what is visible with `id_at`.
+    pub fn set_id_buffer(&mut self, enabled: bool) {
+        match enabled {
+            true if self.ids.len() != self.color.len() => {
+                self.ids = vec![0; self.color.len()];
+                self.triangle_ids = vec![0; self.color.len()];
+            }
+            true => {}
+            false => {
+                self.ids = vec![];
+                self.triangle_ids = vec![];
+            }
+        }
+    }
+    /// The opaque entity and triangle last drawn at a pixel, `None` for background or
+    /// without an id buffer.
+    pub fn id_at(&self, x: u32, y: u32) -> Option<PixelId> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let index = (y * self.width + x) as usize;
+        let entity = *self.ids.get(index)?;
+        (entity > 0).then(|| PixelId {
+            entity: entity as usize - 1,
+            triangle: self.triangle_ids[index] as usize,
+        })
+    }
+    /// `id_at` for a window position, when the target is rendered at a different size than the
+    /// window, see `Config::render_scale`.
+    pub fn id_at_window(&self, x: u32, y: u32, window_size: (u32, u32)) -> Option<PixelId> {
+        let (window_width, window_height) =
+            (window_size.0.max(1) as u64, window_size.1.max(1) as u64);
+        let x = x as u64 * self.width as u64 / window_width;
+        let y = y as u64 * self.height as u64 / window_height;
+        self.id_at(x as u32, y as u32)
+    }
+    /// Id written by the following draws, see `set_id_buffer`.
+    pub fn set_entity_id(&mut self, id: u32) {
+        self.entity_id = id;
+    }
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+    pub fn depth(&self) -> &[f32] {
+        &self.depth
+    }
+    /// Weighted blended order independent transparency. Blended fragments are accumulated
+    /// instead of composited in draw order, and `resolve_oit` blends the weighted average
+    /// over the target, so overlapping transparent geometry needs no sorting and can't pop.
+    pub fn set_oit(&mut self, enabled: bool) {
+        match enabled {
+            true if self.accumulation.len() != self.color.len() => {
+                self.accumulation = vec![[0.0; 4]; self.color.len()];
+                self.revealage = vec![1.0; self.color.len()];
+            }
+            true => {}
+            false => {
+                self.accumulation = vec![];
+                self.revealage = vec![];
+            }
         }
     }
+    pub fn oit_enabled(&self) -> bool {
+        !self.accumulation.is_empty()
+    }
+    /// Composites what blended fragments accumulated since the last resolve and resets the
+    /// buffers. Call it once all transparent entities of a layer are drawn.
+    pub fn resolve_oit(&mut self) {
+        for ((color, sum), revealage) in self
+            .color
+            .iter_mut()
+            .zip(self.accumulation.iter_mut())
+            .zip(self.revealage.iter_mut())
+        {
+            if *revealage < 1.0 {
+                let weight = sum[3].max(1e-5);
+                let average = Color::new(sum[0] / weight, sum[1] / weight, sum[2] / weight, 1.0);
+                let dst = Color::from_u32(*color);
+                *color = average.lerp(&dst, *revealage).as_u32();
+            }
+            *sum = [0.0; 4];
+            *revealage = 1.0;
+        }
+    }
+    pub fn set_interlace(&mut self, interlace: Option<Interlace>) {
+        self.interlace = interlace;
+    }
+    /// With interlacing this also starts the next frame, pixels skipped by it are filled
+    /// from the history instead of cleared.
     pub fn clear(&mut self) {
-        self.color.fill(self.clear_color);
+        self.bins.trim();
+        self.depth.fill(f32::MAX);
+        self.ids.fill(0);
+        self.triangle_ids.fill(0);
+        match self.interlace {
+            Some(interlace) if self.history.len() == self.color.len() => {
+                self.frame = self.frame.wrapping_add(1);
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        let index = (y * self.width + x) as usize;
+                        self.color[index] = if interlace.renders(x, y, self.frame) {
+                            self.clear_color
+                        } else {
+                            self.history[index]
+                        };
+                    }
+                }
+            }
+            _ => self.color.fill(self.clear_color),
+        }
+    }
+    /// Remembers the scene for the next interlaced or unchanged frame, call it before drawing
+    /// 2D overlays that shouldn't linger.
+    pub fn keep_history(&mut self) {
+        self.history.clone_from(&self.color);
+    }
+    /// Puts back the colors saved by `keep_history`, false if there are none for this size.
+    pub fn restore_history(&mut self) -> bool {
+        if self.history.len() != self.color.len() {
+            return false;
+        }
+        self.color.copy_from_slice(&self.history);
+        true
+    }
+    /// Resets depth only, so the next layer draws over everything so far.
+    pub fn clear_depth(&mut self) {
         self.depth.fill(f32::MAX);
     }
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+    /// Writes a pixel without depth testing, used by 2D overlays.
+    pub fn put_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if x >= 0 && y >= 0 && (x as u32) < self.width && (y as u32) < self.height {
+            self.color[(y as u32 * self.width + x as u32) as usize] = color;
+        }
+    }
+    /// Blends `color` over a screen rectangle by its alpha, clipped to the target.
+    pub fn blend_rect(&mut self, x: i32, y: i32, width: u32, height: u32, color: Color) {
+        let Some((x0, y0, x1, y1)) = Rect::new(x, y, width, height).clipped(self.size()) else {
+            return;
+        };
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let index = (py * self.width + px) as usize;
+                let dst = Color::from_u32(self.color[index]);
+                self.color[index] = dst.lerp(&color, color.a).as_u32();
+            }
+        }
+    }
+    /// Fills pixels in parallel from a per pixel function returning a color and depth, e.g.
+    /// a ray tracer. Pixels it returns `None` for keep their contents.
+    pub fn trace_pixels(&mut self, trace: impl Fn(u32, u32) -> Option<(Color, f32)> + Sync) {
+        self.create_slices().par_iter_mut().for_each(|slice| {
+            for y in slice.start..slice.end {
+                for x in slice.left..slice.right {
+                    if !slice.renders(x, y) {
+                        continue;
+                    }
+                    if let Some((color, depth)) = trace(x, y) {
+                        let index = ((y - slice.start) as usize, (x - slice.left) as usize);
+                        slice.color_slice[index] = color.as_u32();
+                        slice.depth_slice[index] = depth;
+                    }
+                }
+            }
+        });
+    }
+    /// The whole target as a rectangle.
+    pub fn rect(&self) -> Rect {
+        Rect::new(0, 0, self.width, self.height)
+    }
+    /// Copies the colors of `src` in another target to `x`, `y` unscaled, clipped to both
+    /// targets.
+    pub fn copy_rect(&mut self, source: &RenderTarget, src: Rect, x: i32, y: i32) {
+        let Some((sx0, sy0, sx1, sy1)) = src.clipped(source.size()) else {
+            return;
+        };
+        // Shift the destination by whatever clipping cut off the source
+        let (x, y) = (x + (sx0 as i32 - src.x), y + (sy0 as i32 - src.y));
+        let dst = Rect::new(x, y, sx1 - sx0, sy1 - sy0);
+        let Some((dx0, dy0, dx1, dy1)) = dst.clipped(self.size()) else {
+            return;
+        };
+        for py in dy0..dy1 {
+            let sy = (py as i32 - y) as u32 + sy0;
+            let sx = (dx0 as i32 - x) as u32 + sx0;
+            let from = (sy * source.width + sx) as usize;
+            let to = (py * self.width + dx0) as usize;
+            let count = (dx1 - dx0) as usize;
+            self.color[to..to + count].copy_from_slice(&source.color[from..from + count]);
+        }
+    }
+    /// Copies the colors of `src` in another target scaled onto `dst`, both clipped to their
+    /// targets, e.g. for picture in picture or downsampling chains.
+    pub fn blit(&mut self, source: &RenderTarget, src: Rect, dst: Rect, filter: BlitFilter) {
+        let (Some((sx0, sy0, sx1, sy1)), Some((dx0, dy0, dx1, dy1))) =
+            (src.clipped(source.size()), dst.clipped(self.size()))
+        else {
+            return;
+        };
+        let scale_x = src.width as f32 / dst.width.max(1) as f32;
+        let scale_y = src.height as f32 / dst.height.max(1) as f32;
+        let fetch = |x: f32, y: f32| {
+            let x = (x.floor() as i32).clamp(sx0 as i32, sx1 as i32 - 1) as u32;
+            let y = (y.floor() as i32).clamp(sy0 as i32, sy1 as i32 - 1) as u32;
+            source.color[(y * source.width + x) as usize]
+        };
+        let width = self.width as usize;
+        self.color
+            .par_chunks_mut(width)
+            .enumerate()
+            .skip(dy0 as usize)
+            .take((dy1 - dy0) as usize)
+            .for_each(|(py, row)| {
+                // Source position of this pixel's center
+                let y = src.y as f32 + (py as f32 - dst.y as f32 + 0.5) * scale_y;
+                for px in dx0..dx1 {
+                    let x = src.x as f32 + (px as f32 - dst.x as f32 + 0.5) * scale_x;
+                    row[px as usize] = match filter {
+                        BlitFilter::Nearest => fetch(x, y),
+                        BlitFilter::Bilinear => {
+                            let (x, y) = (x - 0.5, y - 0.5);
+                            let (fx, fy) = (x - x.floor(), y - y.floor());
+                            let [a, b, c, d] = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)]
+                                .map(|(ox, oy)| Color::from_u32(fetch(x + ox, y + oy)));
+                            a.lerp(&b, fx).lerp(&c.lerp(&d, fx), fy).as_u32()
+                        }
+                    };
+                }
+            });
+    }
+    /// Fills a rectangle with an opaque color, clipped to the target.
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let Some((x0, y0, x1, y1)) = rect.clipped(self.size()) else {
+            return;
+        };
+        let color = color.as_u32();
+        for y in y0..y1 {
+            let row = (y * self.width) as usize;
+            self.color[row + x0 as usize..row + x1 as usize].fill(color);
+        }
+    }
+    /// Replaces the bits in `mask` of every color with the same pixel of an equally sized
+    /// target, e.g. `0x00ffff` takes green and blue from `source`.
+    pub fn copy_channels(&mut self, source: &RenderTarget, mask: u32) {
+        for (dst, src) in self.color.iter_mut().zip(&source.color) {
+            *dst = (*dst & !mask) | (*src & mask);
+        }
+    }
+    /// Copies the color buffer into a texture, e.g. to sample an offscreen pass.
+    pub fn to_texture(&self) -> Texture {
+        let image = RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let color = self.color[(y * self.width + x) as usize];
+            Rgba([(color >> 16) as u8, (color >> 8) as u8, color as u8, 255])
+        });
+        Texture::from_image(DynamicImage::ImageRgba8(image))
+    }
 
-    pub fn create_slices(&mut self) -> Vec<RenderSlice> {
-        let num_threads = rayon::current_num_threads();
-        let rows_per_thread = (self.height as usize + num_threads - 1) / num_threads; // Ceiling division
-        let mut slices = Vec::with_capacity(num_threads);
-        let mut remaining_color = &mut self.color[..];
-        let mut remaining_depth = &mut self.depth[..];
-
-        for i in 0..num_threads {
-            let y_start = i * rows_per_thread;
-            let y_end = (y_start + rows_per_thread).min(self.height as usize);
-            if y_start >= self.height as usize {
-                break; // Avoid empty slices for the last thread if height is small
-            }
-            let start_idx = y_start * self.width as usize;
-            let end_idx = y_end * self.width as usize;
-            let (color_slice, next_color) = remaining_color.split_at_mut(end_idx - start_idx);
-            let (depth_slice, next_depth) = remaining_depth.split_at_mut(end_idx - start_idx);
-            remaining_color = next_color;
-            remaining_depth = next_depth;
+    /// Edge of the square tiles frames are rasterized in, see `RenderThreads::small_tiles`.
+    pub fn set_tile_size(&mut self, size: u32) {
+        self.tile_size = size.max(1);
+    }
+    /// Cuts the target into the same tiles on every machine, see `Deterministic`.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+    fn tile_grid(&self) -> TileGrid {
+        let size = match self.deterministic {
+            true => TILE_SIZE,
+            false => self.tile_size,
+        };
+        TileGrid::new(self.width, self.height, size)
+    }
+    /// Every tile of the target, to be drawn in parallel.
+    pub fn create_slices(&mut self) -> Vec<RenderSlice<'_>> {
+        self.create_tiles(|_| true)
+    }
+    /// The tiles `keep` accepts by their index in the grid, row by row. Each tile holds its
+    /// part of every buffer as one segment per row, so tiles side by side can be drawn by
+    /// different tasks.
+    fn create_tiles(&mut self, keep: impl Fn(usize) -> bool) -> Vec<RenderSlice<'_>> {
+        let grid = self.tile_grid();
+        let mut slots = vec![None; grid.count()];
+        let mut kept = vec![];
+        for tile in (0..grid.count()).filter(|tile| keep(*tile)) {
+            slots[tile] = Some(kept.len());
+            kept.push(tile);
+        }
+        let mut colors = split_tiles(&mut self.color, &grid, &slots, kept.len()).into_iter();
+        let mut depths = split_tiles(&mut self.depth, &grid, &slots, kept.len()).into_iter();
+        let mut ids = split_tiles(&mut self.ids, &grid, &slots, kept.len()).into_iter();
+        let mut triangle_ids =
+            split_tiles(&mut self.triangle_ids, &grid, &slots, kept.len()).into_iter();
+        let mut accumulations =
+            split_tiles(&mut self.accumulation, &grid, &slots, kept.len()).into_iter();
+        let mut revealages =
+            split_tiles(&mut self.revealage, &grid, &slots, kept.len()).into_iter();
+        let mut slices = Vec::with_capacity(kept.len());
+        for tile in kept {
+            let (column, row) = ((tile % grid.columns) as u32, (tile / grid.columns) as u32);
             slices.push(RenderSlice {
-                color_slice,
-                depth_slice,
-                start: y_start as u32,
-                end: y_end as u32,
+                color_slice: colors.next().unwrap_or_default(),
+                depth_slice: depths.next().unwrap_or_default(),
+                id_slice: ids.next().unwrap_or_default(),
+                triangle_id_slice: triangle_ids.next().unwrap_or_default(),
+                entity_id: self.entity_id,
+                triangle_id: 0,
+                accumulation_slice: accumulations.next().unwrap_or_default(),
+                revealage_slice: revealages.next().unwrap_or_default(),
+                tile,
+                left: column * grid.size,
+                right: ((column + 1) * grid.size).min(self.width),
+                start: row * grid.size,
+                end: ((row + 1) * grid.size).min(self.height),
                 width: self.width,
                 height: self.height,
+                time: self.time,
+                ambient: self.ambient,
+                interlace: self.interlace,
+                frame: self.frame,
+                shadow_casters: self.shadow_casters.clone(),
+                inverse_vp: Matrix4::identity(),
+                eye_rays: [-Vector3::z(), Vector3::zeros(), Vector3::zeros()],
+                quality: self.quality,
+                filler: TriangleFiller::default(),
+                collapse_subpixel: false,
+                reference: false,
+                perspective_correct: true,
             });
         }
         slices
     }
-    pub fn draw(&mut self, entity: &Entity, camera: &Camera, mode: &DrawMode) {
+    pub(crate) fn draw(&mut self, entity: &Entity, camera: &Camera, mode: &DrawMode) {
         draw_buffer(self, entity, camera, mode);
     }
+    pub(crate) fn draw_debug(&mut self, debug: &DebugDraw, camera: &Camera) {
+        let v_mat = camera.get_view_matrix();
+        let p_mat = camera.get_perspective_matrix();
+        let size = (self.width, self.height);
+        let lines = debug
+            .lines
+            .iter()
+            .filter_map(|line| {
+                let start = Vertex::new(&line.start).model_to_view(&v_mat);
+                let end = Vertex::new(&line.end).model_to_view(&v_mat);
+                let (start, end) = clip_line(&start, &end, camera)?;
+                let project = |mut v: Vertex| {
+                    v.view_to_clip_mut(&p_mat)
+                        .clip_to_ndc_mut()
+                        .ndc_to_screen_mut(size);
+                    v
+                };
+                Some((project(start), project(end), line.color.as_u32()))
+            })
+            .collect::<Vec<_>>();
+
+        self.create_slices().par_iter_mut().for_each(|slice| {
+            for (start, end, color) in &lines {
+                draw_line(slice, start, end, *color, [1.0, 1.0]);
+            }
+        });
+        draw_labels(self, &debug.labels, camera);
+        for label in &debug.texts {
+            draw_text(self, label.x, label.y, &label.text, label.color, 1);
+        }
+    }
+    /// Draws the grid per pixel by casting camera rays at its plane, blended over whatever
+    /// is closer than the plane is hidden by the depth buffer.
+    pub(crate) fn draw_grid(&mut self, grid: &Grid, camera: &Camera) {
+        let vp_mat = camera.get_perspective_matrix() * camera.get_view_matrix();
+        let Some(inverse) = vp_mat.try_inverse() else {
+            return;
+        };
+        let (width, height) = (self.width as f32, self.height as f32);
+        let plane = Plane::from_point_normal(&Point3::new(0.0, grid.height, 0.0), &Vector3::y());
+        let hit = |x: f32, y: f32| {
+            let ndc = (2.0 * x / width - 1.0, 1.0 - 2.0 * y / height);
+            let near = inverse.transform_point(&Point3::new(ndc.0, ndc.1, -1.0));
+            let far = inverse.transform_point(&Point3::new(ndc.0, ndc.1, 1.0));
+            let ray = Ray::new(near, far - near);
+            ray.intersect_plane(&plane).map(|t| ray.at(t))
+        };
+        self.create_slices().par_iter_mut().for_each(|slice| {
+            for y in slice.start..slice.end {
+                for x in slice.left..slice.right {
+                    if !slice.renders(x, y) {
+                        continue;
+                    }
+                    let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let Some(point) = hit(px, py) else { continue };
+                    let index = ((y - slice.start) as usize, (x - slice.left) as usize);
+                    if vp_mat.transform_point(&point).z >= slice.depth_slice[index] {
+                        continue;
+                    }
+                    // World size of this pixel on the plane, from the neighbouring rays
+                    let (Some(right), Some(down)) = (hit(px + 1.0, py), hit(px, py + 1.0)) else {
+                        continue;
+                    };
+                    let footprint = Vector2::new(
+                        (right.x - point.x).abs() + (down.x - point.x).abs(),
+                        (right.z - point.z).abs() + (down.z - point.z).abs(),
+                    );
+                    let distance = (point - camera.eye()).magnitude();
+                    if let Some(color) = grid.shade(&point, &footprint, distance) {
+                        let dst = Color::from_u32(slice.color_slice[index]);
+                        slice.color_slice[index] = dst.lerp(&color, color.a).as_u32();
+                    }
+                }
+            }
+        });
+    }
 }
-fn calculate_uvs(triangle: &[Vertex], weights: &Vector3<f32>) -> Option<Point2<f32>> {
+/// Clips a view space line to the part in front of the near plane.
+fn clip_line(start: &Vertex, end: &Vertex, camera: &Camera) -> Option<(Vertex, Vertex)> {
+    let near = -camera.near;
+    let (z0, z1) = (start.position.z, end.position.z);
+    if z0 > near && z1 > near {
+        return None;
+    }
+    let t = (near - z0) / (z1 - z0);
+    let clipped_start = if z0 > near {
+        start.lerp(end, t)
+    } else {
+        *start
+    };
+    let clipped_end = if z1 > near { start.lerp(end, t) } else { *end };
+    Some((clipped_start, clipped_end))
+}
+pub(crate) fn calculate_uvs(triangle: &[Vertex], weights: &Vector3<f32>) -> Option<Point2<f32>> {
     let uv0 = triangle[0].uv?;
     let uv1 = triangle[1].uv?;
     let uv2 = triangle[2].uv?;
@@ -149,7 +975,10 @@ fn calculate_uvs(triangle: &[Vertex], weights: &Vector3<f32>) -> Option<Point2<f
     uv += uv2 * weights.z;
     Some(uv)
 }
-fn calculate_normals(triangle: &[Vertex], weights: &Vector3<f32>) -> Option<Vector3<f32>> {
+pub(crate) fn calculate_normals(
+    triangle: &[Vertex],
+    weights: &Vector3<f32>,
+) -> Option<Vector3<f32>> {
     let n0 = triangle[0].normal?;
     let n1 = triangle[1].normal?;
     let n2 = triangle[2].normal?;
@@ -161,6 +990,11 @@ fn calculate_normals(triangle: &[Vertex], weights: &Vector3<f32>) -> Option<Vect
 }
 
 const EPSILON: f32 = 1e-6;
+/// Largest UV magnitude passed on to shaders. Beyond it f32 can't tell texels apart and
+/// wrapping is meaningless anyway, clamping keeps the texel math from overflowing.
+const MAX_UV: f32 = 65536.0;
+/// Largest bounding box extent in pixels that `draw_small_triangle` handles.
+const SMALL_TRIANGLE: f32 = 4.0;
 
 fn calculate_depths(triangle: &[Vertex], weights: &Vector3<f32>) -> f32 {
     let z0 = triangle[0].position.z;
@@ -169,20 +1003,381 @@ fn calculate_depths(triangle: &[Vertex], weights: &Vector3<f32>) -> f32 {
 
     let denominator = weights.x / z0 + weights.y / z1 + weights.z / z2;
 
-    if denominator.abs() > EPSILON {
+    // Too far to resolve rather than in front of everything, so bad input can't win the test
+    if denominator.abs() > EPSILON && denominator.is_finite() {
         1.0 / denominator
     } else {
-        0.0
+        f32::MAX
+    }
+}
+
+/// A buffer's part of one tile, a segment of each row the tile covers. Indexed by the row
+/// and column inside the tile, see `RenderSlice::local`.
+struct TileBuffer<'a, T> {
+    rows: Vec<&'a mut [T]>,
+}
+impl<T> Default for TileBuffer<'_, T> {
+    fn default() -> Self {
+        Self { rows: vec![] }
+    }
+}
+impl<T> TileBuffer<'_, T> {
+    /// True for buffers the target doesn't have, e.g. ids without `set_id_buffer`.
+    fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    fn get_mut(&mut self, (row, column): (usize, usize)) -> Option<&mut T> {
+        self.rows.get_mut(row)?.get_mut(column)
+    }
+}
+impl<T> Index<(usize, usize)> for TileBuffer<'_, T> {
+    type Output = T;
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        &self.rows[row][column]
+    }
+}
+impl<T> IndexMut<(usize, usize)> for TileBuffer<'_, T> {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        &mut self.rows[row][column]
+    }
+}
+
+/// Cuts a row major buffer of the grid's size into the tiles `slots` places, `kept` of them.
+/// Bands of tile rows without a kept tile are skipped whole, empty buffers give empty tiles.
+fn split_tiles<'a, T>(
+    buffer: &'a mut [T],
+    grid: &TileGrid,
+    slots: &[Option<usize>],
+    kept: usize,
+) -> Vec<TileBuffer<'a, T>> {
+    let mut tiles = (0..kept).map(|_| TileBuffer::default()).collect::<Vec<_>>();
+    let width = grid.width as usize;
+    if buffer.is_empty() || width == 0 {
+        return tiles;
+    }
+    let band_slots = slots.chunks(grid.columns.max(1));
+    for (band, band_slots) in buffer
+        .chunks_mut(width * grid.size as usize)
+        .zip(band_slots)
+    {
+        if band_slots.iter().all(Option::is_none) {
+            continue;
+        }
+        for mut row in band.chunks_mut(width) {
+            for slot in band_slots {
+                let (segment, rest) = row.split_at_mut((grid.size as usize).min(row.len()));
+                row = rest;
+                if let Some(slot) = slot {
+                    tiles[*slot].rows.push(segment);
+                }
+            }
+        }
     }
+    tiles
 }
 
+/// One tile of a target being drawn, rows `start..end` and columns `left..right`.
 pub struct RenderSlice<'a> {
-    color_slice: &'a mut [u32],
-    depth_slice: &'a mut [f32],
+    color_slice: TileBuffer<'a, u32>,
+    depth_slice: TileBuffer<'a, f32>,
+    /// Empty when the target has no id buffer.
+    id_slice: TileBuffer<'a, u32>,
+    triangle_id_slice: TileBuffer<'a, u32>,
+    entity_id: u32,
+    /// Model triangle of the triangles being rasterized, set per triangle by `draw_buffer`.
+    triangle_id: u32,
+    /// Empty unless the target has weighted blended transparency enabled.
+    accumulation_slice: TileBuffer<'a, [f32; 4]>,
+    revealage_slice: TileBuffer<'a, f32>,
+    /// Index in the target's tile grid, row by row.
+    tile: usize,
+    left: u32,
+    right: u32,
     start: u32,
     end: u32,
+    /// Size of the whole target.
     width: u32,
     height: u32,
+    time: f32,
+    ambient: Color,
+    interlace: Option<Interlace>,
+    frame: u32,
+    shadow_casters: Option<Arc<ShadowCasters>>,
+    /// Screen to world, for finding the world position of fragments to trace shadows from.
+    inverse_vp: Matrix4<f32>,
+    /// World space eye ray at the NDC origin and its change per NDC unit in x and y.
+    eye_rays: [Vector3<f32>; 3],
+    quality: QualityOverrides,
+    filler: TriangleFiller,
+    collapse_subpixel: bool,
+    reference: bool,
+    perspective_correct: bool,
+}
+impl RenderSlice<'_> {
+    fn contains(&self, x: u32, y: u32) -> bool {
+        (self.left..self.right).contains(&x) && (self.start..self.end).contains(&y)
+    }
+    /// Row and column of a target pixel inside the tile, `None` outside it.
+    fn local(&self, x: u32, y: u32) -> Option<(usize, usize)> {
+        self.contains(x, y)
+            .then(|| ((y - self.start) as usize, (x - self.left) as usize))
+    }
+    /// False for pixels skipped by interlacing this frame.
+    fn renders(&self, x: u32, y: u32) -> bool {
+        self.interlace
+            .is_none_or(|interlace| interlace.renders(x, y, self.frame))
+    }
+    /// Direction towards the camera through the center of a pixel.
+    fn view_dir(&self, x: u32, y: u32) -> Vector3<f32> {
+        let ndc_x = (x as f32 + 0.5) / self.width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y as f32 + 0.5) / self.height as f32 * 2.0;
+        let [center, right, up] = &self.eye_rays;
+        -(center + right * ndc_x + up * ndc_y).normalize()
+    }
+}
+
+/// Affine UV transform applied before texture sampling: scale and rotation about the
+/// texture center, then offset. Transformed UVs wrap, so a scale above one tiles.
+#[derive(Copy, Clone, Debug)]
+pub struct UvTransform {
+    pub offset: Vector2<f32>,
+    pub scale: Vector2<f32>,
+    /// Radians, counter clockwise.
+    pub rotation: f32,
+}
+impl Default for UvTransform {
+    fn default() -> Self {
+        Self {
+            offset: Vector2::zeros(),
+            scale: Vector2::new(1.0, 1.0),
+            rotation: 0.0,
+        }
+    }
+}
+#[allow(unused)]
+impl UvTransform {
+    pub fn new(offset: Vector2<f32>, scale: Vector2<f32>, rotation: f32) -> Self {
+        Self {
+            offset,
+            scale,
+            rotation,
+        }
+    }
+    pub fn is_identity(&self) -> bool {
+        self.offset == Vector2::zeros()
+            && self.scale == Vector2::new(1.0, 1.0)
+            && self.rotation == 0.0
+    }
+    pub fn apply(&self, uv: &Point2<f32>) -> Point2<f32> {
+        if self.is_identity() {
+            return *uv;
+        }
+        let uv = self.transform(uv);
+        Point2::new(uv.x.rem_euclid(1.0), uv.y.rem_euclid(1.0))
+    }
+    /// `apply` without wrapping into 0..1, leaving that to a `Sampler`.
+    pub fn transform(&self, uv: &Point2<f32>) -> Point2<f32> {
+        if self.is_identity() {
+            return *uv;
+        }
+        Point2::new(0.5, 0.5) + self.apply_vector(&(uv - Point2::new(0.5, 0.5))) + self.offset
+    }
+    /// Scale and rotation without the offset, for UV derivatives.
+    pub fn apply_vector(&self, delta: &Vector2<f32>) -> Vector2<f32> {
+        let scaled = delta.component_mul(&self.scale);
+        let (sin, cos) = self.rotation.sin_cos();
+        Vector2::new(
+            scaled.x * cos - scaled.y * sin,
+            scaled.x * sin + scaled.y * cos,
+        )
+    }
+}
+
+/// Texture filtering of a `Sampler`. The trilinear and anisotropic modes use the texture's
+/// mip chain, see `Texture::with_mipmaps`, and fall back to `Texture::sample` without one.
+#[allow(unused)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextureFilter {
+    /// The closest texel.
+    #[default]
+    Nearest,
+    /// The four closest texels of the full size texture, without a mip chain.
+    Bilinear,
+    Trilinear,
+    /// Up to `max_taps` trilinear samples along the longer UV gradient, for floors and roads
+    /// seen at grazing angles.
+    Anisotropic {
+        max_taps: u32,
+    },
+}
+
+/// What a `Sampler` does with UVs outside 0..1.
+#[allow(unused)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum TextureWrap {
+    #[default]
+    Repeat,
+    Mirror,
+    Clamp,
+    /// The sampler's border color.
+    Border,
+}
+
+/// How a material reads its texture, kept apart from the pixels so the same `Texture` (its
+/// clones share them) can be sampled differently by different materials.
+#[derive(Copy, Clone, Debug)]
+pub struct Sampler {
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+    pub border: Color,
+    /// Added to the mip level picked by the filtered modes, positive is blurrier.
+    pub mip_bias: f32,
+}
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::Nearest,
+            wrap: TextureWrap::Repeat,
+            border: Color::new(0.0, 0.0, 0.0, 0.0),
+            mip_bias: 0.0,
+        }
+    }
+}
+#[allow(unused)]
+impl Sampler {
+    pub fn new(filter: TextureFilter, wrap: TextureWrap) -> Self {
+        Self {
+            filter,
+            wrap,
+            ..Self::default()
+        }
+    }
+    pub fn with_border(mut self, border: Color) -> Self {
+        self.wrap = TextureWrap::Border;
+        self.border = border;
+        self
+    }
+    pub fn with_mip_bias(mut self, mip_bias: f32) -> Self {
+        self.mip_bias = mip_bias;
+        self
+    }
+    pub fn sample_uv(
+        &self,
+        texture: &Texture,
+        uv: &Point2<f32>,
+        ddx: &Vector2<f32>,
+        ddy: &Vector2<f32>,
+    ) -> Option<Color> {
+        // UVs already inside stay put so 1.0 keeps reading the last texel
+        let wrap = |c: f32| match self.wrap {
+            _ if (0.0..=1.0).contains(&c) => c,
+            TextureWrap::Repeat => c.rem_euclid(1.0),
+            TextureWrap::Mirror => 1.0 - (c.rem_euclid(2.0) - 1.0).abs(),
+            TextureWrap::Clamp | TextureWrap::Border => c.clamp(0.0, 1.0),
+        };
+        let outside = !(0.0..=1.0).contains(&uv.x) || !(0.0..=1.0).contains(&uv.y);
+        if self.wrap == TextureWrap::Border && outside {
+            return Some(self.border);
+        }
+        let uv = Point2::new(wrap(uv.x), wrap(uv.y));
+        // Scaling the footprint by 2^bias moves the picked level by bias
+        let bias = self.mip_bias.exp2();
+        match self.filter {
+            TextureFilter::Nearest => texture.sample(&uv),
+            TextureFilter::Bilinear => texture.sample_bilinear(&uv),
+            TextureFilter::Trilinear => texture.sample_grad(&uv, &(ddx * bias), &(ddy * bias)),
+            TextureFilter::Anisotropic { max_taps } => {
+                texture.sample_anisotropic(&uv, &(ddx * bias), &(ddy * bias), max_taps)
+            }
+        }
+    }
+    /// Samples at the fragment's UVs moved by `uv_transform`.
+    fn sample(
+        &self,
+        texture: &Texture,
+        uv_transform: &UvTransform,
+        triangle: &[Vertex],
+        fragment: &Fragment,
+    ) -> Option<Color> {
+        let uv = uv_transform.transform(&fragment.uv(triangle).unwrap_or(Point2::origin()));
+        self.sample_fragment(texture, &uv, fragment, || {
+            (
+                uv_transform.apply_vector(&fragment.uv_ddx(triangle).unwrap_or(Vector2::zeros())),
+                uv_transform.apply_vector(&fragment.uv_ddy(triangle).unwrap_or(Vector2::zeros())),
+            )
+        })
+    }
+    /// Samples at `uv` for materials that work out their own UVs. `derivatives` gives the UV
+    /// change per pixel and is only called by the filters that pick a mip level.
+    fn sample_fragment(
+        &self,
+        texture: &Texture,
+        uv: &Point2<f32>,
+        fragment: &Fragment,
+        derivatives: impl FnOnce() -> (Vector2<f32>, Vector2<f32>),
+    ) -> Option<Color> {
+        let filter = if fragment.nearest_sampling {
+            TextureFilter::Nearest
+        } else {
+            self.filter
+        };
+        let (ddx, ddy) = match filter {
+            TextureFilter::Nearest | TextureFilter::Bilinear => {
+                (Vector2::zeros(), Vector2::zeros())
+            }
+            _ => derivatives(),
+        };
+        Sampler { filter, ..*self }.sample_uv(texture, uv, &ddx, &ddy)
+    }
+}
+
+/// Time driven UV animation for `Material::Animated`.
+#[allow(unused)]
+#[derive(Copy, Clone, Debug)]
+pub enum UvAnimation {
+    /// The texture is a sheet of `columns` x `rows` frames, played left to right and top to
+    /// bottom at `frame_rate` frames per second, looping.
+    Flipbook {
+        columns: u32,
+        rows: u32,
+        frame_rate: f32,
+    },
+    /// Offsets the UVs by `velocity` per second, wrapping around the texture.
+    Scroll { velocity: Vector2<f32> },
+}
+impl UvAnimation {
+    pub fn apply(&self, uv: &Point2<f32>, time: f32) -> Point2<f32> {
+        match self {
+            Self::Flipbook {
+                columns,
+                rows,
+                frame_rate,
+            } => {
+                let (columns, rows) = ((*columns).max(1), (*rows).max(1));
+                let frame = (time * frame_rate).max(0.0) as u32 % (columns * rows);
+                let (column, row) = (frame % columns, frame / columns);
+                // V points up while frames are counted from the top row
+                Point2::new(
+                    (column as f32 + uv.x.clamp(0.0, 1.0)) / columns as f32,
+                    ((rows - 1 - row) as f32 + uv.y.clamp(0.0, 1.0)) / rows as f32,
+                )
+            }
+            Self::Scroll { velocity } => {
+                let uv = uv + velocity * time;
+                Point2::new(uv.x.rem_euclid(1.0), uv.y.rem_euclid(1.0))
+            }
+        }
+    }
+    /// UV derivatives after `apply`, a flipbook shrinks them to a single frame.
+    pub fn apply_vector(&self, delta: &Vector2<f32>) -> Vector2<f32> {
+        match self {
+            Self::Flipbook { columns, rows, .. } => Vector2::new(
+                delta.x / (*columns).max(1) as f32,
+                delta.y / (*rows).max(1) as f32,
+            ),
+            Self::Scroll { .. } => *delta,
+        }
+    }
 }
 
 #[allow(unused)]
@@ -191,31 +1386,405 @@ pub enum Material {
     VertexColors,
     Textured {
         texture: Texture,
+        uv_transform: UvTransform,
+        sampler: Sampler,
     },
     LitTexture {
         texture: Texture,
         light_dir: Vector3<f32>,
+        uv_transform: UvTransform,
+        sampler: Sampler,
+        emissive: Option<Emissive>,
+        specular: Option<Specular>,
     },
     LitSolid {
         color: Color,
         light_dir: Vector3<f32>,
+        emissive: Option<Emissive>,
+        specular: Option<Specular>,
     },
-}
+    Checkerboard {
+        color_a: Color,
+        color_b: Color,
+        scale: f32,
+    },
+    Decal {
+        texture: Texture,
+        alpha_cutoff: f32,
+        uv_transform: UvTransform,
+        sampler: Sampler,
+    },
+    /// Samples a reflection rendered from the mirrored camera at the fragment's screen position.
+    Mirror {
+        texture: Texture,
+        sampler: Sampler,
+    },
+    Animated {
+        texture: Texture,
+        animation: UvAnimation,
+        uv_transform: UvTransform,
+        sampler: Sampler,
+    },
+    /// Lit solid color darkened by occlusion baked into the vertex colors, see
+    /// `bake_vertex_ao`. `ambient` is the share of light that doesn't depend on `light_dir`.
+    AmbientOcclusion {
+        color: Color,
+        light_dir: Vector3<f32>,
+        ambient: f32,
+    },
+    /// Alpha blended color, tinted by the texture when there is one. Drawn after the opaque
+    /// entities, see `Shader::blend`.
+    Transparent {
+        color: Color,
+        texture: Option<Texture>,
+        sampler: Sampler,
+    },
+}
+
+/// Light given off by a lit material, added after lighting so it ignores N·L and shows in
+/// the dark. Strengths above one give values over 1.0 for HDR and bloom to pick up, the
+/// 8 bit color buffer saturates them.
+#[derive(Clone, Debug)]
+pub struct Emissive {
+    pub color: Color,
+    /// Multiplies `color` when set, e.g. a mask of the glowing parts, sampled with the
+    /// material's UVs.
+    pub texture: Option<Texture>,
+    pub sampler: Sampler,
+    pub strength: f32,
+}
+#[allow(unused)]
+impl Emissive {
+    pub fn new(color: Color, strength: f32) -> Self {
+        Self {
+            color,
+            texture: None,
+            sampler: Sampler::default(),
+            strength,
+        }
+    }
+    pub fn with_texture(mut self, texture: Texture) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+    fn sample(
+        &self,
+        uv_transform: &UvTransform,
+        triangle: &[Vertex],
+        fragment: &Fragment,
+    ) -> Color {
+        let mask = self
+            .texture
+            .as_ref()
+            .filter(|_| fragment.uv(triangle).is_some())
+            .and_then(|texture| {
+                self.sampler
+                    .sample(texture, uv_transform, triangle, fragment)
+            })
+            .unwrap_or(Color::new(1.0, 1.0, 1.0, 1.0));
+        self.color.modulate(&mask) * self.strength
+    }
+}
 
-pub trait Shader: Sync {
-    fn shade(&self, triangle: &[Vertex], weights: &Vector3<f32>) -> Color;
+/// Blinn-Phong highlight added on top of a lit material's diffuse light.
+#[derive(Clone, Debug)]
+pub struct Specular {
+    pub intensity: f32,
+    /// Exponent at full gloss, higher is a tighter highlight.
+    pub shininess: f32,
+    /// Per texel control sampled with the material's UVs: red scales the intensity, green
+    /// is the gloss, moving the exponent from 1 up to `shininess`.
+    pub map: Option<Texture>,
+    pub sampler: Sampler,
+}
+#[allow(unused)]
+impl Specular {
+    pub fn new(intensity: f32, shininess: f32) -> Self {
+        Self {
+            intensity,
+            shininess,
+            map: None,
+            sampler: Sampler::default(),
+        }
+    }
+    pub fn with_map(mut self, map: Texture) -> Self {
+        self.map = Some(map);
+        self
+    }
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+    fn highlight(
+        &self,
+        normal: &Vector3<f32>,
+        light_dir: &Vector3<f32>,
+        uv_transform: &UvTransform,
+        triangle: &[Vertex],
+        fragment: &Fragment,
+    ) -> f32 {
+        if normal.dot(light_dir) <= 0.0 {
+            return 0.0;
+        }
+        let Some(half) = (light_dir + fragment.view_dir).try_normalize(1e-6) else {
+            return 0.0;
+        };
+        let (intensity, gloss) = match self
+            .map
+            .as_ref()
+            .filter(|_| fragment.uv(triangle).is_some())
+            .and_then(|map| self.sampler.sample(map, uv_transform, triangle, fragment))
+        {
+            Some(texel) => (self.intensity * texel.r, texel.g),
+            None => (self.intensity, 1.0),
+        };
+        let exponent = 1.0 + (self.shininess - 1.0).max(0.0) * gloss;
+        intensity * normal.dot(&half).max(0.0).powf(exponent)
+    }
+}
+
+/// Per-pixel shader input. Fragments are rasterized in 2x2 quads so the
+/// screen-space derivatives of the barycentric weights are known, which lets
+/// shaders derive ddx/ddy of any interpolated attribute.
+#[derive(Copy, Clone, Debug)]
+pub struct Fragment {
+    /// Weights of the triangle corners, perspective correct unless the draw mode turned that
+    /// off, see `Command::set_perspective_correct`.
+    pub weights: Vector3<f32>,
+    pub ddx: Vector3<f32>,
+    pub ddy: Vector3<f32>,
+    /// Pixel center in normalized target coordinates, (0, 0) is the top left corner.
+    pub screen: Point2<f32>,
+    /// Seconds from `RenderTarget::set_time`.
+    pub time: f32,
+    /// Light from `RenderTarget::set_ambient`.
+    pub ambient: Color,
+    /// World space direction from the surface towards the camera, for specular highlights.
+    pub view_dir: Vector3<f32>,
+    /// Samplers read the nearest texel whatever their filter, see `QualityOverrides`.
+    pub nearest_sampling: bool,
+}
+#[allow(unused)]
+impl Fragment {
+    pub fn uv(&self, triangle: &[Vertex]) -> Option<Point2<f32>> {
+        calculate_uvs(triangle, &self.weights)
+    }
+    pub fn uv_ddx(&self, triangle: &[Vertex]) -> Option<Vector2<f32>> {
+        uv_derivative(triangle, &self.ddx)
+    }
+    pub fn uv_ddy(&self, triangle: &[Vertex]) -> Option<Vector2<f32>> {
+        uv_derivative(triangle, &self.ddy)
+    }
+}
+fn uv_derivative(triangle: &[Vertex], delta: &Vector3<f32>) -> Option<Vector2<f32>> {
+    let uv0 = triangle[0].uv?;
+    let uv1 = triangle[1].uv?;
+    let uv2 = triangle[2].uv?;
+    Some(uv0 * delta.x + uv1 * delta.y + uv2 * delta.z)
+}
+
+/// Box filtered checkerboard, returns the coverage of the second color in 0..=1.
+fn filtered_checker(p: &Point2<f32>, ddx: &Vector2<f32>, ddy: &Vector2<f32>) -> f32 {
+    let fract = |v: f32| v - v.floor();
+    let integral = |v: f32, w: f32| {
+        2.0 * ((fract((v - 0.5 * w) * 0.5) - 0.5).abs() - (fract((v + 0.5 * w) * 0.5) - 0.5).abs())
+            / w
+    };
+    let wx = ddx.x.abs() + ddy.x.abs() + 0.001;
+    let wy = ddx.y.abs() + ddy.y.abs() + 0.001;
+    0.5 - 0.5 * integral(p.x, wx) * integral(p.y, wy)
+}
+
+/// Adds the albedo lit by the fragment's ambient light.
+fn add_ambient(lit: Color, albedo: &Color, ambient: &Color) -> Color {
+    let bounce = albedo.modulate(ambient);
+    Color::new(lit.r + bounce.r, lit.g + bounce.g, lit.b + bounce.b, lit.a)
+}
+fn add_highlight(color: Color, highlight: f32) -> Color {
+    Color::new(
+        color.r + highlight,
+        color.g + highlight,
+        color.b + highlight,
+        color.a,
+    )
+}
+fn add_emission(color: Color, emission: &Color) -> Color {
+    Color::new(
+        color.r + emission.r,
+        color.g + emission.g,
+        color.b + emission.b,
+        color.a,
+    )
+}
+/// Shaders are shared by the slices of a frame and may be drawn from a dedicated render pool,
+/// so they have to be `Send` and `Sync`.
+pub trait Shader: Send + Sync {
+    fn shade(&self, triangle: &[Vertex], fragment: &Fragment) -> Color;
+    /// Fragments shaded with an alpha below the cutoff are discarded.
+    fn alpha_cutoff(&self) -> Option<f32> {
+        None
+    }
+    /// Blended shaders are composited over the target by their alpha without writing depth,
+    /// entities using one are drawn back to front after everything opaque.
+    fn blend(&self) -> bool {
+        false
+    }
+    /// Shaders whose output changes with `Fragment::time`, scenes using one are never
+    /// treated as static.
+    fn animated(&self) -> bool {
+        false
+    }
+    /// Direction towards the light, used for shadow rays when ray tracing.
+    fn light_dir(&self) -> Option<Vector3<f32>> {
+        None
+    }
+    /// Surface color without lighting, used by the path tracer which does its own.
+    fn albedo(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        self.shade(triangle, fragment)
+    }
+    /// Light the surface gives off by itself, a light source for the path tracer.
+    fn emission(&self, _triangle: &[Vertex], _fragment: &Fragment) -> Color {
+        Color::new(0.0, 0.0, 0.0, 1.0)
+    }
+    /// Back faces are drawn too, with flipped normals, instead of being culled.
+    fn double_sided(&self) -> bool {
+        false
+    }
+    /// Triangles are lit with their face normal instead of interpolated vertex normals.
+    fn flat_shading(&self) -> bool {
+        false
+    }
+}
+
+/// A shader with rasterizer options, e.g. double sided foliage cards or flat shaded low
+/// poly models.
+pub struct MaterialOptions<S> {
+    pub shader: S,
+    pub double_sided: bool,
+    pub flat_shading: bool,
+}
+#[allow(unused)]
+impl<S: Shader> MaterialOptions<S> {
+    pub fn new(shader: S) -> Self {
+        Self {
+            shader,
+            double_sided: false,
+            flat_shading: false,
+        }
+    }
+    pub fn double_sided(mut self) -> Self {
+        self.double_sided = true;
+        self
+    }
+    pub fn flat_shading(mut self) -> Self {
+        self.flat_shading = true;
+        self
+    }
+}
+impl<S: Shader> Shader for MaterialOptions<S> {
+    fn shade(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        self.shader.shade(triangle, fragment)
+    }
+    fn alpha_cutoff(&self) -> Option<f32> {
+        self.shader.alpha_cutoff()
+    }
+    fn blend(&self) -> bool {
+        self.shader.blend()
+    }
+    fn animated(&self) -> bool {
+        self.shader.animated()
+    }
+    fn light_dir(&self) -> Option<Vector3<f32>> {
+        self.shader.light_dir()
+    }
+    fn albedo(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        self.shader.albedo(triangle, fragment)
+    }
+    fn emission(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        self.shader.emission(triangle, fragment)
+    }
+    fn double_sided(&self) -> bool {
+        self.double_sided
+    }
+    fn flat_shading(&self) -> bool {
+        self.flat_shading
+    }
 }
 impl Shader for Material {
-    fn shade(&self, triangle: &[Vertex], weights: &Vector3<f32>) -> Color {
+    fn alpha_cutoff(&self) -> Option<f32> {
         match self {
-            Self::SolidColor(color) => color.clone(),
+            Self::Decal { alpha_cutoff, .. } => Some(*alpha_cutoff),
+            _ => None,
+        }
+    }
+    fn blend(&self) -> bool {
+        matches!(self, Self::Transparent { .. })
+    }
+    fn animated(&self) -> bool {
+        matches!(self, Self::Animated { .. })
+    }
+    fn light_dir(&self) -> Option<Vector3<f32>> {
+        match self {
+            Self::LitTexture { light_dir, .. }
+            | Self::LitSolid { light_dir, .. }
+            | Self::AmbientOcclusion { light_dir, .. } => Some(*light_dir),
+            _ => None,
+        }
+    }
+    fn emission(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        match self {
+            Self::LitTexture {
+                emissive: Some(emissive),
+                uv_transform,
+                ..
+            } => emissive.sample(uv_transform, triangle, fragment),
+            Self::LitSolid {
+                emissive: Some(emissive),
+                ..
+            } => emissive.sample(&UvTransform::default(), triangle, fragment),
+            _ => Color::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+    fn albedo(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        match self {
+            Self::LitTexture {
+                texture,
+                uv_transform,
+                sampler,
+                ..
+            } => sampler
+                .sample(texture, uv_transform, triangle, fragment)
+                .unwrap_or(Color::new(1.0, 1.0, 1.0, 1.0)),
+            Self::LitSolid { color, .. } => *color,
+            Self::AmbientOcclusion { color, .. } => {
+                let occlusion = match (triangle[0].color, triangle[1].color, triangle[2].color) {
+                    (Some(c1), Some(c2), Some(c3)) => c1.interpolate(&c2, &c3, &fragment.weights).r,
+                    _ => 1.0,
+                };
+                *color * occlusion
+            }
+            _ => self.shade(triangle, fragment),
+        }
+    }
+    fn shade(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        let weights = &fragment.weights;
+        match self {
+            Self::SolidColor(color) => *color,
             Self::VertexColors => match (triangle[0].color, triangle[1].color, triangle[2].color) {
-                (Some(c1), Some(c2), Some(c3)) => c1.interpolate(&c2, &c3, &weights),
+                (Some(c1), Some(c2), Some(c3)) => c1.interpolate(&c2, &c3, weights),
                 _ => Color::new(1.0, 1.0, 1.0, 1.0),
             },
-            Self::Textured { texture } => {
-                if let Some(uv) = calculate_uvs(&triangle, &weights) {
-                    if let Some(color) = texture.sample(&uv) {
+            Self::Textured {
+                texture,
+                uv_transform,
+                sampler,
+            } => {
+                if calculate_uvs(triangle, weights).is_some() {
+                    if let Some(color) = sampler.sample(texture, uv_transform, triangle, fragment) {
                         color
                     } else {
                         Color::new(1.0, 1.0, 1.0, 1.0)
@@ -224,78 +1793,603 @@ impl Shader for Material {
                     Color::new(1.0, 1.0, 1.0, 1.0)
                 }
             }
-            Self::LitTexture { texture, light_dir } => {
-                let uv = calculate_uvs(&triangle, &weights);
-                let mut color = if let Some(color) = texture.sample(&uv.unwrap_or(Point2::origin()))
+            Self::LitTexture {
+                texture,
+                light_dir,
+                uv_transform,
+                sampler,
+                emissive: _,
+                specular,
+            } => {
+                let mut color = if let Some(color) =
+                    sampler.sample(texture, uv_transform, triangle, fragment)
                 {
                     color
                 } else {
                     Color::new(1.0, 1.0, 1.0, 1.0)
                 };
-                if let Some(normal) = calculate_normals(&triangle, &weights) {
-                    color = color * Vector3::dot(&normal, &light_dir).max(0.01);
+                let albedo = color;
+                if let Some(normal) = calculate_normals(triangle, weights) {
+                    color = color * Vector3::dot(&normal, light_dir).max(0.01);
+                    if let Some(specular) = specular {
+                        color = add_highlight(
+                            color,
+                            specular.highlight(
+                                &normal,
+                                light_dir,
+                                uv_transform,
+                                triangle,
+                                fragment,
+                            ),
+                        );
+                    }
+                }
+                add_emission(
+                    add_ambient(color, &albedo, &fragment.ambient),
+                    &self.emission(triangle, fragment),
+                )
+            }
+            Self::LitSolid {
+                color,
+                light_dir,
+                specular,
+                ..
+            } => {
+                let albedo = *color;
+                let mut color = *color;
+                if let Some(normal) = calculate_normals(triangle, weights) {
+                    color = color * Vector3::dot(&normal, light_dir).max(0.01);
+                    if let Some(specular) = specular {
+                        color = add_highlight(
+                            color,
+                            specular.highlight(
+                                &normal,
+                                light_dir,
+                                &UvTransform::default(),
+                                triangle,
+                                fragment,
+                            ),
+                        );
+                    }
                 }
-                color
+                add_emission(
+                    add_ambient(color, &albedo, &fragment.ambient),
+                    &self.emission(triangle, fragment),
+                )
+            }
+            Self::Checkerboard {
+                color_a,
+                color_b,
+                scale,
+            } => {
+                let uv = fragment.uv(triangle).unwrap_or(Point2::origin()) * *scale;
+                let ddx = fragment.uv_ddx(triangle).unwrap_or(Vector2::zeros()) * *scale;
+                let ddy = fragment.uv_ddy(triangle).unwrap_or(Vector2::zeros()) * *scale;
+                color_a.lerp(color_b, filtered_checker(&uv, &ddx, &ddy))
             }
-            Self::LitSolid { color, light_dir } => {
-                let mut color = color.clone();
-                if let Some(normal) = calculate_normals(&triangle, &weights) {
-                    color = color * Vector3::dot(&normal, &light_dir).max(0.01);
+            Self::Decal {
+                texture,
+                alpha_cutoff: _,
+                uv_transform,
+                sampler,
+            } => sampler
+                .sample(texture, uv_transform, triangle, fragment)
+                .unwrap_or(Color::new(1.0, 1.0, 1.0, 0.0)),
+            Self::Mirror { texture, sampler } => {
+                // The reflection camera is a proper rotation, so its image is flipped horizontally
+                let uv = Point2::new(1.0 - fragment.screen.x, 1.0 - fragment.screen.y);
+                sampler
+                    .sample_fragment(texture, &uv, fragment, || {
+                        (Vector2::zeros(), Vector2::zeros())
+                    })
+                    .unwrap_or(Color::new(1.0, 1.0, 1.0, 1.0))
+            }
+            Self::Animated {
+                texture,
+                animation,
+                uv_transform,
+                sampler,
+            } => {
+                let uv = uv_transform.apply(&fragment.uv(triangle).unwrap_or(Point2::origin()));
+                let derivative = |delta: Option<Vector2<f32>>| {
+                    animation.apply_vector(
+                        &uv_transform.apply_vector(&delta.unwrap_or(Vector2::zeros())),
+                    )
+                };
+                sampler
+                    .sample_fragment(
+                        texture,
+                        &animation.apply(&uv, fragment.time),
+                        fragment,
+                        || {
+                            (
+                                derivative(fragment.uv_ddx(triangle)),
+                                derivative(fragment.uv_ddy(triangle)),
+                            )
+                        },
+                    )
+                    .unwrap_or(Color::new(1.0, 1.0, 1.0, 1.0))
+            }
+            Self::AmbientOcclusion {
+                color,
+                light_dir,
+                ambient,
+            } => {
+                let occlusion = match (triangle[0].color, triangle[1].color, triangle[2].color) {
+                    (Some(c1), Some(c2), Some(c3)) => c1.interpolate(&c2, &c3, weights).r,
+                    _ => 1.0,
+                };
+                let diffuse = calculate_normals(triangle, weights)
+                    .map_or(1.0, |normal| normal.dot(light_dir).max(0.0));
+                let lit = *color * (occlusion * (ambient + (1.0 - ambient) * diffuse)).max(0.01);
+                add_ambient(lit, &(*color * occlusion), &fragment.ambient)
+            }
+            Self::Transparent {
+                color,
+                texture,
+                sampler,
+            } => {
+                let sample = texture
+                    .as_ref()
+                    .filter(|_| fragment.uv(triangle).is_some())
+                    .and_then(|texture| {
+                        sampler.sample(texture, &UvTransform::default(), triangle, fragment)
+                    });
+                match sample {
+                    Some(sample) => Color::new(
+                        color.r * sample.r,
+                        color.g * sample.g,
+                        color.b * sample.b,
+                        color.a * sample.a,
+                    ),
+                    None => *color,
                 }
-                color
             }
         }
     }
 }
 
-pub fn clip_triangle(triangle: &[Vertex], camera: &Camera) -> Vec<Vertex> {
-    let clip0 = triangle[0].position.z < camera.near;
-    let clip1 = triangle[1].position.z < camera.near;
-    let clip2 = triangle[2].position.z < camera.near;
-    if !clip0 || !clip1 || !clip2 {return Vec::new();}
+/// Orders view space triangles farthest first, keeping their per triangle data alongside.
+fn sort_back_to_front<T: Copy>(vertices: &mut Vec<Vertex>, materials: &mut Vec<T>) {
+    let depth = |index: usize| {
+        vertices[index * 3..index * 3 + 3]
+            .iter()
+            .map(|v| v.position.z)
+            .sum::<f32>()
+    };
+    let mut order = (0..materials.len()).collect::<Vec<_>>();
+    // The camera looks down -z, the most negative depth is the farthest
+    order.sort_by(|a, b| depth(*a).total_cmp(&depth(*b)));
+    let sorted = order
+        .iter()
+        .flat_map(|index| vertices[index * 3..index * 3 + 3].iter().copied())
+        .collect();
+    *materials = order.iter().map(|index| materials[*index]).collect();
+    *vertices = sorted;
+}
 
-    triangle.to_vec()
+/// Pushes the triangles to rasterize in place of a clipped view space triangle, given the
+/// render time in seconds, see `Entity::with_geometry_hook`.
+pub type GeometryHook = fn(&[Vertex], f32, &mut Vec<Vertex>);
+
+/// Runs the geometry hook over clipped triangles, dropping any incomplete trailing output
+/// and clipping what it emits to the frustum again.
+fn amplify(hook: GeometryHook, triangles: &[Vertex], time: f32, frustum: &[Plane]) -> Vec<Vertex> {
+    let mut emitted = Vec::with_capacity(triangles.len());
+    for triangle in triangles.chunks_exact(3) {
+        hook(triangle, time, &mut emitted);
+    }
+    emitted.truncate(emitted.len() / 3 * 3);
+    emitted
+        .chunks_exact(3)
+        .flat_map(|triangle| clip_triangle(triangle, frustum))
+        .collect()
+}
+
+/// Clips a view space triangle to the view frustum, `Camera::view_frustum_planes`. Triangles
+/// inside come back as they are and ones entirely outside a plane are dropped, the rest are
+/// clipped Sutherland–Hodgman style and fanned into one or more triangles, with UVs, normals
+/// and colors interpolated at the new vertices.
+pub fn clip_triangle(triangle: &[Vertex], frustum: &[Plane]) -> Vec<Vertex> {
+    let mut crossed = Vec::new();
+    for plane in frustum {
+        let inside = triangle
+            .iter()
+            .filter(|vertex| plane.signed_distance(&vertex.position.xyz()) >= 0.0)
+            .count();
+        match inside {
+            0 => return Vec::new(),
+            3 => {}
+            _ => crossed.push(*plane),
+        }
+    }
+    if crossed.is_empty() {
+        return triangle.to_vec();
+    }
+    clip_to_planes(triangle.to_vec(), &crossed)
+}
+
+/// Clips a view space triangle against every plane, fanning the remaining polygon.
+fn clip_to_planes(triangle: Vec<Vertex>, planes: &[Plane]) -> Vec<Vertex> {
+    let mut polygon = triangle;
+    for plane in planes {
+        polygon = clip_polygon(&polygon, |v| plane.signed_distance(&v.position.xyz()));
+        if polygon.len() < 3 {
+            return Vec::new();
+        }
+    }
+    let mut triangles = Vec::with_capacity((polygon.len() - 2) * 3);
+    for i in 1..polygon.len() - 1 {
+        triangles.extend([polygon[0], polygon[i], polygon[i + 1]]);
+    }
+    triangles
+}
+
+/// Replaces the vertex normals of a triangle with its face normal.
+pub(crate) fn set_face_normal(triangle: &mut [Vertex]) {
+    let (a, b, c) = (
+        triangle[0].position.xyz(),
+        triangle[1].position.xyz(),
+        triangle[2].position.xyz(),
+    );
+    if let Some(normal) = (b - a).cross(&(c - a)).try_normalize(1e-12) {
+        for vertex in triangle {
+            vertex.normal = Some(normal);
+        }
+    }
+}
+/// Drops screen space triangles the rasterizer can't handle: positions that aren't finite,
+/// e.g. from w near 0 in the perspective divide, and triangles without area. Attributes that
+/// aren't finite are removed so they can't turn whole fragments into NaN, UVs out of
+/// `MAX_UV` are clamped to it.
+fn sanitize_triangles(vertices: &mut Vec<Vertex>, materials: &mut Vec<(usize, u32)>) {
+    let mut kept = 0;
+    for index in 0..materials.len() {
+        let triangle = &mut vertices[index * 3..index * 3 + 3];
+        let finite = triangle
+            .iter()
+            .all(|vertex| vertex.position.iter().all(|value| value.is_finite()));
+        let area = signed_area(
+            &triangle[0].position.xy(),
+            &triangle[1].position.xy(),
+            &triangle[2].position.xy(),
+        );
+        if !finite || area.abs() < 1e-6 {
+            continue;
+        }
+        for vertex in triangle.iter_mut() {
+            vertex.normal = vertex
+                .normal
+                .filter(|normal| normal.iter().all(|value| value.is_finite()));
+            vertex.uv = vertex
+                .uv
+                .filter(|uv| uv.iter().all(|value| value.is_finite()))
+                .map(|uv| uv.map(|value| value.clamp(-MAX_UV, MAX_UV)));
+            vertex.color = vertex.color.filter(|color| {
+                [color.r, color.g, color.b, color.a]
+                    .iter()
+                    .all(|value| value.is_finite())
+            });
+        }
+        vertices.copy_within(index * 3..index * 3 + 3, kept * 3);
+        materials[kept] = materials[index];
+        kept += 1;
+    }
+    vertices.truncate(kept * 3);
+    materials.truncate(kept);
+}
+/// Splits view space triangles into `tessellation` squared smaller ones and takes their
+/// corners to screen space through the target's projection. Triangles with a corner the
+/// projection can't show are dropped, the finer the tessellation the tighter the edge.
+fn project_nonlinear(
+    target: &RenderTarget,
+    vertices: &[Vertex],
+    materials: &[(usize, u32)],
+    camera: &Camera,
+) -> (Vec<Vertex>, Vec<(usize, u32)>) {
+    let n = target.tessellation.max(1) as usize;
+    let project = |vertex: &Vertex| {
+        let point = vertex.position.xyz();
+        let distance = point.coords.magnitude();
+        if distance < camera.near || distance > camera.far {
+            return None;
+        }
+        let ndc = target
+            .projection
+            .project(&point, camera.fov, camera.aspect_ratio)?;
+        // Same depth curve as the perspective matrix, by distance instead of z
+        let (near, far) = (camera.near, camera.far);
+        let depth = (far + near) / (far - near) - 2.0 * far * near / ((far - near) * distance);
+        let mut projected = *vertex;
+        projected.position = Point4::new(ndc.x, ndc.y, depth, 1.0);
+        projected.ndc_to_screen_mut((target.width, target.height));
+        Some(projected)
+    };
+    let mut projected = Vec::with_capacity(vertices.len() * n * n);
+    let mut projected_materials = Vec::with_capacity(materials.len() * n * n);
+    for (triangle, material) in vertices.chunks_exact(3).zip(materials) {
+        // Grid point i steps along a to b, j along a to c
+        let grid = |i: usize, j: usize| {
+            let (u, v) = (i as f32 / n as f32, j as f32 / n as f32);
+            let ab = if v < 1.0 {
+                triangle[0].lerp(&triangle[1], u / (1.0 - v))
+            } else {
+                triangle[0]
+            };
+            ab.lerp(&triangle[2], v)
+        };
+        let mut corners = vec![];
+        for i in 0..n {
+            for j in 0..n - i {
+                corners.push([(i, j), (i + 1, j), (i, j + 1)]);
+                if i + j + 1 < n {
+                    corners.push([(i + 1, j), (i + 1, j + 1), (i, j + 1)]);
+                }
+            }
+        }
+        for corner in corners {
+            let Some(sub) = corner
+                .into_iter()
+                .map(|(i, j)| project(&grid(i, j)))
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            projected.extend(sub);
+            projected_materials.push(*material);
+        }
+    }
+    (projected, projected_materials)
+}
+/// Screen space triangles with this winding are rejected by the edge tests in `draw_triangle`.
+fn is_back_facing(triangle: &[Vertex]) -> bool {
+    let a = triangle[2].position.xy();
+    let b = triangle[1].position.xy();
+    let c = triangle[0].position.xy();
+    edge_cross(&a, &b, &c) < 0.0
 }
 
 pub fn draw_buffer(target: &mut RenderTarget, entity: &Entity, camera: &Camera, mode: &DrawMode) {
     target.vertex_buffer.clear();
-    let mv_mat =
-        camera.get_view_matrix() * entity.position.to_homogeneous() * entity.scale.to_homogeneous();
+    let mut local = entity.model_matrix();
+    local.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+    let normal_mat = entity.normal_matrix();
+    let mv_mat = if target.camera_relative {
+        let relative = entity.world_translation() - camera.world_position();
+        camera.get_rotation_matrix() * Matrix4::new_translation(&relative.cast::<f32>()) * local
+    } else {
+        let translation = entity.world_translation().cast::<f32>();
+        camera.get_view_matrix() * Matrix4::new_translation(&translation) * local
+    };
     let p_mat = camera.get_perspective_matrix();
 
+    let model = entity.active_model();
     let vertices = &mut target.vertex_buffer;
-    vertices.extend_from_slice(entity.model.vertices.as_slice());
-    for vertex in vertices.iter_mut() {vertex.model_to_view_mut(&mv_mat);}
-    let mut vertices = vertices.chunks_mut(3).flat_map(|v| clip_triangle(v, &camera)).collect::<Vec<_>>();
+    vertices.extend_from_slice(model.vertices.as_slice());
+    if let Some(displacement) = &entity.displacement {
+        for vertex in vertices.iter_mut() {
+            let (Some(uv), Some(normal)) = (vertex.uv, vertex.normal) else {
+                continue;
+            };
+            if let Some(height) = displacement.texture.sample(&Point2::from(uv)) {
+                let offset = normal * (height.r * displacement.scale);
+                vertex.position.x += offset.x;
+                vertex.position.y += offset.y;
+                vertex.position.z += offset.z;
+            }
+        }
+    }
+    if let Some(water) = &entity.water {
+        for vertex in vertices.iter_mut() {
+            water.displace(vertex, target.time);
+        }
+    }
+    if let Some(modifier) = entity.vertex_modifier {
+        for vertex in vertices.iter_mut() {
+            modifier(vertex, target.time);
+        }
+    }
+    // Welded models run the vertex stage once per shared vertex, then expand to triangles
     for vertex in vertices.iter_mut() {
-        vertex.view_to_clip_mut(&p_mat).clip_to_ndc_mut()
-        .ndc_to_screen_mut((target.width, target.height))
-        .update_normal_mut(&entity.position);
+        vertex.model_to_view_mut(&mv_mat);
+    }
+    if !model.indices.is_empty() {
+        target.vertex_buffer = model
+            .indices
+            .iter()
+            .map(|index| target.vertex_buffer[*index as usize])
+            .collect();
+    }
+    // Face normals come from view space positions, taken back to model space so they go
+    // through the normal matrix like every other normal
+    let to_model = mv_mat.fixed_view::<3, 3>(0, 0).transpose()
+        * local.fixed_view::<3, 3>(0, 0).determinant().signum();
+    for (index, triangle) in target.vertex_buffer.chunks_exact_mut(3).enumerate() {
+        let material = model.material_index(index * 3);
+        if entity.material(material).flat_shading() {
+            set_face_normal(triangle);
+            for vertex in triangle {
+                vertex.normal = vertex.normal.map(|normal| to_model * normal);
+            }
+        }
+    }
+    let view_mat = camera.get_view_matrix();
+    let planes = target
+        .clip_planes
+        .iter()
+        .map(|plane| plane.transformed(&view_mat))
+        .collect::<Vec<_>>();
+    // Non-linear projections drop what they can't show per vertex instead
+    let frustum = if target.projection.is_linear() {
+        camera.view_frustum_planes().to_vec()
+    } else {
+        Vec::new()
+    };
+    let mut vertices = Vec::with_capacity(target.vertex_buffer.len());
+    let mut materials = Vec::with_capacity(target.vertex_buffer.len() / 3);
+    for (index, triangle) in target.vertex_buffer.chunks_mut(3).enumerate() {
+        let mut clipped = clip_triangle(triangle, &frustum);
+        if !planes.is_empty() && !clipped.is_empty() {
+            clipped = clip_to_planes(clipped, &planes);
+        }
+        if let Some(hook) = entity.geometry_hook
+            && !clipped.is_empty()
+        {
+            clipped = amplify(hook, &clipped, target.time, &frustum);
+        }
+        let material = model.material_index(index * 3);
+        materials.extend(std::iter::repeat_n(
+            (material, index as u32),
+            clipped.len() / 3,
+        ));
+        vertices.extend(clipped);
     }
+    if entity.sort_triangles && !target.oit_enabled() {
+        sort_back_to_front(&mut vertices, &mut materials);
+    }
+    if target.projection.is_linear() {
+        for vertex in vertices.iter_mut() {
+            // Screen vertices keep 1 / w of their clip position for `perspective_weights`
+            let clip_w = p_mat.row(3).transpose().dot(&vertex.position.coords);
+            vertex
+                .view_to_clip_mut(&p_mat)
+                .clip_to_ndc_mut()
+                .ndc_to_screen_mut((target.width, target.height))
+                .update_normal_mut(&normal_mat);
+            vertex.position.w = if clip_w.abs() > EPSILON {
+                1.0 / clip_w
+            } else {
+                1.0
+            };
+        }
+    } else {
+        (vertices, materials) = project_nonlinear(target, &vertices, &materials, camera);
+        for vertex in vertices.iter_mut() {
+            vertex.update_normal_mut(&normal_mat);
+        }
+    }
+
+    sanitize_triangles(&mut vertices, &mut materials);
 
-    let color = Color::new(1.0, 1.0, 1.0, 1.0).as_u32();
-    let size = 2.0;
-    target.create_slices().par_iter_mut().for_each(|slice| {
-        for triangle in vertices.as_slice().chunks_exact(3) {
+    let style = entity.wire_style.unwrap_or(mode.wire_style);
+    let (wire_color, point_color) = (style.wire_color.as_u32(), style.point_color.as_u32());
+    let size = style.point_size;
+    let cap: Option<Box<dyn Shader>> = match target.clip_cap {
+        Some(cap) if !target.clip_planes.is_empty() => Some(Box::new(Material::SolidColor(cap))),
+        _ => None,
+    };
+    let inverse_vp = (p_mat * view_mat)
+        .try_inverse()
+        .unwrap_or(Matrix4::identity());
+    // Eye rays are linear in NDC x and y, so three of them give every pixel's
+    let eye_ray = |x: f32, y: f32| {
+        inverse_vp.transform_point(&Point3::new(x, y, 1.0))
+            - inverse_vp.transform_point(&Point3::new(x, y, -1.0))
+    };
+    let center = eye_ray(0.0, 0.0);
+    let eye_rays = [
+        center,
+        eye_ray(1.0, 0.0) - center,
+        eye_ray(0.0, 1.0) - center,
+    ];
+    let mut bins = std::mem::take(&mut target.bins);
+    let pad = if mode.points { size.ceil() + 1.0 } else { 1.0 };
+    if !mode.reference {
+        bins.fill(&target.tile_grid(), &vertices, pad);
+    }
+    let every_triangle: Vec<u32> = if mode.reference || bins.any_overflowed() {
+        (0..(vertices.len() / 3) as u32).collect()
+    } else {
+        Vec::new()
+    };
+    // Tiles no triangle touches aren't split off or scheduled at all
+    let mut slices = target.create_tiles(|tile| {
+        mode.reference || bins.queue(tile).is_none_or(|queue| !queue.is_empty())
+    });
+    for slice in &mut slices {
+        slice.inverse_vp = inverse_vp;
+        slice.eye_rays = eye_rays;
+        slice.filler = mode.filler;
+        slice.collapse_subpixel = mode.collapse_subpixel;
+        slice.reference = mode.reference;
+        slice.perspective_correct = mode.perspective_correct;
+    }
+    slices.par_iter_mut().for_each(|slice| {
+        let queue = match bins.queue(slice.tile) {
+            Some(queue) if !mode.reference => queue,
+            _ => every_triangle.as_slice(),
+        };
+        for index in queue {
+            let index = *index as usize;
+            let (triangle, (material, source)) =
+                (&vertices[index * 3..index * 3 + 3], &materials[index]);
+            slice.triangle_id = *source;
             if mode.shaded {
-                draw_triangle(slice, triangle, &entity.shader);
+                let shader = entity.material(*material);
+                draw_triangle(slice, triangle, shader);
+                if shader.double_sided() && is_back_facing(triangle) {
+                    let flipped = [triangle[0], triangle[2], triangle[1]].map(|mut vertex| {
+                        vertex.normal = vertex.normal.map(|normal| -normal);
+                        vertex
+                    });
+                    draw_triangle(slice, &flipped, shader);
+                } else if let Some(cap) = cap.as_deref()
+                    && is_back_facing(triangle)
+                {
+                    draw_triangle(slice, &[triangle[0], triangle[2], triangle[1]], cap);
+                }
             }
+            let cue = [0, 1, 2].map(|i| {
+                mode.depth_cue.map_or(1.0, |cue| {
+                    cue.brightness(screen_distance(&triangle[i], camera))
+                })
+            });
             if mode.wireframe {
-                draw_line(slice, &triangle[0], &triangle[1], color);
-                draw_line(slice, &triangle[1], &triangle[2], color);
-                draw_line(slice, &triangle[2], &triangle[0], color);
+                draw_line(
+                    slice,
+                    &triangle[0],
+                    &triangle[1],
+                    wire_color,
+                    [cue[0], cue[1]],
+                );
+                draw_line(
+                    slice,
+                    &triangle[1],
+                    &triangle[2],
+                    wire_color,
+                    [cue[1], cue[2]],
+                );
+                draw_line(
+                    slice,
+                    &triangle[2],
+                    &triangle[0],
+                    wire_color,
+                    [cue[2], cue[0]],
+                );
             }
             if mode.points {
-                draw_point(slice, &triangle[0], size, color);
-                draw_point(slice, &triangle[1], size, color);
-                draw_point(slice, &triangle[2], size, color);
+                draw_point(slice, &triangle[0], size, dim(point_color, cue[0]));
+                draw_point(slice, &triangle[1], size, dim(point_color, cue[1]));
+                draw_point(slice, &triangle[2], size, dim(point_color, cue[2]));
             }
         }
     });
+    drop(slices);
+    target.bins = bins;
 }
 
-fn draw_triangle(slice: &mut RenderSlice, triangle: &[Vertex], shader: &Box<dyn Shader>) {
+fn draw_triangle(slice: &mut RenderSlice, triangle: &[Vertex], shader: &dyn Shader) {
     let bounds = Bounds::new(triangle, (slice.width, slice.height));
+    if slice.reference {
+        draw_reference_triangle(slice, triangle, shader, &bounds);
+        return;
+    }
+    if bounds.max_x - bounds.min_x <= SMALL_TRIANGLE
+        && bounds.max_y - bounds.min_y <= SMALL_TRIANGLE
+    {
+        draw_small_triangle(slice, triangle, shader, &bounds);
+        return;
+    }
 
     // Extract 2D positions of vertices
     let v0 = triangle[2].position.xy();
@@ -310,9 +2404,9 @@ fn draw_triangle(slice: &mut RenderSlice, triangle: &[Vertex], shader: &Box<dyn
     let delta_x_2 = -(v0.y - v2.y); // Edge v2 to v0
     let delta_y_2 = v0.x - v2.x;
 
-    // Starting pixel coordinates
-    let x_start = bounds.min_x as u32;
-    let y_start = bounds.min_y as u32;
+    // Starting pixel coordinates, aligned to even pixels so quads line up across triangles
+    let x_start = bounds.min_x as u32 & !1;
+    let y_start = bounds.min_y as u32 & !1;
     let p_start = Point2::new(x_start as f32 + 0.5, y_start as f32 + 0.5);
 
     // Initial edge function values at starting pixel center
@@ -320,50 +2414,427 @@ fn draw_triangle(slice: &mut RenderSlice, triangle: &[Vertex], shader: &Box<dyn
     let e1_start = edge_cross(&v1, &v2, &p_start);
     let e2_start = edge_cross(&v2, &v0, &p_start);
 
-    // Base edge values, updated per row
-    let mut e0 = e0_start;
-    let mut e1 = e1_start;
-    let mut e2 = e2_start;
+    let setup = FragmentSetup::new(slice, triangle, shader);
 
-    for y in bounds.y_range() {
-        if y < slice.start || y >= slice.end {
-            e0 += delta_y_0;
-            e1 += delta_y_1;
-            e2 += delta_y_2;
+    // Pixel offsets inside a quad: top left, top right, bottom left, bottom right
+    const QUAD: [(u32, u32); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+    for y in (y_start..=bounds.max_y as u32).step_by(2) {
+        if y + 1 < slice.start || y >= slice.end {
             continue;
         }
+        // Edge values at the row start, taken from the start pixel rather than summed row
+        // by row, so rounding doesn't drift away from `draw_reference_triangle`
+        let rows = (y - y_start) as f32;
+        let e0 = e0_start + rows * delta_y_0;
+        let e1 = e1_start + rows * delta_y_1;
+        let e2 = e2_start + rows * delta_y_2;
 
-        // Edge values for the current row
-        let mut e0_row = e0;
-        let mut e1_row = e1;
-        let mut e2_row = e2;
-
-        for x in bounds.x_range() {
-            if e0_row >= 0.0 && e1_row >= 0.0 && e2_row >= 0.0 {
-                let p = Point2::new(x as f32 + 0.5, y as f32 + 0.5);
-                let weights = triangle_barycentric(triangle, &p);
-                let depth = calculate_depths(triangle, &weights);
-                let idx = ((y - slice.start) * slice.width + x) as usize;
-                if idx < slice.color_slice.len() && depth < slice.depth_slice[idx] {
-                    let texture_color = shader.shade(triangle, &weights);
-                    slice.color_slice[idx] = texture_color.as_u32();
-                    slice.depth_slice[idx] = depth;
+        let x_end = bounds.max_x as u32;
+        let (x_first, x_last) = match slice.filler {
+            TriangleFiller::BoundingBox => (x_start, x_end),
+            TriangleFiller::Spans => {
+                let edges = [e0, e1, e2];
+                let (delta_x, delta_y) = (
+                    [delta_x_0, delta_x_1, delta_x_2],
+                    [delta_y_0, delta_y_1, delta_y_2],
+                );
+                match quad_row_span(
+                    edges,
+                    delta_x,
+                    delta_y,
+                    x_end.saturating_sub(x_start) as f32,
+                ) {
+                    Some((first, last)) => (x_start + (first & !1), x_start + last),
+                    None => continue,
+                }
+            }
+        };
+        // Only the quads of this tile, its left edge is even so quads stay aligned
+        let (x_first, x_last) = (x_first.max(slice.left), x_last.min(slice.right - 1));
+        if x_first > x_last {
+            continue;
+        }
+
+        for x in (x_first..=x_last).step_by(2) {
+            // Edge values for the quad, from the row start for the same reason
+            let columns = (x - x_start) as f32;
+            let e0_row = e0 + columns * delta_x_0;
+            let e1_row = e1 + columns * delta_x_1;
+            let e2_row = e2 + columns * delta_x_2;
+            let covered = QUAD.map(|(dx, dy)| {
+                let (dx, dy) = (dx as f32, dy as f32);
+                e0_row + dx * delta_x_0 + dy * delta_y_0 >= 0.0
+                    && e1_row + dx * delta_x_1 + dy * delta_y_1 >= 0.0
+                    && e2_row + dx * delta_x_2 + dy * delta_y_2 >= 0.0
+            });
+
+            if covered.iter().any(|c| *c) {
+                // Uncovered pixels still get weights, they act as helpers for the derivatives
+                let weights = QUAD.map(|(dx, dy)| {
+                    let p = Point2::new((x + dx) as f32 + 0.5, (y + dy) as f32 + 0.5);
+                    triangle_barycentric(triangle, &p)
+                });
+                let ddx = weights[1] - weights[0];
+                let ddy = weights[2] - weights[0];
+
+                for (i, (dx, dy)) in QUAD.iter().enumerate() {
+                    let (px, py) = (x + dx, y + dy);
+                    if !covered[i] || !slice.contains(px, py) || !slice.renders(px, py) {
+                        continue;
+                    }
+                    shade_fragment(slice, triangle, &setup, (px, py), weights[i], ddx, ddy);
                 }
             }
-            // Increment edge values for next x
-            e0_row += delta_x_0;
-            e1_row += delta_x_1;
-            e2_row += delta_x_2;
         }
-        // Increment edge values for next y
-        e0 += delta_y_0;
-        e1 += delta_y_1;
-        e2 += delta_y_2;
     }
 }
-fn draw_line(slice: &mut RenderSlice, p1: &Vertex, p2: &Vertex, color: u32) {
+/// Fast path for triangles of a few pixels, common for dense meshes in the distance. Tests
+/// the pixel centers in the bounds directly instead of walking quads, the weights are affine
+/// on screen so one pair of derivatives serves every pixel. A triangle covering no pixel
+/// center at all is drawn as a point sample at its centroid with `DrawMode::collapse_subpixel`,
+/// so far away meshes don't break up into holes.
+fn draw_small_triangle(
+    slice: &mut RenderSlice,
+    triangle: &[Vertex],
+    shader: &dyn Shader,
+    bounds: &Bounds,
+) {
+    let (v0, v1, v2) = (
+        triangle[2].position.xy(),
+        triangle[1].position.xy(),
+        triangle[0].position.xy(),
+    );
+    let inside = |p: &Point2<f32>| {
+        edge_cross(&v0, &v1, p) >= 0.0
+            && edge_cross(&v1, &v2, p) >= 0.0
+            && edge_cross(&v2, &v0, p) >= 0.0
+    };
+    let origin = Point2::new(bounds.min_x.floor() + 0.5, bounds.min_y.floor() + 0.5);
+    let weights = triangle_barycentric(triangle, &origin);
+    let ddx = triangle_barycentric(triangle, &(origin + Vector2::x())) - weights;
+    let ddy = triangle_barycentric(triangle, &(origin + Vector2::y())) - weights;
+    let mut setup = None;
+    let mut covers_any = false;
+    let (width, height) = (slice.width, slice.height);
+    // Coverage is tested outside the tile as well, so tiles agree on whether to collapse
+    for py in bounds.y_range().filter(|py| *py < height) {
+        for px in bounds.x_range().filter(|px| *px < width) {
+            let p = Point2::new(px as f32 + 0.5, py as f32 + 0.5);
+            if !inside(&p) {
+                continue;
+            }
+            covers_any = true;
+            if !slice.contains(px, py) || !slice.renders(px, py) {
+                continue;
+            }
+            let setup = setup.get_or_insert_with(|| FragmentSetup::new(slice, triangle, shader));
+            shade_fragment(
+                slice,
+                triangle,
+                setup,
+                (px, py),
+                triangle_barycentric(triangle, &p),
+                ddx,
+                ddy,
+            );
+        }
+    }
+    if covers_any || !slice.collapse_subpixel {
+        return;
+    }
+    let centroid = (v0 + v1.coords + v2.coords) / 3.0;
+    let (x, y) = (centroid.x, centroid.y);
+    if x < 0.0 || y < 0.0 {
+        return;
+    }
+    let (px, py) = (x as u32, y as u32);
+    if slice.contains(px, py) && slice.renders(px, py) {
+        let setup = FragmentSetup::new(slice, triangle, shader);
+        shade_fragment(
+            slice,
+            triangle,
+            &setup,
+            (px, py),
+            Vector3::repeat(1.0 / 3.0),
+            ddx,
+            ddy,
+        );
+    }
+}
+/// The plain scalar rasterizer the optimized paths have to agree with: every pixel center in
+/// the bounds is tested against the three edges from scratch and shaded with its own
+/// derivatives. No quads, spans or small triangle shortcuts, and sub-pixel triangles are
+/// never collapsed.
+fn draw_reference_triangle(
+    slice: &mut RenderSlice,
+    triangle: &[Vertex],
+    shader: &dyn Shader,
+    bounds: &Bounds,
+) {
+    let (v0, v1, v2) = (
+        triangle[2].position.xy(),
+        triangle[1].position.xy(),
+        triangle[0].position.xy(),
+    );
+    let setup = FragmentSetup::new(slice, triangle, shader);
+    let (start, end, left, right) = (slice.start, slice.end, slice.left, slice.right);
+    for py in bounds.y_range().filter(|py| *py >= start && *py < end) {
+        for px in bounds.x_range().filter(|px| *px >= left && *px < right) {
+            let p = Point2::new(px as f32 + 0.5, py as f32 + 0.5);
+            let inside = edge_cross(&v0, &v1, &p) >= 0.0
+                && edge_cross(&v1, &v2, &p) >= 0.0
+                && edge_cross(&v2, &v0, &p) >= 0.0;
+            if !inside || !slice.renders(px, py) {
+                continue;
+            }
+            let weights = triangle_barycentric(triangle, &p);
+            let ddx = triangle_barycentric(triangle, &(p + Vector2::x())) - weights;
+            let ddy = triangle_barycentric(triangle, &(p + Vector2::y())) - weights;
+            shade_fragment(slice, triangle, &setup, (px, py), weights, ddx, ddy);
+        }
+    }
+}
+/// Per triangle shader state shared by its fragments.
+struct FragmentSetup<'a> {
+    shader: &'a dyn Shader,
+    alpha_cutoff: Option<f32>,
+    blend: bool,
+    light_dir: Option<Vector3<f32>>,
+    corner_lighting: Option<[Vector3<f32>; 3]>,
+}
+impl<'a> FragmentSetup<'a> {
+    fn new(slice: &RenderSlice, triangle: &[Vertex], shader: &'a dyn Shader) -> Self {
+        let blend = shader.blend();
+        Self {
+            shader,
+            alpha_cutoff: shader.alpha_cutoff(),
+            blend,
+            light_dir: shader
+                .light_dir()
+                .filter(|_| slice.shadow_casters.is_some()),
+            corner_lighting: (slice.quality.vertex_lighting && !blend)
+                .then(|| corner_lighting(slice, triangle, shader)),
+        }
+    }
+}
+
+/// Turns screen space weights into ones that interpolate attributes linearly in view space:
+/// each corner's weight is divided by its w and the results are normalized, the same as
+/// interpolating attribute / w and 1 / w and dividing at the pixel. Screen vertices hold 1 / w
+/// in `position.w`, which stays 1 where there is no perspective.
+fn perspective_weights(triangle: &[Vertex], weights: &Vector3<f32>) -> Vector3<f32> {
+    let scaled = Vector3::new(
+        weights.x * triangle[0].position.w,
+        weights.y * triangle[1].position.w,
+        weights.z * triangle[2].position.w,
+    );
+    let sum = scaled.sum();
+    if sum.abs() > EPSILON && sum.is_finite() {
+        scaled / sum
+    } else {
+        *weights
+    }
+}
+
+/// Depth tests, shades and writes one covered pixel of `triangle`. `weights` and the
+/// derivatives are in screen space, the shader sees them perspective corrected unless the draw
+/// mode turned that off.
+fn shade_fragment(
+    slice: &mut RenderSlice,
+    triangle: &[Vertex],
+    setup: &FragmentSetup,
+    (px, py): (u32, u32),
+    weights: Vector3<f32>,
+    ddx: Vector3<f32>,
+    ddy: Vector3<f32>,
+) {
+    let depth = calculate_depths(triangle, &weights);
+    let (weights, ddx, ddy) = if slice.perspective_correct {
+        let corrected = perspective_weights(triangle, &weights);
+        (
+            corrected,
+            perspective_weights(triangle, &(weights + ddx)) - corrected,
+            perspective_weights(triangle, &(weights + ddy)) - corrected,
+        )
+    } else {
+        (weights, ddx, ddy)
+    };
+    let fragment = Fragment {
+        weights,
+        ddx,
+        ddy,
+        screen: Point2::new(
+            (px as f32 + 0.5) / slice.width as f32,
+            (py as f32 + 0.5) / slice.height as f32,
+        ),
+        time: slice.time,
+        ambient: slice.ambient,
+        view_dir: slice.view_dir(px, py),
+        nearest_sampling: slice.quality.nearest_sampling,
+    };
+    let Some(idx) = slice.local(px, py) else {
+        return;
+    };
+    if depth.is_finite() && depth < slice.depth_slice[idx] {
+        let mut texture_color = match &setup.corner_lighting {
+            Some(lighting) => {
+                let light = lighting[0] * fragment.weights.x
+                    + lighting[1] * fragment.weights.y
+                    + lighting[2] * fragment.weights.z;
+                let albedo = setup.shader.albedo(triangle, &fragment);
+                Color::new(
+                    albedo.r * light.x,
+                    albedo.g * light.y,
+                    albedo.b * light.z,
+                    albedo.a,
+                )
+            }
+            None => setup.shader.shade(triangle, &fragment),
+        };
+        if setup
+            .alpha_cutoff
+            .is_some_and(|cutoff| texture_color.a < cutoff)
+        {
+            return;
+        }
+        if let Some(light_dir) = &setup.light_dir
+            && in_traced_shadow(slice, triangle, &fragment, depth, light_dir)
+        {
+            texture_color = texture_color * SHADOW_FACTOR;
+        }
+        if setup.blend && !slice.accumulation_slice.is_empty() {
+            // Nearer fragments weigh more, depth is NDC z in -1..1
+            let alpha = texture_color.a;
+            let weight = alpha * (3e3 * (0.5 - depth * 0.5).powi(3)).clamp(1e-2, 3e3);
+            let sum = &mut slice.accumulation_slice[idx];
+            sum[0] += texture_color.r * alpha * weight;
+            sum[1] += texture_color.g * alpha * weight;
+            sum[2] += texture_color.b * alpha * weight;
+            sum[3] += alpha * weight;
+            slice.revealage_slice[idx] *= 1.0 - alpha;
+        } else if setup.blend {
+            let dst = Color::from_u32(slice.color_slice[idx]);
+            slice.color_slice[idx] = dst.lerp(&texture_color, texture_color.a).as_u32();
+        } else {
+            slice.color_slice[idx] = texture_color.as_u32();
+            slice.depth_slice[idx] = depth;
+            if let Some(id) = slice.id_slice.get_mut(idx) {
+                *id = slice.entity_id;
+                slice.triangle_id_slice[idx] = slice.triangle_id;
+            }
+        }
+    }
+}
+/// Offsets from the first pixel of a quad row, first to last, of the pixel centers inside all
+/// three edges on either of its scanlines. `edges` are the edge functions at the first pixel,
+/// the span is padded by a pixel so rounding never loses coverage, which is still tested per
+/// pixel.
+fn quad_row_span(
+    edges: [f32; 3],
+    delta_x: [f32; 3],
+    delta_y: [f32; 3],
+    max_offset: f32,
+) -> Option<(u32, u32)> {
+    let (mut first, mut last) = (f32::MAX, f32::MIN);
+    for dy in [0.0, 1.0] {
+        let (mut low, mut high) = (0.0f32, max_offset);
+        for edge in 0..3 {
+            let value = edges[edge] + dy * delta_y[edge];
+            let slope = delta_x[edge];
+            if slope > 0.0 {
+                low = low.max(-value / slope);
+            } else if slope < 0.0 {
+                high = high.min(-value / slope);
+            } else if value < 0.0 {
+                low = f32::MAX;
+            }
+        }
+        if low <= high {
+            first = first.min(low);
+            last = last.max(high);
+        }
+    }
+    (first <= last).then(|| {
+        let first = (first.floor() - 1.0).max(0.0) as u32;
+        let last = (last.ceil() + 1.0).min(max_offset) as u32;
+        (first, last)
+    })
+}
+/// Lighting at each corner of a screen space triangle as the ratio of the shaded color to the
+/// albedo, for `QualityOverrides::vertex_lighting`.
+fn corner_lighting(
+    slice: &RenderSlice,
+    triangle: &[Vertex],
+    shader: &dyn Shader,
+) -> [Vector3<f32>; 3] {
+    std::array::from_fn(|corner| {
+        let (x, y) = (triangle[corner].position.x, triangle[corner].position.y);
+        let (px, py) = (
+            x.clamp(0.0, slice.width as f32 - 1.0) as u32,
+            y.clamp(0.0, slice.height as f32 - 1.0) as u32,
+        );
+        let fragment = Fragment {
+            weights: Vector3::ith(corner, 1.0),
+            ddx: Vector3::zeros(),
+            ddy: Vector3::zeros(),
+            screen: Point2::new(x / slice.width as f32, y / slice.height as f32),
+            time: slice.time,
+            ambient: slice.ambient,
+            view_dir: slice.view_dir(px, py),
+            nearest_sampling: slice.quality.nearest_sampling,
+        };
+        let (lit, albedo) = (
+            shader.shade(triangle, &fragment),
+            shader.albedo(triangle, &fragment),
+        );
+        let ratio = |lit: f32, albedo: f32| if albedo > 1e-4 { lit / albedo } else { 1.0 };
+        Vector3::new(
+            ratio(lit.r, albedo.r),
+            ratio(lit.g, albedo.g),
+            ratio(lit.b, albedo.b),
+        )
+    })
+}
+fn in_traced_shadow(
+    slice: &RenderSlice,
+    triangle: &[Vertex],
+    fragment: &Fragment,
+    depth: f32,
+    light_dir: &Vector3<f32>,
+) -> bool {
+    let (Some(casters), Some(normal)) = (
+        &slice.shadow_casters,
+        calculate_normals(triangle, &fragment.weights),
+    ) else {
+        return false;
+    };
+    let ndc = Point3::new(
+        fragment.screen.x * 2.0 - 1.0,
+        1.0 - fragment.screen.y * 2.0,
+        depth,
+    );
+    let point = slice.inverse_vp.transform_point(&ndc);
+    casters.occluded(&point, &normal, light_dir)
+}
+/// Distance from the camera of a screen space vertex, undoing the depth curve of the
+/// projection. Exact for the perspective z and for the distance of non-linear projections.
+fn screen_distance(vertex: &Vertex, camera: &Camera) -> f32 {
+    let (near, far) = (camera.near, camera.far);
+    2.0 * far * near / ((far + near) - vertex.position.z * (far - near)).max(1e-6)
+}
+fn dim(color: u32, brightness: f32) -> u32 {
+    if brightness >= 1.0 {
+        return color;
+    }
+    (Color::from_u32(color) * brightness).as_u32()
+}
+
+/// Bresenham line from `p1` to `p2`, with the color scaled by `fade` blended along it.
+fn draw_line(slice: &mut RenderSlice, p1: &Vertex, p2: &Vertex, color: u32, fade: [f32; 2]) {
     let p1 = p1.position.xy();
     let p2 = p2.position.xy();
+    let length = (p2 - p1).magnitude().max(1.0);
 
     let x0 = p1.x as i32;
     let y0 = p1.y as i32;
@@ -380,11 +2851,13 @@ fn draw_line(slice: &mut RenderSlice, p1: &Vertex, p2: &Vertex, color: u32) {
     let mut y = y0;
 
     loop {
-        if y >= slice.start as i32 && y < slice.end as i32 && x >= 0 && x < slice.width as i32 {
-            let relative_y = (y - slice.start as i32) as usize;
-            let index = relative_y * slice.width as usize + x as usize;
-            if index < slice.color_slice.len() {
-                slice.color_slice[index] = color;
+        if x >= 0 && y >= 0 {
+            let (column, row) = (x as u32, y as u32);
+            if let Some(index) = slice.local(column, row)
+                && slice.renders(column, row)
+            {
+                let t = ((Point2::new(x as f32, y as f32) - p1).magnitude() / length).min(1.0);
+                slice.color_slice[index] = dim(color, fade[0] + (fade[1] - fade[0]) * t);
             }
         }
 
@@ -406,17 +2879,306 @@ fn draw_line(slice: &mut RenderSlice, p1: &Vertex, p2: &Vertex, color: u32) {
 fn draw_point(slice: &mut RenderSlice, point: &Vertex, size: f32, color: u32) {
     for x in (point.position.x - size.ceil()) as u32..(point.position.x + size.ceil()) as u32 {
         for y in (point.position.y - size.ceil()) as u32..(point.position.y + size.ceil()) as u32 {
-            if y >= slice.start && y < slice.end && x < slice.width {
-                let relative_y = (y - slice.start) as usize;
-                let index = relative_y * slice.width as usize + x as usize;
-                if index < slice.color_slice.len() {
-                    let test_pos = Point2::new(x as f32, y as f32);
-                    let pos = point.position.xy();
-                    if (test_pos - pos).magnitude() < size {
-                        slice.color_slice[index] = color;
-                    }
+            if let Some(index) = slice.local(x, y) {
+                let test_pos = Point2::new(x as f32, y as f32);
+                let pos = point.position.xy();
+                if (test_pos - pos).magnitude() < size && slice.renders(x, y) {
+                    slice.color_slice[index] = color;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    fn screen_triangle(points: [(f32, f32); 3]) -> Vec<Vertex> {
+        points
+            .iter()
+            .map(|(x, y)| Vertex::new(&Point3::new(*x, *y, 0.5)))
+            .collect()
+    }
+
+    #[test]
+    fn sanitize_drops_degenerate_and_non_finite_triangles() {
+        let mut vertices = screen_triangle([(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)]);
+        vertices.extend(screen_triangle([(0.0, 0.0), (5.0, 5.0), (10.0, 10.0)]));
+        vertices.extend(screen_triangle([(0.0, 0.0), (f32::NAN, 0.0), (0.0, 10.0)]));
+        vertices.extend(screen_triangle([
+            (0.0, 0.0),
+            (f32::INFINITY, 0.0),
+            (0.0, 10.0),
+        ]));
+        vertices.extend(screen_triangle([(20.0, 20.0), (30.0, 20.0), (20.0, 30.0)]));
+        let mut materials = (0..5).map(|index| (index, 0)).collect::<Vec<_>>();
+        sanitize_triangles(&mut vertices, &mut materials);
+        assert_eq!(materials, vec![(0, 0), (4, 0)]);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(vertices[3].position.x, 20.0);
+    }
+
+    #[test]
+    fn sanitize_removes_non_finite_uvs_and_clamps_huge_ones() {
+        let mut vertices = screen_triangle([(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)]);
+        vertices[0].uv = Some(Vector2::new(f32::NAN, 0.0));
+        vertices[1].uv = Some(Vector2::new(1e30, -1e30));
+        vertices[2].uv = Some(Vector2::new(0.25, 3.5));
+        let mut materials = vec![(0, 0)];
+        sanitize_triangles(&mut vertices, &mut materials);
+        assert_eq!(vertices[0].uv, None);
+        assert_eq!(vertices[1].uv, Some(Vector2::new(MAX_UV, -MAX_UV)));
+        assert_eq!(vertices[2].uv, Some(Vector2::new(0.25, 3.5)));
+    }
+
+    /// Screen vertex of a view space point, the way `draw_buffer` projects linear views.
+    fn project(point: &Point3<f32>, p_mat: &Matrix4<f32>, size: (u32, u32)) -> Vertex {
+        let mut vertex = Vertex::new(point);
+        let clip_w = p_mat.row(3).transpose().dot(&vertex.position.coords);
+        vertex
+            .view_to_clip_mut(p_mat)
+            .clip_to_ndc_mut()
+            .ndc_to_screen_mut(size);
+        vertex.position.w = 1.0 / clip_w;
+        vertex
+    }
+
+    #[test]
+    fn perspective_weights_interpolate_in_view_space() {
+        let mut rng = XorShiftRng::seed_from_u64(7);
+        let camera = Camera::default();
+        let p_mat = camera.get_perspective_matrix();
+        let size = (640, 480);
+        for _ in 0..1000 {
+            // Corners at very different depths, where screen space weights are far off
+            let corners = [0; 3].map(|_| {
+                let z = -rng.random_range(0.5..50.0);
+                Point3::new(
+                    rng.random_range(-0.5..0.5) * z,
+                    rng.random_range(-0.5..0.5) * z,
+                    z,
+                )
+            });
+            let triangle = corners.map(|corner| project(&corner, &p_mat, size));
+            let (a, b, c) = (
+                triangle[0].position.xy(),
+                triangle[1].position.xy(),
+                triangle[2].position.xy(),
+            );
+            if signed_area(&a, &b, &c).abs() < 10.0 {
+                continue;
+            }
+            let expected = Vector3::new(
+                rng.random_range(0.0..1.0),
+                rng.random_range(0.0..1.0),
+                rng.random_range(0.0..1.0),
+            );
+            let expected = expected / expected.sum();
+            let point = Point3::from(
+                corners[0].coords * expected.x
+                    + corners[1].coords * expected.y
+                    + corners[2].coords * expected.z,
+            );
+            let screen = project(&point, &p_mat, size).position.xy();
+            let weights = perspective_weights(&triangle, &triangle_barycentric(&triangle, &screen));
+            assert!(
+                (weights - expected).amax() < 1e-2,
+                "{weights} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn perspective_weights_keep_affine_weights() {
+        let triangle = screen_triangle([(0.0, 0.0), (10.0, 0.0), (0.0, 10.0)]);
+        let weights = Vector3::new(0.2, 0.3, 0.5);
+        assert_eq!(perspective_weights(&triangle, &weights), weights);
+        let mut far = triangle.clone();
+        far[2].position.w = 0.25;
+        let corrected = perspective_weights(&far, &weights);
+        assert!((corrected.sum() - 1.0).abs() < 1e-6);
+        assert!(corrected.z < weights.z && corrected.x > weights.x);
+    }
+
+    /// View space vertex with its UV and color an affine function of its position, which
+    /// clipping has to keep.
+    fn attributed(point: Point3<f32>) -> Vertex {
+        let mut vertex = Vertex::new(&point);
+        vertex.uv = Some(Vector2::new(point.x * 0.5 + 0.25, point.z * -0.1));
+        vertex.color = Some(Color::new(point.y, point.x + point.z, 1.0, 1.0));
+        vertex
+    }
+
+    fn area(triangles: &[Vertex]) -> f32 {
+        triangles
+            .chunks_exact(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|i| t[i].position.xyz());
+                (b - a).cross(&(c - a)).norm() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn clipping_keeps_inside_triangles_and_drops_outside_ones() {
+        let frustum = Camera::default().view_frustum_planes();
+        let inside = [(-0.5, 0.0, -2.0), (0.5, 0.0, -2.0), (0.0, 0.5, -3.0)]
+            .map(|(x, y, z)| attributed(Point3::new(x, y, z)));
+        let clipped = clip_triangle(&inside, &frustum);
+        assert_eq!(clipped.len(), 3);
+        assert_eq!(clipped[2].position, inside[2].position);
+        let behind = inside.map(|vertex| attributed(vertex.position.xyz().map(|v| -v)));
+        assert!(clip_triangle(&behind, &frustum).is_empty());
+    }
+
+    #[test]
+    fn clipping_splits_triangles_crossing_the_near_plane() {
+        let camera = Camera::default();
+        let near = Plane {
+            normal: -Vector3::z(),
+            distance: -camera.near,
+        };
+        let keeps_attributes = |vertex: &Vertex| {
+            let expected = attributed(vertex.position.xyz());
+            let (color, expected_color) = (vertex.color.unwrap(), expected.color.unwrap());
+            (vertex.uv.unwrap() - expected.uv.unwrap()).norm() < 1e-4
+                && (color.r - expected_color.r).abs() + (color.g - expected_color.g).abs() < 1e-4
+        };
+        // One corner behind the camera leaves a quad, two leave a triangle. The part in front
+        // spans the fraction of each edge before it crosses the plane.
+        for (corners, triangles) in [
+            ([(-1.0, -0.5, -4.0), (1.0, -0.5, -4.0), (0.0, 0.2, 1.0)], 2),
+            ([(-1.0, -0.5, 2.0), (1.0, -0.5, 2.0), (0.0, 0.2, -4.0)], 1),
+        ] {
+            let triangle = corners.map(|(x, y, z)| attributed(Point3::new(x, y, z)));
+            let clipped = clip_triangle(&triangle, &[near]);
+            assert_eq!(clipped.len(), triangles * 3);
+            assert!(clipped.iter().all(keeps_attributes));
+            let distances = triangle.map(|vertex| near.signed_distance(&vertex.position.xyz()));
+            // The corner on its own side of the plane
+            let lone = distances
+                .iter()
+                .position(|d| (*d >= 0.0) == (triangles == 1))
+                .unwrap();
+            let fractions = [(lone + 1) % 3, (lone + 2) % 3]
+                .map(|other| distances[lone] / (distances[lone] - distances[other]));
+            let expected = match triangles {
+                2 => area(&triangle) * (1.0 - fractions[0] * fractions[1]),
+                _ => area(&triangle) * fractions[0] * fractions[1],
+            };
+            assert!((area(&clipped) - expected).abs() < 1e-3 * expected);
+
+            // The whole frustum cuts more off the sides, never anything outside a plane
+            let frustum = camera.view_frustum_planes();
+            let clipped_to_frustum = clip_triangle(&triangle, &frustum);
+            assert!(!clipped_to_frustum.is_empty());
+            assert!(area(&clipped_to_frustum) <= area(&clipped) + 1e-4);
+            for vertex in &clipped_to_frustum {
+                assert!(keeps_attributes(vertex));
+                for plane in &frustum {
+                    assert!(plane.signed_distance(&vertex.position.xyz()) >= -1e-4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_cover_every_pixel_once_and_skip_rejected_ones() {
+        let mut target = RenderTarget::new(150, 70);
+        target.set_tile_size(32);
+        let mut slices = target.create_slices();
+        assert_eq!(slices.len(), 5 * 3);
+        for slice in &mut slices {
+            for y in slice.start..slice.end {
+                for x in slice.left..slice.right {
+                    let index = slice.local(x, y).unwrap();
+                    slice.color_slice[index] =
+                        slice.color_slice[index].wrapping_add(y * 150 + x + 1);
                 }
             }
+            assert_eq!(slice.local(slice.right, slice.start), None);
         }
+        drop(slices);
+        assert!(
+            target
+                .color
+                .iter()
+                .enumerate()
+                .all(|(i, c)| *c == target.clear_color.wrapping_add(i as u32 + 1))
+        );
+
+        target.clear();
+        let mut slices = target.create_tiles(|tile| tile == 6);
+        assert_eq!(slices.len(), 1);
+        let slice = &mut slices[0];
+        assert_eq!(
+            (slice.left, slice.right, slice.start, slice.end),
+            (32, 64, 32, 64)
+        );
+        slice.color_slice[(0, 0)] = 7;
+        drop(slices);
+        assert_eq!(target.color[32 * 150 + 32], 7);
+        assert_eq!(target.color.iter().filter(|c| **c == 7).count(), 1);
+    }
+
+    #[test]
+    fn bins_fall_back_to_every_triangle_once_a_queue_overflows() {
+        let grid = TileGrid::new(128, 64, 64);
+        let small = screen_triangle([(4.0, 4.0), (10.0, 4.0), (4.0, 10.0)]);
+        let wide = screen_triangle([(4.0, 4.0), (120.0, 4.0), (4.0, 40.0)]);
+        let mut bins = TriangleBins::default();
+        bins.fill(&grid, &[small.clone(), wide.clone()].concat(), 1.0);
+        assert_eq!(bins.queue(0), Some(&[0, 1][..]));
+        assert_eq!(bins.queue(1), Some(&[1][..]));
+        assert!(!bins.any_overflowed());
+
+        let mut vertices = small.repeat(MAX_BIN_LEN);
+        vertices.extend(wide);
+        bins.fill(&grid, &vertices, 1.0);
+        assert_eq!(bins.queue(0), None);
+        assert_eq!(bins.queue(1), Some(&[MAX_BIN_LEN as u32][..]));
+        assert!(bins.queues[0].capacity() <= MAX_BIN_LEN);
+
+        bins.fill(&grid, &small, 1.0);
+        assert_eq!(bins.queue(0), Some(&[0][..]));
+        assert_eq!(bins.queue(1), Some(&[][..]));
+
+        let mut sparse = TriangleBins::default();
+        sparse.fill(&grid, &small.repeat(INLINE_BIN_LEN), 1.0);
+        assert!(sparse.queues.iter().all(|queue| !queue.spilled()));
+    }
+
+    /// A cube a little over four units in front of a camera, both moved out to `offset`.
+    fn draw_cube_at(offset: Vector3<f64>, camera_relative: bool) -> Vec<u32> {
+        let material = Material::SolidColor(Color::new(1.0, 0.5, 0.25, 1.0));
+        let entity = Entity::new(
+            "cube",
+            &crate::primitives::cube(1.0),
+            &nalgebra::Isometry3::rotation(Vector3::new(0.3, 0.6, 0.0)),
+            &nalgebra::Scale3::identity(),
+            material,
+        )
+        .with_world_position(offset + Vector3::new(0.3, 0.2, -4.1));
+        let camera = Camera {
+            world_offset: offset,
+            ..Camera::default()
+        };
+        let mut target = RenderTarget::new(64, 48);
+        target.set_camera_relative(camera_relative);
+        target.clear();
+        target.draw(&entity, &camera, &DrawMode::default());
+        target.color.clone()
+    }
+
+    #[test]
+    fn camera_relative_draws_far_out_entities_like_ones_at_the_origin() {
+        let near = draw_cube_at(Vector3::zeros(), true);
+        assert!(near.iter().any(|color| *color != near[0]));
+        assert!(draw_cube_at(Vector3::zeros(), false) == near);
+        let far = Vector3::new(3.0e6, 1.0e5, -2.0e6);
+        assert!(draw_cube_at(far, true) == near);
+        assert!(draw_cube_at(far, false) != near);
     }
 }
@@ -0,0 +1,201 @@
+use crate::InputState;
+use crate::error::SoftRastError;
+use std::fs;
+use std::time::Duration;
+use winit::event::MouseButton;
+
+/// Captures the input and delta of every frame so the session can be played back exactly.
+/// Files start with `start <seconds>`, the clock when recording began, followed by one line
+/// per frame: `delta dx dy cursor_x cursor_y width height buttons keys`.
+pub struct InputRecorder {
+    path: String,
+    start: Duration,
+    frames: Vec<(Duration, InputState)>,
+}
+impl InputRecorder {
+    pub fn new(path: &str, start: Duration) -> Self {
+        Self {
+            path: path.to_owned(),
+            start,
+            frames: vec![],
+        }
+    }
+    pub fn record(&mut self, delta: Duration, input: &InputState) {
+        self.frames.push((delta, input.clone()));
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn save(&self) -> Result<(), SoftRastError> {
+        let mut contents = format!("start {}\n", self.start.as_secs_f64());
+        for (delta, input) in &self.frames {
+            contents.push_str(&format!(
+                "{} {} {} {} {} {} {} {} {}\n",
+                delta.as_secs_f64(),
+                input.mouse_dx,
+                input.mouse_dy,
+                input.cursor_x,
+                input.cursor_y,
+                input.window_size.0,
+                input.window_size.1,
+                join_or_dash(input.pressed_buttons.iter().map(button_name)),
+                join_or_dash(input.pressed_keys.iter().map(|key| encode_key(key))),
+            ));
+        }
+        fs::write(&self.path, contents).map_err(|source| SoftRastError::Io {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+/// Plays back a file written by `InputRecorder`, one frame per call to `next_frame`.
+pub struct InputReplay {
+    pub start: Duration,
+    frames: Vec<(Duration, InputState)>,
+    index: usize,
+}
+impl InputReplay {
+    /// Fails on a malformed start line or the first malformed frame, the rest would play back
+    /// out of step.
+    pub fn load(path: &str) -> Result<Self, SoftRastError> {
+        let contents = fs::read_to_string(path).map_err(|source| SoftRastError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        let mut lines = contents.lines();
+        let first = lines.next().unwrap_or_default();
+        let start = first
+            .strip_prefix("start ")
+            .and_then(|seconds| seconds.parse::<f64>().ok())
+            .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+            .ok_or_else(|| SoftRastError::Parse {
+                path: path.to_owned(),
+                line: 1,
+                message: format!("expected 'start <seconds>', got '{}'", first),
+            })?;
+        let mut frames = vec![];
+        // Frames start on the second line
+        for (index, line) in lines.enumerate() {
+            let frame = parse_frame(line).ok_or_else(|| SoftRastError::Parse {
+                path: path.to_owned(),
+                line: index + 2,
+                message: format!("malformed input frame '{}'", line),
+            })?;
+            frames.push(frame);
+        }
+        Ok(Self {
+            start,
+            frames,
+            index: 0,
+        })
+    }
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+    pub fn next_frame(&mut self) -> Option<(Duration, InputState)> {
+        let frame = self.frames.get(self.index).cloned();
+        self.index += 1;
+        frame
+    }
+}
+
+fn parse_frame(line: &str) -> Option<(Duration, InputState)> {
+    let parts = line.split_whitespace().collect::<Vec<_>>();
+    if parts.len() != 9 {
+        return None;
+    }
+    let delta = Duration::try_from_secs_f64(parts[0].parse().ok()?).ok()?;
+    let mut input = InputState {
+        mouse_dx: parts[1].parse().ok()?,
+        mouse_dy: parts[2].parse().ok()?,
+        cursor_x: parts[3].parse().ok()?,
+        cursor_y: parts[4].parse().ok()?,
+        window_size: (parts[5].parse().ok()?, parts[6].parse().ok()?),
+        ..InputState::default()
+    };
+    if parts[7] != "-" {
+        for name in parts[7].split(',') {
+            input.pressed_buttons.insert(parse_button(name)?);
+        }
+    }
+    if parts[8] != "-" {
+        for key in parts[8].split(',') {
+            input.pressed_keys.insert(decode_key(key)?);
+        }
+    }
+    Some((delta, input))
+}
+
+fn join_or_dash(items: impl Iterator<Item = String>) -> String {
+    let joined = items.collect::<Vec<_>>().join(",");
+    if joined.is_empty() {
+        "-".to_owned()
+    } else {
+        joined
+    }
+}
+
+/// Keys are stored as hex code points joined by '+', so spaces and commas survive.
+fn encode_key(key: &str) -> String {
+    key.chars()
+        .map(|ch| format!("{:x}", ch as u32))
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
+fn decode_key(encoded: &str) -> Option<String> {
+    encoded
+        .split('+')
+        .map(|code| u32::from_str_radix(code, 16).ok().and_then(char::from_u32))
+        .collect()
+}
+
+fn button_name(button: &MouseButton) -> String {
+    match button {
+        MouseButton::Left => "left".to_owned(),
+        MouseButton::Right => "right".to_owned(),
+        MouseButton::Middle => "middle".to_owned(),
+        MouseButton::Back => "back".to_owned(),
+        MouseButton::Forward => "forward".to_owned(),
+        MouseButton::Other(id) => id.to_string(),
+    }
+}
+
+fn parse_button(name: &str) -> Option<MouseButton> {
+    Some(match name {
+        "left" => MouseButton::Left,
+        "right" => MouseButton::Right,
+        "middle" => MouseButton::Middle,
+        "back" => MouseButton::Back,
+        "forward" => MouseButton::Forward,
+        id => MouseButton::Other(id.parse().ok()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_with_bad_deltas_are_malformed() {
+        let frame = |delta: &str| parse_frame(&format!("{} 0 0 0 0 64 48 - -", delta));
+        assert_eq!(frame("0.5").unwrap().0, Duration::from_millis(500));
+        for delta in ["-0.5", "NaN", "inf", "1e300"] {
+            assert!(frame(delta).is_none(), "{}", delta);
+        }
+    }
+
+    #[test]
+    fn bad_start_line_is_a_parse_error() {
+        let path = std::env::temp_dir().join("softrast-bad-start.replay");
+        let path = path.to_string_lossy().into_owned();
+        fs::write(&path, "start -1\n").unwrap();
+        let result = InputReplay::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(SoftRastError::Parse { line: 1, .. })));
+    }
+}
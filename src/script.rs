@@ -0,0 +1,182 @@
+use crate::Scene;
+use nalgebra::{Isometry3, Scale3, Translation3, UnitQuaternion, Vector3};
+use rhai::{AST, Array, CallFnOptions, Dynamic, Engine, FLOAT, Map, Scope};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The entity as seen by a script through `this`, copied in before `on_update` and
+/// written back after it.
+#[derive(Debug, Clone)]
+pub struct ScriptEntity {
+    pub id: String,
+    pub position: Vector3<f32>,
+    /// Euler angles in radians, roll/pitch/yaw about x/y/z.
+    pub rotation: Vector3<f32>,
+    pub scale: Vector3<f32>,
+    /// Script owned values kept between frames, rhai functions can't see outer variables.
+    pub vars: Map,
+}
+
+/// A rhai script attached to an entity. The file is recompiled whenever it changes on disk,
+/// which also resets `this.vars`.
+pub struct Script {
+    path: PathBuf,
+    ast: Option<AST>,
+    vars: Map,
+    modified: Option<SystemTime>,
+}
+impl Script {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            ast: None,
+            vars: Map::new(),
+            modified: None,
+        }
+    }
+}
+
+/// Entity ids and positions at the start of the frame, shared with the query functions.
+type Snapshot = Arc<Mutex<Vec<(String, Vector3<f32>)>>>;
+
+/// Owns the script engine and runs `on_update(dt)` for every scripted entity each frame.
+pub struct ScriptHost {
+    engine: Engine,
+    /// Backs the scene queries.
+    snapshot: Snapshot,
+}
+impl Default for ScriptHost {
+    fn default() -> Self {
+        let mut engine = Engine::new();
+        engine
+            .register_type_with_name::<ScriptEntity>("Entity")
+            .register_get("id", |e: &mut ScriptEntity| e.id.clone())
+            .register_get_set(
+                "vars",
+                |e: &mut ScriptEntity| e.vars.clone(),
+                |e: &mut ScriptEntity, vars: Map| e.vars = vars,
+            );
+        macro_rules! vector_property {
+            ($name:literal, $field:ident, $axis:ident) => {
+                engine.register_get_set(
+                    $name,
+                    |e: &mut ScriptEntity| e.$field.$axis as FLOAT,
+                    |e: &mut ScriptEntity, value: FLOAT| e.$field.$axis = value as f32,
+                );
+            };
+        }
+        vector_property!("x", position, x);
+        vector_property!("y", position, y);
+        vector_property!("z", position, z);
+        vector_property!("roll", rotation, x);
+        vector_property!("pitch", rotation, y);
+        vector_property!("yaw", rotation, z);
+        vector_property!("scale_x", scale, x);
+        vector_property!("scale_y", scale, y);
+        vector_property!("scale_z", scale, z);
+        engine.register_fn(
+            "translate",
+            |e: &mut ScriptEntity, x: FLOAT, y: FLOAT, z: FLOAT| {
+                e.position += Vector3::new(x as f32, y as f32, z as f32);
+            },
+        );
+
+        let snapshot = Snapshot::default();
+        let entities = snapshot.clone();
+        engine.register_fn("entity_ids", move || -> Array {
+            let entities = entities.lock().unwrap();
+            entities
+                .iter()
+                .map(|(id, _)| Dynamic::from(id.clone()))
+                .collect()
+        });
+        let entities = snapshot.clone();
+        // Position of the first entity with `id` as [x, y, z], or () if there is none
+        engine.register_fn("position_of", move |id: &str| -> Dynamic {
+            match entities
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|(other, _)| other == id)
+            {
+                Some((_, p)) => Dynamic::from_array(vec![
+                    Dynamic::from(p.x as FLOAT),
+                    Dynamic::from(p.y as FLOAT),
+                    Dynamic::from(p.z as FLOAT),
+                ]),
+                None => Dynamic::UNIT,
+            }
+        });
+        Self { engine, snapshot }
+    }
+}
+#[allow(unused)]
+impl ScriptHost {
+    /// Recompiles the script if its file changed. Errors are reported once per change and
+    /// leave the script disabled until the file is saved again.
+    fn reload(&self, script: &mut Script) {
+        let modified = fs::metadata(&script.path).and_then(|m| m.modified()).ok();
+        if modified.is_some() && modified == script.modified {
+            return;
+        }
+        script.modified = modified;
+        script.vars = Map::new();
+        script.ast = match self.engine.compile_file(script.path.clone()) {
+            Ok(ast) => Some(ast),
+            Err(err) => {
+                eprintln!("{}: {}", script.path.display(), err);
+                None
+            }
+        };
+    }
+    pub fn update(&self, scene: &mut Scene, delta: f32) {
+        *self.snapshot.lock().unwrap() = scene
+            .entities
+            .iter()
+            .map(|e| (e.id.clone(), e.position.translation.vector))
+            .collect();
+
+        for entity in &mut scene.entities {
+            let Some(script) = &mut entity.script else {
+                continue;
+            };
+            self.reload(script);
+            let Some(ast) = &script.ast else { continue };
+
+            let (roll, pitch, yaw) = entity.position.rotation.euler_angles();
+            let mut this = Dynamic::from(ScriptEntity {
+                id: entity.id.clone(),
+                position: entity.position.translation.vector,
+                rotation: Vector3::new(roll, pitch, yaw),
+                scale: entity.scale.vector,
+                vars: std::mem::take(&mut script.vars),
+            });
+            let options = CallFnOptions::new()
+                .eval_ast(false)
+                .bind_this_ptr(&mut this);
+            let result = self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut Scope::new(),
+                ast,
+                "on_update",
+                (delta as FLOAT,),
+            );
+            if let Err(err) = result {
+                eprintln!("{}: {}", script.path.display(), err);
+                script.ast = None;
+                continue;
+            }
+            if let Some(state) = this.try_cast::<ScriptEntity>() {
+                let r = state.rotation;
+                entity.position = Isometry3::from_parts(
+                    Translation3::from(state.position),
+                    UnitQuaternion::from_euler_angles(r.x, r.y, r.z),
+                );
+                entity.scale = Scale3::new(state.scale.x, state.scale.y, state.scale.z);
+                script.vars = state.vars;
+            }
+        }
+    }
+}
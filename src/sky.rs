@@ -0,0 +1,110 @@
+use crate::camera::Camera;
+use crate::renderer::{Color, RenderTarget};
+use nalgebra::{Point3, Vector3};
+use rayon::prelude::*;
+
+/// Analytic sky filling the background before any geometry is drawn: a gradient from the
+/// horizon up to the zenith that warms as the sun gets low, a glow around the sun and its
+/// disc. Cheaper than a cubemap and nothing to author.
+#[derive(Debug, Copy, Clone)]
+pub struct Sky {
+    pub zenith: Color,
+    pub horizon: Color,
+    /// Below the horizon, faded in just under it.
+    pub ground: Color,
+    /// Horizon color when the sun sets.
+    pub sunset: Color,
+    pub sun_color: Color,
+    /// Angular radius of the sun disc in radians.
+    pub sun_size: f32,
+    /// Direction towards the sun, `None` follows the directional light of the first lit
+    /// material in the scene.
+    pub sun: Option<Vector3<f32>>,
+}
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            zenith: Color::new(0.15, 0.35, 0.75, 1.0),
+            horizon: Color::new(0.7, 0.82, 0.95, 1.0),
+            ground: Color::new(0.3, 0.28, 0.25, 1.0),
+            sunset: Color::new(0.95, 0.55, 0.3, 1.0),
+            sun_color: Color::new(1.0, 0.95, 0.85, 1.0),
+            sun_size: 0.02,
+            sun: None,
+        }
+    }
+}
+#[allow(unused)]
+impl Sky {
+    pub fn new(zenith: Color, horizon: Color, ground: Color) -> Self {
+        Self {
+            zenith,
+            horizon,
+            ground,
+            ..Self::default()
+        }
+    }
+    /// Pins the sun to a direction instead of following the scene's light.
+    pub fn with_sun(mut self, direction: Vector3<f32>) -> Self {
+        self.sun = direction.try_normalize(1e-6);
+        self
+    }
+    pub fn with_sun_color(mut self, color: Color, size: f32) -> Self {
+        self.sun_color = color;
+        self.sun_size = size;
+        self
+    }
+    /// Sky color looking along a normalized world direction.
+    pub fn color(&self, direction: &Vector3<f32>, sun: Option<&Vector3<f32>>) -> Color {
+        let elevation = direction.y;
+        let low_sun = sun.map_or(0.0, |sun| (1.0 - sun.y.max(0.0) * 4.0).clamp(0.0, 1.0));
+        let horizon = self.horizon.lerp(&self.sunset, low_sun);
+        let mut color = if elevation >= 0.0 {
+            horizon.lerp(&self.zenith, elevation.sqrt())
+        } else {
+            horizon.lerp(&self.ground, (-elevation * 10.0).min(1.0))
+        };
+        if let Some(sun) = sun {
+            let cos = direction.dot(sun);
+            let glow = cos.max(0.0).powi(64) * 0.6 + cos.max(0.0).powi(8) * 0.15 * low_sun;
+            color = color.lerp(&self.sun_color, glow.min(1.0));
+            if cos >= self.sun_size.cos() {
+                color = self.sun_color;
+            }
+        }
+        color
+    }
+    /// Overwrites every pixel of the target with the sky seen from `camera`.
+    pub(crate) fn draw(
+        &self,
+        target: &mut RenderTarget,
+        camera: &Camera,
+        light_dir: Option<Vector3<f32>>,
+    ) {
+        let (width, height) = target.size();
+        let Some(inverse) =
+            (camera.get_perspective_matrix() * camera.get_view_matrix()).try_inverse()
+        else {
+            return;
+        };
+        let sun = self
+            .sun
+            .or(light_dir.and_then(|light| light.try_normalize(1e-6)));
+        target
+            .color
+            .par_chunks_mut(width.max(1) as usize)
+            .enumerate()
+            .for_each(|(y, row)| {
+                let ndc_y = 1.0 - 2.0 * (y as f32 + 0.5) / height as f32;
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let ndc_x = 2.0 * (x as f32 + 0.5) / width as f32 - 1.0;
+                    let near = inverse.transform_point(&Point3::new(ndc_x, ndc_y, -1.0));
+                    let far = inverse.transform_point(&Point3::new(ndc_x, ndc_y, 1.0));
+                    let Some(direction) = (far - near).try_normalize(1e-9) else {
+                        continue;
+                    };
+                    *pixel = self.color(&direction, sun.as_ref()).as_u32();
+                }
+            });
+    }
+}
@@ -0,0 +1,253 @@
+use crate::Entity;
+use crate::geometry::{Plane, Ray};
+use nalgebra::{Matrix4, Point3, Vector3};
+use std::collections::HashMap;
+
+type Cell = (i32, i32, i32);
+
+/// Entities covering more cells than this are kept in a list every query checks instead.
+const MAX_CELLS: i64 = 64;
+
+/// World space axis aligned box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+#[allow(unused)]
+impl Aabb {
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Self { min, max }
+    }
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|axis| self.min[axis] <= other.max[axis] && other.min[axis] <= self.max[axis])
+    }
+    pub fn distance_squared(&self, point: &Point3<f32>) -> f32 {
+        (point.coords.sup(&self.min.coords).inf(&self.max.coords) - point.coords)
+            .magnitude_squared()
+    }
+    /// Entirely behind the plane, tested with the corner furthest along its normal.
+    pub fn outside(&self, plane: &Plane) -> bool {
+        let corner = Point3::new(
+            if plane.normal.x >= 0.0 {
+                self.max.x
+            } else {
+                self.min.x
+            },
+            if plane.normal.y >= 0.0 {
+                self.max.y
+            } else {
+                self.min.y
+            },
+            if plane.normal.z >= 0.0 {
+                self.max.z
+            } else {
+                self.min.z
+            },
+        );
+        plane.signed_distance(&corner) < 0.0
+    }
+    /// Bounds of an entity's base model with its current transform, world offset included.
+    pub fn of_entity(entity: &Entity) -> Self {
+        let (min, max) = entity.model.bounds();
+        let mut local = entity.model_matrix();
+        local.fixed_view_mut::<3, 1>(0, 3).fill(0.0);
+        let translation = entity.world_translation().cast::<f32>();
+        let mut bounds = Self::new(
+            Point3::from(Vector3::repeat(f32::MAX)),
+            Point3::from(Vector3::repeat(f32::MIN)),
+        );
+        for i in 0..8 {
+            let corner = Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            );
+            let world = local.transform_point(&corner) + translation;
+            bounds.min = bounds.min.inf(&world);
+            bounds.max = bounds.max.sup(&world);
+        }
+        bounds
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    /// What the bounds were computed from, the entry is moved when any of it changes.
+    key: (Option<crate::EntityId>, Matrix4<f32>, Vector3<f64>, usize),
+    bounds: Aabb,
+    /// Cell range covered, `None` for entities in the large list.
+    cells: Option<(Cell, Cell)>,
+}
+
+/// Uniform grid over the world bounds of the scene's entities. `update` only moves entities
+/// whose transform changed, so culling, picking and overlap queries cost about the same no
+/// matter how many entities sit elsewhere in the world.
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    pub cell_size: f32,
+    cells: HashMap<Cell, Vec<usize>>,
+    large: Vec<usize>,
+    entries: Vec<Entry>,
+}
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        Self::new(8.0)
+    }
+}
+#[allow(unused)]
+impl SpatialIndex {
+    pub fn new(cell_size: f32) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+            large: vec![],
+            entries: vec![],
+        }
+    }
+    fn cell(&self, point: &Point3<f32>) -> Cell {
+        let cell = (point.coords / self.cell_size)
+            .map(|c| c.floor().clamp(i32::MIN as f32, i32::MAX as f32) as i32);
+        (cell.x, cell.y, cell.z)
+    }
+    fn cell_range(&self, bounds: &Aabb) -> (Cell, Cell) {
+        (self.cell(&bounds.min), self.cell(&bounds.max))
+    }
+    fn cell_bounds(&self, cell: Cell) -> Aabb {
+        let min = Point3::new(cell.0 as f32, cell.1 as f32, cell.2 as f32) * self.cell_size;
+        Aabb::new(min, min + Vector3::repeat(self.cell_size))
+    }
+    fn range_cells((min, max): (Cell, Cell)) -> impl Iterator<Item = Cell> {
+        (min.2..=max.2).flat_map(move |z| {
+            (min.1..=max.1).flat_map(move |y| (min.0..=max.0).map(move |x| (x, y, z)))
+        })
+    }
+    /// Brings the grid up to date with the entities, indices are positions in `entities`.
+    pub fn update(&mut self, entities: &[Entity]) {
+        if entities.len() != self.entries.len() {
+            self.cells.clear();
+            self.large.clear();
+            self.entries.clear();
+        }
+        for (index, entity) in entities.iter().enumerate() {
+            let key = (
+                entity.handle,
+                entity.model_matrix(),
+                entity.world_offset,
+                entity.model.vertices.len(),
+            );
+            if self
+                .entries
+                .get(index)
+                .is_some_and(|entry| entry.key == key)
+            {
+                continue;
+            }
+            if let Some(entry) = self.entries.get(index) {
+                match entry.cells {
+                    Some(range) => {
+                        for cell in Self::range_cells(range) {
+                            if let Some(list) = self.cells.get_mut(&cell) {
+                                list.retain(|i| *i != index);
+                            }
+                        }
+                    }
+                    None => self.large.retain(|i| *i != index),
+                }
+            }
+            let bounds = Aabb::of_entity(entity);
+            let range = self.cell_range(&bounds);
+            let cells = (cell_count(range) <= MAX_CELLS).then_some(range);
+            match cells {
+                Some(range) => {
+                    for cell in Self::range_cells(range) {
+                        self.cells.entry(cell).or_default().push(index);
+                    }
+                }
+                None => self.large.push(index),
+            }
+            let entry = Entry { key, bounds, cells };
+            if index < self.entries.len() {
+                self.entries[index] = entry;
+            } else {
+                self.entries.push(entry);
+            }
+        }
+        self.cells.retain(|_, list| !list.is_empty());
+    }
+    pub fn bounds(&self, index: usize) -> Option<&Aabb> {
+        self.entries.get(index).map(|entry| &entry.bounds)
+    }
+    /// Indices of entities whose bounds pass `test`, checking only cells that pass `cell`.
+    fn query(&self, cell: impl Fn(&Aabb) -> bool, test: impl Fn(&Aabb) -> bool) -> Vec<usize> {
+        let mut found = self
+            .cells
+            .iter()
+            .filter(|(key, _)| cell(&self.cell_bounds(**key)))
+            .flat_map(|(_, list)| list.iter().copied())
+            .chain(self.large.iter().copied())
+            .filter(|index| test(&self.entries[*index].bounds))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+    /// Entities whose bounds overlap the box.
+    pub fn query_box(&self, bounds: &Aabb) -> Vec<usize> {
+        let range = self.cell_range(bounds);
+        // Large boxes visit the occupied cells instead of every cell they cover
+        if cell_count(range) > self.cells.len() as i64 {
+            return self.query(
+                |cell| cell.intersects(bounds),
+                |other| other.intersects(bounds),
+            );
+        }
+        let mut found = Self::range_cells(range)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .chain(self.large.iter().copied())
+            .filter(|index| self.entries[*index].bounds.intersects(bounds))
+            .collect::<Vec<_>>();
+        found.sort_unstable();
+        found.dedup();
+        found
+    }
+    /// Entities whose bounds come within `radius` of `center`.
+    pub fn query_sphere(&self, center: &Point3<f32>, radius: f32) -> Vec<usize> {
+        let bounds = Aabb::new(
+            center - Vector3::repeat(radius),
+            center + Vector3::repeat(radius),
+        );
+        let mut found = self.query_box(&bounds);
+        found.retain(|index| {
+            self.entries[*index].bounds.distance_squared(center) <= radius * radius
+        });
+        found
+    }
+    /// Entities whose bounds aren't entirely behind any of the planes, e.g. a view frustum.
+    pub fn query_planes(&self, planes: &[Plane]) -> Vec<usize> {
+        let inside = |bounds: &Aabb| !planes.iter().any(|plane| bounds.outside(plane));
+        self.query(inside, inside)
+    }
+    /// Entities whose bounds the ray enters within `max_distance`, nearest entry first.
+    pub fn raycast(&self, ray: &Ray, max_distance: f32) -> Vec<(usize, f32)> {
+        let hit = |bounds: &Aabb| {
+            ray.intersect_aabb(&bounds.min, &bounds.max)
+                .filter(|t| *t <= max_distance)
+        };
+        let mut found = self
+            .query(|cell| hit(cell).is_some(), |bounds| hit(bounds).is_some())
+            .into_iter()
+            .filter_map(|index| hit(&self.entries[index].bounds).map(|t| (index, t)))
+            .collect::<Vec<_>>();
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+}
+
+fn cell_count((min, max): (Cell, Cell)) -> i64 {
+    (max.0 as i64 - min.0 as i64 + 1)
+        * (max.1 as i64 - min.1 as i64 + 1)
+        * (max.2 as i64 - min.2 as i64 + 1)
+}
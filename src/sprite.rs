@@ -0,0 +1,192 @@
+use crate::geometry::Texture;
+use crate::renderer::{Color, RenderTarget};
+use image::{GenericImageView, Rgba};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Pixel rectangle of one frame in a sprite sheet.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpriteFrame {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A texture holding many sprite frames, shared between sprites.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    pub texture: Texture,
+    pub frames: Vec<SpriteFrame>,
+}
+#[allow(unused)]
+impl SpriteSheet {
+    pub fn new(texture: Texture, frames: Vec<SpriteFrame>) -> Self {
+        Self { texture, frames }
+    }
+    /// Equally sized frames in `columns` x `rows` cells, numbered left to right and top to
+    /// bottom.
+    pub fn from_grid(texture: Texture, columns: u32, rows: u32) -> Self {
+        let (columns, rows) = (columns.max(1), rows.max(1));
+        let width = texture.texture.width() / columns;
+        let height = texture.texture.height() / rows;
+        let frames = (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| SpriteFrame {
+                    x: column * width,
+                    y: row * height,
+                    width,
+                    height,
+                })
+            })
+            .collect();
+        Self { texture, frames }
+    }
+}
+
+/// Frame indices into a sheet played at `frame_rate` frames per second.
+#[derive(Debug, Clone)]
+pub struct SpriteAnimation {
+    pub frames: Vec<usize>,
+    pub frame_rate: f32,
+    /// Holds the last frame at the end instead of starting over.
+    pub once: bool,
+}
+impl SpriteAnimation {
+    pub fn new(frames: Vec<usize>, frame_rate: f32) -> Self {
+        Self {
+            frames,
+            frame_rate,
+            once: false,
+        }
+    }
+}
+
+/// Screen space 2D sprite drawn over the scene, `x` and `y` are its top left corner in
+/// pixels. Sprites in `Scene::sprites` are advanced by the frame delta and drawn after the
+/// 3D entities.
+#[derive(Debug, Clone)]
+pub struct Sprite {
+    pub sheet: Arc<SpriteSheet>,
+    pub x: i32,
+    pub y: i32,
+    pub scale: u32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Multiplies the sheet colors, alpha included.
+    pub tint: Color,
+    pub visible: bool,
+    animations: HashMap<String, SpriteAnimation>,
+    playing: Option<String>,
+    elapsed: f32,
+    frame: usize,
+}
+#[allow(unused)]
+impl Sprite {
+    pub fn new(sheet: Arc<SpriteSheet>, x: i32, y: i32) -> Self {
+        Self {
+            sheet,
+            x,
+            y,
+            scale: 1,
+            flip_x: false,
+            flip_y: false,
+            tint: Color::new(1.0, 1.0, 1.0, 1.0),
+            visible: true,
+            animations: HashMap::new(),
+            playing: None,
+            elapsed: 0.0,
+            frame: 0,
+        }
+    }
+    pub fn with_animation(mut self, name: &str, animation: SpriteAnimation) -> Self {
+        self.animations.insert(name.to_owned(), animation);
+        self
+    }
+    /// Starts the named animation from its first frame, unless it is already playing.
+    pub fn play(&mut self, name: &str) {
+        if self.playing.as_deref() == Some(name) || !self.animations.contains_key(name) {
+            return;
+        }
+        self.playing = Some(name.to_owned());
+        self.elapsed = 0.0;
+        self.update(0.0);
+    }
+    /// Stops animating and shows a fixed sheet frame.
+    pub fn set_frame(&mut self, frame: usize) {
+        self.playing = None;
+        self.frame = frame;
+    }
+    /// The sheet frame shown now.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+    /// True once an animation played `once` has reached its last frame.
+    pub fn finished(&self) -> bool {
+        self.playing
+            .as_ref()
+            .and_then(|name| self.animations.get(name))
+            .is_some_and(|animation| {
+                animation.once
+                    && self.elapsed * animation.frame_rate >= animation.frames.len() as f32
+            })
+    }
+    pub fn update(&mut self, delta: f32) {
+        let Some(animation) = self
+            .playing
+            .as_ref()
+            .and_then(|name| self.animations.get(name))
+        else {
+            return;
+        };
+        if animation.frames.is_empty() {
+            return;
+        }
+        self.elapsed += delta;
+        let step = (self.elapsed * animation.frame_rate).max(0.0) as usize;
+        let index = if animation.once {
+            step.min(animation.frames.len() - 1)
+        } else {
+            step % animation.frames.len()
+        };
+        self.frame = animation.frames[index];
+    }
+    /// Blends the current frame over the target by its tinted alpha.
+    pub fn draw(&self, target: &mut RenderTarget) {
+        let Some(frame) = self.sheet.frames.get(self.frame) else {
+            return;
+        };
+        if !self.visible {
+            return;
+        }
+        let image = &self.sheet.texture.texture;
+        let scale = self.scale.max(1);
+        for sy in 0..frame.height {
+            let v = if self.flip_y {
+                frame.height - 1 - sy
+            } else {
+                sy
+            };
+            for sx in 0..frame.width {
+                let u = if self.flip_x {
+                    frame.width - 1 - sx
+                } else {
+                    sx
+                };
+                let (px, py) = (frame.x + u, frame.y + v);
+                if px >= image.width() || py >= image.height() {
+                    continue;
+                }
+                let Rgba([r, g, b, a]) = image.get_pixel(px, py);
+                let mut color = Color::from_rgba(r, g, b, a).modulate(&self.tint);
+                color.a *= self.tint.a;
+                if color.a <= 0.0 {
+                    continue;
+                }
+                let x = self.x + (sx * scale) as i32;
+                let y = self.y + (sy * scale) as i32;
+                target.blend_rect(x, y, scale, scale, color);
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::renderer::{DrawMode, WireStyle};
+use crate::{Entity, Scene};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+/// Hash of everything that affects a frame, two equal fingerprints mean the previous image
+/// can be presented again. `None` for scenes that change with time on their own, like
+/// vertex modifiers or animated materials, which have to be drawn every frame.
+///
+/// Edits the hash can't see, such as replacing a material or changing model vertices in
+/// place, need `Command::redraw`.
+pub fn scene_fingerprint(scene: &Scene, mode: &DrawMode, size: (u32, u32)) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    size.hash(&mut hasher);
+    (
+        mode.shaded,
+        mode.wireframe,
+        mode.points,
+        mode.ray_traced,
+        mode.shadows,
+    )
+        .hash(&mut hasher);
+    (
+        mode.collapse_subpixel,
+        mode.reference,
+        mode.perspective_correct,
+    )
+        .hash(&mut hasher);
+    hash_wire_style(&mut hasher, &mode.wire_style);
+    if let Some(cue) = mode.depth_cue {
+        hash_floats(&mut hasher, &[cue.start, cue.end, cue.min]);
+    }
+
+    let camera = &scene.camera;
+    hash_floats(&mut hasher, camera.position.coords.as_slice());
+    camera
+        .world_offset
+        .map(f64::to_bits)
+        .as_slice()
+        .hash(&mut hasher);
+    hash_floats(&mut hasher, camera.orientation.coords.as_slice());
+    hash_floats(
+        &mut hasher,
+        &[camera.fov, camera.aspect_ratio, camera.near, camera.far],
+    );
+
+    // Keep drawing until the jittered history has converged, accumulation replaces TAA
+    if scene.accumulation.is_none() && scene.taa.as_ref().is_some_and(|taa| !taa.settled()) {
+        return None;
+    }
+    scene.taa.is_some().hash(&mut hasher);
+    scene
+        .accumulation
+        .as_ref()
+        .map(|accumulation| accumulation.frames)
+        .hash(&mut hasher);
+    // Same for the exposure adapting to a new view
+    if scene
+        .exposure
+        .as_ref()
+        .is_some_and(|exposure| !exposure.settled())
+    {
+        return None;
+    }
+    scene
+        .exposure
+        .as_ref()
+        .map(|exposure| exposure.exposure().to_bits())
+        .hash(&mut hasher);
+    if let Some(palette) = &scene.palette {
+        palette.dither.hash(&mut hasher);
+        for color in palette.colors() {
+            color.as_u32().hash(&mut hasher);
+        }
+    }
+    for entity in &scene.entities {
+        hash_entity(&mut hasher, entity)?;
+    }
+
+    scene.camera_relative.hash(&mut hasher);
+    (format!("{:?}", scene.projection), scene.tessellation).hash(&mut hasher);
+    scene.grid.is_some().hash(&mut hasher);
+    scene.show_frusta.hash(&mut hasher);
+    scene
+        .index_labels
+        .as_ref()
+        .map(|labels| format!("{:?}", labels))
+        .hash(&mut hasher);
+    scene.irradiance.is_some().hash(&mut hasher);
+    scene.clip_cap.map(|cap| cap.as_u32()).hash(&mut hasher);
+    for plane in &scene.clip_planes {
+        hash_floats(&mut hasher, plane.normal.as_slice());
+        hash_floats(&mut hasher, &[plane.distance]);
+    }
+    for inset in &scene.insets {
+        hash_floats(&mut hasher, &[inset.x, inset.y, inset.width, inset.height]);
+        hash_floats(&mut hasher, inset.camera.position.coords.as_slice());
+        inset
+            .camera
+            .world_offset
+            .map(f64::to_bits)
+            .as_slice()
+            .hash(&mut hasher);
+        hash_floats(&mut hasher, inset.camera.orientation.coords.as_slice());
+        inset.follow_height.map(f32::to_bits).hash(&mut hasher);
+    }
+    if let Some(stereo) = &scene.stereo {
+        format!("{:?}", stereo.mode).hash(&mut hasher);
+        hash_floats(&mut hasher, &[stereo.eye_separation, stereo.convergence]);
+    }
+    if let Some(outline) = &scene.outline {
+        (
+            outline.color.as_u32(),
+            outline.highlight.as_u32(),
+            &outline.selected,
+        )
+            .hash(&mut hasher);
+        hash_floats(
+            &mut hasher,
+            &[outline.depth_threshold, outline.normal_threshold],
+        );
+    }
+    if let Some(portals) = &scene.portals {
+        (portals.cells.len(), portals.portals.len()).hash(&mut hasher);
+    }
+    if let Some(sky) = &scene.sky {
+        (
+            sky.zenith.as_u32(),
+            sky.horizon.as_u32(),
+            sky.ground.as_u32(),
+        )
+            .hash(&mut hasher);
+        (sky.sunset.as_u32(), sky.sun_color.as_u32()).hash(&mut hasher);
+        hash_floats(&mut hasher, &[sky.sun_size]);
+        if let Some(sun) = sky.sun {
+            hash_floats(&mut hasher, sun.as_slice());
+        }
+    }
+    for line in &scene.debug.lines {
+        hash_floats(&mut hasher, line.start.coords.as_slice());
+        hash_floats(&mut hasher, line.end.coords.as_slice());
+        line.color.as_u32().hash(&mut hasher);
+    }
+    for text in &scene.debug.texts {
+        (text.x, text.y, &text.text, text.color.as_u32()).hash(&mut hasher);
+    }
+    for label in &scene.debug.labels {
+        hash_floats(&mut hasher, label.position.coords.as_slice());
+        (
+            &label.text,
+            label.color.as_u32(),
+            label.scale,
+            label.depth_tested,
+        )
+            .hash(&mut hasher);
+        if let Some((start, end)) = label.fade {
+            hash_floats(&mut hasher, &[start, end]);
+        }
+    }
+    for panel in &scene.panels {
+        (
+            Arc::as_ptr(&panel.slices) as usize,
+            panel.x,
+            panel.y,
+            panel.width,
+            panel.height,
+        )
+            .hash(&mut hasher);
+        panel.visible.hash(&mut hasher);
+        hash_floats(
+            &mut hasher,
+            &[panel.tint.r, panel.tint.g, panel.tint.b, panel.tint.a],
+        );
+    }
+    for sprite in &scene.sprites {
+        (Arc::as_ptr(&sprite.sheet) as usize, sprite.frame()).hash(&mut hasher);
+        (
+            sprite.x,
+            sprite.y,
+            sprite.scale,
+            sprite.flip_x,
+            sprite.flip_y,
+            sprite.visible,
+        )
+            .hash(&mut hasher);
+        hash_floats(
+            &mut hasher,
+            &[sprite.tint.r, sprite.tint.g, sprite.tint.b, sprite.tint.a],
+        );
+    }
+    Some(hasher.finish())
+}
+
+fn hash_entity(hasher: &mut DefaultHasher, entity: &Entity) -> Option<()> {
+    let animated = entity.materials.iter().any(|material| material.animated());
+    if animated
+        || entity.vertex_modifier.is_some()
+        || entity.geometry_hook.is_some()
+        || entity.animation.is_some()
+    {
+        return None;
+    }
+    #[cfg(feature = "scripting")]
+    if entity.script.is_some() {
+        return None;
+    }
+    entity.id.hash(hasher);
+    hash_floats(hasher, entity.model_matrix().as_slice());
+    entity
+        .world_offset
+        .map(f64::to_bits)
+        .as_slice()
+        .hash(hasher);
+    (entity.lod_level, entity.layer, entity.sort_key).hash(hasher);
+    (entity.active_model().vertices.len(), entity.materials.len()).hash(hasher);
+    (entity.mirror.is_some(), entity.displacement.is_some()).hash(hasher);
+    if let Some(style) = &entity.wire_style {
+        hash_wire_style(hasher, style);
+    }
+    Some(())
+}
+
+fn hash_wire_style(hasher: &mut DefaultHasher, style: &WireStyle) {
+    (
+        style.wire_color.as_u32(),
+        style.point_color.as_u32(),
+        style.point_size.to_bits(),
+    )
+        .hash(hasher);
+}
+
+fn hash_floats(hasher: &mut DefaultHasher, floats: &[f32]) {
+    for float in floats {
+        float.to_bits().hash(hasher);
+    }
+}
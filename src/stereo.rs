@@ -0,0 +1,43 @@
+use crate::camera::Camera;
+use nalgebra::UnitQuaternion;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StereoMode {
+    /// Left eye in the red channel, right eye in green and blue, for red/cyan glasses.
+    Anaglyph,
+    /// Left eye on the left half of the target, right eye on the right half.
+    SideBySide,
+}
+
+/// Renders the scene once per eye from cameras offset along the camera's right vector.
+#[derive(Debug, Copy, Clone)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    /// Distance between the eyes in world units.
+    pub eye_separation: f32,
+    /// Distance in front of the camera where both eyes converge, objects there have no
+    /// parallax, nearer ones pop out of the screen.
+    pub convergence: f32,
+}
+impl Stereo {
+    pub fn new(mode: StereoMode) -> Self {
+        Self {
+            mode,
+            eye_separation: 0.065,
+            convergence: 5.0,
+        }
+    }
+    /// Left and right eye cameras, toed in towards the convergence point.
+    pub(crate) fn eye_cameras(&self, camera: &Camera) -> [Camera; 2] {
+        let focus = camera.position + camera.forward() * self.convergence;
+        [-0.5, 0.5].map(|side| {
+            let mut eye = *camera;
+            eye.position += camera.right() * (side * self.eye_separation);
+            eye.orientation = UnitQuaternion::face_towards(&(focus - eye.position), &camera.up());
+            if self.mode == StereoMode::SideBySide {
+                eye.aspect_ratio *= 0.5;
+            }
+            eye
+        })
+    }
+}
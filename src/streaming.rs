@@ -0,0 +1,232 @@
+use crate::asset_loader::AssetLoader;
+use crate::camera::Camera;
+use crate::renderer::{Color, Material, Sampler, UvTransform};
+use crate::spatial::Aabb;
+use crate::{Asset, Command, Entity, EntityId};
+use nalgebra::{Isometry3, Point3, Scale3, Vector3};
+use std::collections::{HashMap, HashSet};
+
+/// When a chunk is wanted.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ChunkTrigger {
+    /// While the camera is within `radius` of `center`.
+    Distance { center: Point3<f32>, radius: f32 },
+    /// While the camera is inside the box, e.g. a room or a valley.
+    Zone(Aabb),
+}
+
+/// An entity of a chunk, described by the files it is made from.
+#[derive(Debug, Clone)]
+pub struct ChunkEntity {
+    pub id: String,
+    /// OBJ, PLY or glTF model.
+    pub model: String,
+    /// Lit texture, the entity is lit `color` without one.
+    pub texture: Option<String>,
+    pub color: Color,
+    pub position: Isometry3<f32>,
+    pub scale: Scale3<f32>,
+}
+#[allow(unused)]
+impl ChunkEntity {
+    pub fn new(id: &str, model: &str, position: Isometry3<f32>) -> Self {
+        Self {
+            id: id.to_owned(),
+            model: model.to_owned(),
+            texture: None,
+            color: Color::new(0.8, 0.8, 0.8, 1.0),
+            position,
+            scale: Scale3::identity(),
+        }
+    }
+    pub fn with_texture(mut self, path: &str) -> Self {
+        self.texture = Some(path.to_owned());
+        self
+    }
+    pub fn with_color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+    pub fn with_scale(mut self, scale: Scale3<f32>) -> Self {
+        self.scale = scale;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChunkState {
+    Unloaded,
+    /// Waiting for its files, entities are spawned once all of them are in.
+    Loading,
+    Loaded,
+}
+
+/// A group of entities loaded and unloaded together.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub name: String,
+    pub trigger: ChunkTrigger,
+    pub entities: Vec<ChunkEntity>,
+    state: ChunkState,
+    /// Entities spawned for the chunk while it is loaded.
+    spawned: Vec<EntityId>,
+}
+#[allow(unused)]
+impl Chunk {
+    pub fn new(name: &str, trigger: ChunkTrigger) -> Self {
+        Self {
+            name: name.to_owned(),
+            trigger,
+            entities: vec![],
+            state: ChunkState::Unloaded,
+            spawned: vec![],
+        }
+    }
+    pub fn with_entity(mut self, entity: ChunkEntity) -> Self {
+        self.entities.push(entity);
+        self
+    }
+    pub fn state(&self) -> ChunkState {
+        self.state
+    }
+    /// Files of all entities, shared ones repeated.
+    fn paths(&self) -> impl Iterator<Item = &String> {
+        self.entities
+            .iter()
+            .flat_map(|entity| [Some(&entity.model), entity.texture.as_ref()])
+            .flatten()
+    }
+    /// How far outside the trigger the camera is, 0 or less inside it.
+    fn distance_outside(&self, position: &Point3<f32>) -> f32 {
+        match &self.trigger {
+            ChunkTrigger::Distance { center, radius } => (position - center).magnitude() - radius,
+            ChunkTrigger::Zone(zone) => zone.distance_squared(position).sqrt(),
+        }
+    }
+}
+
+/// Keeps the chunks near the camera in the scene, see `Scene::set_streaming`. Chunks load
+/// once the camera enters their trigger, their files are read by an `AssetLoader` in the
+/// background and the entities spawned when all are in. They unload again once the camera
+/// is `unload_margin` outside the trigger, so walking along the edge doesn't thrash.
+pub struct WorldStreamer {
+    pub chunks: Vec<Chunk>,
+    pub unload_margin: f32,
+    loader: AssetLoader,
+    /// Files requested and not yet in.
+    requested: HashSet<String>,
+    /// Files in and waiting for their chunks, `None` when the file didn't load.
+    ready: HashMap<String, Option<Asset>>,
+}
+#[allow(unused)]
+impl WorldStreamer {
+    pub fn new(chunks: Vec<Chunk>) -> Self {
+        Self {
+            chunks,
+            unload_margin: 5.0,
+            loader: AssetLoader::default(),
+            requested: HashSet::new(),
+            ready: HashMap::new(),
+        }
+    }
+    pub fn with_unload_margin(mut self, margin: f32) -> Self {
+        self.unload_margin = margin;
+        self
+    }
+    pub fn chunk(&self, name: &str) -> Option<&Chunk> {
+        self.chunks.iter().find(|chunk| chunk.name == name)
+    }
+    /// Starts and finishes loads and unloads for the camera's position, entities are spawned
+    /// and despawned and failed loads reported through `command`.
+    pub(crate) fn update(&mut self, command: &mut Command, camera: &Camera) {
+        for (path, result) in self.loader.poll() {
+            self.requested.remove(&path);
+            let asset = result.map_err(|err| command.report_error(err)).ok();
+            self.ready.insert(path, asset);
+        }
+        for index in 0..self.chunks.len() {
+            let distance = self.chunks[index].distance_outside(&camera.eye());
+            match self.chunks[index].state {
+                ChunkState::Unloaded if distance <= 0.0 => {
+                    self.chunks[index].state = ChunkState::Loading;
+                    self.request_files(index);
+                }
+                ChunkState::Loading if distance > self.unload_margin => {
+                    self.chunks[index].state = ChunkState::Unloaded;
+                }
+                ChunkState::Loading => self.try_spawn(index, command),
+                ChunkState::Loaded if distance > self.unload_margin => {
+                    let chunk = &mut self.chunks[index];
+                    for id in chunk.spawned.drain(..) {
+                        command.despawn(id);
+                    }
+                    chunk.state = ChunkState::Unloaded;
+                }
+                _ => {}
+            }
+        }
+        // Files no loading chunk waits for any more are dropped, loaded chunks keep theirs
+        // in their entities
+        let chunks = &self.chunks;
+        self.ready.retain(|path, _| {
+            chunks.iter().any(|chunk| {
+                chunk.state == ChunkState::Loading && chunk.paths().any(|other| other == path)
+            })
+        });
+    }
+    fn request_files(&mut self, index: usize) {
+        for path in self.chunks[index].paths() {
+            if self.ready.contains_key(path) || !self.requested.insert(path.clone()) {
+                continue;
+            }
+            match self.chunks[index]
+                .entities
+                .iter()
+                .any(|entity| &entity.model == path)
+            {
+                true => self.loader.request_model(path),
+                false => self.loader.request_texture(path),
+            }
+        }
+    }
+    /// Spawns the chunk's entities once all its files are in, entities whose model didn't
+    /// load are left out.
+    fn try_spawn(&mut self, index: usize, command: &mut Command) {
+        let chunk = &mut self.chunks[index];
+        if !chunk.paths().all(|path| self.ready.contains_key(path)) {
+            return;
+        }
+        let light_dir = Vector3::<f32>::new(1.0, 1.0, 0.0).normalize();
+        for entity in &chunk.entities {
+            let Some(Some(Asset::Model(model))) = self.ready.get(&entity.model) else {
+                continue;
+            };
+            let texture = entity
+                .texture
+                .as_ref()
+                .and_then(|path| match self.ready.get(path) {
+                    Some(Some(Asset::Texture(texture))) => Some(texture.clone()),
+                    _ => None,
+                });
+            let material = match texture {
+                Some(texture) => Material::LitTexture {
+                    texture,
+                    light_dir,
+                    uv_transform: UvTransform::default(),
+                    sampler: Sampler::default(),
+                    emissive: None,
+                    specular: None,
+                },
+                None => Material::LitSolid {
+                    color: entity.color,
+                    light_dir,
+                    emissive: None,
+                    specular: None,
+                },
+            };
+            let spawned = Entity::new(&entity.id, model, &entity.position, &entity.scale, material);
+            chunk.spawned.push(command.spawn(spawned));
+        }
+        chunk.state = ChunkState::Loaded;
+    }
+}
@@ -0,0 +1,199 @@
+use crate::camera::Camera;
+use crate::renderer::{Color, RenderTarget};
+use nalgebra::{Matrix4, Point3, Vector2};
+use rayon::prelude::*;
+
+/// Frames accumulated after a camera change before the image counts as settled.
+const SETTLE_FRAMES: u32 = 16;
+
+/// Temporal anti-aliasing. Every frame is drawn with the projection jittered by a different
+/// sub-pixel offset, then blended with the previous result reprojected through a velocity
+/// buffer. History is clamped to the current neighbourhood's color range so disocclusions
+/// and moving entities don't ghost.
+///
+/// Velocity comes from depth and the previous camera, so it covers camera motion only,
+/// moving entities rely on the clamp.
+#[derive(Debug, Clone)]
+pub struct Taa {
+    /// Weight of the history in 0..1, higher is smoother but slower to respond.
+    pub feedback: f32,
+    frame: u32,
+    size: (u32, u32),
+    history: Vec<Color>,
+    /// Screen space motion in pixels since the previous frame, per pixel.
+    velocity: Vec<Vector2<f32>>,
+    previous_vp: Option<Matrix4<f32>>,
+    /// Frames since the camera last changed.
+    settled_frames: u32,
+}
+impl Default for Taa {
+    fn default() -> Self {
+        Self {
+            feedback: 0.9,
+            frame: 0,
+            size: (0, 0),
+            history: vec![],
+            velocity: vec![],
+            previous_vp: None,
+            settled_frames: 0,
+        }
+    }
+}
+#[allow(unused)]
+impl Taa {
+    /// The camera with this frame's jitter applied, draw with it and pass it to `resolve`.
+    pub fn jittered(&self, camera: &Camera, size: (u32, u32)) -> Camera {
+        jittered(camera, self.frame % 8 + 1, size)
+    }
+    /// True once the camera has been still long enough for the history to converge.
+    pub fn settled(&self) -> bool {
+        self.settled_frames >= SETTLE_FRAMES
+    }
+    pub fn velocity(&self) -> &[Vector2<f32>] {
+        &self.velocity
+    }
+    /// Forgets the history, e.g. after a cut.
+    pub fn reset(&mut self) {
+        self.history.clear();
+        self.previous_vp = None;
+        self.settled_frames = 0;
+    }
+    /// Blends the target's colors with the reprojected history and keeps the result for the
+    /// next frame. `camera` is the jittered camera the target was drawn with.
+    pub fn resolve(&mut self, target: &mut RenderTarget, camera: &Camera) {
+        let size = target.size();
+        let (width, height) = size;
+        let mut unjittered = *camera;
+        unjittered.jitter = Vector2::zeros();
+        let vp = unjittered.get_perspective_matrix() * unjittered.get_view_matrix();
+        let inverse = (camera.get_perspective_matrix() * camera.get_view_matrix())
+            .try_inverse()
+            .unwrap_or(Matrix4::identity());
+
+        if self.size != size {
+            self.reset();
+            self.size = size;
+        }
+        if self.previous_vp == Some(vp) {
+            self.settled_frames = self.settled_frames.saturating_add(1);
+        } else {
+            self.settled_frames = 0;
+        }
+        let previous_vp = self.previous_vp.unwrap_or(vp);
+
+        let depth = target.depth();
+        self.velocity = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                // The background is reprojected as if it was on the far plane
+                let z = depth[i as usize].min(1.0);
+                let ndc = Point3::new(
+                    (x as f32 + 0.5) / width as f32 * 2.0 - 1.0,
+                    1.0 - (y as f32 + 0.5) / height as f32 * 2.0,
+                    z,
+                );
+                let world = inverse.transform_point(&ndc);
+                let previous = previous_vp.transform_point(&world);
+                let previous_x = (previous.x + 1.0) * 0.5 * width as f32;
+                let previous_y = (1.0 - previous.y) * 0.5 * height as f32;
+                Vector2::new(x as f32 + 0.5 - previous_x, y as f32 + 0.5 - previous_y)
+            })
+            .collect();
+
+        let current = target
+            .color
+            .iter()
+            .map(|c| Color::from_u32(*c))
+            .collect::<Vec<_>>();
+        let has_history = self.history.len() == current.len();
+        let history = &self.history;
+        let velocity = &self.velocity;
+        let feedback = self.feedback;
+        let resolved = (0..width * height)
+            .into_par_iter()
+            .map(|i| {
+                let (x, y) = (i % width, i / width);
+                let color = current[i as usize];
+                if !has_history {
+                    return color;
+                }
+                let source = Vector2::new(x as f32 + 0.5, y as f32 + 0.5) - velocity[i as usize];
+                let Some(previous) = sample(history, size, source) else {
+                    return color;
+                };
+                let (min, max) = neighbourhood(&current, size, x, y);
+                let previous = Color::new(
+                    previous.r.clamp(min.r, max.r),
+                    previous.g.clamp(min.g, max.g),
+                    previous.b.clamp(min.b, max.b),
+                    1.0,
+                );
+                color.lerp(&previous, feedback)
+            })
+            .collect::<Vec<_>>();
+
+        for (dst, color) in target.color.iter_mut().zip(&resolved) {
+            *dst = color.as_u32();
+        }
+        self.history = resolved;
+        self.previous_vp = Some(vp);
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+/// Component wise color range of the 3x3 pixels around `x`, `y`.
+fn neighbourhood(colors: &[Color], size: (u32, u32), x: u32, y: u32) -> (Color, Color) {
+    let mut min = Color::new(1.0, 1.0, 1.0, 1.0);
+    let mut max = Color::new(0.0, 0.0, 0.0, 1.0);
+    for ny in y.saturating_sub(1)..=(y + 1).min(size.1 - 1) {
+        for nx in x.saturating_sub(1)..=(x + 1).min(size.0 - 1) {
+            let c = colors[(ny * size.0 + nx) as usize];
+            min = Color::new(min.r.min(c.r), min.g.min(c.g), min.b.min(c.b), 1.0);
+            max = Color::new(max.r.max(c.r), max.g.max(c.g), max.b.max(c.b), 1.0);
+        }
+    }
+    (min, max)
+}
+
+/// Bilinear sample at a pixel position, `None` outside the image.
+fn sample(colors: &[Color], size: (u32, u32), position: Vector2<f32>) -> Option<Color> {
+    let (x, y) = (position.x - 0.5, position.y - 0.5);
+    if x < -0.5 || y < -0.5 || x > size.0 as f32 - 0.5 || y > size.1 as f32 - 0.5 {
+        return None;
+    }
+    let (x0, y0) = (x.floor().max(0.0) as u32, y.floor().max(0.0) as u32);
+    let (x1, y1) = ((x0 + 1).min(size.0 - 1), (y0 + 1).min(size.1 - 1));
+    let (tx, ty) = (
+        (x - x0 as f32).clamp(0.0, 1.0),
+        (y - y0 as f32).clamp(0.0, 1.0),
+    );
+    let at = |x: u32, y: u32| colors[(y * size.0 + x) as usize];
+    let top = at(x0, y0).lerp(&at(x1, y0), tx);
+    let bottom = at(x0, y1).lerp(&at(x1, y1), tx);
+    Some(top.lerp(&bottom, ty))
+}
+
+/// `camera` offset by less than a pixel, by the `sample`th point of the Halton (2, 3)
+/// sequence.
+pub(crate) fn jittered(camera: &Camera, sample: u32, size: (u32, u32)) -> Camera {
+    let (x, y) = (halton(sample, 2) - 0.5, halton(sample, 3) - 0.5);
+    let mut camera = *camera;
+    camera.jitter = Vector2::new(
+        2.0 * x / size.0.max(1) as f32,
+        2.0 * y / size.1.max(1) as f32,
+    );
+    camera
+}
+
+/// Radical inverse of `index` in `base`, a low discrepancy sequence in 0..1.
+pub(crate) fn halton(mut index: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut fraction = 1.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
@@ -0,0 +1,237 @@
+use crate::Entity;
+use crate::error::SoftRastError;
+use crate::geometry::{Model, Vertex};
+use crate::renderer::Shader;
+use nalgebra::{Isometry3, Point3, Scale3, Vector2, Vector3};
+
+/// Height samples on a regular grid, `width` along x and `depth` along z.
+#[derive(Debug, Clone)]
+pub struct Heightmap {
+    pub width: usize,
+    pub depth: usize,
+    pub heights: Vec<f32>,
+}
+#[allow(unused)]
+impl Heightmap {
+    pub fn new(width: usize, depth: usize, height: impl Fn(usize, usize) -> f32) -> Self {
+        let heights = (0..depth)
+            .flat_map(|z| (0..width).map(move |x| (x, z)))
+            .map(|(x, z)| height(x, z))
+            .collect();
+        Self {
+            width,
+            depth,
+            heights,
+        }
+    }
+    /// Grayscale image where white is `scale` high and black is zero.
+    pub fn from_image(path: &str, scale: f32) -> Result<Self, SoftRastError> {
+        let image = image::open(path)
+            .map_err(|err| SoftRastError::from_image(path, err))?
+            .into_luma16();
+        Ok(Self::new(
+            image.width() as usize,
+            image.height() as usize,
+            |x, z| image.get_pixel(x as u32, z as u32).0[0] as f32 / u16::MAX as f32 * scale,
+        ))
+    }
+    /// Sample height, coordinates outside the grid are clamped to the border.
+    pub fn get(&self, x: isize, z: isize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let z = z.clamp(0, self.depth as isize - 1) as usize;
+        self.heights[z * self.width + x]
+    }
+    /// Bilinear height between samples.
+    pub fn sample(&self, x: f32, z: f32) -> f32 {
+        let (x0, z0) = (x.floor(), z.floor());
+        let (tx, tz) = (x - x0, z - z0);
+        let (x0, z0) = (x0 as isize, z0 as isize);
+        let top = self.get(x0, z0) * (1.0 - tx) + self.get(x0 + 1, z0) * tx;
+        let bottom = self.get(x0, z0 + 1) * (1.0 - tx) + self.get(x0 + 1, z0 + 1) * tx;
+        top * (1.0 - tz) + bottom * tz
+    }
+    /// Normal at a sample from central differences, with samples `cell_size` apart.
+    pub fn normal(&self, x: isize, z: isize, cell_size: f32) -> Vector3<f32> {
+        let dx = self.get(x + 1, z) - self.get(x - 1, z);
+        let dz = self.get(x, z + 1) - self.get(x, z - 1);
+        Vector3::new(-dx, 2.0 * cell_size, -dz).normalize()
+    }
+}
+
+/// Heightmap terrain split into square chunks, each an entity whose LODs skip every second,
+/// fourth, ... sample (geo-mipmapping). Chunks at different levels meet with T-junctions, so
+/// every chunk hangs a skirt down from its borders that covers the cracks.
+#[derive(Debug, Clone)]
+pub struct Terrain {
+    pub heightmap: Heightmap,
+    /// Distance between samples in world units.
+    pub cell_size: f32,
+    /// Cells along a chunk side, a multiple of `1 << (levels - 1)`.
+    pub chunk_cells: usize,
+    /// Detail levels per chunk including the full one.
+    pub levels: u32,
+    /// Camera distance where a chunk drops to level 1, doubling for every further level.
+    pub lod_distance: f32,
+}
+#[allow(unused)]
+impl Terrain {
+    pub fn new(heightmap: Heightmap, cell_size: f32) -> Self {
+        Self {
+            heightmap,
+            cell_size,
+            chunk_cells: 32,
+            levels: 4,
+            lod_distance: 32.0 * cell_size * 2.0,
+        }
+    }
+    pub fn with_chunks(mut self, chunk_cells: usize, levels: u32) -> Self {
+        self.levels = levels.max(1);
+        let step = 1 << (self.levels - 1);
+        self.chunk_cells = chunk_cells.max(1).div_ceil(step) * step;
+        self
+    }
+    pub fn with_lod_distance(mut self, distance: f32) -> Self {
+        self.lod_distance = distance;
+        self
+    }
+    /// Chunks along x and z.
+    pub fn chunk_count(&self) -> (usize, usize) {
+        (
+            (self.heightmap.width - 1).div_ceil(self.chunk_cells).max(1),
+            (self.heightmap.depth - 1).div_ceil(self.chunk_cells).max(1),
+        )
+    }
+    /// World height under a point, the terrain spans from the origin along +x and +z.
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        self.heightmap
+            .sample(x / self.cell_size, z / self.cell_size)
+    }
+    /// Center of a chunk on the ground, where its entity is placed.
+    fn chunk_center(&self, chunk: (usize, usize)) -> Point3<f32> {
+        let size = self.chunk_cells as f32;
+        let (x, z) = ((chunk.0 as f32 + 0.5) * size, (chunk.1 as f32 + 0.5) * size);
+        Point3::new(
+            x * self.cell_size,
+            self.heightmap.sample(x, z),
+            z * self.cell_size,
+        )
+    }
+    /// Largest height error along a chunk's borders at any level, against the full samples.
+    fn edge_error(&self, chunk: (usize, usize)) -> f32 {
+        let (x0, z0) = (chunk.0 * self.chunk_cells, chunk.1 * self.chunk_cells);
+        let (x1, z1) = (x0 + self.chunk_cells, z0 + self.chunk_cells);
+        let mut error: f32 = 0.0;
+        for level in 1..self.levels {
+            let step = 1 << level;
+            for i in 0..=self.chunk_cells {
+                let (coarse, t) = (i / step * step, (i % step) as f32 / step as f32);
+                for edge in [
+                    [(x0 + i, z0), (x0 + coarse, z0), (x0 + coarse + step, z0)],
+                    [(x0, z0 + i), (x0, z0 + coarse), (x0, z0 + coarse + step)],
+                    [(x1, z0 + i), (x1, z0 + coarse), (x1, z0 + coarse + step)],
+                    [(x0 + i, z1), (x0 + coarse, z1), (x0 + coarse + step, z1)],
+                ] {
+                    let [full, a, b] =
+                        edge.map(|(x, z)| self.heightmap.get(x as isize, z as isize));
+                    error = error.max((full - (a * (1.0 - t) + b * t)).abs());
+                }
+            }
+        }
+        error
+    }
+    /// Mesh of one chunk at a detail level, relative to `chunk_center`. Level 0 uses every
+    /// sample, each level above halves the samples along both axes.
+    pub fn chunk_model(&self, chunk: (usize, usize), level: u32) -> Model {
+        let step = 1usize << level.min(self.levels - 1);
+        let cells = self.chunk_cells / step;
+        let center = self.chunk_center(chunk);
+        let (x0, z0) = (chunk.0 * self.chunk_cells, chunk.1 * self.chunk_cells);
+        let (width, depth) = (self.heightmap.width as f32, self.heightmap.depth as f32);
+        let vertex = |i: usize, j: usize| {
+            let (x, z) = (x0 + i * step, z0 + j * step);
+            let height = self.heightmap.get(x as isize, z as isize);
+            let position =
+                Point3::new(x as f32 * self.cell_size, height, z as f32 * self.cell_size);
+            Vertex::new(&(position - center.coords))
+                .with_normal(
+                    self.heightmap
+                        .normal(x as isize, z as isize, self.cell_size),
+                )
+                .with_uv(Vector2::new(
+                    x as f32 / (width - 1.0),
+                    1.0 - z as f32 / (depth - 1.0),
+                ))
+        };
+
+        let mut vertices = Vec::with_capacity(cells * cells * 6 + cells * 24);
+        for j in 0..cells {
+            for i in 0..cells {
+                // Counter clockwise seen from above
+                let quad = [
+                    vertex(i, j + 1),
+                    vertex(i + 1, j + 1),
+                    vertex(i + 1, j),
+                    vertex(i, j),
+                ];
+                vertices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+            }
+        }
+
+        // Twice the border error, as both chunks on a border can be off in opposite directions
+        let skirt = 2.0 * self.edge_error(chunk) + self.cell_size * 0.1;
+        let borders = [
+            (
+                (0..=cells).map(|i| (i, 0)).collect::<Vec<_>>(),
+                -Vector3::z(),
+            ),
+            ((0..=cells).map(|i| (i, cells)).collect(), Vector3::z()),
+            ((0..=cells).map(|j| (0, j)).collect(), -Vector3::x()),
+            ((0..=cells).map(|j| (cells, j)).collect(), Vector3::x()),
+        ];
+        for (border, outward) in borders {
+            for pair in border.windows(2) {
+                let (a, b) = (vertex(pair[0].0, pair[0].1), vertex(pair[1].0, pair[1].1));
+                let drop = |v: Vertex| {
+                    let mut v = v;
+                    v.position.y -= skirt;
+                    v
+                };
+                let quad = [a, b, drop(b), drop(a)];
+                let normal = (quad[1].position.xyz() - quad[0].position.xyz())
+                    .cross(&(quad[2].position.xyz() - quad[0].position.xyz()));
+                if normal.dot(&outward) >= 0.0 {
+                    vertices
+                        .extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                } else {
+                    vertices
+                        .extend_from_slice(&[quad[0], quad[2], quad[1], quad[0], quad[3], quad[2]]);
+                }
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+    /// One entity per chunk with every detail level as a LOD, `shader` is called per chunk.
+    pub fn entities<S: Shader + 'static>(&self, id: &str, shader: impl Fn() -> S) -> Vec<Entity> {
+        let (columns, rows) = self.chunk_count();
+        let mut entities = Vec::with_capacity(columns * rows);
+        for z in 0..rows {
+            for x in 0..columns {
+                let center = self.chunk_center((x, z));
+                let mut entity = Entity::new(
+                    &format!("{}_{}_{}", id, x, z),
+                    &self.chunk_model((x, z), 0),
+                    &Isometry3::translation(center.x, center.y, center.z),
+                    &Scale3::new(1.0, 1.0, 1.0),
+                    shader(),
+                )
+                .with_lod_hysteresis(self.chunk_cells as f32 * self.cell_size * 0.1);
+                for level in 1..self.levels {
+                    let distance = self.lod_distance * (1 << (level - 1)) as f32;
+                    entity = entity.with_lod(&self.chunk_model((x, z), level), distance);
+                }
+                entities.push(entity);
+            }
+        }
+        entities
+    }
+}
@@ -0,0 +1,280 @@
+use image::{DynamicImage, Rgba32FImage, RgbaImage};
+
+/// Block compressed formats decoded to RGBA8 at load.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Bc {
+    Bc1,
+    Bc2,
+    Bc3,
+}
+
+/// Pixel layouts of the top mip level found in a container.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Layout {
+    Rgba8,
+    Bgra8,
+    Rgb8,
+    Rgba16F,
+    Rgba32F,
+    Compressed(Bc),
+}
+
+/// Reads the top mip level of a DDS file, uncompressed 24/32 bit or BC1/BC2/BC3.
+pub(crate) fn load_dds(data: &[u8]) -> Result<DynamicImage, String> {
+    if data.get(..4) != Some(b"DDS ") {
+        return Err("not a DDS file".to_owned());
+    }
+    let (height, width) = (read_u32(data, 12)?, read_u32(data, 16)?);
+    let flags = read_u32(data, 80)?;
+    let four_cc = data.get(84..88).ok_or("truncated DDS header")?;
+    let bit_count = read_u32(data, 88)?;
+    let red_mask = read_u32(data, 92)?;
+    let (layout, offset) = match four_cc {
+        b"DXT1" => (Layout::Compressed(Bc::Bc1), 128),
+        b"DXT2" | b"DXT3" => (Layout::Compressed(Bc::Bc2), 128),
+        b"DXT4" | b"DXT5" => (Layout::Compressed(Bc::Bc3), 128),
+        b"DX10" => {
+            let layout = match read_u32(data, 128)? {
+                2 => Layout::Rgba32F,
+                10 => Layout::Rgba16F,
+                28 | 29 => Layout::Rgba8,
+                71 | 72 => Layout::Compressed(Bc::Bc1),
+                74 | 75 => Layout::Compressed(Bc::Bc2),
+                77 | 78 => Layout::Compressed(Bc::Bc3),
+                87 | 91 => Layout::Bgra8,
+                format => return Err(format!("unsupported DXGI format {}", format)),
+            };
+            (layout, 148)
+        }
+        // Uncompressed, told apart by the position of the red channel
+        _ if flags & 0x40 != 0 => {
+            let layout = match (bit_count, red_mask) {
+                (32, 0x0000_00ff) => Layout::Rgba8,
+                (32, 0x00ff_0000) => Layout::Bgra8,
+                (24, 0x0000_00ff) => Layout::Rgb8,
+                _ => return Err(format!("unsupported {} bit DDS layout", bit_count)),
+            };
+            (layout, 128)
+        }
+        _ => {
+            return Err(format!(
+                "unsupported DDS format {}",
+                String::from_utf8_lossy(four_cc)
+            ));
+        }
+    };
+    decode(
+        data.get(offset..).ok_or("truncated DDS data")?,
+        width,
+        height,
+        layout,
+    )
+}
+
+/// Reads the top mip level of a KTX2 file without supercompression, 8 bit, half and float
+/// RGBA or BC1/BC2/BC3.
+pub(crate) fn load_ktx2(data: &[u8]) -> Result<DynamicImage, String> {
+    if data.get(..12) != Some(b"\xABKTX 20\xBB\r\n\x1A\n") {
+        return Err("not a KTX2 file".to_owned());
+    }
+    let format = read_u32(data, 12)?;
+    let (width, height) = (read_u32(data, 20)?, read_u32(data, 24)?.max(1));
+    if read_u32(data, 44)? != 0 {
+        return Err("supercompressed KTX2 files are not supported".to_owned());
+    }
+    let layout = match format {
+        37 | 43 => Layout::Rgba8,
+        44 | 50 => Layout::Bgra8,
+        23 | 29 => Layout::Rgb8,
+        97 => Layout::Rgba16F,
+        109 => Layout::Rgba32F,
+        131..=134 => Layout::Compressed(Bc::Bc1),
+        135 | 136 => Layout::Compressed(Bc::Bc2),
+        137 | 138 => Layout::Compressed(Bc::Bc3),
+        format => return Err(format!("unsupported Vulkan format {}", format)),
+    };
+    // Level 0 is the first entry of the level index
+    let offset = read_u64(data, 80)? as usize;
+    let length = read_u64(data, 88)? as usize;
+    let level = data
+        .get(offset..offset + length)
+        .ok_or("truncated KTX2 data")?;
+    decode(level, width, height, layout)
+}
+
+fn decode(data: &[u8], width: u32, height: u32, layout: Layout) -> Result<DynamicImage, String> {
+    let pixels = (width * height) as usize;
+    let truncated = || "truncated image data".to_owned();
+    Ok(match layout {
+        Layout::Rgba8 => {
+            let bytes = data.get(..pixels * 4).ok_or_else(truncated)?;
+            DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(width, height, bytes.to_vec()).ok_or_else(truncated)?,
+            )
+        }
+        Layout::Bgra8 => {
+            let bytes = data.get(..pixels * 4).ok_or_else(truncated)?;
+            let rgba = bytes
+                .chunks_exact(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+            DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(width, height, rgba).ok_or_else(truncated)?,
+            )
+        }
+        Layout::Rgb8 => {
+            let bytes = data.get(..pixels * 3).ok_or_else(truncated)?;
+            let rgba = bytes
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 255])
+                .collect();
+            DynamicImage::ImageRgba8(
+                RgbaImage::from_raw(width, height, rgba).ok_or_else(truncated)?,
+            )
+        }
+        Layout::Rgba16F => {
+            let bytes = data.get(..pixels * 8).ok_or_else(truncated)?;
+            let floats = bytes
+                .chunks_exact(2)
+                .map(|h| half_to_f32(u16::from_le_bytes([h[0], h[1]])))
+                .collect();
+            DynamicImage::ImageRgba32F(
+                Rgba32FImage::from_raw(width, height, floats).ok_or_else(truncated)?,
+            )
+        }
+        Layout::Rgba32F => {
+            let bytes = data.get(..pixels * 16).ok_or_else(truncated)?;
+            let floats = bytes
+                .chunks_exact(4)
+                .map(|f| f32::from_le_bytes([f[0], f[1], f[2], f[3]]))
+                .collect();
+            DynamicImage::ImageRgba32F(
+                Rgba32FImage::from_raw(width, height, floats).ok_or_else(truncated)?,
+            )
+        }
+        Layout::Compressed(bc) => {
+            DynamicImage::ImageRgba8(decode_bc(data, width, height, bc).ok_or_else(truncated)?)
+        }
+    })
+}
+
+/// Decodes 4x4 blocks row by row, partial blocks at the right and bottom edges are cropped.
+fn decode_bc(data: &[u8], width: u32, height: u32, bc: Bc) -> Option<RgbaImage> {
+    let block_size = if bc == Bc::Bc1 { 8 } else { 16 };
+    let (columns, rows) = (width.div_ceil(4), height.div_ceil(4));
+    let mut image = RgbaImage::new(width, height);
+    for by in 0..rows {
+        for bx in 0..columns {
+            let start = ((by * columns + bx) * block_size) as usize;
+            let block = data.get(start..start + block_size as usize)?;
+            let texels = match bc {
+                Bc::Bc1 => color_block(block, true),
+                Bc::Bc2 => {
+                    let mut texels = color_block(&block[8..], false);
+                    for (i, texel) in texels.iter_mut().enumerate() {
+                        let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0x0f;
+                        texel[3] = nibble * 17;
+                    }
+                    texels
+                }
+                Bc::Bc3 => {
+                    let mut texels = color_block(&block[8..], false);
+                    let alphas = alpha_block(&block[..8]);
+                    for (texel, alpha) in texels.iter_mut().zip(alphas) {
+                        texel[3] = alpha;
+                    }
+                    texels
+                }
+            };
+            for (i, texel) in texels.into_iter().enumerate() {
+                let (x, y) = (bx * 4 + i as u32 % 4, by * 4 + i as u32 / 4);
+                if x < width && y < height {
+                    image.put_pixel(x, y, image::Rgba(texel));
+                }
+            }
+        }
+    }
+    Some(image)
+}
+
+/// BC1 color endpoints and 2 bit indices. Only BC1 blocks use the three color mode with
+/// transparent black, the color half of BC2 and BC3 always interpolates four colors.
+fn color_block(block: &[u8], allow_transparent: bool) -> [[u8; 4]; 16] {
+    let (c0, c1) = (
+        u16::from_le_bytes([block[0], block[1]]),
+        u16::from_le_bytes([block[2], block[3]]),
+    );
+    let expand = |c: u16| {
+        let (r, g, b) = ((c >> 11) & 31, (c >> 5) & 63, c & 31);
+        [
+            (r * 255 / 31) as u32,
+            (g * 255 / 63) as u32,
+            (b * 255 / 31) as u32,
+        ]
+    };
+    let (a, b) = (expand(c0), expand(c1));
+    let mix = |wa: u32, wb: u32| {
+        let total = wa + wb;
+        [0, 1, 2].map(|i| ((a[i] * wa + b[i] * wb) / total) as u8)
+    };
+    let palette = if c0 > c1 || !allow_transparent {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)].map(|[r, g, b]| [r, g, b, 255])
+    } else {
+        let [m, n] = [mix(1, 0), mix(0, 1)];
+        let half = mix(1, 1);
+        [
+            [m[0], m[1], m[2], 255],
+            [n[0], n[1], n[2], 255],
+            [half[0], half[1], half[2], 255],
+            [0, 0, 0, 0],
+        ]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[((indices >> (i * 2)) & 3) as usize])
+}
+
+/// BC3 alpha endpoints and 3 bit indices.
+fn alpha_block(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = if a0 > a1 {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            i => ((a0 * (8 - i as u32) + a1 * (i as u32 - 1)) / 7) as u8,
+        })
+    } else {
+        std::array::from_fn(|i| match i {
+            0 => a0 as u8,
+            1 => a1 as u8,
+            6 => 0,
+            7 => 255,
+            i => ((a0 * (6 - i as u32) + a1 * (i as u32 - 1)) / 5) as u8,
+        })
+    };
+    let mut bits = 0u64;
+    for (i, byte) in block[2..8].iter().enumerate() {
+        bits |= (*byte as u64) << (i * 8);
+    }
+    std::array::from_fn(|i| palette[((bits >> (i * 3)) & 7) as usize])
+}
+
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        e => (1.0 + mantissa / 1024.0) * 2f32.powi(e - 15),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, String> {
+    let bytes = data.get(offset..offset + 4).ok_or("truncated header")?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, String> {
+    Ok(read_u32(data, offset)? as u64 | (read_u32(data, offset + 4)? as u64) << 32)
+}
@@ -0,0 +1,69 @@
+use crate::renderer::TILE_SIZE;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+/// How many threads rasterize. By default the renderer shares rayon's global pool with
+/// everything else, which fights with game code that also uses rayon. Any other setting gives
+/// the renderer a pool of its own.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct RenderThreads {
+    /// Threads in the render pool, `None` for every core but the `reserved` ones.
+    pub threads: Option<usize>,
+    /// Cores left free for game logic, audio and the like.
+    pub reserved: usize,
+    /// Halves the edge of the tiles frames are cut into, four times as many tiles for rayon
+    /// to spread over the threads. Evens out the load when the triangles bunch up in part of
+    /// the screen, costs a little binning work otherwise.
+    pub small_tiles: bool,
+}
+#[allow(unused)]
+impl RenderThreads {
+    pub fn new(threads: usize) -> Self {
+        Self {
+            threads: Some(threads.max(1)),
+            ..Self::default()
+        }
+    }
+    /// Every core but `reserved` ones.
+    pub fn reserving(reserved: usize) -> Self {
+        Self {
+            reserved,
+            ..Self::default()
+        }
+    }
+    pub fn with_small_tiles(mut self, small_tiles: bool) -> Self {
+        self.small_tiles = small_tiles;
+        self
+    }
+    /// Threads the pool gets.
+    pub fn thread_count(&self) -> usize {
+        let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+        self.threads
+            .unwrap_or(cores.saturating_sub(self.reserved))
+            .max(1)
+    }
+    /// Side of the square tiles `RenderTarget::create_tiles` makes.
+    pub(crate) fn tile_size(&self) -> u32 {
+        if self.small_tiles {
+            TILE_SIZE / 2
+        } else {
+            TILE_SIZE
+        }
+    }
+    /// The renderer's own pool, `None` to use the global one, also when building it failed.
+    pub(crate) fn build_pool(&self) -> Option<ThreadPool> {
+        if self.threads.is_none() && self.reserved == 0 {
+            return None;
+        }
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(self.thread_count())
+            .thread_name(|index| format!("softrast-render-{}", index))
+            .build();
+        match pool {
+            Ok(pool) => Some(pool),
+            Err(err) => {
+                eprintln!("render thread pool: {}", err);
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::error::SoftRastError;
+use crate::offline::{OfflineRender, model_scene, render_image, save_image};
+use crate::renderer::DrawMode;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+use nalgebra::{UnitQuaternion, Vector3};
+use std::fs::File;
+
+/// Orbits the offline render camera once around its target over `frames` frames. An `out`
+/// ending in `.gif` writes an animation, anything else is a numbered image sequence with
+/// `#` marking where the frame number goes, e.g. `turn_###.png`.
+#[derive(Debug, Clone)]
+pub struct Turntable {
+    pub frames: u32,
+    /// Frame delay for GIF output.
+    pub frame_ms: u32,
+    pub out: String,
+}
+impl Default for Turntable {
+    fn default() -> Self {
+        Self {
+            frames: 60,
+            frame_ms: 40,
+            out: "turntable.gif".to_owned(),
+        }
+    }
+}
+
+pub fn render_turntable(
+    options: &OfflineRender,
+    turntable: &Turntable,
+) -> Result<(), SoftRastError> {
+    let (mut scene, pivot) = model_scene(options)?;
+    let mode = DrawMode {
+        shaded: true,
+        wireframe: options.wireframe,
+        points: false,
+        ray_traced: options.ray_traced,
+        shadows: options.shadows,
+        ..DrawMode::default()
+    };
+    let start = scene.camera;
+    let frames = turntable.frames.max(1);
+    let gif = turntable.out.to_lowercase().ends_with(".gif");
+    let mut gif_frames = vec![];
+    for frame in 0..frames {
+        let angle = frame as f32 / frames as f32 * std::f32::consts::TAU;
+        let rotation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), angle);
+        scene.camera.position = pivot + rotation * (start.position - pivot);
+        scene.camera.orientation = rotation * start.orientation;
+        let image = render_image(&mut scene, (options.width, options.height), &mode);
+        if gif {
+            gif_frames.push(Frame::from_parts(
+                image,
+                0,
+                0,
+                Delay::from_numer_denom_ms(turntable.frame_ms, 1),
+            ));
+        } else {
+            save_image(&image, &numbered_path(&turntable.out, frame))?;
+        }
+    }
+    if gif {
+        let gif_error = |err| SoftRastError::from_image(&turntable.out, err);
+        let file = File::create(&turntable.out).map_err(|source| SoftRastError::Io {
+            path: turntable.out.clone(),
+            source,
+        })?;
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+        encoder.encode_frames(gif_frames).map_err(gif_error)?;
+    }
+    Ok(())
+}
+
+/// Replaces the run of `#` in `pattern` with the zero padded frame number, or appends the
+/// number before the extension when there is none.
+fn numbered_path(pattern: &str, frame: u32) -> String {
+    match pattern.find('#') {
+        Some(start) => {
+            let width = pattern[start..].chars().take_while(|c| *c == '#').count();
+            format!(
+                "{}{:0width$}{}",
+                &pattern[..start],
+                frame,
+                &pattern[start + width..],
+                width = width
+            )
+        }
+        None => match pattern.rsplit_once('.') {
+            Some((stem, extension)) => format!("{}_{:04}.{}", stem, frame, extension),
+            None => format!("{}_{:04}.png", pattern, frame),
+        },
+    }
+}
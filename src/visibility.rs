@@ -0,0 +1,73 @@
+use crate::{EntityId, Scene, cull};
+
+/// What changed about an entity seen from the scene's camera.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VisibilityChange {
+    /// Passed culling for the first time, or again after leaving.
+    Entered,
+    /// Culled by the frustum or the portals.
+    Left,
+    /// Switched level of detail, 0 is the full model.
+    Lod { from: usize, to: usize },
+}
+
+/// A change of an entity with `Entity::with_visibility_events`, see `SoftRastEvent::Visibility`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisibilityEvent {
+    pub id: String,
+    /// Set when the entity was added through `Command::spawn`.
+    pub handle: Option<EntityId>,
+    pub change: VisibilityChange,
+}
+
+/// What was last reported for an entity.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub(crate) struct VisibilityState {
+    pub(crate) in_view: bool,
+    pub(crate) lod: usize,
+}
+
+/// Culls the tracked entities against the scene's camera like the main view does and
+/// reports the ones that changed since the last call. Entities start out of view, so the
+/// first call reports every visible one as entered.
+pub(crate) fn track_visibility(scene: &mut Scene) -> Vec<VisibilityEvent> {
+    if !scene
+        .entities
+        .iter()
+        .any(|entity| entity.visibility.is_some())
+    {
+        return vec![];
+    }
+    let camera = scene.camera;
+    let visible = cull(scene, &camera);
+    let mut events = vec![];
+    for (entity, in_view) in scene.entities.iter_mut().zip(visible) {
+        let Some(state) = entity.visibility else {
+            continue;
+        };
+        entity.select_lod(&camera.world_position());
+        let mut changes = vec![];
+        if in_view != state.in_view {
+            changes.push(match in_view {
+                true => VisibilityChange::Entered,
+                false => VisibilityChange::Left,
+            });
+        }
+        if entity.lod_level != state.lod {
+            changes.push(VisibilityChange::Lod {
+                from: state.lod,
+                to: entity.lod_level,
+            });
+        }
+        entity.visibility = Some(VisibilityState {
+            in_view,
+            lod: entity.lod_level,
+        });
+        events.extend(changes.into_iter().map(|change| VisibilityEvent {
+            id: entity.id.clone(),
+            handle: entity.handle,
+            change,
+        }));
+    }
+    events
+}
@@ -0,0 +1,240 @@
+use crate::geometry::{Model, Vertex};
+use crate::renderer::Color;
+use nalgebra::{Point3, Vector2, Vector3};
+use std::collections::HashMap;
+
+pub type BlockId = u16;
+pub type ChunkKey = (i32, i32, i32);
+
+/// Block id of empty space, every other id is treated as a solid cube.
+pub const AIR: BlockId = 0;
+pub const CHUNK_SIZE: i32 = 16;
+
+/// Cube faces in the order used by `TextureAtlas` tiles: +X, -X, +Y, -Y, +Z, -Z.
+const FACES: [([i32; 3], [[f32; 3]; 4]); 6] = [
+    (
+        [1, 0, 0],
+        [
+            [1.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.0],
+            [1.0, 1.0, 1.0],
+        ],
+    ),
+    (
+        [-1, 0, 0],
+        [
+            [0.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.0, 1.0, 1.0],
+            [0.0, 1.0, 0.0],
+        ],
+    ),
+    (
+        [0, 1, 0],
+        [
+            [0.0, 1.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [1.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0],
+        ],
+    ),
+    (
+        [0, -1, 0],
+        [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 0.0, 1.0],
+            [0.0, 0.0, 1.0],
+        ],
+    ),
+    (
+        [0, 0, 1],
+        [
+            [0.0, 0.0, 1.0],
+            [1.0, 0.0, 1.0],
+            [1.0, 1.0, 1.0],
+            [0.0, 1.0, 1.0],
+        ],
+    ),
+    (
+        [0, 0, -1],
+        [
+            [1.0, 0.0, 0.0],
+            [0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [1.0, 1.0, 0.0],
+        ],
+    ),
+];
+
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    blocks: Vec<BlockId>,
+}
+#[allow(unused)]
+impl Chunk {
+    pub fn new() -> Self {
+        Self {
+            blocks: vec![AIR; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
+        }
+    }
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        (x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+    pub fn get(&self, x: i32, y: i32, z: i32) -> BlockId {
+        self.blocks[Self::index(x, y, z)]
+    }
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block: BlockId) {
+        self.blocks[Self::index(x, y, z)] = block;
+    }
+    pub fn is_empty(&self) -> bool {
+        self.blocks.iter().all(|block| *block == AIR)
+    }
+}
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps block ids to tiles of a grid shaped texture atlas, tile 0 is the top left one.
+#[allow(unused)]
+#[derive(Clone, Debug)]
+pub struct TextureAtlas {
+    pub columns: u32,
+    pub rows: u32,
+    tiles: HashMap<BlockId, [u32; 6]>,
+}
+#[allow(unused)]
+impl TextureAtlas {
+    pub fn new(columns: u32, rows: u32) -> Self {
+        Self {
+            columns,
+            rows,
+            tiles: HashMap::new(),
+        }
+    }
+    pub fn with_block(mut self, block: BlockId, tile: u32) -> Self {
+        self.tiles.insert(block, [tile; 6]);
+        self
+    }
+    /// Tiles per face in the order +X, -X, +Y, -Y, +Z, -Z.
+    pub fn with_block_faces(mut self, block: BlockId, tiles: [u32; 6]) -> Self {
+        self.tiles.insert(block, tiles);
+        self
+    }
+    /// UV corners of a tile as (min, max).
+    pub fn tile_uvs(&self, tile: u32) -> (Vector2<f32>, Vector2<f32>) {
+        let column = (tile % self.columns) as f32;
+        let row = (tile / self.columns) as f32;
+        let (columns, rows) = (self.columns as f32, self.rows as f32);
+        (
+            Vector2::new(column / columns, 1.0 - (row + 1.0) / rows),
+            Vector2::new((column + 1.0) / columns, 1.0 - row / rows),
+        )
+    }
+    fn face_tile(&self, block: BlockId, face: usize) -> u32 {
+        self.tiles.get(&block).map(|tiles| tiles[face]).unwrap_or(0)
+    }
+}
+
+/// A sparse world of `CHUNK_SIZE`³ chunks addressed by block coordinates.
+#[allow(unused)]
+#[derive(Clone, Debug, Default)]
+pub struct VoxelWorld {
+    pub chunks: HashMap<ChunkKey, Chunk>,
+}
+#[allow(unused)]
+impl VoxelWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn split(x: i32, y: i32, z: i32) -> (ChunkKey, (i32, i32, i32)) {
+        (
+            (
+                x.div_euclid(CHUNK_SIZE),
+                y.div_euclid(CHUNK_SIZE),
+                z.div_euclid(CHUNK_SIZE),
+            ),
+            (
+                x.rem_euclid(CHUNK_SIZE),
+                y.rem_euclid(CHUNK_SIZE),
+                z.rem_euclid(CHUNK_SIZE),
+            ),
+        )
+    }
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let (key, (lx, ly, lz)) = Self::split(x, y, z);
+        self.chunks
+            .get(&key)
+            .map(|chunk| chunk.get(lx, ly, lz))
+            .unwrap_or(AIR)
+    }
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: BlockId) {
+        let (key, (lx, ly, lz)) = Self::split(x, y, z);
+        self.chunks.entry(key).or_default().set(lx, ly, lz, block);
+    }
+    /// Builds a world space mesh of one chunk, only emitting faces that border air
+    /// (neighbouring chunks included).
+    pub fn mesh_chunk(&self, key: ChunkKey, atlas: &TextureAtlas) -> Model {
+        let mut vertices = Vec::new();
+        let Some(chunk) = self.chunks.get(&key) else {
+            return Model::from_vertices(&vertices);
+        };
+        let color = Color::new(1.0, 1.0, 1.0, 1.0);
+        let origin = Vector3::new(key.0, key.1, key.2) * CHUNK_SIZE;
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = chunk.get(x, y, z);
+                    if block == AIR {
+                        continue;
+                    }
+                    let world = origin + Vector3::new(x, y, z);
+                    for (face, (normal, corners)) in FACES.iter().enumerate() {
+                        let normal = Vector3::new(normal[0], normal[1], normal[2]);
+                        let neighbour = world + normal;
+                        if self.get_block(neighbour.x, neighbour.y, neighbour.z) != AIR {
+                            continue;
+                        }
+                        let (uv_min, uv_max) = atlas.tile_uvs(atlas.face_tile(block, face));
+                        let uvs = [
+                            Vector2::new(uv_min.x, uv_min.y),
+                            Vector2::new(uv_max.x, uv_min.y),
+                            Vector2::new(uv_max.x, uv_max.y),
+                            Vector2::new(uv_min.x, uv_max.y),
+                        ];
+                        let quad = corners
+                            .iter()
+                            .zip(uvs)
+                            .map(|(corner, uv)| {
+                                let position = Point3::new(
+                                    world.x as f32 + corner[0],
+                                    world.y as f32 + corner[1],
+                                    world.z as f32 + corner[2],
+                                );
+                                let mut vertex = Vertex::new(&position)
+                                    .with_normal(normal.cast::<f32>())
+                                    .with_uv(uv);
+                                vertex.color = Some(color);
+                                vertex
+                            })
+                            .collect::<Vec<_>>();
+                        vertices.extend([quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+                    }
+                }
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+    pub fn mesh_all(&self, atlas: &TextureAtlas) -> Vec<(ChunkKey, Model)> {
+        self.chunks
+            .iter()
+            .filter(|(_, chunk)| !chunk.is_empty())
+            .map(|(key, _)| (*key, self.mesh_chunk(*key, atlas)))
+            .collect()
+    }
+}
@@ -0,0 +1,198 @@
+use crate::geometry::{Model, Texture, Vertex};
+use crate::renderer::{Color, Fragment, Shader, calculate_normals, calculate_uvs};
+use nalgebra::{Point2, Point3, Vector2, Vector3};
+use std::f32::consts::TAU;
+
+/// One travelling sine wave of a water surface.
+#[derive(Debug, Copy, Clone)]
+pub struct Wave {
+    /// Direction of travel in the local xz plane.
+    pub direction: Vector2<f32>,
+    pub amplitude: f32,
+    pub wavelength: f32,
+    /// World units per second.
+    pub speed: f32,
+}
+impl Wave {
+    pub fn new(direction: Vector2<f32>, amplitude: f32, wavelength: f32, speed: f32) -> Self {
+        Self {
+            direction: direction.try_normalize(1e-6).unwrap_or(Vector2::x()),
+            amplitude,
+            wavelength,
+            speed,
+        }
+    }
+}
+
+/// Water surface settings, see `Entity::with_water`. The surface is the local y = 0 plane,
+/// moved by the sum of `waves` in the vertex stage. It reflects the scene through a planar
+/// reflection pass rendered every frame, distorted by scrolling ripples, and is tinted from
+/// `shallow` to `deep` by the water depth baked into the model by `Water::model`.
+#[derive(Debug, Clone)]
+pub struct Water {
+    pub waves: Vec<Wave>,
+    pub shallow: Color,
+    pub deep: Color,
+    /// Foam color along the shoreline.
+    pub shore: Color,
+    /// Water depth at which `deep` is reached.
+    pub depth_range: f32,
+    /// Share of the reflection in the final color, at full depth.
+    pub reflectivity: f32,
+    /// Strength of the ripple distortion of the reflection, in screen UVs.
+    pub distortion: f32,
+    /// Scroll speed of the ripples in UVs per second.
+    pub ripple_velocity: Vector2<f32>,
+    /// Size of the reflection pass relative to the main target.
+    pub resolution_scale: f32,
+    /// Direction towards the light shading the waves.
+    pub light_dir: Vector3<f32>,
+}
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            waves: vec![
+                Wave::new(Vector2::new(1.0, 0.3), 0.08, 6.0, 1.5),
+                Wave::new(Vector2::new(-0.4, 1.0), 0.04, 2.5, 1.0),
+            ],
+            shallow: Color::new(0.2, 0.6, 0.6, 1.0),
+            deep: Color::new(0.02, 0.12, 0.25, 1.0),
+            shore: Color::new(0.9, 0.95, 0.95, 1.0),
+            depth_range: 4.0,
+            reflectivity: 0.5,
+            distortion: 0.01,
+            ripple_velocity: Vector2::new(0.03, 0.02),
+            resolution_scale: 0.5,
+            light_dir: Vector3::new(1.0, 1.0, 0.0).normalize(),
+        }
+    }
+}
+#[allow(unused)]
+impl Water {
+    pub fn new(shallow: Color, deep: Color) -> Self {
+        Self {
+            shallow,
+            deep,
+            ..Self::default()
+        }
+    }
+    pub fn with_wave(mut self, wave: Wave) -> Self {
+        self.waves.push(wave);
+        self
+    }
+    pub fn with_waves(mut self, waves: Vec<Wave>) -> Self {
+        self.waves = waves;
+        self
+    }
+    pub fn with_reflection(mut self, reflectivity: f32, distortion: f32) -> Self {
+        self.reflectivity = reflectivity;
+        self.distortion = distortion;
+        self
+    }
+    /// Surface height and normal at a local xz position.
+    pub fn surface(&self, x: f32, z: f32, time: f32) -> (f32, Vector3<f32>) {
+        let (mut height, mut dx, mut dz) = (0.0, 0.0, 0.0);
+        for wave in &self.waves {
+            let k = TAU / wave.wavelength.max(1e-3);
+            let phase = k * (wave.direction.x * x + wave.direction.y * z - wave.speed * time);
+            height += wave.amplitude * phase.sin();
+            let slope = wave.amplitude * k * phase.cos();
+            dx += slope * wave.direction.x;
+            dz += slope * wave.direction.y;
+        }
+        (height, Vector3::new(-dx, 1.0, -dz).normalize())
+    }
+    /// Moves a model space vertex of the surface by the waves.
+    pub fn displace(&self, vertex: &mut Vertex, time: f32) {
+        let (height, normal) = self.surface(vertex.position.x, vertex.position.z, time);
+        vertex.position.y += height;
+        vertex.normal = Some(normal);
+    }
+    /// A flat `width` x `depth` grid of `cells` x `cells` quads centered on the origin. `floor`
+    /// gives the ground height under a local xz position, e.g. from `Terrain::height_at`, and
+    /// the water depth above it is stored in the red channel of the vertex colors for the
+    /// shoreline tint.
+    pub fn model(
+        &self,
+        width: f32,
+        depth: f32,
+        cells: usize,
+        floor: impl Fn(f32, f32) -> f32,
+    ) -> Model {
+        let cells = cells.max(1);
+        let vertex = |i: usize, j: usize| {
+            let u = i as f32 / cells as f32;
+            let v = j as f32 / cells as f32;
+            let (x, z) = ((u - 0.5) * width, (0.5 - v) * depth);
+            let water_depth = (-floor(x, z) / self.depth_range.max(1e-3)).clamp(0.0, 1.0);
+            let mut vertex = Vertex::new(&Point3::new(x, 0.0, z))
+                .with_normal(Vector3::y())
+                .with_uv(Vector2::new(u, v));
+            vertex.color = Some(Color::new(water_depth, water_depth, water_depth, 1.0));
+            vertex
+        };
+        let mut vertices = Vec::with_capacity(cells * cells * 6);
+        for j in 0..cells {
+            for i in 0..cells {
+                // Counter clockwise seen from above, v grows towards -z
+                let quad = [
+                    vertex(i, j),
+                    vertex(i + 1, j),
+                    vertex(i + 1, j + 1),
+                    vertex(i, j + 1),
+                ];
+                vertices.extend_from_slice(&[quad[0], quad[1], quad[2], quad[0], quad[2], quad[3]]);
+            }
+        }
+        Model::from_vertices(&vertices)
+    }
+}
+
+/// Shades a water surface from its settings and the latest reflection pass.
+pub struct WaterShader {
+    pub water: Water,
+    pub reflection: Option<Texture>,
+}
+impl WaterShader {
+    pub fn new(water: Water, reflection: Option<Texture>) -> Self {
+        Self { water, reflection }
+    }
+}
+impl Shader for WaterShader {
+    fn shade(&self, triangle: &[Vertex], fragment: &Fragment) -> Color {
+        let water = &self.water;
+        let depth = match (triangle[0].color, triangle[1].color, triangle[2].color) {
+            (Some(a), Some(b), Some(c)) => a.interpolate(&b, &c, &fragment.weights).r,
+            _ => 1.0,
+        };
+        let mut color = water.shallow.lerp(&water.deep, depth);
+        if let Some(normal) = calculate_normals(triangle, &fragment.weights) {
+            color = color * normal.dot(&water.light_dir).max(0.2);
+        }
+        // Two layers of ripples scrolling in different directions
+        let uv = calculate_uvs(triangle, &fragment.weights).unwrap_or(Point2::origin());
+        let ripple = |uv: Point2<f32>, scale: f32| {
+            Vector2::new((uv.x * scale * TAU).sin(), (uv.y * scale * TAU).cos())
+        };
+        let scroll = water.ripple_velocity * fragment.time;
+        let offset =
+            (ripple(uv + scroll, 7.0) + ripple(uv - scroll * 1.3, 13.0)) * 0.5 * water.distortion;
+        if let Some(reflection) = &self.reflection {
+            // The reflection camera is a proper rotation, so its image is flipped horizontally
+            let screen = Point2::new(1.0 - fragment.screen.x, 1.0 - fragment.screen.y) + offset;
+            if let Some(reflected) = reflection.sample(&screen) {
+                color = color.lerp(&reflected, water.reflectivity * depth.max(0.2));
+            }
+        }
+        // Foam fades out over the shallowest tenth of the depth range
+        let foam = (1.0 - depth / 0.1).clamp(0.0, 1.0)
+            * (0.75 + 0.25 * (offset.x / water.distortion.max(1e-6)));
+        color.lerp(&water.shore, foam.clamp(0.0, 1.0))
+    }
+    fn animated(&self) -> bool {
+        true
+    }
+    fn light_dir(&self) -> Option<Vector3<f32>> {
+        Some(self.water.light_dir)
+    }
+}